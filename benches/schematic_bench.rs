@@ -1,4 +1,4 @@
-use criterion::{criterion_group, criterion_main, Criterion};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
 use minecraft_schematic_utils::{BlockState, Region, UniversalSchematic};
 
 fn benchmark_schematic_creation(c: &mut Criterion) {
@@ -56,7 +56,48 @@ fn benchmark_big_schematic_creation_with_region_prealloc(c: &mut Criterion) {
     });
 }
 
+// Decorated builds easily reach hundreds of distinct states. Compares the palette index used by
+// set_block with the linear scan it replaced.
+fn benchmark_large_palette(c: &mut Criterion) {
+    let mut group = c.benchmark_group("large palette");
+    for palette_size in [16, 256, 1024] {
+        let states: Vec<BlockState> = (0..palette_size)
+            .map(|i| BlockState::new("minecraft:oak_sign".to_string())
+                .with_property("rotation".to_string(), (i % 16).to_string())
+                .with_property("variant".to_string(), (i / 16).to_string()))
+            .collect();
 
+        group.bench_with_input(BenchmarkId::new("set block", palette_size), &states, |b, states| {
+            b.iter(|| {
+                let mut region = Region::new("Main".to_string(), (0, 0, 0), (32, 32, 32));
+                for (index, state) in states.iter().cycle().take(region.volume()).enumerate() {
+                    let (x, y, z) = region.index_to_coords(index);
+                    region.set_block(x, y, z, state.clone());
+                }
+                region
+            })
+        });
+
+        group.bench_with_input(BenchmarkId::new("linear scan", palette_size), &states, |b, states| {
+            b.iter(|| {
+                let mut palette: Vec<BlockState> = Vec::new();
+                let mut blocks = Vec::with_capacity(32 * 32 * 32);
+                for state in states.iter().cycle().take(32 * 32 * 32) {
+                    let index = match palette.iter().position(|b| b == state) {
+                        Some(index) => index,
+                        None => {
+                            palette.push(state.clone());
+                            palette.len() - 1
+                        }
+                    };
+                    blocks.push(index);
+                }
+                blocks
+            })
+        });
+    }
+    group.finish();
+}
 
-criterion_group!(benches, benchmark_schematic_creation, benchmark_block_setting, benchmark_big_schematic_creation, benchmark_big_schematic_creation_with_region_prealloc);
+criterion_group!(benches, benchmark_schematic_creation, benchmark_block_setting, benchmark_big_schematic_creation, benchmark_big_schematic_creation_with_region_prealloc, benchmark_large_palette);
 criterion_main!(benches);
@@ -1,5 +1,5 @@
 use std::collections::HashMap;
-use quartz_nbt::NbtCompound;
+use quartz_nbt::{NbtCompound, NbtTag};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use crate::entity::Entity;
 use crate::item::ItemStack;
@@ -69,6 +69,60 @@ impl BlockEntity {
         BlockEntity { nbt: nbt_map, id, position }
     }
 
+    /// Reads a block entity in the vanilla layout shared by most formats, with the id in `id`
+    /// and the position in `x`/`y`/`z` relative to `origin`. Missing coordinates count as 0.
+    /// The NBT keeps `Id` and `Pos` the way a Sponge schematic does, so it exports unchanged.
+    pub fn from_format_nbt(nbt: &NbtCompound, origin: (i32, i32, i32)) -> Self {
+        let id = nbt.get::<_, &str>("id")
+            .map(|s| s.to_string())
+            .unwrap_or_else(|_| "unknown".to_string());
+        let position = (
+            origin.0 + nbt.get::<_, i32>("x").unwrap_or(0),
+            origin.1 + nbt.get::<_, i32>("y").unwrap_or(0),
+            origin.2 + nbt.get::<_, i32>("z").unwrap_or(0),
+        );
+
+        let mut nbt_map = NbtMap::from_quartz_nbt(nbt);
+        for key in ["id", "x", "y", "z", "keepPacked"] {
+            nbt_map.remove(key);
+        }
+        nbt_map.insert("Id".to_string(), NbtValue::String(id.clone()));
+        nbt_map.insert("Pos".to_string(), NbtValue::IntArray(vec![position.0, position.1, position.2]));
+
+        BlockEntity { nbt: nbt_map, id, position }
+    }
+
+    /// Writes the block entity in the vanilla layout, the reverse of [`BlockEntity::from_format_nbt`].
+    pub fn to_format_nbt(&self, origin: (i32, i32, i32)) -> NbtCompound {
+        let mut nbt = NbtCompound::new();
+        for (key, value) in &self.nbt {
+            if key == "Id" || key == "Pos" {
+                continue;
+            }
+            nbt.insert(key, value.to_quartz_nbt());
+        }
+        nbt.insert("id", NbtTag::String(self.id.clone()));
+        nbt.insert("x", NbtTag::Int(self.position.0 - origin.0));
+        nbt.insert("y", NbtTag::Int(self.position.1 - origin.1));
+        nbt.insert("z", NbtTag::Int(self.position.2 - origin.2));
+        nbt
+    }
+
+    // Keeps the `Id` tag in sync, for formats that spell block entity ids differently.
+    pub(crate) fn with_id(mut self, id: String) -> Self {
+        self.nbt.insert("Id".to_string(), NbtValue::String(id.clone()));
+        self.id = id;
+        self
+    }
+
+    // Keeps the `Pos` tag in sync when there is one.
+    pub(crate) fn move_to(&mut self, position: (i32, i32, i32)) {
+        self.position = position;
+        if let Some(NbtValue::IntArray(_)) = self.nbt.get("Pos") {
+            self.nbt.insert("Pos".to_string(), NbtValue::IntArray(vec![position.0, position.1, position.2]));
+        }
+    }
+
     pub fn to_nbt(&self) -> NbtCompound {
         let mut nbt = NbtCompound::new();
         for (key, value) in &self.nbt {
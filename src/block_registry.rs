@@ -0,0 +1,276 @@
+//! Vanilla block ids and the properties they accept, per Minecraft version.
+
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use crate::BlockState;
+use crate::error::BlockValidationError;
+
+// One `since[-until] id[properties] [block_entity]` entry per line, see the header of the file.
+const BLOCK_REGISTRY: &str = include_str!("block_registry.txt");
+
+/// The newest DataVersion the registry knows about (1.21.4).
+pub const LATEST_DATA_VERSION: i32 = 4189;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PropertyDefinition {
+    pub name: String,
+    /// Every value the property accepts, starting with its default.
+    pub values: Vec<String>,
+}
+
+impl PropertyDefinition {
+    pub fn default_value(&self) -> &str {
+        &self.values[0]
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockDefinition {
+    pub id: String,
+    /// Sorted by name.
+    pub properties: Vec<PropertyDefinition>,
+    pub has_block_entity: bool,
+    /// First DataVersion the definition applies to.
+    pub since: i32,
+    /// Last DataVersion the definition applies to, if a later version changed or removed the block.
+    pub until: Option<i32>,
+}
+
+impl BlockDefinition {
+    pub fn property(&self, name: &str) -> Option<&PropertyDefinition> {
+        self.properties.iter().find(|property| property.name == name)
+    }
+
+    fn applies_to(&self, data_version: i32) -> bool {
+        data_version >= self.since && self.until.is_none_or(|until| data_version <= until)
+    }
+}
+
+/// A palette entry that the game would not accept, as found by [`UniversalSchematic::validate`].
+///
+/// [`UniversalSchematic::validate`]: crate::UniversalSchematic::validate
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationIssue {
+    pub region: String,
+    pub block: BlockState,
+    /// How many blocks in the region use this state.
+    pub count: usize,
+    pub error: BlockValidationError,
+}
+
+fn registry() -> &'static HashMap<String, Vec<BlockDefinition>> {
+    static REGISTRY: OnceLock<HashMap<String, Vec<BlockDefinition>>> = OnceLock::new();
+    REGISTRY.get_or_init(|| {
+        parse_registry(BLOCK_REGISTRY)
+            .unwrap_or_else(|line| panic!("Invalid line in block_registry.txt: {}", line))
+    })
+}
+
+// Fails with the first line that doesn't parse, after expanding its macros.
+fn parse_registry(text: &str) -> Result<HashMap<String, Vec<BlockDefinition>>, String> {
+    let mut macros: HashMap<String, String> = HashMap::new();
+    let mut registry: HashMap<String, Vec<BlockDefinition>> = HashMap::new();
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some((name, text)) = line.strip_prefix('$').and_then(|rest| rest.split_once(" = ")) {
+            let text = expand_macros(text, &macros);
+            macros.insert(name.to_string(), text);
+            continue;
+        }
+        let line = expand_macros(line, &macros);
+        for definition in parse_entry(&line).ok_or(line)? {
+            registry.entry(definition.id.clone()).or_default().push(definition);
+        }
+    }
+    Ok(registry)
+}
+
+fn expand_macros(text: &str, macros: &HashMap<String, String>) -> String {
+    let mut expanded = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('$') {
+        expanded.push_str(&rest[..start]);
+        let name_len = rest[start + 1..]
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len() - start - 1);
+        let name = &rest[start + 1..start + 1 + name_len];
+        expanded.push_str(macros.get(name).map_or(&rest[start..start + 1 + name_len], String::as_str));
+        rest = &rest[start + 1 + name_len..];
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+fn parse_entry(line: &str) -> Option<Vec<BlockDefinition>> {
+    // Left over from a macro that was never defined.
+    if line.contains('$') {
+        return None;
+    }
+    let (versions, rest) = line.split_once(' ')?;
+    let (since, until) = match versions.split_once('-') {
+        Some((since, until)) => (since.parse().ok()?, Some(until.parse().ok()?)),
+        None => (versions.parse().ok()?, None),
+    };
+
+    let (block, flags) = rest.split_once(' ').unwrap_or((rest, ""));
+    if !matches!(flags, "" | "block_entity") {
+        return None;
+    }
+    let (id, properties) = match block.split_once('[') {
+        Some((id, properties)) => (id, properties.strip_suffix(']')?),
+        None => (block, ""),
+    };
+
+    let mut properties = properties.split(',')
+        .filter(|property| !property.is_empty())
+        .map(|property| {
+            let (name, values) = property.split_once('=')?;
+            Some(PropertyDefinition { name: name.to_string(), values: expand_values(values)? })
+        })
+        .collect::<Option<Vec<_>>>()?;
+    properties.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Some(expand_braces(id).into_iter().map(|id| BlockDefinition {
+        id,
+        properties: properties.clone(),
+        has_block_entity: flags == "block_entity",
+        since,
+        until,
+    }).collect())
+}
+
+fn expand_values(values: &str) -> Option<Vec<String>> {
+    let mut expanded = Vec::new();
+    for value in values.split('|') {
+        match value.split_once("..") {
+            Some((from, to)) => {
+                let (from, to): (i32, i32) = (from.parse().ok()?, to.parse().ok()?);
+                expanded.extend((from..=to).map(|n| n.to_string()));
+            }
+            None => expanded.push(value.to_string()),
+        }
+    }
+    Some(expanded)
+}
+
+// Expands the first `{...}` group, recursing for nested and later groups.
+fn expand_braces(id: &str) -> Vec<String> {
+    let Some(start) = id.find('{') else { return vec![id.to_string()] };
+    let mut depth = 0;
+    let mut alternatives = Vec::new();
+    let mut alternative_start = start + 1;
+    for (i, c) in id.char_indices().skip(start) {
+        match c {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    alternatives.push(&id[alternative_start..i]);
+                    let (prefix, suffix) = (&id[..start], &id[i + 1..]);
+                    return alternatives.iter()
+                        .flat_map(|alternative| expand_braces(&format!("{}{}{}", prefix, alternative, suffix)))
+                        .collect();
+                }
+            }
+            ',' if depth == 1 => {
+                alternatives.push(&id[alternative_start..i]);
+                alternative_start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    vec![id.to_string()]
+}
+
+/// The definition of `id` in the given version, if the block exists there.
+pub fn block_definition(id: &str, data_version: i32) -> Option<&'static BlockDefinition> {
+    registry().get(id)?.iter().find(|definition| definition.applies_to(data_version))
+}
+
+/// Every block id in the given version, sorted.
+pub fn block_ids(data_version: i32) -> Vec<&'static str> {
+    let mut ids: Vec<&'static str> = registry().values()
+        .flatten()
+        .filter(|definition| definition.applies_to(data_version))
+        .map(|definition| definition.id.as_str())
+        .collect();
+    ids.sort_unstable();
+    ids
+}
+
+pub(crate) fn validate_block(block: &BlockState, data_version: i32) -> Result<(), BlockValidationError> {
+    // Modded blocks, and blocks newer than the table, can't be checked.
+    if !block.name.starts_with("minecraft:") {
+        return Ok(());
+    }
+    let Some(definition) = block_definition(&block.name, data_version.min(LATEST_DATA_VERSION)) else {
+        if data_version > LATEST_DATA_VERSION {
+            return Ok(());
+        }
+        return Err(BlockValidationError::UnknownBlock(block.name.clone()));
+    };
+
+    for (name, value) in &block.properties {
+        let property = definition.property(name).ok_or_else(|| BlockValidationError::UnknownProperty {
+            block: block.name.clone(),
+            property: name.clone(),
+        })?;
+        if !property.values.contains(value) {
+            return Err(BlockValidationError::InvalidValue {
+                block: block.name.clone(),
+                property: name.clone(),
+                value: value.clone(),
+            });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_every_registry_line_parses() {
+        assert_eq!(parse_registry(BLOCK_REGISTRY).err(), None);
+        assert_eq!(parse_registry("1343 minecraft:stone glowing").err(), Some("1343 minecraft:stone glowing".to_string()));
+        assert_eq!(parse_registry("1343 minecraft:repeater[delay=1..x]").err(), Some("1343 minecraft:repeater[delay=1..x]".to_string()));
+        assert!(parse_registry("$facing = facing=north|south\n1343 minecraft:chest[$facings]").is_err());
+    }
+
+    #[test]
+    fn test_registry_entries_parse() {
+        let lines = BLOCK_REGISTRY.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#') && !line.starts_with('$'))
+            .count();
+        let parsed: usize = registry().values().flatten().count();
+        assert!(parsed > lines, "every entry should expand to at least one block");
+        assert!(registry().keys().all(|id| id.starts_with("minecraft:") && !id.contains(['{', '}', '$'])));
+
+        let repeater = block_definition("minecraft:repeater", LATEST_DATA_VERSION).unwrap();
+        assert_eq!(repeater.property("delay").unwrap().values, vec!["1", "2", "3", "4"]);
+        assert_eq!(repeater.property("facing").unwrap().default_value(), "north");
+        assert!(block_definition("minecraft:waxed_oxidized_cut_copper_stairs", LATEST_DATA_VERSION).is_some());
+        assert!(block_definition("minecraft:lime_shulker_box", LATEST_DATA_VERSION).unwrap().has_block_entity);
+    }
+
+    #[test]
+    fn test_definitions_follow_versions() {
+        assert!(block_definition("minecraft:grass", 2586).is_some());
+        assert!(block_definition("minecraft:grass", 3953).is_none());
+        assert!(block_definition("minecraft:short_grass", 3953).is_some());
+        assert!(block_definition("minecraft:cherry_log", 3105).is_none());
+
+        let old_wall = block_definition("minecraft:cobblestone_wall", 1976).unwrap();
+        assert_eq!(old_wall.property("north").unwrap().values, vec!["false", "true"]);
+        let wall = block_definition("minecraft:cobblestone_wall", 3953).unwrap();
+        assert_eq!(wall.property("north").unwrap().values, vec!["none", "low", "tall"]);
+
+        assert!(block_ids(1519).contains(&"minecraft:sign"));
+        assert!(!block_ids(1519).contains(&"minecraft:oak_sign"));
+    }
+}
@@ -1,13 +1,17 @@
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::fmt;
-use std::hash::{Hash, Hasher};
+use std::str::FromStr;
 use quartz_nbt::{NbtCompound, NbtTag};
 use serde::{Deserialize, Serialize};
+use crate::block_registry::{self, LATEST_DATA_VERSION};
+use crate::error::{BlockStateParseError, BlockValidationError};
+use crate::utils::NbtMap;
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+// Properties are kept sorted by key, so equal states always hash, print and export the same way.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct BlockState {
     pub name: String,
-    pub properties: HashMap<String, String>,
+    pub properties: BTreeMap<String, String>,
 }
 
 impl fmt::Display for BlockState {
@@ -19,7 +23,12 @@ impl fmt::Display for BlockState {
                 if i > 0 {
                     write!(f, ",")?;
                 }
-                write!(f, "{}={}", key, value)?;
+                write!(f, "{}=", key)?;
+                if needs_quotes(value) {
+                    write!(f, "\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))?;
+                } else {
+                    write!(f, "{}", value)?;
+                }
             }
             write!(f, "]")?;
         }
@@ -27,13 +36,21 @@ impl fmt::Display for BlockState {
     }
 }
 
-impl Hash for BlockState {
-    fn hash<H: Hasher>(&self, state: &mut H) {
-        self.name.hash(state);
-        for (k, v) in &self.properties {
-            k.hash(state);
-            v.hash(state);
-        }
+// Values that wouldn't survive parsing unquoted. Vanilla values never need this.
+fn needs_quotes(value: &str) -> bool {
+    value.is_empty()
+        || value.trim() != value
+        || value.contains([',', '[', ']', '=', '"', '\\', '{', '}'])
+}
+
+/// Parses the `/setblock` form, e.g. `minecraft:chest[facing=north]`. Ids without a namespace
+/// get `minecraft:`. A trailing block entity tag is accepted but dropped, see
+/// [`BlockState::parse_with_nbt`].
+impl FromStr for BlockState {
+    type Err = BlockStateParseError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        BlockState::parse_with_nbt(input).map(|(block, _)| block)
     }
 }
 
@@ -41,7 +58,7 @@ impl BlockState {
     pub fn new(name: String) -> Self {
         BlockState {
             name,
-            properties: HashMap::new(),
+            properties: BTreeMap::new(),
         }
     }
 
@@ -50,7 +67,7 @@ impl BlockState {
         self
     }
 
-    pub fn with_properties(mut self, properties: HashMap<String, String>) -> Self {
+    pub fn with_properties(mut self, properties: BTreeMap<String, String>) -> Self {
         self.properties = properties;
         self
     }
@@ -66,6 +83,59 @@ impl BlockState {
     pub fn get_property(&self, key: &str) -> Option<&String> {
         self.properties.get(key)
     }
+
+    /// Checks the block id and properties against the vanilla registry for `data_version`.
+    /// Properties that are left out are fine, the game uses their defaults. Blocks outside the
+    /// `minecraft` namespace are not checked.
+    pub fn validate(&self, data_version: i32) -> Result<(), BlockValidationError> {
+        block_registry::validate_block(self, data_version)
+    }
+
+    /// Fills in every property the block leaves out with its default in the latest version.
+    /// Blocks the registry doesn't know are returned unchanged.
+    pub fn with_defaults(mut self) -> Self {
+        if let Some(definition) = block_registry::block_definition(&self.name, LATEST_DATA_VERSION) {
+            for property in &definition.properties {
+                self.properties.entry(property.name.clone())
+                    .or_insert_with(|| property.default_value().to_string());
+            }
+        }
+        self
+    }
+
+    /// Checks this state against a pattern with the same name. Only the properties listed in the
+    /// pattern are compared, and a value of `*` accepts any value the property has.
+    pub fn matches_pattern(&self, pattern: &BlockState) -> bool {
+        self.name == pattern.name && pattern.properties.iter().all(|(key, value)| {
+            self.properties.get(key).is_some_and(|actual| value == "*" || actual == value)
+        })
+    }
+
+    /// Parses a block state followed by an optional SNBT block entity tag, as in
+    /// `minecraft:chest[facing=north]{Items:[]}`.
+    pub fn parse_with_nbt(input: &str) -> Result<(BlockState, Option<NbtMap>), BlockStateParseError> {
+        let input = input.trim();
+        let name_end = input.find(['[', '{']).unwrap_or(input.len());
+        let name = parse_block_id(input[..name_end].trim())?;
+
+        let mut rest = &input[name_end..];
+        let mut properties = BTreeMap::new();
+        if let Some(list) = rest.strip_prefix('[') {
+            rest = parse_properties(list, &mut properties)?;
+        }
+
+        let rest = rest.trim_start();
+        let nbt = if rest.is_empty() {
+            None
+        } else if rest.starts_with('{') {
+            Some(NbtMap::from_snbt(rest)?)
+        } else {
+            return Err(BlockStateParseError::TrailingInput(rest.to_string()));
+        };
+
+        Ok((BlockState { name, properties }, nbt))
+    }
+
     pub fn to_nbt(&self) -> NbtTag {
         let mut compound = NbtCompound::new();
         compound.insert("Name", self.name.clone());
@@ -87,7 +157,7 @@ impl BlockState {
             .map_err(|e| format!("Failed to get Name: {}", e))?
             .clone();
 
-        let mut properties = HashMap::new();
+        let mut properties = BTreeMap::new();
         if let Ok(props) = compound.get::<_, &NbtCompound>("Properties") {
             for (key, value) in props.inner() {
                 if let NbtTag::String(value_str) = value {
@@ -103,11 +173,84 @@ impl BlockState {
 
 
 
+}
+
+fn parse_block_id(id: &str) -> Result<String, BlockStateParseError> {
+    if id.is_empty() {
+        return Err(BlockStateParseError::Empty);
+    }
+    let (namespace, path) = id.split_once(':').unwrap_or(("minecraft", id));
+    let valid_namespace = !namespace.is_empty()
+        && namespace.chars().all(|c| matches!(c, 'a'..='z' | '0'..='9' | '_' | '-' | '.'));
+    let valid_path = !path.is_empty()
+        && path.chars().all(|c| matches!(c, 'a'..='z' | '0'..='9' | '_' | '-' | '.' | '/'));
+    if !valid_namespace || !valid_path {
+        return Err(BlockStateParseError::InvalidName(id.to_string()));
+    }
+    Ok(format!("{}:{}", namespace, path))
+}
+
+// Parses everything after the opening `[` and returns what follows the closing `]`.
+fn parse_properties<'a>(mut input: &'a str, properties: &mut BTreeMap<String, String>) -> Result<&'a str, BlockStateParseError> {
+    input = input.trim_start();
+    if let Some(rest) = input.strip_prefix(']') {
+        return Ok(rest);
+    }
+    loop {
+        let key_end = input.find(['=', ',', ']']).ok_or(BlockStateParseError::UnterminatedProperties)?;
+        let key = input[..key_end].trim();
+        if key.is_empty() || !key.chars().all(|c| matches!(c, 'a'..='z' | '0'..='9' | '_')) {
+            return Err(BlockStateParseError::InvalidPropertyName(key.to_string()));
+        }
+        if !input[key_end..].starts_with('=') {
+            return Err(BlockStateParseError::MissingValue(key.to_string()));
+        }
+        input = input[key_end + 1..].trim_start();
+
+        let value = if let Some(quoted) = input.strip_prefix('"') {
+            let mut value = String::new();
+            let mut chars = quoted.char_indices();
+            let end = loop {
+                match chars.next() {
+                    Some((index, '"')) => break index,
+                    Some((_, '\\')) => match chars.next() {
+                        Some((_, escaped)) => value.push(escaped),
+                        None => return Err(BlockStateParseError::UnterminatedQuote),
+                    },
+                    Some((_, c)) => value.push(c),
+                    None => return Err(BlockStateParseError::UnterminatedQuote),
+                }
+            };
+            input = quoted[end + 1..].trim_start();
+            value
+        } else {
+            let value_end = input.find([',', ']']).ok_or(BlockStateParseError::UnterminatedProperties)?;
+            let value = input[..value_end].trim();
+            if value.is_empty() {
+                return Err(BlockStateParseError::MissingValue(key.to_string()));
+            }
+            input = &input[value_end..];
+            value.to_string()
+        };
+
+        if properties.insert(key.to_string(), value).is_some() {
+            return Err(BlockStateParseError::DuplicateProperty(key.to_string()));
+        }
+
+        if let Some(rest) = input.strip_prefix(',') {
+            input = rest.trim_start();
+        } else if let Some(rest) = input.strip_prefix(']') {
+            return Ok(rest);
+        } else {
+            return Err(BlockStateParseError::UnterminatedProperties);
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::BlockState;
+    use crate::error::{BlockStateParseError, BlockValidationError};
 
     #[test]
     fn test_block_state_creation() {
@@ -117,4 +260,66 @@ mod tests {
         assert_eq!(block.name, "minecraft:stone");
         assert_eq!(block.properties.get("variant"), Some(&"granite".to_string()));
     }
+
+    #[test]
+    fn test_parse_block_state() {
+        let block: BlockState = "repeater[facing=east, delay=1]".parse().unwrap();
+        assert_eq!(block.name, "minecraft:repeater");
+        assert_eq!(block.get_property("delay"), Some(&"1".to_string()));
+        assert_eq!(block.to_string(), "minecraft:repeater[delay=1,facing=east]");
+        assert_eq!(block.to_string().parse::<BlockState>().unwrap(), block);
+
+        let sign = BlockState::new("mymod:sign".to_string())
+            .with_property("text".to_string(), "a, \"b\" [c]".to_string());
+        assert_eq!(sign.to_string().parse::<BlockState>().unwrap(), sign);
+
+        let (chest, nbt) = BlockState::parse_with_nbt("minecraft:chest[facing=north]{CustomName:\"Loot\"}").unwrap();
+        assert_eq!(chest.to_string(), "minecraft:chest[facing=north]");
+        assert_eq!(nbt.unwrap().get("CustomName").and_then(|value| value.as_string()), Some(&"Loot".to_string()));
+    }
+
+    #[test]
+    fn test_parse_block_state_errors() {
+        assert_eq!("".parse::<BlockState>(), Err(BlockStateParseError::Empty));
+        assert_eq!("Stone".parse::<BlockState>(), Err(BlockStateParseError::InvalidName("Stone".to_string())));
+        assert_eq!("stone[facing=north".parse::<BlockState>(), Err(BlockStateParseError::UnterminatedProperties));
+        assert_eq!("stone[facing]".parse::<BlockState>(), Err(BlockStateParseError::MissingValue("facing".to_string())));
+        assert_eq!("stone[a=1,a=2]".parse::<BlockState>(), Err(BlockStateParseError::DuplicateProperty("a".to_string())));
+        assert_eq!("stone[a=\"1]".parse::<BlockState>(), Err(BlockStateParseError::UnterminatedQuote));
+        assert_eq!("stone[] x".parse::<BlockState>(), Err(BlockStateParseError::TrailingInput("x".to_string())));
+        assert!(matches!("stone{a:".parse::<BlockState>(), Err(BlockStateParseError::InvalidNbt(_))));
+    }
+
+    #[test]
+    fn test_property_order_is_canonical() {
+        let a = BlockState::new("minecraft:repeater".to_string())
+            .with_property("facing".to_string(), "east".to_string())
+            .with_property("delay".to_string(), "1".to_string());
+        let b = BlockState::new("minecraft:repeater".to_string())
+            .with_property("delay".to_string(), "1".to_string())
+            .with_property("facing".to_string(), "east".to_string());
+
+        assert_eq!(a.to_string(), "minecraft:repeater[delay=1,facing=east]");
+        assert_eq!(a.to_string(), b.to_string());
+        let mut counts = std::collections::HashMap::new();
+        *counts.entry(a).or_insert(0) += 1;
+        *counts.entry(b).or_insert(0) += 1;
+        assert_eq!(counts.len(), 1);
+    }
+
+    #[test]
+    fn test_validate_and_defaults() {
+        let repeater: BlockState = "repeater[delay=2]".parse().unwrap();
+        assert_eq!(repeater.validate(3953), Ok(()));
+        assert_eq!("repeater[delay=5]".parse::<BlockState>().unwrap().validate(3953), Err(BlockValidationError::InvalidValue {
+            block: "minecraft:repeater".to_string(),
+            property: "delay".to_string(),
+            value: "5".to_string(),
+        }));
+        assert!(matches!("repeater[color=red]".parse::<BlockState>().unwrap().validate(3953), Err(BlockValidationError::UnknownProperty { .. })));
+        assert_eq!("short_grass".parse::<BlockState>().unwrap().validate(2586), Err(BlockValidationError::UnknownBlock("minecraft:short_grass".to_string())));
+        assert_eq!("mymod:machine[tier=9]".parse::<BlockState>().unwrap().validate(3953), Ok(()));
+
+        assert_eq!(repeater.with_defaults().to_string(), "minecraft:repeater[delay=2,facing=north,locked=false,powered=false]");
+    }
 }
@@ -0,0 +1,622 @@
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use crate::bounding_box::BoundingBox;
+
+/// Holds the palette index of every position in a region. Positions are absolute, and the
+/// region's bounding box is passed in so storage doesn't need to track it.
+pub(crate) trait BlockStorage {
+    fn get(&self, bounding_box: &BoundingBox, position: (i32, i32, i32)) -> usize;
+
+    fn set(&mut self, bounding_box: &BoundingBox, position: (i32, i32, i32), palette_index: usize);
+
+    /// Moves from `old` to `new`. Positions that weren't in `old` read as `fill` afterwards.
+    fn resize(&mut self, old: &BoundingBox, new: &BoundingBox, fill: usize);
+
+    /// Replaces every stored index `i` with `mapping[i]`.
+    fn remap(&mut self, mapping: &[usize]);
+
+    /// Visits the positions inside `bounding_box` that hold their own value. All others hold
+    /// `default_index()`.
+    fn for_each_stored(&self, bounding_box: &BoundingBox, f: &mut dyn FnMut((i32, i32, i32), usize));
+
+    fn default_index(&self) -> usize;
+}
+
+/// One entry per position of the bounding box, in litematic order (x, then z, then y).
+/// Entries are as narrow as the largest index stored so far allows, and widen on demand.
+/// A growing region gets room to spare, so growing again one block at a time doesn't copy
+/// every entry.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(from = "Vec<usize>", into = "Vec<usize>")]
+pub(crate) struct DenseStorage {
+    data: DenseData,
+    spare: Option<Spare>,
+}
+
+// The box `data` was allocated for, and the region's box within it.
+#[derive(Debug, Clone, PartialEq)]
+struct Spare {
+    allocated: BoundingBox,
+    used: BoundingBox,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum DenseData {
+    U8(Vec<u8>),
+    U16(Vec<u16>),
+    Packed(PackedArray),
+}
+
+fn bits_for(value: usize) -> usize {
+    (usize::BITS - value.leading_zeros()) as usize
+}
+
+impl DenseStorage {
+    pub fn new(volume: usize, fill: usize) -> Self {
+        let mut storage = DenseStorage { data: DenseData::U8(vec![0; volume]), spare: None };
+        if fill != 0 {
+            storage.widen_for(fill);
+            for index in 0..volume {
+                storage.set_at(index, fill);
+            }
+        }
+        storage
+    }
+
+    pub fn from_indices(blocks: Vec<usize>) -> Self {
+        let mut storage = DenseStorage { data: DenseData::U8(vec![0; blocks.len()]), spare: None };
+        storage.widen_for(blocks.iter().copied().max().unwrap_or(0));
+        for (index, block) in blocks.into_iter().enumerate() {
+            storage.set_at(index, block);
+        }
+        storage
+    }
+
+    fn allocated_len(&self) -> usize {
+        match &self.data {
+            DenseData::U8(data) => data.len(),
+            DenseData::U16(data) => data.len(),
+            DenseData::Packed(data) => data.len,
+        }
+    }
+
+    /// Bits used per entry.
+    pub fn bits_per_entry(&self) -> usize {
+        match &self.data {
+            DenseData::U8(_) => 8,
+            DenseData::U16(_) => 16,
+            DenseData::Packed(data) => data.bits,
+        }
+    }
+
+    /// Entries of the region's box, in litematic order.
+    pub fn iter(&self) -> Box<dyn Iterator<Item = usize> + '_> {
+        match &self.spare {
+            Some(spare) => Box::new((0..spare.used.volume() as usize).map(move |index| {
+                let (x, y, z) = spare.used.index_to_coords(index);
+                self.get_at(spare.allocated.coords_to_index(x, y, z))
+            })),
+            None => Box::new(self.entries()),
+        }
+    }
+
+    // Every allocated entry, spare ones included.
+    fn entries(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.allocated_len()).map(|index| self.get_at(index))
+    }
+
+    fn index(&self, bounding_box: &BoundingBox, (x, y, z): (i32, i32, i32)) -> usize {
+        self.spare.as_ref().map_or(bounding_box, |spare| &spare.allocated).coords_to_index(x, y, z)
+    }
+
+    /// Whether resizing to `new` fits in what is already allocated.
+    pub fn has_room_for(&self, old: &BoundingBox, new: &BoundingBox) -> bool {
+        let allocated = self.spare.as_ref().map_or(old, |spare| &spare.allocated);
+        allocated.contains(new.min) && allocated.contains(new.max)
+    }
+
+    fn get_at(&self, index: usize) -> usize {
+        match &self.data {
+            DenseData::U8(data) => data[index] as usize,
+            DenseData::U16(data) => data[index] as usize,
+            DenseData::Packed(data) => data.get(index),
+        }
+    }
+
+    // The entry must already be wide enough for `value`, see `widen_for`.
+    fn set_at(&mut self, index: usize, value: usize) {
+        match &mut self.data {
+            DenseData::U8(data) => data[index] = value as u8,
+            DenseData::U16(data) => data[index] = value as u16,
+            DenseData::Packed(data) => data.set(index, value),
+        }
+    }
+
+    fn widen_for(&mut self, value: usize) {
+        let bits = bits_for(value);
+        if bits <= self.bits_per_entry() {
+            return;
+        }
+        let len = self.allocated_len();
+        let data = if bits <= 16 {
+            DenseData::U16(self.entries().map(|block| block as u16).collect())
+        } else {
+            let mut packed = PackedArray::new(bits, len);
+            for (index, block) in self.entries().enumerate() {
+                packed.set(index, block);
+            }
+            DenseData::Packed(packed)
+        };
+        self.data = data;
+    }
+}
+
+impl From<Vec<usize>> for DenseStorage {
+    fn from(blocks: Vec<usize>) -> Self {
+        DenseStorage::from_indices(blocks)
+    }
+}
+
+// Visits the positions of `new` that are not in `old`, row by row rather than testing every
+// position of `new`.
+fn for_each_outside(new: &BoundingBox, old: &BoundingBox, mut f: impl FnMut((i32, i32, i32))) {
+    for y in new.min.1..=new.max.1 {
+        for z in new.min.2..=new.max.2 {
+            if y < old.min.1 || y > old.max.1 || z < old.min.2 || z > old.max.2 {
+                for x in new.min.0..=new.max.0 {
+                    f((x, y, z));
+                }
+                continue;
+            }
+            for x in new.min.0..=new.max.0.min(old.min.0 - 1) {
+                f((x, y, z));
+            }
+            for x in new.min.0.max(old.max.0 + 1)..=new.max.0 {
+                f((x, y, z));
+            }
+        }
+    }
+}
+
+impl From<DenseStorage> for Vec<usize> {
+    fn from(storage: DenseStorage) -> Self {
+        storage.iter().collect()
+    }
+}
+
+impl BlockStorage for DenseStorage {
+    fn get(&self, bounding_box: &BoundingBox, position: (i32, i32, i32)) -> usize {
+        self.get_at(self.index(bounding_box, position))
+    }
+
+    fn set(&mut self, bounding_box: &BoundingBox, position: (i32, i32, i32), palette_index: usize) {
+        self.widen_for(palette_index);
+        self.set_at(self.index(bounding_box, position), palette_index);
+    }
+
+    fn resize(&mut self, old: &BoundingBox, new: &BoundingBox, fill: usize) {
+        let allocated_volume = self.allocated_len() as u64;
+        if self.has_room_for(old, new) && new.volume() * 4 >= allocated_volume {
+            // Entries outside `old` may be left over from an earlier shrink.
+            let allocated = self.spare.as_ref().map_or(old, |spare| &spare.allocated).clone();
+            self.widen_for(fill);
+            for_each_outside(new, old, |(x, y, z)| self.set_at(allocated.coords_to_index(x, y, z), fill));
+            self.spare = (allocated != *new).then(|| Spare { allocated, used: new.clone() });
+            return;
+        }
+
+        // Half as much again on each side the region grew towards.
+        let grown = |new_min: i32, new_max: i32, old_min: i32, old_max: i32| {
+            let room = (new_max - new_min + 1) / 2;
+            (if new_min < old_min { new_min - room } else { new_min }, if new_max > old_max { new_max + room } else { new_max })
+        };
+        let (min_x, max_x) = grown(new.min.0, new.max.0, old.min.0, old.max.0);
+        let (min_y, max_y) = grown(new.min.1, new.max.1, old.min.1, old.max.1);
+        let (min_z, max_z) = grown(new.min.2, new.max.2, old.min.2, old.max.2);
+        let allocated = BoundingBox::new((min_x, min_y, min_z), (max_x, max_y, max_z));
+
+        let mut resized = DenseStorage::new(allocated.volume() as usize, fill);
+        resized.widen_for((1 << self.bits_per_entry()) - 1);
+        for (index, block) in self.iter().enumerate() {
+            let (x, y, z) = old.index_to_coords(index);
+            if new.contains((x, y, z)) {
+                resized.set_at(allocated.coords_to_index(x, y, z), block);
+            }
+        }
+        resized.spare = (allocated != *new).then(|| Spare { allocated, used: new.clone() });
+        *self = resized;
+    }
+
+    fn remap(&mut self, mapping: &[usize]) {
+        self.widen_for(mapping.iter().copied().max().unwrap_or(0));
+        for index in 0..self.allocated_len() {
+            let block = self.get_at(index);
+            // Spare entries may hold anything, including indices past the mapping.
+            if let Some(&mapped) = mapping.get(block) {
+                self.set_at(index, mapped);
+            }
+        }
+    }
+
+    fn for_each_stored(&self, bounding_box: &BoundingBox, f: &mut dyn FnMut((i32, i32, i32), usize)) {
+        for (index, block) in self.iter().enumerate() {
+            f(bounding_box.index_to_coords(index), block);
+        }
+    }
+
+    fn default_index(&self) -> usize {
+        0
+    }
+}
+
+// Fixed-width entries packed into longs without spanning, like chunk sections since 1.16.
+// Zero bits means every entry is 0 and nothing is allocated.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct PackedArray {
+    bits: usize,
+    len: usize,
+    data: Vec<u64>,
+}
+
+impl PackedArray {
+    fn new(bits: usize, len: usize) -> Self {
+        let data = 64usize.checked_div(bits).map_or(Vec::new(), |per_long| vec![0; len.div_ceil(per_long)]);
+        PackedArray { bits, len, data }
+    }
+
+    fn get(&self, index: usize) -> usize {
+        if self.bits == 0 {
+            return 0;
+        }
+        let per_long = 64 / self.bits;
+        ((self.data[index / per_long] >> ((index % per_long) * self.bits)) & ((1 << self.bits) - 1)) as usize
+    }
+
+    fn set(&mut self, index: usize, value: usize) {
+        let per_long = 64 / self.bits;
+        let shift = (index % per_long) * self.bits;
+        let mask = ((1u64 << self.bits) - 1) << shift;
+        let long = &mut self.data[index / per_long];
+        *long = (*long & !mask) | ((value as u64) << shift);
+    }
+
+    fn repacked(&self, bits: usize) -> Self {
+        let mut repacked = PackedArray::new(bits, self.len);
+        for index in 0..self.len {
+            repacked.set(index, self.get(index));
+        }
+        repacked
+    }
+}
+
+const SECTION_SIZE: i32 = 16;
+const SECTION_VOLUME: usize = 16 * 16 * 16;
+
+/// 16³ sections keyed by section coordinates, created on first write. Growing the region
+/// doesn't touch existing sections, so far apart blocks don't allocate the space between them.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct ChunkedStorage {
+    #[serde(with = "sections_as_list")]
+    sections: HashMap<(i32, i32, i32), Section>,
+    default: usize,
+}
+
+// A section maps its own small palette onto region palette indices, so most sections
+// only need a few bits per block.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct Section {
+    palette: Vec<usize>,
+    blocks: PackedArray,
+}
+
+impl Section {
+    fn new(fill: usize) -> Self {
+        Section { palette: vec![fill], blocks: PackedArray::new(0, SECTION_VOLUME) }
+    }
+
+    fn get(&self, index: usize) -> usize {
+        self.palette[self.blocks.get(index)]
+    }
+
+    fn set(&mut self, index: usize, palette_index: usize) {
+        let local = match self.palette.iter().position(|&entry| entry == palette_index) {
+            Some(local) => local,
+            None => {
+                self.palette.push(palette_index);
+                let needed = bits_for(self.palette.len() - 1);
+                if needed > self.blocks.bits {
+                    self.blocks = self.blocks.repacked(needed);
+                }
+                self.palette.len() - 1
+            }
+        };
+        if self.blocks.bits > 0 {
+            self.blocks.set(index, local);
+        }
+    }
+}
+
+fn section_key(position: (i32, i32, i32)) -> ((i32, i32, i32), usize) {
+    let key = (position.0.div_euclid(SECTION_SIZE), position.1.div_euclid(SECTION_SIZE), position.2.div_euclid(SECTION_SIZE));
+    let local = (
+        position.0.rem_euclid(SECTION_SIZE) as usize,
+        position.1.rem_euclid(SECTION_SIZE) as usize,
+        position.2.rem_euclid(SECTION_SIZE) as usize,
+    );
+    (key, local.0 + local.2 * 16 + local.1 * 256)
+}
+
+fn section_origin(key: (i32, i32, i32)) -> (i32, i32, i32) {
+    (key.0 * SECTION_SIZE, key.1 * SECTION_SIZE, key.2 * SECTION_SIZE)
+}
+
+impl ChunkedStorage {
+    pub fn new(fill: usize) -> Self {
+        ChunkedStorage { sections: HashMap::new(), default: fill }
+    }
+
+    pub fn from_dense(dense: &DenseStorage, bounding_box: &BoundingBox, fill: usize) -> Self {
+        let mut chunked = ChunkedStorage::new(fill);
+        for (index, block) in dense.iter().enumerate() {
+            chunked.set(bounding_box, bounding_box.index_to_coords(index), block);
+        }
+        chunked
+    }
+
+    #[cfg(test)]
+    pub fn section_count(&self) -> usize {
+        self.sections.len()
+    }
+
+    fn section_box(key: (i32, i32, i32)) -> BoundingBox {
+        let origin = section_origin(key);
+        BoundingBox::new(origin, (origin.0 + SECTION_SIZE - 1, origin.1 + SECTION_SIZE - 1, origin.2 + SECTION_SIZE - 1))
+    }
+}
+
+impl BlockStorage for ChunkedStorage {
+    fn get(&self, _bounding_box: &BoundingBox, position: (i32, i32, i32)) -> usize {
+        let (key, index) = section_key(position);
+        self.sections.get(&key).map_or(self.default, |section| section.get(index))
+    }
+
+    fn set(&mut self, _bounding_box: &BoundingBox, position: (i32, i32, i32), palette_index: usize) {
+        let (key, index) = section_key(position);
+        if palette_index == self.default && !self.sections.contains_key(&key) {
+            return;
+        }
+        let default = self.default;
+        self.sections.entry(key).or_insert_with(|| Section::new(default)).set(index, palette_index);
+    }
+
+    fn resize(&mut self, old: &BoundingBox, new: &BoundingBox, fill: usize) {
+        // Anything outside the new box is cleared so it reads as `fill` if the box grows back.
+        // Nothing outside the old box is stored, so growing has nothing to clear.
+        let default = self.default;
+        if !(new.contains(old.min) && new.contains(old.max)) {
+            self.sections.retain(|&key, _| Self::section_box(key).intersects(new));
+            for (&key, section) in self.sections.iter_mut() {
+                let section_box = Self::section_box(key);
+                if new.contains(section_box.min) && new.contains(section_box.max) {
+                    continue;
+                }
+                for index in 0..SECTION_VOLUME {
+                    if !new.contains(section_box.index_to_coords(index)) {
+                        section.set(index, default);
+                    }
+                }
+            }
+        }
+
+        if fill != self.default {
+            for index in 0..new.volume() as usize {
+                let position = new.index_to_coords(index);
+                if !old.contains(position) {
+                    self.set(new, position, fill);
+                }
+            }
+        }
+    }
+
+    fn remap(&mut self, mapping: &[usize]) {
+        self.default = mapping[self.default];
+        for section in self.sections.values_mut() {
+            for entry in &mut section.palette {
+                *entry = mapping[*entry];
+            }
+        }
+    }
+
+    fn for_each_stored(&self, bounding_box: &BoundingBox, f: &mut dyn FnMut((i32, i32, i32), usize)) {
+        for (&key, section) in &self.sections {
+            let section_box = Self::section_box(key);
+            if !section_box.intersects(bounding_box) {
+                continue;
+            }
+            for index in 0..SECTION_VOLUME {
+                let position = section_box.index_to_coords(index);
+                if bounding_box.contains(position) {
+                    f(position, section.get(index));
+                }
+            }
+        }
+    }
+
+    fn default_index(&self) -> usize {
+        self.default
+    }
+}
+
+// JSON maps need string keys, so sections are written as a list of pairs.
+mod sections_as_list {
+    use std::collections::HashMap;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use super::Section;
+
+    type Sections = HashMap<(i32, i32, i32), Section>;
+
+    pub fn serialize<S: Serializer>(sections: &Sections, serializer: S) -> Result<S::Ok, S::Error> {
+        sections.iter().collect::<Vec<_>>().serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Sections, D::Error> {
+        Ok(Vec::<((i32, i32, i32), Section)>::deserialize(deserializer)?.into_iter().collect())
+    }
+}
+
+// Untagged so regions serialized before chunked storage existed still load as dense.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub(crate) enum RegionStorage {
+    Dense(DenseStorage),
+    Chunked(ChunkedStorage),
+}
+
+impl RegionStorage {
+    /// Storage of the same kind holding nothing but the default index.
+    pub fn empty_like(&self, volume: usize) -> Self {
+        match self {
+            RegionStorage::Dense(_) => RegionStorage::Dense(DenseStorage::new(volume, 0)),
+            RegionStorage::Chunked(storage) => RegionStorage::Chunked(ChunkedStorage::new(storage.default)),
+        }
+    }
+}
+
+impl BlockStorage for RegionStorage {
+    fn get(&self, bounding_box: &BoundingBox, position: (i32, i32, i32)) -> usize {
+        match self {
+            RegionStorage::Dense(storage) => storage.get(bounding_box, position),
+            RegionStorage::Chunked(storage) => storage.get(bounding_box, position),
+        }
+    }
+
+    fn set(&mut self, bounding_box: &BoundingBox, position: (i32, i32, i32), palette_index: usize) {
+        match self {
+            RegionStorage::Dense(storage) => storage.set(bounding_box, position, palette_index),
+            RegionStorage::Chunked(storage) => storage.set(bounding_box, position, palette_index),
+        }
+    }
+
+    fn resize(&mut self, old: &BoundingBox, new: &BoundingBox, fill: usize) {
+        match self {
+            RegionStorage::Dense(storage) => storage.resize(old, new, fill),
+            RegionStorage::Chunked(storage) => storage.resize(old, new, fill),
+        }
+    }
+
+    fn remap(&mut self, mapping: &[usize]) {
+        match self {
+            RegionStorage::Dense(storage) => storage.remap(mapping),
+            RegionStorage::Chunked(storage) => storage.remap(mapping),
+        }
+    }
+
+    fn for_each_stored(&self, bounding_box: &BoundingBox, f: &mut dyn FnMut((i32, i32, i32), usize)) {
+        match self {
+            RegionStorage::Dense(storage) => storage.for_each_stored(bounding_box, f),
+            RegionStorage::Chunked(storage) => storage.for_each_stored(bounding_box, f),
+        }
+    }
+
+    fn default_index(&self) -> usize {
+        match self {
+            RegionStorage::Dense(storage) => storage.default_index(),
+            RegionStorage::Chunked(storage) => storage.default_index(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_section_grows_bit_width() {
+        let bounding_box = BoundingBox::new((-16, 0, -16), (15, 15, 15));
+        let mut storage = ChunkedStorage::new(0);
+        for (index, position) in [(-1, 0, -1), (0, 0, 0), (15, 15, 15), (-16, 3, 7)].into_iter().enumerate() {
+            storage.set(&bounding_box, position, index + 1);
+        }
+        for value in 5..40 {
+            storage.set(&bounding_box, (value as i32 % 16, 1, 0), value);
+        }
+
+        assert_eq!(storage.get(&bounding_box, (-1, 0, -1)), 1);
+        assert_eq!(storage.get(&bounding_box, (15, 15, 15)), 3);
+        assert_eq!(storage.get(&bounding_box, (-16, 3, 7)), 4);
+        assert_eq!(storage.get(&bounding_box, (7, 1, 0)), 39);
+        assert_eq!(storage.get(&bounding_box, (1, 1, 1)), 0);
+        assert_eq!(storage.section_count(), 3);
+
+        // Setting the default into a missing section doesn't create it
+        storage.set(&bounding_box, (-5, 10, 5), 0);
+        assert_eq!(storage.section_count(), 3);
+    }
+
+    #[test]
+    fn test_remap_and_shrink() {
+        let bounding_box = BoundingBox::new((0, 0, 0), (31, 0, 0));
+        let mut storage = ChunkedStorage::new(0);
+        storage.set(&bounding_box, (3, 0, 0), 1);
+        storage.set(&bounding_box, (20, 0, 0), 1);
+
+        storage.remap(&[2, 0, 1]);
+        assert_eq!(storage.get(&bounding_box, (3, 0, 0)), 0);
+        assert_eq!(storage.get(&bounding_box, (4, 0, 0)), 2);
+        assert_eq!(storage.get(&bounding_box, (400, 0, 0)), 2);
+
+        let smaller = BoundingBox::new((0, 0, 0), (3, 0, 0));
+        storage.resize(&bounding_box, &smaller, 2);
+        assert_eq!(storage.section_count(), 1);
+        storage.resize(&smaller, &bounding_box, 2);
+        assert_eq!(storage.get(&bounding_box, (3, 0, 0)), 0);
+        assert_eq!(storage.get(&bounding_box, (20, 0, 0)), 2);
+        let mut stored = Vec::new();
+        storage.for_each_stored(&BoundingBox::new((0, 0, 0), (5, 0, 0)), &mut |position, index| stored.push((position, index)));
+        assert_eq!(stored.len(), 6);
+    }
+
+    #[test]
+    fn test_dense_widens_with_palette() {
+        let bounding_box = BoundingBox::new((0, 0, 0), (99, 0, 0));
+        let mut storage = DenseStorage::new(100, 0);
+        storage.set(&bounding_box, (1, 0, 0), 255);
+        assert_eq!(storage.bits_per_entry(), 8);
+        storage.set(&bounding_box, (2, 0, 0), 256);
+        assert_eq!(storage.bits_per_entry(), 16);
+        storage.set(&bounding_box, (99, 0, 0), 70_000);
+        assert_eq!(storage.bits_per_entry(), 17);
+
+        assert_eq!(storage.get(&bounding_box, (1, 0, 0)), 255);
+        assert_eq!(storage.get(&bounding_box, (2, 0, 0)), 256);
+        assert_eq!(storage.get(&bounding_box, (99, 0, 0)), 70_000);
+        assert_eq!(storage.get(&bounding_box, (50, 0, 0)), 0);
+
+        // Serialized as plain indices, like the old `Vec<usize>` field
+        let json = serde_json::to_string(&storage).unwrap();
+        let indices: Vec<usize> = serde_json::from_str(&json).unwrap();
+        assert_eq!(indices.len(), 100);
+        assert_eq!(serde_json::from_str::<DenseStorage>(&json).unwrap(), storage);
+        assert_eq!(DenseStorage::from_indices(vec![1, 2, 3]).bits_per_entry(), 8);
+    }
+
+    #[test]
+    fn test_dense_grows_with_room_to_spare() {
+        let mut bounding_box = BoundingBox::new((0, 0, 0), (0, 0, 0));
+        let mut storage = DenseStorage::new(1, 0);
+        storage.set(&bounding_box, (0, 0, 0), 1);
+        for x in 1..50 {
+            let grown = BoundingBox::new((0, 0, 0), (x, 0, 0));
+            storage.resize(&bounding_box, &grown, 0);
+            bounding_box = grown;
+            storage.set(&bounding_box, (x, 0, 0), x as usize % 3);
+        }
+        assert!(storage.allocated_len() > 50);
+        assert_eq!(storage.get(&bounding_box, (0, 0, 0)), 1);
+        assert_eq!(storage.get(&bounding_box, (49, 0, 0)), 1);
+
+        // Only the region's box is serialized.
+        let indices: Vec<usize> = storage.clone().into();
+        assert_eq!(indices, (0..50).map(|x| if x == 0 { 1 } else { x % 3 }).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,261 @@
+use std::sync::OnceLock;
+use crate::BlockState;
+use crate::block_entity::BlockEntity;
+use crate::entity::Entity;
+use crate::pending_tick::{PendingTick, TickKind};
+use crate::utils::{NbtMap, NbtValue};
+
+// One `data_version kind arguments...` entry per line.
+const DATA_FIXES: &str = include_str!("data_fixes.txt");
+
+#[derive(Debug)]
+enum Fix {
+    Block { from: String, to: String },
+    Item { from: String, to: String },
+    Value { block: String, property: String, from: String, to: String },
+}
+
+fn parse_fix(line: &str) -> Option<(i32, Fix)> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    let fix = match fields.get(1..)? {
+        ["block", from, to] => Fix::Block { from: from.to_string(), to: to.to_string() },
+        ["item", from, to] => Fix::Item { from: from.to_string(), to: to.to_string() },
+        ["value", block, property, from, to] => Fix::Value {
+            block: block.to_string(),
+            property: property.to_string(),
+            from: from.to_string(),
+            to: to.to_string(),
+        },
+        _ => return None,
+    };
+    Some((fields[0].parse().ok()?, fix))
+}
+
+fn data_fixes() -> &'static [(i32, Fix)] {
+    static FIXES: OnceLock<Vec<(i32, Fix)>> = OnceLock::new();
+    FIXES.get_or_init(|| {
+        let mut fixes: Vec<(i32, Fix)> = DATA_FIXES.lines()
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| parse_fix(line).unwrap_or_else(|| panic!("Invalid line in data_fixes.txt: {}", line)))
+            .collect();
+        fixes.sort_by_key(|(data_version, _)| *data_version);
+        fixes
+    })
+}
+
+fn matches_id(pattern: &str, id: &str) -> bool {
+    match pattern.strip_prefix('*') {
+        Some(suffix) => id.ends_with(suffix),
+        None => pattern == id,
+    }
+}
+
+/// The renames and property changes between two DataVersions, in the order they apply.
+pub(crate) struct DataFixer {
+    // Each fix with whether it is undone rather than applied.
+    steps: Vec<(&'static Fix, bool)>,
+}
+
+impl DataFixer {
+    pub(crate) fn new(from: i32, to: i32) -> Self {
+        let steps = if from <= to {
+            data_fixes().iter()
+                .filter(|(data_version, _)| *data_version > from && *data_version <= to)
+                .map(|(_, fix)| (fix, false))
+                .collect()
+        } else {
+            data_fixes().iter().rev()
+                .filter(|(data_version, _)| *data_version > to && *data_version <= from)
+                .map(|(_, fix)| (fix, true))
+                .collect()
+        };
+        DataFixer { steps }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    pub(crate) fn fix_block(&self, block: &BlockState) -> BlockState {
+        let mut block = block.clone();
+        for (fix, undo) in &self.steps {
+            match fix {
+                Fix::Block { from, to } => {
+                    let (from, to) = if *undo { (to, from) } else { (from, to) };
+                    if block.name == *from {
+                        block.name = to.clone();
+                    }
+                }
+                Fix::Value { block: pattern, property, from, to } => {
+                    let (from, to) = if *undo { (to, from) } else { (from, to) };
+                    if matches_id(pattern, &block.name) && block.properties.get(property) == Some(from) {
+                        block.properties.insert(property.clone(), to.clone());
+                    }
+                }
+                Fix::Item { .. } => {}
+            }
+        }
+        block
+    }
+
+    pub(crate) fn fix_item_id(&self, id: &str) -> String {
+        let mut id = id.to_string();
+        for (fix, undo) in &self.steps {
+            if let Fix::Item { from, to } = fix {
+                let (from, to) = if *undo { (to, from) } else { (from, to) };
+                if id == *from {
+                    id = to.clone();
+                }
+            }
+        }
+        id
+    }
+
+    /// Renames every item the block entity holds, including items inside other items, such
+    /// as the contents of a shulker box kept in a chest.
+    pub(crate) fn fix_block_entity(&self, block_entity: &mut BlockEntity) {
+        self.fix_items(&mut block_entity.nbt, false);
+    }
+
+    /// Renames the items an entity carries, e.g. its armor or an item frame's item.
+    pub(crate) fn fix_entity(&self, entity: &mut Entity) {
+        self.fix_items(&mut entity.nbt, false);
+    }
+
+    pub(crate) fn fix_pending_tick(&self, tick: &mut PendingTick) {
+        if tick.kind == TickKind::Block {
+            tick.id = self.fix_block(&BlockState::new(tick.id.clone())).name;
+        }
+    }
+
+    // Items are compounds with a count, or the value of an `item` key, which may leave the
+    // count out since 1.20.5. Everything below is searched as well.
+    fn fix_items(&self, nbt: &mut NbtMap, is_item: bool) {
+        if is_item || nbt.get("Count").is_some() || nbt.get("count").is_some() {
+            if let Some(NbtValue::String(id)) = nbt.get_mut("id") {
+                *id = self.fix_item_id(id);
+            }
+        }
+        for (key, value) in nbt.iter_mut() {
+            self.fix_items_in(value, key == "item" || key == "Item");
+        }
+    }
+
+    fn fix_items_in(&self, value: &mut NbtValue, is_item: bool) {
+        match value {
+            NbtValue::Compound(nbt) => self.fix_items(nbt, is_item),
+            NbtValue::List(values) => {
+                for value in values {
+                    self.fix_items_in(value, false);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::item::ItemStack;
+
+    const V1_13: i32 = 1519;
+    const V1_16: i32 = 2586;
+    const V1_21: i32 = 3953;
+
+    #[test]
+    fn test_upgrade_and_downgrade_blocks() {
+        let upgrade = DataFixer::new(V1_16, V1_21);
+        assert_eq!(upgrade.fix_block(&BlockState::new("minecraft:grass_path".to_string())).name, "minecraft:dirt_path");
+        assert_eq!(upgrade.fix_block(&BlockState::new("minecraft:grass".to_string())).name, "minecraft:short_grass");
+        assert_eq!(upgrade.fix_block(&BlockState::new("minecraft:stone".to_string())).name, "minecraft:stone");
+
+        let downgrade = DataFixer::new(V1_21, V1_16);
+        assert_eq!(downgrade.fix_block(&BlockState::new("minecraft:short_grass".to_string())).name, "minecraft:grass");
+        assert!(DataFixer::new(V1_21, V1_21).is_empty());
+    }
+
+    #[test]
+    fn test_wall_connections() {
+        let wall = BlockState::new("minecraft:cobblestone_wall".to_string())
+            .with_property("north".to_string(), "true".to_string())
+            .with_property("east".to_string(), "false".to_string())
+            .with_property("up".to_string(), "true".to_string());
+        let upgraded = DataFixer::new(V1_13, V1_21).fix_block(&wall);
+        assert_eq!(upgraded.properties.get("north").unwrap(), "low");
+        assert_eq!(upgraded.properties.get("east").unwrap(), "none");
+        assert_eq!(upgraded.properties.get("up").unwrap(), "true");
+
+        let tall = upgraded.with_property("north".to_string(), "tall".to_string());
+        let downgraded = DataFixer::new(V1_21, V1_13).fix_block(&tall);
+        assert_eq!(downgraded, wall);
+    }
+
+    #[test]
+    fn test_items_in_block_entities() {
+        let mut chest = BlockEntity::create_chest((0, 0, 0), vec![
+            ItemStack::new("minecraft:scute", 3).with_slot(0),
+            ItemStack::new("minecraft:grass", 1).with_slot(1),
+        ]);
+        DataFixer::new(V1_16, V1_21).fix_block_entity(&mut chest);
+
+        let Some(NbtValue::List(items)) = chest.nbt.get("Items") else { panic!("Items are missing") };
+        let ids: Vec<_> = items.iter()
+            .map(|item| match item {
+                NbtValue::Compound(item) => item.get("id").cloned(),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(ids, vec![
+            Some(NbtValue::String("minecraft:turtle_scute".to_string())),
+            Some(NbtValue::String("minecraft:short_grass".to_string())),
+        ]);
+    }
+
+    #[test]
+    fn test_nested_items_entities_and_ticks() {
+        let fixer = DataFixer::new(V1_16, V1_21);
+        let grass = || ItemStack::new("minecraft:grass", 1).with_slot(0).to_nbt();
+
+        // A shulker box in a chest, holding grass.
+        let mut items = NbtMap::new();
+        items.insert("Items".to_string(), NbtValue::List(vec![grass()]));
+        let mut block_entity_tag = NbtMap::new();
+        block_entity_tag.insert("BlockEntityTag".to_string(), NbtValue::Compound(items));
+        let mut shulker = ItemStack::new("minecraft:shulker_box", 1).with_slot(0).to_nbt();
+        if let NbtValue::Compound(shulker) = &mut shulker {
+            shulker.insert("tag".to_string(), NbtValue::Compound(block_entity_tag));
+        }
+        let mut chest = BlockEntity::new("minecraft:chest".to_string(), (0, 0, 0))
+            .with_nbt_data("Items".to_string(), NbtValue::List(vec![shulker]));
+        fixer.fix_block_entity(&mut chest);
+        let Some(NbtValue::List(items)) = chest.nbt.get("Items") else { panic!("Items are missing") };
+        let NbtValue::Compound(shulker) = &items[0] else { panic!("Shulker box is missing") };
+        let Some(NbtValue::Compound(tag)) = shulker.get("tag") else { panic!("tag is missing") };
+        let Some(NbtValue::Compound(block_entity_tag)) = tag.get("BlockEntityTag") else { panic!("BlockEntityTag is missing") };
+        let Some(NbtValue::List(inner)) = block_entity_tag.get("Items") else { panic!("Inner items are missing") };
+        let NbtValue::Compound(inner) = &inner[0] else { panic!("Inner item is missing") };
+        assert_eq!(inner.get("id"), Some(&NbtValue::String("minecraft:short_grass".to_string())));
+
+        let mut item = NbtMap::new();
+        item.insert("id".to_string(), NbtValue::String("minecraft:scute".to_string()));
+        let mut frame = Entity::new("minecraft:item_frame".to_string(), (0.5, 0.5, 0.5));
+        frame.nbt.insert("Item".to_string(), NbtValue::Compound(item));
+        fixer.fix_entity(&mut frame);
+        let Some(NbtValue::Compound(item)) = frame.nbt.get("Item") else { panic!("Item is missing") };
+        assert_eq!(item.get("id"), Some(&NbtValue::String("minecraft:turtle_scute".to_string())));
+        // The entity's own id is not an item.
+        assert_eq!(frame.id, "minecraft:item_frame");
+
+        let mut tick = PendingTick::new(TickKind::Block, "minecraft:grass_path".to_string(), (0, 0, 0), 1);
+        fixer.fix_pending_tick(&mut tick);
+        assert_eq!(tick.id, "minecraft:dirt_path");
+    }
+
+    #[test]
+    fn test_every_fix_parses() {
+        for line in DATA_FIXES.lines().filter(|line| !line.is_empty() && !line.starts_with('#')) {
+            assert!(parse_fix(line).is_some(), "Invalid line in data_fixes.txt: {}", line);
+        }
+    }
+}
@@ -0,0 +1,385 @@
+use std::collections::{BTreeMap, HashMap};
+use serde::{Deserialize, Serialize};
+use crate::{BlockState, UniversalSchematic};
+use crate::block_entity::BlockEntity;
+use crate::block_position::BlockPosition;
+use crate::bounding_box::BoundingBox;
+use crate::entity::Entity;
+use crate::error::SchematicError;
+
+const AIR: &str = "minecraft:air";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct BlockChange {
+    pub position: (i32, i32, i32),
+    pub from: BlockState,
+    pub to: BlockState,
+}
+
+/// A block entity that was added (`from` is `None`), removed (`to` is `None`) or whose NBT changed.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BlockEntityChange {
+    pub position: (i32, i32, i32),
+    pub from: Option<BlockEntity>,
+    pub to: Option<BlockEntity>,
+}
+
+/// The changes that turn one schematic into another, in world coordinates, so regions may
+/// differ in position and size. Serializes with a palette of `{name, properties}` entries,
+/// each block change being `[x, y, z, from, to]` indices into it.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(into = "CompactDiff", try_from = "CompactDiff")]
+pub struct SchematicDiff {
+    /// Sorted by y, then z, then x.
+    pub blocks: Vec<BlockChange>,
+    /// Sorted like `blocks`.
+    pub block_entities: Vec<BlockEntityChange>,
+    pub added_entities: Vec<Entity>,
+    pub removed_entities: Vec<Entity>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CompactDiff {
+    palette: Vec<PaletteEntry>,
+    blocks: Vec<(i32, i32, i32, usize, usize)>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    block_entities: Vec<BlockEntityChange>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    added_entities: Vec<Entity>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    removed_entities: Vec<Entity>,
+}
+
+// Kept structured rather than in the `/setblock` form, which can't spell every state a
+// schematic may hold, such as ids with uppercase letters.
+#[derive(Serialize, Deserialize)]
+struct PaletteEntry {
+    name: String,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    properties: BTreeMap<String, String>,
+}
+
+impl From<SchematicDiff> for CompactDiff {
+    fn from(diff: SchematicDiff) -> Self {
+        let mut palette = Vec::new();
+        let mut indices: HashMap<BlockState, usize> = HashMap::new();
+        let mut index_of = |block: BlockState| *indices.entry(block).or_insert_with_key(|block| {
+            palette.push(PaletteEntry { name: block.name.clone(), properties: block.properties.clone() });
+            palette.len() - 1
+        });
+        let blocks = diff.blocks.into_iter()
+            .map(|change| {
+                let (x, y, z) = change.position;
+                (x, y, z, index_of(change.from), index_of(change.to))
+            })
+            .collect();
+        CompactDiff {
+            palette,
+            blocks,
+            block_entities: diff.block_entities,
+            added_entities: diff.added_entities,
+            removed_entities: diff.removed_entities,
+        }
+    }
+}
+
+impl TryFrom<CompactDiff> for SchematicDiff {
+    type Error = SchematicError;
+
+    fn try_from(compact: CompactDiff) -> Result<Self, Self::Error> {
+        let palette: Vec<BlockState> = compact.palette.into_iter()
+            .map(|entry| BlockState::new(entry.name).with_properties(entry.properties))
+            .collect();
+        let block = |index: usize| palette.get(index).cloned()
+            .ok_or(SchematicError::PaletteIndexOutOfRange { index, palette_len: palette.len() });
+        let blocks = compact.blocks.into_iter()
+            .map(|(x, y, z, from, to)| Ok(BlockChange { position: (x, y, z), from: block(from)?, to: block(to)? }))
+            .collect::<Result<_, SchematicError>>()?;
+        Ok(SchematicDiff {
+            blocks,
+            block_entities: compact.block_entities,
+            added_entities: compact.added_entities,
+            removed_entities: compact.removed_entities,
+        })
+    }
+}
+
+pub(crate) fn block_at(schematic: &UniversalSchematic, (x, y, z): (i32, i32, i32)) -> BlockState {
+    schematic.get_block(x, y, z).cloned().unwrap_or_else(|| BlockState::new(AIR.to_string()))
+}
+
+fn block_entities_by_position(schematic: &UniversalSchematic) -> HashMap<(i32, i32, i32), &BlockEntity> {
+    schematic.regions.values()
+        .flat_map(|region| region.block_entities.iter())
+        .map(|(position, block_entity)| (*position, block_entity))
+        .collect()
+}
+
+fn all_entities(schematic: &UniversalSchematic) -> Vec<&Entity> {
+    schematic.regions.values().flat_map(|region| region.entities.iter()).collect()
+}
+
+// Entities in `entities` that have no equal entity in `others`, counting duplicates.
+pub(crate) fn missing_from(entities: &[&Entity], others: &[&Entity]) -> Vec<Entity> {
+    let mut unmatched: Vec<&Entity> = others.to_vec();
+    entities.iter()
+        .filter(|entity| match unmatched.iter().position(|other| other == *entity) {
+            Some(index) => {
+                unmatched.swap_remove(index);
+                false
+            }
+            None => true,
+        })
+        .map(|entity| (*entity).clone())
+        .collect()
+}
+
+impl SchematicDiff {
+    pub fn between(from: &UniversalSchematic, to: &UniversalSchematic) -> Self {
+        // Only positions inside some region can differ, so the empty space between far-apart
+        // regions is never visited. Each position is compared once, by the first box holding it.
+        let boxes: Vec<BoundingBox> = [from, to].into_iter()
+            .flat_map(|schematic| schematic.regions.values())
+            .map(|region| region.get_bounding_box())
+            .collect();
+        let mut blocks = Vec::new();
+        for (index, bounds) in boxes.iter().enumerate() {
+            for y in bounds.min.1..=bounds.max.1 {
+                for z in bounds.min.2..=bounds.max.2 {
+                    for x in bounds.min.0..=bounds.max.0 {
+                        if boxes[..index].iter().any(|earlier| earlier.contains((x, y, z))) {
+                            continue;
+                        }
+                        let (before, after) = (block_at(from, (x, y, z)), block_at(to, (x, y, z)));
+                        if before != after {
+                            blocks.push(BlockChange { position: (x, y, z), from: before, to: after });
+                        }
+                    }
+                }
+            }
+        }
+        blocks.sort_by_key(|change| (change.position.1, change.position.2, change.position.0));
+
+        let (before, after) = (block_entities_by_position(from), block_entities_by_position(to));
+        let mut block_entities: Vec<BlockEntityChange> = before.keys().chain(after.keys().filter(|position| !before.contains_key(position)))
+            .filter_map(|position| {
+                let (from, to) = (before.get(position).copied(), after.get(position).copied());
+                (from != to).then(|| BlockEntityChange { position: *position, from: from.cloned(), to: to.cloned() })
+            })
+            .collect();
+        block_entities.sort_by_key(|change| (change.position.1, change.position.2, change.position.0));
+
+        let (before, after) = (all_entities(from), all_entities(to));
+        SchematicDiff {
+            blocks,
+            block_entities,
+            added_entities: missing_from(&after, &before),
+            removed_entities: missing_from(&before, &after),
+        }
+    }
+
+    /// The diff that undoes this one.
+    pub fn inverse(&self) -> SchematicDiff {
+        SchematicDiff {
+            blocks: self.blocks.iter()
+                .map(|change| BlockChange { position: change.position, from: change.to.clone(), to: change.from.clone() })
+                .collect(),
+            block_entities: self.block_entities.iter()
+                .map(|change| BlockEntityChange { position: change.position, from: change.to.clone(), to: change.from.clone() })
+                .collect(),
+            added_entities: self.removed_entities.clone(),
+            removed_entities: self.added_entities.clone(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty() && self.block_entities.is_empty()
+            && self.added_entities.is_empty() && self.removed_entities.is_empty()
+    }
+
+    /// The smallest box holding every changed block and block entity.
+    pub fn bounding_box(&self) -> Option<BoundingBox> {
+        self.blocks.iter().map(|change| change.position)
+            .chain(self.block_entities.iter().map(|change| change.position))
+            .map(|position| BoundingBox::new(position, position))
+            .reduce(|a, b| a.union(&b))
+    }
+
+    pub fn to_json(&self) -> Result<String, SchematicError> {
+        serde_json::to_string(self).map_err(|e| SchematicError::InvalidData(e.to_string()))
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, SchematicError> {
+        serde_json::from_str(json).map_err(|e| SchematicError::InvalidData(e.to_string()))
+    }
+
+    /// Checks that `schematic` still looks like the diff's `from` side everywhere the diff
+    /// touches, then applies it. Nothing is changed when a check fails.
+    pub fn apply_to(&self, schematic: &mut UniversalSchematic) -> Result<(), SchematicError> {
+        for change in &self.blocks {
+            let current = block_at(schematic, change.position);
+            if current != change.from {
+                return Err(SchematicError::InvalidData(format!(
+                    "Diff expects {} at {:?} but found {}", change.from, change.position, current
+                )));
+            }
+        }
+        let block_entities = block_entities_by_position(schematic);
+        for change in &self.block_entities {
+            if block_entities.get(&change.position).copied() != change.from.as_ref() {
+                return Err(SchematicError::InvalidData(format!(
+                    "Diff expects a different block entity at {:?}", change.position
+                )));
+            }
+        }
+        let entities = all_entities(schematic);
+        let removed: Vec<&Entity> = self.removed_entities.iter().collect();
+        if let Some(entity) = missing_from(&removed, &entities).first() {
+            return Err(SchematicError::InvalidData(format!(
+                "Diff removes a {} at {:?} that isn't in the schematic", entity.id, entity.position
+            )));
+        }
+
+        for change in &self.blocks {
+            set_block(schematic, change.position, &change.to);
+        }
+        for change in &self.block_entities {
+            for region in schematic.regions.values_mut() {
+                region.block_entities.remove(&change.position);
+            }
+            if let Some(block_entity) = &change.to {
+                let (x, y, z) = change.position;
+                let region_name = schematic.regions.values()
+                    .find(|region| region.get_bounding_box().contains(change.position))
+                    .map_or_else(|| schematic.default_region_name.clone(), |region| region.name.clone());
+                schematic.set_block_entity_in_region(&region_name, BlockPosition { x, y, z }, block_entity.clone());
+            }
+        }
+        for entity in &self.removed_entities {
+            for region in schematic.regions.values_mut() {
+                if let Some(index) = region.entities.iter().position(|other| other == entity) {
+                    region.entities.remove(index);
+                    break;
+                }
+            }
+        }
+        for entity in &self.added_entities {
+            let (x, y, z) = entity.position;
+            let position = (x.floor() as i32, y.floor() as i32, z.floor() as i32);
+            let region_name = schematic.regions.values()
+                .find(|region| region.get_bounding_box().contains(position))
+                .map_or_else(|| schematic.default_region_name.clone(), |region| region.name.clone());
+            schematic.add_entity_in_region(&region_name, entity.clone());
+        }
+        Ok(())
+    }
+}
+
+// Sets the block in every region covering the position, so lookups agree, or grows the
+// default region when none does. Air outside every region is already there.
+fn set_block(schematic: &mut UniversalSchematic, (x, y, z): (i32, i32, i32), block: &BlockState) {
+    let mut covered = false;
+    for region in schematic.regions.values_mut() {
+        if region.get_bounding_box().contains((x, y, z)) {
+            region.set_block(x, y, z, block.clone());
+            covered = true;
+        }
+    }
+    if !covered && block.name != AIR {
+        schematic.set_block(x, y, z, block.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::region::Region;
+
+    fn block(state: &str) -> BlockState {
+        state.parse().unwrap()
+    }
+
+    fn sample() -> UniversalSchematic {
+        let mut schematic = UniversalSchematic::new("Test".to_string());
+        for x in 0..3 {
+            schematic.set_block(x, 0, 0, block("stone"));
+        }
+        schematic.set_block(0, 1, 0, block("chest[facing=north]"));
+        schematic.set_block_entity(BlockPosition { x: 0, y: 1, z: 0 }, BlockEntity::new("minecraft:chest".to_string(), (0, 1, 0)));
+        schematic.add_entity(Entity::new("minecraft:armor_stand".to_string(), (0.5, 1.0, 2.5)));
+        schematic
+    }
+
+    #[test]
+    fn test_diff_and_apply() {
+        let before = sample();
+        let mut after = sample();
+        after.set_block(1, 0, 0, block("dirt"));
+        after.set_block(0, 1, 0, block("chest[facing=east]"));
+        after.set_block_entity(BlockPosition { x: 0, y: 1, z: 0 }, BlockEntity::new("minecraft:chest".to_string(), (0, 1, 0))
+            .with_nbt_data("CustomName".to_string(), crate::utils::NbtValue::String("Loot".to_string())));
+        after.remove_entity(0);
+        // A second region further away grows the compared area.
+        let mut extra = Region::new("Extra".to_string(), (5, 0, 0), (1, 1, 1));
+        extra.set_block(5, 0, 0, block("glass"));
+        after.add_region(extra);
+
+        let diff = before.diff(&after);
+        assert_eq!(diff.blocks.iter().map(|change| change.position).collect::<Vec<_>>(), vec![(1, 0, 0), (5, 0, 0), (0, 1, 0)]);
+        assert_eq!(diff.blocks[1].from.name, "minecraft:air");
+        assert_eq!(diff.block_entities.len(), 1);
+        assert_eq!(diff.removed_entities.len(), 1);
+        assert!(diff.added_entities.is_empty());
+
+        let mut patched = sample();
+        patched.apply(&diff).unwrap();
+        assert!(patched.diff(&after).is_empty());
+        assert!(patched.apply(&diff).is_err());
+        assert!(after.diff(&after).is_empty());
+    }
+
+    #[test]
+    fn test_diff_skips_space_between_regions() {
+        // The union of both regions covers about 10^12 positions.
+        let mut before = UniversalSchematic::new("Test".to_string());
+        before.set_block(0, 0, 0, block("stone"));
+        let mut after = before.clone();
+        let mut far = Region::new("Far".to_string(), (1_000_000, 0, 1_000_000), (2, 1, 1));
+        far.set_block(1_000_001, 0, 1_000_000, block("glass"));
+        after.add_region(far);
+
+        let diff = before.diff(&after);
+        assert_eq!(diff.blocks.len(), 1);
+        assert_eq!(diff.blocks[0].position, (1_000_001, 0, 1_000_000));
+    }
+
+    #[test]
+    fn test_compact_json_round_trip() {
+        let mut after = sample();
+        after.set_block(0, 0, 0, block("dirt"));
+        after.set_block(2, 0, 0, block("dirt"));
+        let diff = sample().diff(&after);
+
+        let json = diff.to_json().unwrap();
+        assert!(json.contains("\"palette\":[{\"name\":\"minecraft:stone\"},{\"name\":\"minecraft:dirt\"}]"));
+        assert!(json.contains("[0,0,0,0,1]"));
+        assert_eq!(SchematicDiff::from_json(&json).unwrap(), diff);
+        assert!(SchematicDiff::from_json("{\"palette\":[],\"blocks\":[[0,0,0,0,1]]}").is_err());
+    }
+
+    #[test]
+    fn test_compact_json_keeps_unusual_states() {
+        // Neither state survives a trip through the `/setblock` form.
+        let odd = BlockState::new("MyMod:Odd Block".to_string()).with_property("Facing".to_string(), "Up Right".to_string());
+        let bare = BlockState::new("stone".to_string());
+        let mut after = sample();
+        after.set_block(0, 0, 0, odd.clone());
+        after.set_block(1, 0, 0, bare.clone());
+        let diff = sample().diff(&after);
+
+        let loaded = SchematicDiff::from_json(&diff.to_json().unwrap()).unwrap();
+        assert_eq!(loaded, diff);
+        assert_eq!(loaded.blocks[0].to, odd);
+        assert_eq!(loaded.blocks[1].to, bare);
+    }
+}
@@ -1,12 +1,14 @@
-use std::collections::HashMap;
 use quartz_nbt::{NbtCompound, NbtList, NbtTag};
 use serde::{Deserialize, Serialize};
+use crate::transform::Transform;
+use crate::utils::{NbtMap, NbtValue};
 
 #[derive(Clone, Debug, PartialEq,Serialize, Deserialize)]
 pub struct Entity {
     pub id: String,
     pub position: (f64, f64, f64),
-    pub nbt: HashMap<String, String>, // Simplified NBT data
+    /// Every tag of the entity except `id` and `Pos`, which live in the fields above.
+    pub nbt: NbtMap,
 }
 
 impl Entity {
@@ -14,17 +16,67 @@ impl Entity {
         Entity {
             id,
             position,
-            nbt: HashMap::new(),
+            nbt: NbtMap::new(),
         }
     }
 
-    pub fn with_nbt_data(mut self, key: String, value: String) -> Self {
+    pub fn with_nbt_data(mut self, key: String, value: NbtValue) -> Self {
         self.nbt.insert(key, value);
         self
     }
 
+    // Formats name the id and position tags differently, so they say which ones to leave out.
+    pub(crate) fn with_nbt_from(mut self, compound: &NbtCompound, skipped: &[&str]) -> Self {
+        for (key, value) in compound.inner() {
+            if !skipped.contains(&key.as_str()) {
+                self.nbt.insert(key.clone(), NbtValue::from_quartz_nbt(value));
+            }
+        }
+        self
+    }
+
+    pub fn motion(&self) -> Option<(f64, f64, f64)> {
+        match self.nbt.get("Motion")? {
+            NbtValue::List(values) => match values.as_slice() {
+                [x, y, z] => Some((x.as_f64()?, y.as_f64()?, z.as_f64()?)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Yaw and pitch in degrees.
+    pub fn rotation(&self) -> Option<(f32, f32)> {
+        match self.nbt.get("Rotation")? {
+            NbtValue::List(values) => match values.as_slice() {
+                [yaw, pitch] => Some((yaw.as_f64()? as f32, pitch.as_f64()? as f32)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    pub fn set_rotation(&mut self, yaw: f32, pitch: f32) {
+        self.nbt.insert("Rotation".to_string(), NbtValue::List(vec![NbtValue::Float(yaw), NbtValue::Float(pitch)]));
+    }
+
+    /// The UUID as the four ints used since 1.16.
+    pub fn uuid(&self) -> Option<[i32; 4]> {
+        self.nbt.get("UUID")?.as_int_array()?.as_slice().try_into().ok()
+    }
+
+    // Only the yaw changes, and only for transforms that keep the entity upright.
+    pub(crate) fn transform_rotation(&mut self, transform: &Transform) {
+        let Some((yaw, pitch)) = self.rotation() else { return };
+        if !transform.keeps_vertical() {
+            return;
+        }
+        self.set_rotation(transform.transform_yaw(yaw as f64) as f32, pitch);
+    }
+
+    /// The entity as vanilla stores it: its tags plus `id` and `Pos`.
     pub fn to_nbt(&self) -> NbtTag {
-        let mut compound = NbtCompound::new();
+        let mut compound = self.nbt.to_quartz_nbt();
         compound.insert("id", NbtTag::String(self.id.clone()));
 
         let pos_list = NbtList::from(vec![
@@ -34,12 +86,6 @@ impl Entity {
         ]);
         compound.insert("Pos", NbtTag::List(pos_list));
 
-        let mut nbt_data = NbtCompound::new();
-        for (key, value) in &self.nbt {
-            nbt_data.insert(key, NbtTag::String(value.clone()));
-        }
-        compound.insert("NBT", NbtTag::Compound(nbt_data));
-
         NbtTag::Compound(compound)
     }
 
@@ -60,19 +106,56 @@ impl Entity {
             return Err("Invalid position data".to_string());
         };
 
-        let nbt_data = nbt.get::<_, &NbtCompound>("NBT")
-            .map_err(|e| format!("Failed to get Entity NBT data: {}", e))?;
-        let mut nbt_map = HashMap::new();
-        for (key, value) in nbt_data.inner() {
-            if let NbtTag::String(s) = value {
-                nbt_map.insert(key.clone(), s.clone());
+        let mut entity = Entity::new(id, position).with_nbt_from(nbt, &["id", "Pos", "NBT"]);
+
+        // Older versions of this crate kept the tags as SNBT strings in an `NBT` compound.
+        if let Ok(legacy) = nbt.get::<_, &NbtCompound>("NBT") {
+            for (key, value) in legacy.inner() {
+                let value = match value {
+                    NbtTag::String(snbt) => NbtValue::from_snbt(snbt).unwrap_or_else(|_| NbtValue::String(snbt.clone())),
+                    other => NbtValue::from_quartz_nbt(other),
+                };
+                entity.nbt.insert(key.clone(), value);
             }
         }
 
-        Ok(Entity {
-            id,
-            position,
-            nbt: nbt_map,
-        })
+        Ok(entity)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vanilla_entity_round_trip() {
+        let snbt = r#"{id:"minecraft:item_frame",Pos:[1.5d,64.0d,-3.5d],Motion:[0.0d,-0.1d,0.0d],Rotation:[90.0f,0.0f],UUID:[I;1,2,3,4],Facing:3b,Item:{id:"minecraft:map",count:1,components:{"minecraft:map_id":7}}}"#;
+        let compound = NbtMap::from_snbt(snbt).unwrap().to_quartz_nbt();
+        let entity = Entity::from_nbt(&compound).unwrap();
+
+        assert_eq!(entity.id, "minecraft:item_frame");
+        assert_eq!(entity.position, (1.5, 64.0, -3.5));
+        assert_eq!(entity.motion(), Some((0.0, -0.1, 0.0)));
+        assert_eq!(entity.rotation(), Some((90.0, 0.0)));
+        assert_eq!(entity.uuid(), Some([1, 2, 3, 4]));
+        assert_eq!(entity.nbt.get("Facing"), Some(&NbtValue::Byte(3)));
+
+        assert_eq!(entity.to_nbt(), NbtTag::Compound(compound));
+    }
+
+    #[test]
+    fn test_legacy_string_nbt() {
+        let mut legacy = NbtCompound::new();
+        legacy.insert("Rotation", NbtTag::String("[45.0f,10.0f]".to_string()));
+        legacy.insert("CustomName", NbtTag::String("\"Bob\"".to_string()));
+        let mut compound = NbtCompound::new();
+        compound.insert("id", NbtTag::String("minecraft:armor_stand".to_string()));
+        compound.insert("Pos", NbtTag::List(NbtList::from(vec![NbtTag::Double(0.0), NbtTag::Double(0.0), NbtTag::Double(0.0)])));
+        compound.insert("NBT", NbtTag::Compound(legacy));
+
+        let entity = Entity::from_nbt(&compound).unwrap();
+        assert_eq!(entity.rotation(), Some((45.0, 10.0)));
+        assert_eq!(entity.nbt.get("CustomName"), Some(&NbtValue::String("Bob".to_string())));
+        assert!(entity.nbt.get("NBT").is_none());
+    }
+}
@@ -0,0 +1,130 @@
+use std::fmt;
+use quartz_nbt::io::NbtIoError;
+use quartz_nbt::NbtReprError;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum SchematicError {
+    #[error("Failed to decompress data: {0}")]
+    Decompression(#[source] std::io::Error),
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid NBT: {0}")]
+    Nbt(String),
+    #[error("Missing field '{0}'")]
+    MissingField(String),
+    #[error("Palette index {index} is out of range for a palette of {palette_len} entries")]
+    PaletteIndexOutOfRange { index: usize, palette_len: usize },
+    #[error("Block data length mismatch: expected {expected} entries, got {actual}")]
+    BlockDataLengthMismatch { expected: usize, actual: usize },
+    #[error("Simulation world initialization failed: {0}")]
+    Simulation(String),
+    #[error("Invalid block state: {0}")]
+    InvalidBlockState(#[from] BlockStateParseError),
+    #[error("{0}")]
+    InvalidData(String),
+}
+
+#[derive(Error, Debug, Clone, PartialEq)]
+#[error("Invalid SNBT at position {position}: {message}")]
+pub struct SnbtError {
+    pub position: usize,
+    pub message: String,
+}
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum BlockStateParseError {
+    #[error("Block state is empty")]
+    Empty,
+    #[error("Invalid block id '{0}'")]
+    InvalidName(String),
+    #[error("Invalid property name '{0}'")]
+    InvalidPropertyName(String),
+    #[error("Property '{0}' has no value")]
+    MissingValue(String),
+    #[error("Property '{0}' is set more than once")]
+    DuplicateProperty(String),
+    #[error("Property list is missing its closing ']'")]
+    UnterminatedProperties,
+    #[error("Quoted property value is missing its closing '\"'")]
+    UnterminatedQuote,
+    #[error("Invalid block entity NBT: {0}")]
+    InvalidNbt(#[from] SnbtError),
+    #[error("Unexpected '{0}' after the block state")]
+    TrailingInput(String),
+}
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum BlockValidationError {
+    #[error("Unknown block '{0}'")]
+    UnknownBlock(String),
+    #[error("Block '{block}' has no property '{property}'")]
+    UnknownProperty { block: String, property: String },
+    #[error("'{value}' is not a valid value for property '{property}' of block '{block}'")]
+    InvalidValue { block: String, property: String, value: String },
+}
+
+impl From<NbtIoError> for SchematicError {
+    fn from(error: NbtIoError) -> Self {
+        SchematicError::Nbt(error.to_string())
+    }
+}
+
+// quartz_nbt keeps the structure error kinds private, so missing tags are recognised by message.
+impl From<NbtReprError> for SchematicError {
+    fn from(error: NbtReprError) -> Self {
+        let message = error.to_string();
+        match message.strip_prefix("Missing tag \"").and_then(|rest| rest.strip_suffix('"')) {
+            Some(field) => SchematicError::MissingField(field.to_string()),
+            None => SchematicError::Nbt(message),
+        }
+    }
+}
+
+impl From<String> for SchematicError {
+    fn from(message: String) -> Self {
+        SchematicError::InvalidData(message)
+    }
+}
+
+impl From<&str> for SchematicError {
+    fn from(message: &str) -> Self {
+        SchematicError::InvalidData(message.to_string())
+    }
+}
+
+/// Something unexpected that was skipped or worked around instead of failing the whole operation.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Warning {
+    UnknownBlock { name: String, position: (i32, i32, i32) },
+    MissingPaletteEntry { index: usize },
+    SkippedEntity { reason: String },
+    NotALever { position: (i32, i32, i32) },
+    UnknownLayerCharacter { character: char, position: (i32, i32, i32) },
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Warning::UnknownBlock { name, position } => write!(f, "Unknown block '{}' at {:?}", name, position),
+            Warning::MissingPaletteEntry { index } => write!(f, "Palette has no entry for index {}, using air", index),
+            Warning::SkippedEntity { reason } => write!(f, "Skipped entity: {}", reason),
+            Warning::NotALever { position } => write!(f, "Tried to use block at {:?} which is not a lever", position),
+            Warning::UnknownLayerCharacter { character, position } => write!(f, "No block is mapped to '{}' at {:?}, using air", character, position),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quartz_nbt::NbtCompound;
+
+    #[test]
+    fn test_missing_field_from_nbt() {
+        let compound = NbtCompound::new();
+        let error: SchematicError = compound.get::<_, i32>("Width").unwrap_err().into();
+        assert!(matches!(error, SchematicError::MissingField(ref field) if field == "Width"));
+        assert_eq!(error.to_string(), "Missing field 'Width'");
+    }
+}
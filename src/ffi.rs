@@ -1,6 +1,6 @@
 use std::os::raw::{c_char, c_uchar};
 use std::ffi::{CStr, CString};
-use crate::{UniversalSchematic, formats::{litematic, schematic}};
+use crate::{UniversalSchematic, block_registry, formats::{litematic, schematic}};
 
 #[repr(C)]
 pub struct ByteArray {
@@ -28,16 +28,18 @@ pub extern "C" fn convert_schematic(
     let result = match format {
         "litematic" => {
             if schematic::is_schematic(input_slice) {
-                let schematic = schematic::from_schematic(input_slice).unwrap();
-                litematic::to_litematic(&schematic).unwrap()
+                schematic::from_schematic(input_slice)
+                    .and_then(|schematic| litematic::to_litematic(&schematic))
+                    .unwrap_or_default()
             } else {
                 Vec::new()
             }
         },
         "schem" => {
             if litematic::is_litematic(input_slice) {
-                let schematic = litematic::from_litematic(input_slice).unwrap();
-                schematic::to_schematic(&schematic).unwrap()
+                litematic::from_litematic(input_slice)
+                    .and_then(|schematic| schematic::to_schematic(&schematic))
+                    .unwrap_or_default()
             } else {
                 Vec::new()
             }
@@ -57,4 +59,21 @@ pub extern "C" fn free_byte_array(array: ByteArray) {
     unsafe {
         let _ = Box::from_raw(std::slice::from_raw_parts_mut(array.data, array.len));
     }
-}
\ No newline at end of file
+}
+
+/// Newline separated list of every vanilla block id in `data_version`.
+/// Release it with `free_string`.
+#[no_mangle]
+pub extern "C" fn list_block_ids(data_version: i32) -> *mut c_char {
+    let ids = block_registry::block_ids(data_version).join("\n");
+    CString::new(ids).map_or(std::ptr::null_mut(), CString::into_raw)
+}
+
+#[no_mangle]
+pub extern "C" fn free_string(string: *mut c_char) {
+    if !string.is_null() {
+        unsafe {
+            let _ = CString::from_raw(string);
+        }
+    }
+}
@@ -0,0 +1,1068 @@
+use std::collections::HashMap;
+use std::io::{Cursor, Read};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use flate2::read::{GzDecoder, ZlibDecoder};
+use flate2::write::{GzEncoder, ZlibEncoder};
+use flate2::Compression;
+use quartz_nbt::{NbtCompound, NbtList, NbtTag};
+use quartz_nbt::io::Flavor;
+use crate::{UniversalSchematic, BlockState};
+use crate::block_entity::BlockEntity;
+use crate::bounding_box::BoundingBox;
+use crate::data_fixer::DataFixer;
+use crate::entity::Entity;
+use crate::region::{pack_packed_longs, packed_longs_len, unpack_packed_longs, Region};
+use crate::error::SchematicError;
+
+const SECTOR_SIZE: usize = 4096;
+const SECTION_VOLUME: usize = 16 * 16 * 16;
+const CHUNKS_PER_REGION: usize = 32 * 32;
+
+// Chunks saved before 1.16 (20w17a) let packed values straddle two longs.
+const PADDED_BLOCK_STATES_DATA_VERSION: i32 = 2527;
+// Chunks saved since 1.18 (21w43a) drop the `Level` wrapper and reach down to y=-64.
+const FLAT_CHUNK_DATA_VERSION: i32 = 2844;
+const DEFAULT_DATA_VERSION: i32 = 3700;
+// The `version` field of level.dat, unchanged since Anvil replaced McRegion.
+const ANVIL_VERSION: i32 = 19133;
+
+// Overworld sections, which cover y=-64 to y=319.
+const MIN_SECTION_Y: i32 = -4;
+const MAX_SECTION_Y: i32 = 19;
+
+const STRUCTURE_VOID: &str = "minecraft:structure_void";
+const DEFAULT_BIOME: &str = "minecraft:plains";
+const VOID_BIOME: &str = "minecraft:the_void";
+
+/// Region file contents together with their region coordinates, the `X` and `Z` of `r.X.Z.mca`.
+pub type RegionData = ((i32, i32), Vec<u8>);
+
+/// Parses the region coordinates out of a file name such as `r.-1.2.mca`.
+pub fn parse_region_file_name(file_name: &str) -> Option<(i32, i32)> {
+    let coords = file_name.strip_prefix("r.")?.strip_suffix(".mca")?;
+    let (x, z) = coords.split_once('.')?;
+    Some((x.parse().ok()?, z.parse().ok()?))
+}
+
+/// Imports the blocks, block entities and entities inside `bounding_box` from a world
+/// folder. Coordinates stay in world space, so the region is positioned at the box minimum.
+pub fn from_world<P: AsRef<Path>>(world: P, bounding_box: &BoundingBox) -> Result<UniversalSchematic, SchematicError> {
+    let world = world.as_ref();
+    let regions = read_region_folder(&world.join("region"), bounding_box)?;
+    // Entities moved out of the chunk data into their own region files in 1.17.
+    let entity_regions = read_region_folder(&world.join("entities"), bounding_box)?;
+    import(&regions, &entity_regions, bounding_box)
+}
+
+/// Imports from individual `r.X.Z.mca` files, which must keep their original names.
+pub fn from_region_files<P: AsRef<Path>>(paths: &[P], bounding_box: &BoundingBox) -> Result<UniversalSchematic, SchematicError> {
+    let mut regions = Vec::with_capacity(paths.len());
+    for path in paths {
+        let path = path.as_ref();
+        let coords = path.file_name()
+            .and_then(|name| name.to_str())
+            .and_then(parse_region_file_name)
+            .ok_or_else(|| format!("{} is not named like a region file (r.X.Z.mca)", path.display()))?;
+        regions.push((coords, std::fs::read(path)?));
+    }
+    import(&regions, &[], bounding_box)
+}
+
+pub fn from_region_data(regions: &[RegionData], bounding_box: &BoundingBox) -> Result<UniversalSchematic, SchematicError> {
+    import(regions, &[], bounding_box)
+}
+
+fn read_region_folder(folder: &Path, bounding_box: &BoundingBox) -> Result<Vec<RegionData>, SchematicError> {
+    let mut regions = Vec::new();
+    for region_x in (bounding_box.min.0 >> 9)..=(bounding_box.max.0 >> 9) {
+        for region_z in (bounding_box.min.2 >> 9)..=(bounding_box.max.2 >> 9) {
+            let path: PathBuf = folder.join(format!("r.{}.{}.mca", region_x, region_z));
+            // Regions that were never generated are simply left as air.
+            if path.is_file() {
+                regions.push(((region_x, region_z), std::fs::read(&path)?));
+            }
+        }
+    }
+    Ok(regions)
+}
+
+fn import(regions: &[RegionData], entity_regions: &[RegionData], bounding_box: &BoundingBox) -> Result<UniversalSchematic, SchematicError> {
+    let (width, height, length) = bounding_box.get_dimensions();
+    if width <= 0 || height <= 0 || length <= 0 {
+        return Err("Bounding box must not be empty".into());
+    }
+
+    let mut schematic = UniversalSchematic::new("Unnamed".to_string());
+    let mut region = Region::new("Main".to_string(), bounding_box.min, (width, height, length));
+
+    for ((region_x, region_z), data) in regions {
+        for chunk in read_chunks(*region_x, *region_z, data, bounding_box)? {
+            if schematic.metadata.mc_version.is_none() {
+                schematic.metadata.mc_version = chunk.get::<_, i32>("DataVersion").ok();
+            }
+            copy_chunk(&chunk, &mut region, bounding_box)?;
+        }
+    }
+
+    for ((region_x, region_z), data) in entity_regions {
+        for chunk in read_chunks(*region_x, *region_z, data, bounding_box)? {
+            if let Ok(entities) = chunk.get::<_, &NbtList>("Entities") {
+                copy_entities(entities, &mut region, bounding_box);
+            }
+        }
+    }
+
+    schematic.add_region(region);
+    Ok(schematic)
+}
+
+// Reads the chunks of one region file that overlap the bounding box.
+fn read_chunks(region_x: i32, region_z: i32, data: &[u8], bounding_box: &BoundingBox) -> Result<Vec<NbtCompound>, SchematicError> {
+    let region_file = RegionFile::parse(data)
+        .map_err(|e| format!("Invalid region file r.{}.{}.mca: {}", region_x, region_z, e))?;
+
+    let mut chunks = Vec::new();
+    for chunk_x in (bounding_box.min.0 >> 4)..=(bounding_box.max.0 >> 4) {
+        for chunk_z in (bounding_box.min.2 >> 4)..=(bounding_box.max.2 >> 4) {
+            if chunk_x >> 5 != region_x || chunk_z >> 5 != region_z {
+                continue;
+            }
+            if let Some(chunk) = region_file.read_chunk(chunk_x, chunk_z)? {
+                chunks.push(chunk);
+            }
+        }
+    }
+    Ok(chunks)
+}
+
+// The chunks of a region file, kept compressed so untouched chunks are written back as they were.
+struct RegionFile {
+    chunks: Vec<Option<(u8, Vec<u8>)>>,
+    timestamps: Vec<u32>,
+}
+
+impl RegionFile {
+    fn new() -> Self {
+        RegionFile {
+            chunks: vec![None; CHUNKS_PER_REGION],
+            timestamps: vec![0; CHUNKS_PER_REGION],
+        }
+    }
+
+    fn parse(data: &[u8]) -> Result<Self, SchematicError> {
+        if data.len() < SECTOR_SIZE * 2 {
+            return Err("Region file is missing its header".into());
+        }
+
+        let mut region_file = RegionFile::new();
+        for index in 0..CHUNKS_PER_REGION {
+            let location = &data[index * 4..index * 4 + 4];
+            let timestamp = &data[SECTOR_SIZE + index * 4..SECTOR_SIZE + index * 4 + 4];
+            region_file.timestamps[index] = u32::from_be_bytes([timestamp[0], timestamp[1], timestamp[2], timestamp[3]]);
+
+            let sector_offset = u32::from_be_bytes([0, location[0], location[1], location[2]]) as usize;
+            if sector_offset == 0 {
+                continue;
+            }
+            let start = sector_offset * SECTOR_SIZE;
+            if start + 5 > data.len() {
+                return Err(format!("Chunk {} points past the end of the region file", index).into());
+            }
+            let length = u32::from_be_bytes([data[start], data[start + 1], data[start + 2], data[start + 3]]) as usize;
+            if length == 0 || start + 4 + length > data.len() {
+                return Err(format!("Chunk {} has an invalid length", index).into());
+            }
+            region_file.chunks[index] = Some((data[start + 4], data[start + 5..start + 4 + length].to_vec()));
+        }
+        Ok(region_file)
+    }
+
+    fn chunk_index(chunk_x: i32, chunk_z: i32) -> usize {
+        ((chunk_x & 31) + (chunk_z & 31) * 32) as usize
+    }
+
+    fn read_chunk(&self, chunk_x: i32, chunk_z: i32) -> Result<Option<NbtCompound>, SchematicError> {
+        let (compression, payload) = match &self.chunks[Self::chunk_index(chunk_x, chunk_z)] {
+            Some(chunk) => chunk,
+            None => return Ok(None),
+        };
+
+        let mut decompressed = Vec::new();
+        match compression {
+            1 => { GzDecoder::new(payload.as_slice()).read_to_end(&mut decompressed).map_err(SchematicError::Decompression)?; }
+            2 => { ZlibDecoder::new(payload.as_slice()).read_to_end(&mut decompressed).map_err(SchematicError::Decompression)?; }
+            3 => decompressed.extend_from_slice(payload),
+            other if other & 0x80 != 0 => {
+                return Err(format!("Chunk ({}, {}) is stored in an external .mcc file, which is not supported", chunk_x, chunk_z).into());
+            }
+            other => return Err(format!("Unsupported chunk compression type {}", other).into()),
+        }
+
+        let (chunk, _) = quartz_nbt::io::read_nbt(&mut Cursor::new(decompressed), Flavor::Uncompressed)?;
+        Ok(Some(chunk))
+    }
+
+    fn write_chunk(&mut self, chunk_x: i32, chunk_z: i32, chunk: &NbtCompound) -> Result<(), SchematicError> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        quartz_nbt::io::write_nbt(&mut encoder, None, chunk, Flavor::Uncompressed)?;
+        let payload = encoder.finish()?;
+        if (payload.len() + 5 + SECTOR_SIZE - 1) / SECTOR_SIZE > 255 {
+            return Err(format!("Chunk ({}, {}) is too large for a region file", chunk_x, chunk_z).into());
+        }
+
+        let index = Self::chunk_index(chunk_x, chunk_z);
+        self.chunks[index] = Some((2, payload));
+        self.timestamps[index] = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() as u32).unwrap_or(0);
+        Ok(())
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut data = vec![0u8; SECTOR_SIZE * 2];
+        for (index, chunk) in self.chunks.iter().enumerate() {
+            let (compression, payload) = match chunk {
+                Some(chunk) => chunk,
+                None => continue,
+            };
+            let sector = data.len() / SECTOR_SIZE;
+            let sectors = (payload.len() + 5 + SECTOR_SIZE - 1) / SECTOR_SIZE;
+            data[index * 4..index * 4 + 4].copy_from_slice(&[(sector >> 16) as u8, (sector >> 8) as u8, sector as u8, sectors as u8]);
+            data[SECTOR_SIZE + index * 4..SECTOR_SIZE + index * 4 + 4].copy_from_slice(&self.timestamps[index].to_be_bytes());
+
+            data.extend_from_slice(&((payload.len() + 1) as u32).to_be_bytes());
+            data.push(*compression);
+            data.extend_from_slice(payload);
+            data.resize((sector + sectors) * SECTOR_SIZE, 0);
+        }
+        data
+    }
+}
+
+fn copy_chunk(chunk: &NbtCompound, region: &mut Region, bounding_box: &BoundingBox) -> Result<(), SchematicError> {
+    let data_version = chunk.get::<_, i32>("DataVersion").unwrap_or(0);
+    // Before 1.18 everything lived in a `Level` compound with capitalized keys.
+    let level = chunk.get::<_, &NbtCompound>("Level").unwrap_or(chunk);
+    let chunk_x = level.get::<_, i32>("xPos")?;
+    let chunk_z = level.get::<_, i32>("zPos")?;
+
+    let sections = level.get::<_, &NbtList>("sections")
+        .or_else(|_| level.get::<_, &NbtList>("Sections"));
+    if let Ok(sections) = sections {
+        for tag in sections.iter() {
+            if let NbtTag::Compound(section) = tag {
+                copy_section(section, (chunk_x, chunk_z), data_version, region, bounding_box)?;
+            }
+        }
+    }
+
+    let block_entities = level.get::<_, &NbtList>("block_entities")
+        .or_else(|_| level.get::<_, &NbtList>("TileEntities"));
+    if let Ok(block_entities) = block_entities {
+        for tag in block_entities.iter() {
+            if let NbtTag::Compound(nbt) = tag {
+                if let Some(block_entity) = parse_block_entity(nbt) {
+                    if bounding_box.contains(block_entity.position) {
+                        region.add_block_entity(block_entity);
+                    }
+                }
+            }
+        }
+    }
+
+    if let Ok(entities) = level.get::<_, &NbtList>("Entities") {
+        copy_entities(entities, region, bounding_box);
+    }
+
+    Ok(())
+}
+
+fn copy_section(section: &NbtCompound, (chunk_x, chunk_z): (i32, i32), data_version: i32, region: &mut Region, bounding_box: &BoundingBox) -> Result<(), SchematicError> {
+    let section_y = match section.get::<_, i8>("Y") {
+        Ok(y) => y as i32,
+        Err(_) => section.get::<_, i32>("Y")?,
+    };
+    let min = (chunk_x * 16, section_y * 16, chunk_z * 16);
+    let section_box = BoundingBox::new(min, (min.0 + 15, min.1 + 15, min.2 + 15));
+    if !section_box.intersects(bounding_box) {
+        return Ok(());
+    }
+
+    let (palette, packed) = match section.get::<_, &NbtCompound>("block_states") {
+        Ok(block_states) => (
+            block_states.get::<_, &NbtList>("palette").ok(),
+            block_states.get::<_, &[i64]>("data").ok(),
+        ),
+        Err(_) => (
+            section.get::<_, &NbtList>("Palette").ok(),
+            section.get::<_, &[i64]>("BlockStates").ok(),
+        ),
+    };
+    // Sections without a palette only carry light or biome data.
+    let palette = match palette {
+        Some(palette) if palette.len() > 0 => palette,
+        _ => return Ok(()),
+    };
+
+    let mut palette_indices = Vec::with_capacity(palette.len());
+    for tag in palette.iter() {
+        match tag {
+            NbtTag::Compound(compound) => palette_indices.push(region.get_or_insert_in_palette(BlockState::from_nbt(compound)?)),
+            _ => return Err("Invalid block state palette entry".into()),
+        }
+    }
+
+    let states = match packed {
+        Some(packed) if palette.len() > 1 => {
+            let bits_per_entry = bits_per_block_state(palette.len());
+            let spanning = data_version < PADDED_BLOCK_STATES_DATA_VERSION;
+            let expected = packed_longs_len(bits_per_entry, SECTION_VOLUME, spanning);
+            if packed.len() < expected {
+                return Err(format!("Section {} of chunk ({}, {}) has {} longs of block data, expected {}",
+                                   section_y, chunk_x, chunk_z, packed.len(), expected).into());
+            }
+            unpack_packed_longs(packed, bits_per_entry, SECTION_VOLUME, spanning)
+        }
+        // A single entry palette means the whole section is that block.
+        _ => vec![0; SECTION_VOLUME],
+    };
+
+    for (index, &state) in states.iter().enumerate() {
+        let x = min.0 + (index & 15) as i32;
+        let z = min.2 + ((index >> 4) & 15) as i32;
+        let y = min.1 + (index >> 8) as i32;
+        if !bounding_box.contains((x, y, z)) {
+            continue;
+        }
+        let palette_index = *palette_indices.get(state)
+            .ok_or_else(|| format!("Palette index {} out of range in chunk ({}, {})", state, chunk_x, chunk_z))?;
+        region.set_block_index(x, y, z, palette_index);
+    }
+
+    Ok(())
+}
+
+// Chunks store absolute positions, entries without an id or position are skipped.
+fn parse_block_entity(nbt: &NbtCompound) -> Option<BlockEntity> {
+    if ["id", "x", "y", "z"].iter().any(|key| !nbt.contains_key(*key)) {
+        return None;
+    }
+    Some(BlockEntity::from_format_nbt(nbt, (0, 0, 0)))
+}
+
+fn copy_entities(entities: &NbtList, region: &mut Region, bounding_box: &BoundingBox) {
+    for tag in entities.iter() {
+        if let NbtTag::Compound(compound) = tag {
+            if let Some(entity) = parse_entity(compound) {
+                let block = (entity.position.0.floor() as i32, entity.position.1.floor() as i32, entity.position.2.floor() as i32);
+                if bounding_box.contains(block) {
+                    region.add_entity(entity);
+                }
+            }
+        }
+    }
+}
+
+fn parse_entity(compound: &NbtCompound) -> Option<Entity> {
+    let id = compound.get::<_, &str>("id").ok()?.to_string();
+    let pos = compound.get::<_, &NbtList>("Pos").ok()?;
+    if pos.len() != 3 {
+        return None;
+    }
+    Some(Entity::new(id, (pos.get::<f64>(0).ok()?, pos.get::<f64>(1).ok()?, pos.get::<f64>(2).ok()?))
+        .with_nbt_from(compound, &["id", "Pos"]))
+}
+
+/// Pastes a schematic into region files held in memory, with the minimum corner of its
+/// bounding box at `origin`. Missing regions, chunks and sections are created. Structure
+/// voids keep whatever the world had, every other block, air included, replaces it.
+pub fn paste_into_region_data(schematic: &UniversalSchematic, origin: (i32, i32, i32), regions: &mut Vec<RegionData>, entity_regions: &mut Vec<RegionData>) -> Result<(), SchematicError> {
+    let mut region_files = parse_region_files(regions)?;
+    let mut entity_region_files = parse_region_files(entity_regions)?;
+    paste(schematic, origin, &mut region_files, &mut entity_region_files, DEFAULT_BIOME)?;
+    *regions = into_region_data(region_files);
+    *entity_regions = into_region_data(entity_region_files);
+    Ok(())
+}
+
+/// Pastes a schematic into the overworld of an existing world folder.
+pub fn paste_into_world<P: AsRef<Path>>(schematic: &UniversalSchematic, world: P, origin: (i32, i32, i32)) -> Result<(), SchematicError> {
+    let world = world.as_ref();
+    let target = paste_bounding_box(schematic, origin);
+    let mut regions = read_region_folder(&world.join("region"), &target)?;
+    let mut entity_regions = read_region_folder(&world.join("entities"), &target)?;
+    paste_into_region_data(schematic, origin, &mut regions, &mut entity_regions)?;
+
+    write_region_folder(&world.join("region"), &regions)?;
+    write_region_folder(&world.join("entities"), &entity_regions)?;
+    Ok(())
+}
+
+/// Builds the files of a minimal void world containing the schematic, as paths relative
+/// to the world folder. The player spawns on top of the build.
+pub fn to_void_world(schematic: &UniversalSchematic, origin: (i32, i32, i32), level_name: &str) -> Result<Vec<(String, Vec<u8>)>, SchematicError> {
+    let mut regions = HashMap::new();
+    let mut entity_regions = HashMap::new();
+    paste(schematic, origin, &mut regions, &mut entity_regions, VOID_BIOME)?;
+
+    let target = paste_bounding_box(schematic, origin);
+    let spawn = (origin.0, target.max.1 + 1, origin.2);
+    let data_version = target_data_version(schematic);
+
+    let mut files = vec![("level.dat".to_string(), create_level_dat(level_name, spawn, data_version)?)];
+    for ((region_x, region_z), data) in into_region_data(regions) {
+        files.push((format!("region/r.{}.{}.mca", region_x, region_z), data));
+    }
+    for ((region_x, region_z), data) in into_region_data(entity_regions) {
+        files.push((format!("entities/r.{}.{}.mca", region_x, region_z), data));
+    }
+    Ok(files)
+}
+
+/// Writes a minimal void world containing the schematic. Refuses to touch a folder
+/// that already holds a world.
+pub fn write_void_world<P: AsRef<Path>>(schematic: &UniversalSchematic, folder: P, origin: (i32, i32, i32), level_name: &str) -> Result<(), SchematicError> {
+    let folder = folder.as_ref();
+    if folder.join("level.dat").exists() {
+        return Err(format!("{} already contains a world", folder.display()).into());
+    }
+
+    for (path, data) in to_void_world(schematic, origin, level_name)? {
+        let path = folder.join(path);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, data)?;
+    }
+    Ok(())
+}
+
+fn parse_region_files(regions: &[RegionData]) -> Result<HashMap<(i32, i32), RegionFile>, SchematicError> {
+    let mut region_files = HashMap::new();
+    for ((region_x, region_z), data) in regions {
+        let region_file = RegionFile::parse(data)
+            .map_err(|e| format!("Invalid region file r.{}.{}.mca: {}", region_x, region_z, e))?;
+        region_files.insert((*region_x, *region_z), region_file);
+    }
+    Ok(region_files)
+}
+
+fn into_region_data(region_files: HashMap<(i32, i32), RegionFile>) -> Vec<RegionData> {
+    let mut regions: Vec<RegionData> = region_files.into_iter()
+        .map(|(coords, region_file)| (coords, region_file.to_bytes()))
+        .collect();
+    regions.sort_by_key(|(coords, _)| *coords);
+    regions
+}
+
+fn write_region_folder(folder: &Path, regions: &[RegionData]) -> Result<(), SchematicError> {
+    if regions.is_empty() {
+        return Ok(());
+    }
+    std::fs::create_dir_all(folder)?;
+    for ((region_x, region_z), data) in regions {
+        std::fs::write(folder.join(format!("r.{}.{}.mca", region_x, region_z)), data)?;
+    }
+    Ok(())
+}
+
+fn paste_bounding_box(schematic: &UniversalSchematic, origin: (i32, i32, i32)) -> BoundingBox {
+    let (width, height, length) = schematic.get_bounding_box().get_dimensions();
+    BoundingBox::new(origin, (origin.0 + width - 1, origin.1 + height - 1, origin.2 + length - 1))
+}
+
+fn paste(schematic: &UniversalSchematic, origin: (i32, i32, i32), regions: &mut HashMap<(i32, i32), RegionFile>, entity_regions: &mut HashMap<(i32, i32), RegionFile>, biome: &str) -> Result<(), SchematicError> {
+    let mut merged_region = schematic.get_merged_region();
+    let source = schematic.get_bounding_box();
+    let offset = (origin.0 - source.min.0, origin.1 - source.min.1, origin.2 - source.min.2);
+    let target = paste_bounding_box(schematic, origin);
+    if target.min.1 < MIN_SECTION_Y * 16 || target.max.1 >= (MAX_SECTION_Y + 1) * 16 {
+        return Err(format!("Blocks from y={} to y={} do not fit in the build height", target.min.1, target.max.1).into());
+    }
+
+    let data_version = target_data_version(schematic);
+    // The game only upgrades what a chunk's DataVersion says is old, so an older schematic is
+    // renamed to the version it is written with.
+    if let Some(mc_version) = schematic.metadata.mc_version {
+        let fixer = DataFixer::new(mc_version, data_version);
+        if !fixer.is_empty() {
+            merged_region.apply_data_fixer(&fixer);
+        }
+    }
+    let void_index = merged_region.get_palette_index(&BlockState::new(STRUCTURE_VOID.to_string()));
+
+    let mut block_entities: HashMap<(i32, i32), Vec<NbtTag>> = HashMap::new();
+    for (&(x, y, z), block_entity) in &merged_region.block_entities {
+        let position = (x + offset.0, y + offset.1, z + offset.2);
+        block_entities.entry((position.0 >> 4, position.2 >> 4)).or_default()
+            .push(convert_block_entity(block_entity, position));
+    }
+
+    let paste = ChunkPaste { region: &merged_region, offset, target: &target, void_index, biome };
+    for chunk_x in (target.min.0 >> 4)..=(target.max.0 >> 4) {
+        for chunk_z in (target.min.2 >> 4)..=(target.max.2 >> 4) {
+            let region_file = regions.entry((chunk_x >> 5, chunk_z >> 5)).or_insert_with(RegionFile::new);
+            let mut chunk = match region_file.read_chunk(chunk_x, chunk_z)? {
+                Some(chunk) if chunk.get::<_, i32>("DataVersion").unwrap_or(0) < FLAT_CHUNK_DATA_VERSION => {
+                    return Err(format!("Chunk ({}, {}) was saved before 1.18 and cannot be pasted into", chunk_x, chunk_z).into());
+                }
+                Some(chunk) => chunk,
+                None => new_chunk(chunk_x, chunk_z, data_version, biome),
+            };
+
+            let chunk_block_entities = block_entities.remove(&(chunk_x, chunk_z)).unwrap_or_default();
+            paste.apply(&mut chunk, (chunk_x, chunk_z), chunk_block_entities)?;
+            region_file.write_chunk(chunk_x, chunk_z, &chunk)?;
+        }
+    }
+
+    let mut entities: HashMap<(i32, i32), Vec<NbtTag>> = HashMap::new();
+    for entity in &merged_region.entities {
+        let position = (entity.position.0 + offset.0 as f64, entity.position.1 + offset.1 as f64, entity.position.2 + offset.2 as f64);
+        entities.entry(((position.0.floor() as i32) >> 4, (position.2.floor() as i32) >> 4)).or_default()
+            .push(convert_entity(entity, position));
+    }
+
+    for ((chunk_x, chunk_z), chunk_entities) in entities {
+        let region_file = entity_regions.entry((chunk_x >> 5, chunk_z >> 5)).or_insert_with(RegionFile::new);
+        let mut chunk = match region_file.read_chunk(chunk_x, chunk_z)? {
+            Some(chunk) => chunk,
+            None => {
+                let mut chunk = NbtCompound::new();
+                chunk.insert("DataVersion", NbtTag::Int(data_version));
+                chunk.insert("Position", NbtTag::IntArray(vec![chunk_x, chunk_z]));
+                chunk
+            }
+        };
+        let mut list = chunk.get::<_, &NbtList>("Entities").cloned().unwrap_or_else(|_| NbtList::new());
+        for entity in chunk_entities {
+            list.push(entity);
+        }
+        chunk.insert("Entities", NbtTag::List(list));
+        region_file.write_chunk(chunk_x, chunk_z, &chunk)?;
+    }
+
+    Ok(())
+}
+
+// New chunks use the 1.18 layout, so they are never written with an older DataVersion
+// even when the schematic itself comes from an older release.
+fn target_data_version(schematic: &UniversalSchematic) -> i32 {
+    schematic.metadata.mc_version.unwrap_or(DEFAULT_DATA_VERSION).max(FLAT_CHUNK_DATA_VERSION)
+}
+
+fn new_chunk(chunk_x: i32, chunk_z: i32, data_version: i32, biome: &str) -> NbtCompound {
+    let sections: Vec<NbtTag> = (MIN_SECTION_Y..=MAX_SECTION_Y)
+        .map(|section_y| NbtTag::Compound(new_section(section_y, biome)))
+        .collect();
+
+    let mut chunk = NbtCompound::new();
+    chunk.insert("DataVersion", NbtTag::Int(data_version));
+    chunk.insert("xPos", NbtTag::Int(chunk_x));
+    chunk.insert("yPos", NbtTag::Int(MIN_SECTION_Y));
+    chunk.insert("zPos", NbtTag::Int(chunk_z));
+    chunk.insert("Status", NbtTag::String("minecraft:full".to_string()));
+    chunk.insert("LastUpdate", NbtTag::Long(0));
+    chunk.insert("InhabitedTime", NbtTag::Long(0));
+    chunk.insert("sections", NbtTag::List(NbtList::from(sections)));
+    chunk.insert("block_entities", NbtTag::List(NbtList::new()));
+    chunk
+}
+
+fn new_section(section_y: i32, biome: &str) -> NbtCompound {
+    let mut block_states = NbtCompound::new();
+    block_states.insert("palette", NbtTag::List(NbtList::from(vec![BlockState::new("minecraft:air".to_string()).to_nbt()])));
+    let mut biomes = NbtCompound::new();
+    biomes.insert("palette", NbtTag::List(NbtList::from(vec![NbtTag::String(biome.to_string())])));
+
+    let mut section = NbtCompound::new();
+    section.insert("Y", NbtTag::Byte(section_y as i8));
+    section.insert("block_states", NbtTag::Compound(block_states));
+    section.insert("biomes", NbtTag::Compound(biomes));
+    section
+}
+
+// Everything a chunk needs to know about the paste, shared across chunks.
+struct ChunkPaste<'a> {
+    region: &'a Region,
+    offset: (i32, i32, i32),
+    target: &'a BoundingBox,
+    void_index: Option<usize>,
+    biome: &'a str,
+}
+
+impl ChunkPaste<'_> {
+    // Palette index of the schematic block that lands on a world position, skipping structure voids.
+    fn source_block(&self, (x, y, z): (i32, i32, i32)) -> Option<usize> {
+        if !self.target.contains((x, y, z)) {
+            return None;
+        }
+        let index = self.region.get_block_index(x - self.offset.0, y - self.offset.1, z - self.offset.2)?;
+        if Some(index) == self.void_index {
+            None
+        } else {
+            Some(index)
+        }
+    }
+
+    fn apply(&self, chunk: &mut NbtCompound, (chunk_x, chunk_z): (i32, i32), block_entities: Vec<NbtTag>) -> Result<(), SchematicError> {
+        let min = ((chunk_x * 16).max(self.target.min.0), self.target.min.1, (chunk_z * 16).max(self.target.min.2));
+        let max = ((chunk_x * 16 + 15).min(self.target.max.0), self.target.max.1, (chunk_z * 16 + 15).min(self.target.max.2));
+
+        let mut sections: Vec<NbtCompound> = match chunk.get::<_, &NbtList>("sections") {
+            Ok(list) => list.iter()
+                .filter_map(|tag| match tag {
+                    NbtTag::Compound(section) => Some(section.clone()),
+                    _ => None,
+                })
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+
+        for section_y in (min.1 >> 4)..=(max.1 >> 4) {
+            let index = match sections.iter().position(|section| section.get::<_, i8>("Y").ok() == Some(section_y as i8)) {
+                Some(index) => index,
+                None => {
+                    sections.push(new_section(section_y, self.biome));
+                    sections.len() - 1
+                }
+            };
+
+            let (mut palette, mut states) = decode_section(&sections[index])?;
+            let mut palette_lookup: HashMap<usize, usize> = HashMap::new();
+            for y in min.1.max(section_y * 16)..=max.1.min(section_y * 16 + 15) {
+                for z in min.2..=max.2 {
+                    for x in min.0..=max.0 {
+                        let source_index = match self.source_block((x, y, z)) {
+                            Some(index) => index,
+                            None => continue,
+                        };
+                        let section_index = *palette_lookup.entry(source_index).or_insert_with(|| {
+                            let block = &self.region.palette[source_index];
+                            palette.iter().position(|existing| existing == block).unwrap_or_else(|| {
+                                palette.push(block.clone());
+                                palette.len() - 1
+                            })
+                        });
+                        states[(((y & 15) << 8) | ((z & 15) << 4) | (x & 15)) as usize] = section_index;
+                    }
+                }
+            }
+            encode_section(&mut sections[index], &palette, &states);
+        }
+
+        sections.sort_by_key(|section| section.get::<_, i8>("Y").unwrap_or(0));
+        chunk.insert("sections", NbtTag::List(NbtList::from(sections.into_iter().map(NbtTag::Compound).collect::<Vec<_>>())));
+
+        // Block entities of replaced blocks go away, the schematic's take their place.
+        let mut list = NbtList::new();
+        if let Ok(existing) = chunk.get::<_, &NbtList>("block_entities") {
+            for tag in existing.iter() {
+                if let NbtTag::Compound(nbt) = tag {
+                    let position = (nbt.get::<_, i32>("x").unwrap_or(0), nbt.get::<_, i32>("y").unwrap_or(0), nbt.get::<_, i32>("z").unwrap_or(0));
+                    if self.source_block(position).is_none() {
+                        list.push(tag.clone());
+                    }
+                }
+            }
+        }
+        for tag in block_entities {
+            list.push(tag);
+        }
+        chunk.insert("block_entities", NbtTag::List(list));
+
+        // Heightmaps and light are stale now, the game rebuilds both when they are missing.
+        chunk.insert("Heightmaps", NbtTag::Compound(NbtCompound::new()));
+        chunk.insert("isLightOn", NbtTag::Byte(0));
+        Ok(())
+    }
+}
+
+fn decode_section(section: &NbtCompound) -> Result<(Vec<BlockState>, Vec<usize>), SchematicError> {
+    let block_states = section.get::<_, &NbtCompound>("block_states")?;
+    let mut palette = Vec::new();
+    for tag in block_states.get::<_, &NbtList>("palette")?.iter() {
+        match tag {
+            NbtTag::Compound(compound) => palette.push(BlockState::from_nbt(compound)?),
+            _ => return Err("Invalid block state palette entry".into()),
+        }
+    }
+    if palette.is_empty() {
+        palette.push(BlockState::new("minecraft:air".to_string()));
+    }
+
+    let states = match block_states.get::<_, &[i64]>("data") {
+        Ok(packed) if palette.len() > 1 => {
+            let bits_per_entry = bits_per_block_state(palette.len());
+            if packed.len() < packed_longs_len(bits_per_entry, SECTION_VOLUME, false) {
+                return Err("Section block data is too short".into());
+            }
+            let states = unpack_packed_longs(packed, bits_per_entry, SECTION_VOLUME, false);
+            if states.iter().any(|&state| state >= palette.len()) {
+                return Err("Section block data references a missing palette entry".into());
+            }
+            states
+        }
+        _ => vec![0; SECTION_VOLUME],
+    };
+    Ok((palette, states))
+}
+
+// Drops palette entries the paste overwrote entirely, so the section stays compact.
+fn encode_section(section: &mut NbtCompound, palette: &[BlockState], states: &[usize]) {
+    let mut remap = vec![usize::MAX; palette.len()];
+    let mut used = Vec::new();
+    let states: Vec<usize> = states.iter().map(|&state| {
+        if remap[state] == usize::MAX {
+            remap[state] = used.len();
+            used.push(palette[state].to_nbt());
+        }
+        remap[state]
+    }).collect();
+
+    let mut block_states = NbtCompound::new();
+    if used.len() > 1 {
+        block_states.insert("data", NbtTag::LongArray(pack_packed_longs(&states, bits_per_block_state(used.len()), false)));
+    }
+    block_states.insert("palette", NbtTag::List(NbtList::from(used)));
+    section.insert("block_states", NbtTag::Compound(block_states));
+}
+
+fn bits_per_block_state(palette_len: usize) -> usize {
+    std::cmp::max((palette_len as f64).log2().ceil() as usize, 4)
+}
+
+fn convert_block_entity(block_entity: &BlockEntity, position: (i32, i32, i32)) -> NbtTag {
+    let mut nbt = block_entity.to_format_nbt((block_entity.position.0 - position.0, block_entity.position.1 - position.1, block_entity.position.2 - position.2));
+    nbt.insert("keepPacked", NbtTag::Byte(0));
+    NbtTag::Compound(nbt)
+}
+
+fn convert_entity(entity: &Entity, position: (f64, f64, f64)) -> NbtTag {
+    let mut nbt = entity.nbt.to_quartz_nbt();
+    nbt.insert("id", NbtTag::String(entity.id.clone()));
+    nbt.insert("Pos", NbtTag::List(NbtList::from(vec![
+        NbtTag::Double(position.0),
+        NbtTag::Double(position.1),
+        NbtTag::Double(position.2),
+    ])));
+    NbtTag::Compound(nbt)
+}
+
+// A creative superflat world without layers, with commands enabled and the usual
+// time, weather and mob spawning switched off.
+fn create_level_dat(level_name: &str, spawn: (i32, i32, i32), data_version: i32) -> Result<Vec<u8>, SchematicError> {
+    let mut flat_settings = NbtCompound::new();
+    flat_settings.insert("layers", NbtTag::List(NbtList::new()));
+    flat_settings.insert("biome", NbtTag::String(VOID_BIOME.to_string()));
+    flat_settings.insert("features", NbtTag::Byte(0));
+    flat_settings.insert("lakes", NbtTag::Byte(0));
+    flat_settings.insert("structure_overrides", NbtTag::List(NbtList::new()));
+
+    let mut overworld_generator = NbtCompound::new();
+    overworld_generator.insert("type", NbtTag::String("minecraft:flat".to_string()));
+    overworld_generator.insert("settings", NbtTag::Compound(flat_settings));
+
+    let mut dimensions = NbtCompound::new();
+    dimensions.insert("minecraft:overworld", NbtTag::Compound(dimension("minecraft:overworld", overworld_generator)));
+    let mut nether_biomes = NbtCompound::new();
+    nether_biomes.insert("type", NbtTag::String("minecraft:multi_noise".to_string()));
+    nether_biomes.insert("preset", NbtTag::String("minecraft:nether".to_string()));
+    let mut end_biomes = NbtCompound::new();
+    end_biomes.insert("type", NbtTag::String("minecraft:the_end".to_string()));
+    for (name, settings, biome_source) in [("minecraft:the_nether", "minecraft:nether", nether_biomes), ("minecraft:the_end", "minecraft:end", end_biomes)] {
+        let mut generator = NbtCompound::new();
+        generator.insert("type", NbtTag::String("minecraft:noise".to_string()));
+        generator.insert("settings", NbtTag::String(settings.to_string()));
+        generator.insert("biome_source", NbtTag::Compound(biome_source));
+        dimensions.insert(name, NbtTag::Compound(dimension(name, generator)));
+    }
+
+    let mut world_gen_settings = NbtCompound::new();
+    world_gen_settings.insert("seed", NbtTag::Long(0));
+    world_gen_settings.insert("generate_features", NbtTag::Byte(0));
+    world_gen_settings.insert("bonus_chest", NbtTag::Byte(0));
+    world_gen_settings.insert("dimensions", NbtTag::Compound(dimensions));
+
+    let mut game_rules = NbtCompound::new();
+    for rule in ["doDaylightCycle", "doWeatherCycle", "doMobSpawning", "doFireTick"] {
+        game_rules.insert(rule, NbtTag::String("false".to_string()));
+    }
+
+    let mut data_packs = NbtCompound::new();
+    data_packs.insert("Enabled", NbtTag::List(NbtList::from(vec![NbtTag::String("vanilla".to_string())])));
+    data_packs.insert("Disabled", NbtTag::List(NbtList::new()));
+
+    let mut data = NbtCompound::new();
+    data.insert("DataVersion", NbtTag::Int(data_version));
+    data.insert("version", NbtTag::Int(ANVIL_VERSION));
+    data.insert("LevelName", NbtTag::String(level_name.to_string()));
+    data.insert("GameType", NbtTag::Int(1));
+    data.insert("Difficulty", NbtTag::Byte(0));
+    data.insert("allowCommands", NbtTag::Byte(1));
+    data.insert("hardcore", NbtTag::Byte(0));
+    data.insert("initialized", NbtTag::Byte(1));
+    data.insert("SpawnX", NbtTag::Int(spawn.0));
+    data.insert("SpawnY", NbtTag::Int(spawn.1));
+    data.insert("SpawnZ", NbtTag::Int(spawn.2));
+    data.insert("Time", NbtTag::Long(0));
+    data.insert("DayTime", NbtTag::Long(6000));
+    data.insert("LastPlayed", NbtTag::Long(0));
+    data.insert("GameRules", NbtTag::Compound(game_rules));
+    data.insert("DataPacks", NbtTag::Compound(data_packs));
+    data.insert("WorldGenSettings", NbtTag::Compound(world_gen_settings));
+
+    let mut root = NbtCompound::new();
+    root.insert("Data", NbtTag::Compound(data));
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    quartz_nbt::io::write_nbt(&mut encoder, None, &root, Flavor::Uncompressed)?;
+    Ok(encoder.finish()?)
+}
+
+fn dimension(dimension_type: &str, generator: NbtCompound) -> NbtCompound {
+    let mut dimension = NbtCompound::new();
+    dimension.insert("type", NbtTag::String(dimension_type.to_string()));
+    dimension.insert("generator", NbtTag::Compound(generator));
+    dimension
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::item::ItemStack;
+
+    fn palette_nbt(names: &[&str]) -> NbtList {
+        NbtList::from(names.iter().map(|name| BlockState::new(name.to_string()).to_nbt()).collect::<Vec<_>>())
+    }
+
+    fn region_file(chunks: &[((i32, i32), NbtCompound)]) -> Vec<u8> {
+        let mut region_file = RegionFile::new();
+        for ((chunk_x, chunk_z), chunk) in chunks {
+            region_file.write_chunk(*chunk_x, *chunk_z, chunk).unwrap();
+        }
+        region_file.to_bytes()
+    }
+
+    #[test]
+    fn test_parse_region_file_name() {
+        assert_eq!(parse_region_file_name("r.0.0.mca"), Some((0, 0)));
+        assert_eq!(parse_region_file_name("r.-1.12.mca"), Some((-1, 12)));
+        assert_eq!(parse_region_file_name("r.0.0.mcr"), None);
+        assert_eq!(parse_region_file_name("level.dat"), None);
+    }
+
+    #[test]
+    fn test_import_modern_chunk() {
+        // Chunk (-1, 0) lives in region (-1, 0) at local index 31
+        let mut states = vec![0usize; SECTION_VOLUME];
+        states[(5 << 8) | (3 << 4) | 2] = 1; // x = -14, y = -59, z = 3
+        states[(5 << 8) | (3 << 4) | 4] = 2; // x = -12, y = -59, z = 3
+
+        let mut block_states = NbtCompound::new();
+        block_states.insert("palette", NbtTag::List(palette_nbt(&["minecraft:air", "minecraft:stone", "minecraft:chest"])));
+        block_states.insert("data", NbtTag::LongArray(pack_packed_longs(&states, 4, false)));
+        let mut section = NbtCompound::new();
+        section.insert("Y", NbtTag::Byte(-4));
+        section.insert("block_states", NbtTag::Compound(block_states));
+
+        let mut chest = NbtCompound::new();
+        chest.insert("id", NbtTag::String("minecraft:chest".to_string()));
+        chest.insert("x", NbtTag::Int(-12));
+        chest.insert("y", NbtTag::Int(-59));
+        chest.insert("z", NbtTag::Int(3));
+        chest.insert("Items", NbtTag::List(NbtList::new()));
+
+        let mut chunk = NbtCompound::new();
+        chunk.insert("DataVersion", NbtTag::Int(3700));
+        chunk.insert("xPos", NbtTag::Int(-1));
+        chunk.insert("zPos", NbtTag::Int(0));
+        chunk.insert("sections", NbtTag::List(NbtList::from(vec![NbtTag::Compound(section)])));
+        chunk.insert("block_entities", NbtTag::List(NbtList::from(vec![NbtTag::Compound(chest)])));
+
+        let data = region_file(&[((-1, 0), chunk)]);
+        let bounding_box = BoundingBox::new((-14, -60, 2), (-12, -59, 3));
+        let schematic = from_region_data(&[((-1, 0), data)], &bounding_box).expect("Failed to import region");
+
+        assert_eq!(schematic.metadata.mc_version, Some(3700));
+        assert_eq!(schematic.get_bounding_box(), bounding_box);
+        assert_eq!(schematic.get_block(-14, -59, 3), Some(&BlockState::new("minecraft:stone".to_string())));
+        assert_eq!(schematic.get_block(-12, -59, 3), Some(&BlockState::new("minecraft:chest".to_string())));
+        assert_eq!(schematic.get_block(-13, -60, 2), Some(&BlockState::new("minecraft:air".to_string())));
+
+        let chest = schematic.get_region("Main").unwrap().block_entities.get(&(-12, -59, 3)).expect("Chest was not imported");
+        assert_eq!(chest.id, "minecraft:chest");
+        assert!(chest.nbt.get("x").is_none());
+    }
+
+    #[test]
+    fn test_import_pre_1_16_chunk() {
+        // 17 palette entries need 5 bits, which straddle longs in the old layout
+        let names: Vec<String> = (0..17).map(|i| format!("minecraft:block_{}", i)).collect();
+        let names: Vec<&str> = names.iter().map(String::as_str).collect();
+        let states: Vec<usize> = (0..SECTION_VOLUME).map(|i| i % 17).collect();
+
+        let mut section = NbtCompound::new();
+        section.insert("Y", NbtTag::Byte(0));
+        section.insert("Palette", NbtTag::List(palette_nbt(&names)));
+        section.insert("BlockStates", NbtTag::LongArray(pack_packed_longs(&states, 5, true)));
+
+        let mut level = NbtCompound::new();
+        level.insert("xPos", NbtTag::Int(0));
+        level.insert("zPos", NbtTag::Int(0));
+        level.insert("Sections", NbtTag::List(NbtList::from(vec![NbtTag::Compound(section)])));
+        let mut chunk = NbtCompound::new();
+        chunk.insert("DataVersion", NbtTag::Int(2230));
+        chunk.insert("Level", NbtTag::Compound(level));
+
+        let data = region_file(&[((0, 0), chunk)]);
+        let bounding_box = BoundingBox::new((0, 0, 0), (15, 15, 15));
+        let schematic = from_region_data(&[((0, 0), data)], &bounding_box).unwrap();
+
+        for (index, &state) in states.iter().enumerate().step_by(97) {
+            let (x, y, z) = ((index & 15) as i32, (index >> 8) as i32, ((index >> 4) & 15) as i32);
+            assert_eq!(schematic.get_block(x, y, z).unwrap().name, names[state]);
+        }
+    }
+
+    #[test]
+    fn test_paste_roundtrip() {
+        let mut schematic = UniversalSchematic::new("Paste".to_string());
+        let stone = BlockState::new("minecraft:stone".to_string());
+        let lever = BlockState::new("minecraft:lever".to_string())
+            .with_property("face".to_string(), "floor".to_string())
+            .with_property("facing".to_string(), "north".to_string());
+        schematic.set_block(0, 0, 0, stone.clone());
+        schematic.set_block(20, 3, 1, lever.clone());
+        schematic.set_block(1, 0, 0, BlockState::new("minecraft:chest".to_string()));
+        schematic.add_block_entity(BlockEntity::create_chest((1, 0, 0), vec![ItemStack::new("minecraft:diamond", 64).with_slot(0)]));
+        schematic.add_entity(Entity::new("minecraft:armor_stand".to_string(), (0.5, 1.0, 0.5)));
+
+        // Straddles the chunk and region borders at x = -512
+        let origin = (-520, -62, 7);
+        let mut regions = Vec::new();
+        let mut entity_regions = Vec::new();
+        paste_into_region_data(&schematic, origin, &mut regions, &mut entity_regions).expect("Failed to paste");
+        assert_eq!(regions.iter().map(|(coords, _)| *coords).collect::<Vec<_>>(), vec![(-2, 0), (-1, 0)]);
+        assert_eq!(entity_regions.len(), 1);
+
+        let bounding_box = BoundingBox::new(origin, (origin.0 + 20, origin.1 + 3, origin.2 + 1));
+        let loaded = import(&regions, &entity_regions, &bounding_box).expect("Failed to read the paste back");
+        assert_eq!(loaded.get_block(-520, -62, 7), Some(&stone));
+        assert_eq!(loaded.get_block(-500, -59, 8), Some(&lever));
+        assert_eq!(loaded.get_block(-510, -62, 7), Some(&BlockState::new("minecraft:air".to_string())));
+
+        let region = loaded.get_region("Main").unwrap();
+        let chest = region.block_entities.get(&(-519, -62, 7)).expect("Chest was not pasted");
+        assert_eq!(chest.id, "minecraft:chest");
+        assert!(chest.nbt.get("Items").is_some());
+        assert_eq!(region.entities.len(), 1);
+        assert_eq!(region.entities[0].position, (-519.5, -61.0, 7.5));
+    }
+
+    #[test]
+    fn test_paste_keeps_world_under_structure_voids() {
+        let mut first = UniversalSchematic::new("First".to_string());
+        first.set_block(0, 0, 0, BlockState::new("minecraft:stone".to_string()));
+        first.set_block(1, 0, 0, BlockState::new("minecraft:dirt".to_string()));
+        first.set_block(40, 0, 0, BlockState::new("minecraft:glass".to_string()));
+        first.set_block(2, 0, 0, BlockState::new("minecraft:chest".to_string()));
+        first.add_block_entity(BlockEntity::create_chest((2, 0, 0), vec![]));
+
+        let mut regions = Vec::new();
+        let mut entity_regions = Vec::new();
+        paste_into_region_data(&first, (0, 64, 0), &mut regions, &mut entity_regions).unwrap();
+
+        let mut second = UniversalSchematic::new("Second".to_string());
+        second.set_block(0, 0, 0, BlockState::new(STRUCTURE_VOID.to_string()));
+        second.set_block(1, 0, 0, BlockState::new("minecraft:gold_block".to_string()));
+        second.set_block(2, 0, 0, BlockState::new("minecraft:air".to_string()));
+        paste_into_region_data(&second, (0, 64, 0), &mut regions, &mut entity_regions).unwrap();
+
+        let loaded = from_region_data(&regions, &BoundingBox::new((0, 64, 0), (40, 64, 0))).unwrap();
+        assert_eq!(loaded.get_block(0, 64, 0).unwrap().name, "minecraft:stone");
+        assert_eq!(loaded.get_block(1, 64, 0).unwrap().name, "minecraft:gold_block");
+        assert_eq!(loaded.get_block(2, 64, 0).unwrap().name, "minecraft:air");
+        assert_eq!(loaded.get_block(40, 64, 0).unwrap().name, "minecraft:glass");
+        assert!(loaded.get_region("Main").unwrap().block_entities.is_empty());
+    }
+
+    #[test]
+    fn test_void_world() {
+        let mut schematic = UniversalSchematic::new("World".to_string());
+        schematic.set_block(0, 0, 0, BlockState::new("minecraft:stone".to_string()));
+        schematic.set_block(0, 4, 0, BlockState::new("minecraft:glass".to_string()));
+
+        let files = to_void_world(&schematic, (8, 64, 8), "Test World").unwrap();
+        let names: Vec<&str> = files.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["level.dat", "region/r.0.0.mca"]);
+
+        let mut decompressed = Vec::new();
+        GzDecoder::new(files[0].1.as_slice()).read_to_end(&mut decompressed).unwrap();
+        let (root, _) = quartz_nbt::io::read_nbt(&mut Cursor::new(decompressed), Flavor::Uncompressed).unwrap();
+        let data = root.get::<_, &NbtCompound>("Data").unwrap();
+        assert_eq!(data.get::<_, &str>("LevelName").unwrap(), "Test World");
+        assert_eq!(data.get::<_, i32>("SpawnY").unwrap(), 69);
+        let generator = data.get::<_, &NbtCompound>("WorldGenSettings").unwrap()
+            .get::<_, &NbtCompound>("dimensions").unwrap()
+            .get::<_, &NbtCompound>("minecraft:overworld").unwrap()
+            .get::<_, &NbtCompound>("generator").unwrap();
+        assert_eq!(generator.get::<_, &str>("type").unwrap(), "minecraft:flat");
+
+        let region = vec![((0, 0), files[1].1.clone())];
+        let loaded = from_region_data(&region, &BoundingBox::new((8, 64, 8), (8, 68, 8))).unwrap();
+        assert_eq!(loaded.get_block(8, 68, 8).unwrap().name, "minecraft:glass");
+    }
+
+    #[test]
+    fn test_paste_old_schematic_into_empty_world() {
+        for mc_version in [1343, 1519, 2586] {
+            let mut schematic = UniversalSchematic::new("Old".to_string());
+            schematic.metadata.mc_version = Some(mc_version);
+            schematic.set_block(0, 0, 0, BlockState::new("minecraft:stone".to_string()));
+            schematic.add_entity(Entity::new("minecraft:armor_stand".to_string(), (0.5, 1.0, 0.5)));
+
+            let mut regions = Vec::new();
+            let mut entity_regions = Vec::new();
+            paste_into_region_data(&schematic, (0, 64, 0), &mut regions, &mut entity_regions).expect("Failed to paste an old schematic");
+            let chunk = RegionFile::parse(&regions[0].1).unwrap().read_chunk(0, 0).unwrap().unwrap();
+            assert_eq!(chunk.get::<_, i32>("DataVersion").unwrap(), FLAT_CHUNK_DATA_VERSION);
+            let loaded = import(&regions, &entity_regions, &BoundingBox::new((0, 64, 0), (0, 64, 0))).unwrap();
+            assert_eq!(loaded.get_block(0, 64, 0).unwrap().name, "minecraft:stone");
+
+            let files = to_void_world(&schematic, (0, 64, 0), "Old World").expect("Failed to build a void world");
+            let mut decompressed = Vec::new();
+            GzDecoder::new(files[0].1.as_slice()).read_to_end(&mut decompressed).unwrap();
+            let (root, _) = quartz_nbt::io::read_nbt(&mut Cursor::new(decompressed), Flavor::Uncompressed).unwrap();
+            let data = root.get::<_, &NbtCompound>("Data").unwrap();
+            assert_eq!(data.get::<_, i32>("DataVersion").unwrap(), FLAT_CHUNK_DATA_VERSION);
+        }
+    }
+
+    #[test]
+    fn test_paste_upgrades_old_block_names() {
+        let mut schematic = UniversalSchematic::new("Old".to_string());
+        schematic.metadata.mc_version = Some(2586);
+        schematic.set_block(0, 0, 0, BlockState::new("minecraft:grass_path".to_string()));
+
+        let mut regions = Vec::new();
+        let mut entity_regions = Vec::new();
+        paste_into_region_data(&schematic, (0, 64, 0), &mut regions, &mut entity_regions).unwrap();
+        let chunk = RegionFile::parse(&regions[0].1).unwrap().read_chunk(0, 0).unwrap().unwrap();
+        let section = chunk.get::<_, &NbtList>("sections").unwrap().iter()
+            .filter_map(|section| match section {
+                NbtTag::Compound(section) => Some(section),
+                _ => None,
+            })
+            .find(|section| section.get::<_, i8>("Y").unwrap() == 4)
+            .unwrap();
+        let palette = section.get::<_, &NbtCompound>("block_states").unwrap().get::<_, &NbtList>("palette").unwrap();
+        let names: Vec<&str> = palette.iter()
+            .filter_map(|entry| match entry {
+                NbtTag::Compound(entry) => entry.get::<_, &str>("Name").ok(),
+                _ => None,
+            })
+            .collect();
+        assert!(names.contains(&"minecraft:dirt_path"));
+        assert!(!names.contains(&"minecraft:grass_path"));
+    }
+}
@@ -7,6 +7,8 @@ use crate::{UniversalSchematic, BlockState};
 use crate::block_entity::BlockEntity;
 use crate::entity::Entity;
 use crate::region::Region;
+use crate::error::SchematicError;
+use crate::pending_tick::{PendingTick, TickKind};
 
 
 pub fn is_litematic(data: &[u8]) -> bool {
@@ -28,7 +30,7 @@ pub fn is_litematic(data: &[u8]) -> bool {
         root.get::<_, &NbtCompound>("Metadata").is_ok() &&
         root.get::<_, &NbtCompound>("Regions").is_ok()
 }
-pub fn to_litematic(schematic: &UniversalSchematic) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+pub fn to_litematic(schematic: &UniversalSchematic) -> Result<Vec<u8>, SchematicError> {
     let mut root = NbtCompound::new();
 
     // Add Version and SubVersion
@@ -52,10 +54,10 @@ pub fn to_litematic(schematic: &UniversalSchematic) -> Result<Vec<u8>, Box<dyn s
     Ok(encoder.finish()?)
 }
 
-pub fn from_litematic(data: &[u8]) -> Result<UniversalSchematic, Box<dyn std::error::Error>> {
+pub fn from_litematic(data: &[u8]) -> Result<UniversalSchematic, SchematicError> {
     let mut decoder = flate2::read::GzDecoder::new(data);
     let mut decompressed = Vec::new();
-    std::io::Read::read_to_end(&mut decoder, &mut decompressed)?;
+    std::io::Read::read_to_end(&mut decoder, &mut decompressed).map_err(SchematicError::Decompression)?;
 
     let (root, _) = quartz_nbt::io::read_nbt(&mut std::io::Cursor::new(decompressed), quartz_nbt::io::Flavor::Uncompressed)?;
 
@@ -131,14 +133,19 @@ fn create_regions(schematic: &UniversalSchematic) -> NbtCompound {
 
         // TileEntities
         let tile_entities = NbtList::from(region.block_entities.values().map(|block_entity| {
-            NbtTag::Compound(block_entity.to_nbt())
+            NbtTag::Compound(block_entity.to_format_nbt(region.position))
         }).collect::<Vec<NbtTag>>());
         region_nbt.insert("TileEntities", NbtTag::List(tile_entities));
 
 
-        // PendingBlockTicks and PendingFluidTicks (not fully supported, using empty lists)
-        region_nbt.insert("PendingBlockTicks", NbtTag::List(NbtList::new()));
-        region_nbt.insert("PendingFluidTicks", NbtTag::List(NbtList::new()));
+        // PendingBlockTicks and PendingFluidTicks
+        for (key, kind) in [("PendingBlockTicks", TickKind::Block), ("PendingFluidTicks", TickKind::Fluid)] {
+            let ticks = NbtList::from(region.pending_ticks.iter()
+                .filter(|tick| tick.kind == kind)
+                .map(|tick| NbtTag::Compound(tick.to_litematic_nbt(region.position)))
+                .collect::<Vec<NbtTag>>());
+            region_nbt.insert(key, NbtTag::List(ticks));
+        }
 
         regions.insert(name, NbtTag::Compound(region_nbt));
     }
@@ -147,7 +154,7 @@ fn create_regions(schematic: &UniversalSchematic) -> NbtCompound {
 }
 
 
-fn parse_metadata(root: &NbtCompound, schematic: &mut UniversalSchematic) -> Result<(), Box<dyn std::error::Error>> {
+fn parse_metadata(root: &NbtCompound, schematic: &mut UniversalSchematic) -> Result<(), SchematicError> {
     let metadata = root.get::<_, &NbtCompound>("Metadata")?;
 
     schematic.metadata.name = metadata.get::<_, &str>("Name").ok().map(String::from);
@@ -161,7 +168,7 @@ fn parse_metadata(root: &NbtCompound, schematic: &mut UniversalSchematic) -> Res
     Ok(())
 }
 
-fn parse_regions(root: &NbtCompound, schematic: &mut UniversalSchematic) -> Result<(), Box<dyn std::error::Error>> {
+fn parse_regions(root: &NbtCompound, schematic: &mut UniversalSchematic) -> Result<(), SchematicError> {
     let regions = root.get::<_, &NbtCompound>("Regions")?;
     let mut loop_count = 0;
     for (name, region_tag) in regions.inner() {
@@ -191,18 +198,18 @@ fn parse_regions(root: &NbtCompound, schematic: &mut UniversalSchematic) -> Resu
 
             // Parse BlockStatePalette
             let palette = region_nbt.get::<_, &NbtList>("BlockStatePalette")?;
-            region.palette = palette.iter().filter_map(|tag| {
+            region.set_palette(palette.iter().filter_map(|tag| {
                 if let NbtTag::Compound(compound) = tag {
                     BlockState::from_nbt(compound).ok()
                 } else {
                     None
                 }
-            }).collect();
+            }).collect());
 
             // Parse BlockStates
             let block_states = region_nbt.get::<_, &[i64]>("BlockStates")?;
             // region.unpack_block_states(block_states);
-            region.blocks = region.unpack_block_states(block_states);
+            region.set_block_indices(region.unpack_block_states(block_states));
             // Parse Entities
             if let Ok(entities_list) = region_nbt.get::<_, &NbtList>("Entities") {
                 region.entities = entities_list.iter().filter_map(|tag| {
@@ -218,8 +225,20 @@ fn parse_regions(root: &NbtCompound, schematic: &mut UniversalSchematic) -> Resu
             if let Ok(tile_entities_list) = region_nbt.get::<_, &NbtList>("TileEntities") {
                 for tag in tile_entities_list.iter() {
                     if let NbtTag::Compound(compound) = tag {
-                        if let block_entity = BlockEntity::from_nbt(compound) {
-                            region.block_entities.insert(block_entity.position, block_entity);
+                        let block_entity = BlockEntity::from_format_nbt(compound, region.position);
+                        region.block_entities.insert(block_entity.position, block_entity);
+                    }
+                }
+            }
+
+            // Parse PendingBlockTicks and PendingFluidTicks
+            for (key, kind) in [("PendingBlockTicks", TickKind::Block), ("PendingFluidTicks", TickKind::Fluid)] {
+                if let Ok(ticks) = region_nbt.get::<_, &NbtList>(key) {
+                    for tag in ticks.iter() {
+                        if let NbtTag::Compound(compound) = tag {
+                            if let Some(tick) = PendingTick::from_litematic_nbt(compound, kind, region.position) {
+                                region.add_pending_tick(tick);
+                            }
                         }
                     }
                 }
@@ -239,6 +258,9 @@ mod tests {
     use num_complex::Complex;
     use super::*;
     use crate::{UniversalSchematic, BlockState};
+    use crate::item::ItemStack;
+    use crate::{PendingTick, TickKind};
+    use crate::utils::NbtValue;
 
     #[test]
     fn test_create_metadata() {
@@ -361,6 +383,70 @@ mod tests {
         assert_eq!(parsed_region.palette.len(), 2);
         assert_eq!(parsed_region.count_blocks(), 2); // 2 stone blocks
     }
+
+    #[test]
+    fn test_multi_region_chests_keep_their_positions() {
+        let mut schematic = UniversalSchematic::new("Chests".to_string());
+        let chest = BlockState::new("minecraft:chest".to_string());
+        let regions = [("East", (10, 0, -5), (3, 1, 3)), ("West", (-20, 5, 7), (2, 2, 2))];
+
+        let mut expected = Vec::new();
+        for (name, position, size) in regions {
+            let mut region = Region::new(name.to_string(), position, size);
+            for x in 0..size.0 {
+                for y in 0..size.1 {
+                    for z in 0..size.2 {
+                        let pos = (position.0 + x, position.1 + y, position.2 + z);
+                        let count = expected.len() as u8 + 1;
+                        region.set_block(pos.0, pos.1, pos.2, chest.clone());
+                        region.add_block_entity(BlockEntity::create_chest(pos, vec![ItemStack::new("minecraft:diamond", count).with_slot(0)]));
+                        expected.push((name, pos, count));
+                    }
+                }
+            }
+            schematic.add_region(region);
+        }
+
+        let regions_nbt = create_regions(&schematic);
+        let west = regions_nbt.get::<_, &NbtCompound>("West").unwrap();
+        for tag in west.get::<_, &NbtList>("TileEntities").unwrap().iter() {
+            let NbtTag::Compound(tile_entity) = tag else { panic!("Tile entity is not a compound") };
+            assert!((0..2).contains(&tile_entity.get::<_, i32>("x").unwrap()));
+            assert_eq!(tile_entity.get::<_, &str>("id").unwrap(), "minecraft:chest");
+        }
+
+        let loaded = from_litematic(&to_litematic(&schematic).unwrap()).unwrap();
+        for (name, pos, count) in expected {
+            let region = loaded.get_region(name).unwrap();
+            assert_eq!(region.block_entities.len(), region.volume());
+            let block_entity = region.block_entities.get(&pos).expect("Chest was not read back");
+            assert_eq!(block_entity.position, pos);
+            assert_eq!(block_entity.nbt.get("Items"), Some(&NbtValue::List(vec![ItemStack::new("minecraft:diamond", count).with_slot(0).to_nbt()])));
+        }
+    }
+
+    #[test]
+    fn test_pending_ticks_round_trip() {
+        let mut schematic = UniversalSchematic::new("Ticks".to_string());
+        let mut region = Region::new("Clock".to_string(), (5, 10, -3), (3, 1, 1));
+        region.set_block(5, 10, -3, BlockState::new("minecraft:repeater".to_string()).with_property("delay".to_string(), "4".to_string()));
+        region.set_block(7, 10, -3, BlockState::new("minecraft:water".to_string()).with_property("level".to_string(), "1".to_string()));
+        let repeater_tick = PendingTick::new(TickKind::Block, "minecraft:repeater".to_string(), (5, 10, -3), 6).with_priority(-3);
+        let water_tick = PendingTick::new(TickKind::Fluid, "minecraft:flowing_water".to_string(), (7, 10, -3), 5);
+        region.add_pending_tick(repeater_tick.clone());
+        region.add_pending_tick(water_tick.clone());
+        schematic.add_region(region);
+
+        let regions = create_regions(&schematic);
+        let clock = regions.get::<_, &NbtCompound>("Clock").unwrap();
+        let NbtTag::Compound(block_tick) = &clock.get::<_, &NbtList>("PendingBlockTicks").unwrap()[0] else { panic!("Tick is not a compound") };
+        assert_eq!(block_tick.get::<_, &str>("Block").unwrap(), "minecraft:repeater");
+        assert_eq!(block_tick.get::<_, i32>("x").unwrap(), 0);
+        assert_eq!(clock.get::<_, &NbtList>("PendingFluidTicks").unwrap().len(), 1);
+
+        let loaded = from_litematic(&to_litematic(&schematic).unwrap()).unwrap();
+        assert_eq!(loaded.get_region("Clock").unwrap().pending_ticks, vec![repeater_tick, water_tick]);
+    }
     #[test]
     fn test_simple_litematic() {
         let mut schematic = UniversalSchematic::new("Simple Cube".to_string());
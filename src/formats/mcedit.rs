@@ -0,0 +1,416 @@
+use std::collections::HashMap;
+use std::io::{Cursor, Read};
+use std::sync::OnceLock;
+use flate2::read::GzDecoder;
+use quartz_nbt::{NbtCompound, NbtList, NbtTag};
+use quartz_nbt::io::Flavor;
+use crate::{UniversalSchematic, BlockState};
+use crate::block_entity::BlockEntity;
+use crate::bounding_box::BoundingBox;
+use crate::entity::Entity;
+use crate::formats::schematic::parse_block_state;
+use crate::region::Region;
+use crate::error::{SchematicError, Warning};
+
+// One `id:meta minecraft:name[properties]` entry per line.
+const LEGACY_BLOCKS: &str = include_str!("mcedit_blocks.txt");
+
+// The mapping table targets the block names introduced by the 1.13 flattening.
+const FLATTENING_DATA_VERSION: i32 = 1519;
+
+fn legacy_block_table() -> &'static HashMap<(u16, u8), BlockState> {
+    static TABLE: OnceLock<HashMap<(u16, u8), BlockState>> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        parse_legacy_blocks(LEGACY_BLOCKS)
+            .unwrap_or_else(|line| panic!("Invalid line in mcedit_blocks.txt: {}", line))
+    })
+}
+
+// Fails with the first line that doesn't parse.
+fn parse_legacy_blocks(text: &str) -> Result<HashMap<(u16, u8), BlockState>, String> {
+    let parse_line = |line: &str| {
+        let (key, state) = line.split_once(' ')?;
+        let (id, meta) = key.split_once(':')?;
+        let state = state.trim();
+        if state.is_empty() {
+            return None;
+        }
+        Some(((id.parse().ok()?, meta.parse().ok()?), parse_block_state(state)))
+    };
+    text.lines()
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| parse_line(line).ok_or_else(|| line.to_string()))
+        .collect()
+}
+
+/// Maps a legacy numeric block id and metadata value to its flattened block state.
+/// Unknown metadata falls back to the block's default variant and unknown ids become air.
+pub fn legacy_to_block_state(id: u16, meta: u8) -> BlockState {
+    lookup_legacy_block(id, meta).unwrap_or_else(|| BlockState::new("minecraft:air".to_string()))
+}
+
+fn lookup_legacy_block(id: u16, meta: u8) -> Option<BlockState> {
+    let table = legacy_block_table();
+    table.get(&(id, meta))
+        .or_else(|| table.get(&(id, 0)))
+        .cloned()
+}
+
+pub fn is_mcedit(data: &[u8]) -> bool {
+    // Decompress the data
+    let mut decoder = GzDecoder::new(data);
+    let mut decompressed = Vec::new();
+    if decoder.read_to_end(&mut decompressed).is_err() {
+        return false;
+    }
+
+    // Read the NBT data
+    let (root, _) = match quartz_nbt::io::read_nbt(&mut Cursor::new(decompressed), Flavor::Uncompressed) {
+        Ok(result) => result,
+        Err(_) => return false,
+    };
+
+    // Check for the fields written by MCEdit, Schematica and pre-1.13 WorldEdit
+    root.get::<_, i16>("Width").is_ok() &&
+        root.get::<_, i16>("Height").is_ok() &&
+        root.get::<_, i16>("Length").is_ok() &&
+        root.get::<_, &Vec<i8>>("Blocks").is_ok() &&
+        root.get::<_, &Vec<i8>>("Data").is_ok() &&
+        root.get::<_, &str>("Materials").map_or(true, |materials| materials == "Alpha")
+}
+
+pub fn from_mcedit(data: &[u8]) -> Result<UniversalSchematic, SchematicError> {
+    from_mcedit_with_warnings(data).map(|(schematic, _)| schematic)
+}
+
+/// Like `from_mcedit`, but also returns the problems that were worked around while reading.
+pub fn from_mcedit_with_warnings(data: &[u8]) -> Result<(UniversalSchematic, Vec<Warning>), SchematicError> {
+    let mut warnings = Vec::new();
+    let mut decoder = GzDecoder::new(data);
+    let mut decompressed = Vec::new();
+    decoder.read_to_end(&mut decompressed).map_err(SchematicError::Decompression)?;
+
+    let (root, _) = quartz_nbt::io::read_nbt(&mut Cursor::new(decompressed), Flavor::Uncompressed)?;
+
+    if let Ok(materials) = root.get::<_, &str>("Materials") {
+        if materials != "Alpha" {
+            return Err(format!("Unsupported MCEdit materials: {}", materials).into());
+        }
+    }
+
+    let width = root.get::<_, i16>("Width")? as u16 as i32;
+    let height = root.get::<_, i16>("Height")? as u16 as i32;
+    let length = root.get::<_, i16>("Length")? as u16 as i32;
+
+    let mut schematic = UniversalSchematic::new("Unnamed".to_string());
+    schematic.metadata.mc_version = Some(FLATTENING_DATA_VERSION);
+
+    let mut region = Region::new("Main".to_string(), (0, 0, 0), (width, height, length));
+    let (palette, blocks) = parse_blocks(&root, &region.get_bounding_box(), &mut warnings)?;
+    region.set_palette(palette);
+    region.set_block_indices(blocks);
+
+    if let Ok(tile_entities) = root.get::<_, &NbtList>("TileEntities") {
+        for tag in tile_entities.iter() {
+            if let NbtTag::Compound(compound) = tag {
+                region.add_block_entity(parse_tile_entity(compound));
+            }
+        }
+    }
+
+    if let Ok(entities) = root.get::<_, &NbtList>("Entities") {
+        for tag in entities.iter() {
+            if let NbtTag::Compound(compound) = tag {
+                match parse_entity(compound) {
+                    Some(entity) => region.add_entity(entity),
+                    None => warnings.push(Warning::SkippedEntity { reason: "Entity has no id or position".to_string() }),
+                }
+            }
+        }
+    }
+
+    schematic.add_region(region);
+    Ok((schematic, warnings))
+}
+
+// Unknown ids become air, with a warning at the first place each id and metadata pair is used.
+fn parse_blocks(root: &NbtCompound, bounding_box: &BoundingBox, warnings: &mut Vec<Warning>) -> Result<(Vec<BlockState>, Vec<usize>), SchematicError> {
+    let volume = bounding_box.volume() as usize;
+    let block_ids = root.get::<_, &Vec<i8>>("Blocks")?;
+    let block_data = root.get::<_, &Vec<i8>>("Data")?;
+    let add_blocks = root.get::<_, &Vec<i8>>("AddBlocks").ok();
+
+    if block_ids.len() < volume || block_data.len() < volume {
+        return Err(format!(
+            "Block array length mismatch. Got: {} ids and {} data values, Expected: {}",
+            block_ids.len(), block_data.len(), volume
+        ).into());
+    }
+
+    let mut palette = vec![BlockState::new("minecraft:air".to_string())];
+    let mut palette_indices: HashMap<(u16, u8), usize> = HashMap::new();
+    palette_indices.insert((0, 0), 0);
+
+    let mut blocks = Vec::with_capacity(volume);
+    for index in 0..volume {
+        let id = get_block_id(block_ids, add_blocks, index);
+        let meta = (block_data[index] as u8) & 0x0F;
+        let palette_index = *palette_indices.entry((id, meta)).or_insert_with(|| {
+            let block_state = lookup_legacy_block(id, meta).unwrap_or_else(|| {
+                let name = format!("{}:{}", id, meta);
+                warnings.push(Warning::UnknownBlock { name, position: bounding_box.index_to_coords(index) });
+                BlockState::new("minecraft:air".to_string())
+            });
+            match palette.iter().position(|b| b == &block_state) {
+                Some(existing) => existing,
+                None => {
+                    palette.push(block_state);
+                    palette.len() - 1
+                }
+            }
+        });
+        blocks.push(palette_index);
+    }
+
+    Ok((palette, blocks))
+}
+
+// `AddBlocks` packs the upper four bits of two block ids into each byte, even indices in the low nibble.
+fn get_block_id(block_ids: &[i8], add_blocks: Option<&Vec<i8>>, index: usize) -> u16 {
+    let low = block_ids[index] as u8 as u16;
+    let high = match add_blocks.and_then(|add| add.get(index >> 1)) {
+        Some(&add) if index & 1 == 0 => (add as u8 & 0x0F) as u16,
+        Some(&add) => ((add as u8 & 0xF0) >> 4) as u16,
+        None => 0,
+    };
+    (high << 8) | low
+}
+
+fn parse_tile_entity(compound: &NbtCompound) -> BlockEntity {
+    let block_entity = BlockEntity::from_format_nbt(compound, (0, 0, 0));
+    match compound.get::<_, &str>("id") {
+        Ok(id) => block_entity.with_id(normalize_tile_entity_id(id)),
+        Err(_) => block_entity,
+    }
+}
+
+fn parse_entity(compound: &NbtCompound) -> Option<Entity> {
+    let id = normalize_entity_id(compound.get::<_, &str>("id").ok()?);
+    let pos = compound.get::<_, &NbtList>("Pos").ok()?;
+    if pos.len() != 3 {
+        return None;
+    }
+    Some(Entity::new(id, (pos.get::<f64>(0).ok()?, pos.get::<f64>(1).ok()?, pos.get::<f64>(2).ok()?))
+        .with_nbt_from(compound, &["id", "Pos"]))
+}
+
+// Block entity ids before 1.11 were CamelCase names without a namespace.
+fn normalize_tile_entity_id(id: &str) -> String {
+    if id.contains(':') {
+        return id.to_string();
+    }
+    let modern = match id {
+        "Airportal" => "end_portal",
+        "Banner" => "banner",
+        "Beacon" => "beacon",
+        "Cauldron" => "brewing_stand",
+        "Chest" => "chest",
+        "Comparator" => "comparator",
+        "Control" => "command_block",
+        "DLDetector" => "daylight_detector",
+        "Dropper" => "dropper",
+        "EnchantTable" => "enchanting_table",
+        "EndGateway" => "end_gateway",
+        "EnderChest" => "ender_chest",
+        "FlowerPot" => "flower_pot",
+        "Furnace" => "furnace",
+        "Hopper" => "hopper",
+        "MobSpawner" => "mob_spawner",
+        "Music" => "noteblock",
+        "Piston" => "piston",
+        "RecordPlayer" => "jukebox",
+        "Sign" => "sign",
+        "Skull" => "skull",
+        "Structure" => "structure_block",
+        "Trap" => "dispenser",
+        other => return format!("minecraft:{}", to_snake_case(other)),
+    };
+    format!("minecraft:{}", modern)
+}
+
+// Entity ids before 1.11 were CamelCase as well, e.g. `ItemFrame` or `ArmorStand`. Those that
+// were renamed rather than just re-cased are listed, using their names as of the flattening.
+fn normalize_entity_id(id: &str) -> String {
+    if id.contains(':') {
+        return id.to_string();
+    }
+    let modern = match id {
+        "EnderCrystal" => "end_crystal",
+        "EntityHorse" => "horse",
+        "EyeOfEnderSignal" => "eye_of_ender",
+        "FallingSand" => "falling_block",
+        "FireworksRocketEntity" => "firework_rocket",
+        "LavaSlime" => "magma_cube",
+        "MinecartChest" => "chest_minecart",
+        "MinecartCommandBlock" => "command_block_minecart",
+        "MinecartFurnace" => "furnace_minecart",
+        "MinecartHopper" => "hopper_minecart",
+        "MinecartRideable" => "minecart",
+        "MinecartSpawner" => "spawner_minecart",
+        "MinecartTNT" => "tnt_minecart",
+        "MushroomCow" => "mooshroom",
+        "Ozelot" => "ocelot",
+        "PigZombie" => "zombie_pigman",
+        "PrimedTnt" => "tnt",
+        "SnowMan" => "snow_golem",
+        "ThrownEgg" => "egg",
+        "ThrownEnderpearl" => "ender_pearl",
+        "ThrownExpBottle" => "experience_bottle",
+        "ThrownPotion" => "potion",
+        "TippedArrow" => "arrow",
+        "VillagerGolem" => "iron_golem",
+        "WitherBoss" => "wither",
+        "XPOrb" => "experience_orb",
+        other => return format!("minecraft:{}", to_snake_case(other)),
+    };
+    format!("minecraft:{}", modern)
+}
+
+pub(crate) fn to_snake_case(name: &str) -> String {
+    let mut snake = String::with_capacity(name.len() + 4);
+    for (i, c) in name.chars().enumerate() {
+        if c.is_ascii_uppercase() {
+            if i > 0 {
+                snake.push('_');
+            }
+            snake.push(c.to_ascii_lowercase());
+        } else {
+            snake.push(c);
+        }
+    }
+    snake
+}
+
+#[cfg(test)]
+mod tests {
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use super::*;
+
+    fn create_legacy_schematic(blocks: Vec<i8>, data: Vec<i8>, add_blocks: Option<Vec<i8>>) -> Vec<u8> {
+        let mut root = NbtCompound::new();
+        root.insert("Width", NbtTag::Short(2));
+        root.insert("Height", NbtTag::Short(1));
+        root.insert("Length", NbtTag::Short(2));
+        root.insert("Materials", NbtTag::String("Alpha".to_string()));
+        root.insert("Blocks", NbtTag::ByteArray(blocks));
+        root.insert("Data", NbtTag::ByteArray(data));
+        if let Some(add_blocks) = add_blocks {
+            root.insert("AddBlocks", NbtTag::ByteArray(add_blocks));
+        }
+
+        let mut chest = NbtCompound::new();
+        chest.insert("id", NbtTag::String("Chest".to_string()));
+        chest.insert("x", NbtTag::Int(1));
+        chest.insert("y", NbtTag::Int(0));
+        chest.insert("z", NbtTag::Int(0));
+        chest.insert("Items", NbtTag::List(NbtList::new()));
+        root.insert("TileEntities", NbtTag::List(NbtList::from(vec![NbtTag::Compound(chest)])));
+
+        let mut creeper = NbtCompound::new();
+        creeper.insert("id", NbtTag::String("Creeper".to_string()));
+        creeper.insert("Pos", NbtTag::List(NbtList::from(vec![NbtTag::Double(0.5), NbtTag::Double(1.0), NbtTag::Double(0.5)])));
+        root.insert("Entities", NbtTag::List(NbtList::from(vec![NbtTag::Compound(creeper)])));
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        quartz_nbt::io::write_nbt(&mut encoder, Some("Schematic"), &root, Flavor::Uncompressed).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn test_legacy_to_block_state() {
+        assert_eq!(legacy_to_block_state(1, 0), BlockState::new("minecraft:stone".to_string()));
+        assert_eq!(legacy_to_block_state(35, 14), BlockState::new("minecraft:red_wool".to_string()));
+
+        let stairs = legacy_to_block_state(53, 6);
+        assert_eq!(stairs.name, "minecraft:oak_stairs");
+        assert_eq!(stairs.get_property("facing"), Some(&"south".to_string()));
+        assert_eq!(stairs.get_property("half"), Some(&"top".to_string()));
+
+        // Unlisted metadata falls back to the default variant, unknown ids to air
+        assert_eq!(legacy_to_block_state(4, 7), BlockState::new("minecraft:cobblestone".to_string()));
+        assert_eq!(legacy_to_block_state(4000, 0), BlockState::new("minecraft:air".to_string()));
+    }
+
+    #[test]
+    fn test_every_legacy_block_line_parses() {
+        assert_eq!(parse_legacy_blocks(LEGACY_BLOCKS).err(), None);
+        assert_eq!(parse_legacy_blocks("1:x minecraft:stone").err(), Some("1:x minecraft:stone".to_string()));
+        assert_eq!(parse_legacy_blocks("1 minecraft:stone").err(), Some("1 minecraft:stone".to_string()));
+        assert_eq!(parse_legacy_blocks("1:0").err(), Some("1:0".to_string()));
+    }
+
+    #[test]
+    fn test_normalize_entity_id() {
+        let cases = [
+            ("MinecartChest", "minecraft:chest_minecart"),
+            ("MinecartTNT", "minecraft:tnt_minecart"),
+            ("MinecartRideable", "minecraft:minecart"),
+            ("XPOrb", "minecraft:experience_orb"),
+            ("PrimedTnt", "minecraft:tnt"),
+            ("FallingSand", "minecraft:falling_block"),
+            ("VillagerGolem", "minecraft:iron_golem"),
+            ("SnowMan", "minecraft:snow_golem"),
+            ("PigZombie", "minecraft:zombie_pigman"),
+            ("LavaSlime", "minecraft:magma_cube"),
+            ("EntityHorse", "minecraft:horse"),
+            ("Ozelot", "minecraft:ocelot"),
+            ("ArmorStand", "minecraft:armor_stand"),
+            ("Creeper", "minecraft:creeper"),
+            ("minecraft:pig", "minecraft:pig"),
+        ];
+        for (legacy, modern) in cases {
+            assert_eq!(normalize_entity_id(legacy), modern, "{}", legacy);
+        }
+    }
+
+    #[test]
+    fn test_from_mcedit() {
+        // Index order is x, then z, then y: (0,0,0) (1,0,0) (0,0,1) (1,0,1)
+        let data = create_legacy_schematic(vec![1, 54, 35, 0], vec![0, 2, 14, 0], None);
+        assert!(is_mcedit(&data));
+        assert!(!crate::schematic::is_schematic(&data));
+
+        let schematic = from_mcedit(&data).expect("Failed to parse legacy schematic");
+        assert_eq!(schematic.metadata.mc_version, Some(FLATTENING_DATA_VERSION));
+        assert_eq!(schematic.get_block(0, 0, 0), Some(&BlockState::new("minecraft:stone".to_string())));
+        assert_eq!(schematic.get_block(0, 0, 1), Some(&BlockState::new("minecraft:red_wool".to_string())));
+        assert_eq!(schematic.get_block(1, 0, 1), Some(&BlockState::new("minecraft:air".to_string())));
+        assert_eq!(schematic.get_block(1, 0, 0).unwrap().name, "minecraft:chest");
+
+        let region = schematic.get_region("Main").unwrap();
+        let chest = region.block_entities.get(&(1, 0, 0)).expect("Chest was not imported");
+        assert_eq!(chest.id, "minecraft:chest");
+        assert!(chest.nbt.get("Items").is_some());
+        assert!(chest.nbt.get("x").is_none());
+
+        assert_eq!(region.entities.len(), 1);
+        assert_eq!(region.entities[0].id, "minecraft:creeper");
+        assert_eq!(region.entities[0].position, (0.5, 1.0, 0.5));
+    }
+
+    #[test]
+    fn test_add_blocks() {
+        // 0x21 puts id 256 + 1 at index 0 and 512 + 1 at index 1, neither of which exists
+        let data = create_legacy_schematic(vec![1, 1, 1, 1], vec![0; 4], Some(vec![0x21, 0x00]));
+        let (schematic, warnings) = from_mcedit_with_warnings(&data).unwrap();
+
+        assert_eq!(schematic.get_block(0, 0, 0), Some(&BlockState::new("minecraft:air".to_string())));
+        assert_eq!(schematic.get_block(1, 0, 0), Some(&BlockState::new("minecraft:air".to_string())));
+        assert_eq!(warnings, vec![
+            Warning::UnknownBlock { name: "257:0".to_string(), position: (0, 0, 0) },
+            Warning::UnknownBlock { name: "513:0".to_string(), position: (1, 0, 0) },
+        ]);
+        assert_eq!(schematic.get_block(0, 0, 1), Some(&BlockState::new("minecraft:stone".to_string())));
+    }
+}
@@ -0,0 +1,344 @@
+use serde_json::json;
+use crate::UniversalSchematic;
+use crate::block_entity::BlockEntity;
+use crate::entity::Entity;
+use crate::region::Region;
+use crate::utils::zip::ZipWriter;
+use crate::error::SchematicError;
+
+// `fill` refuses to touch more blocks than this in one command.
+const MAX_FILL_VOLUME: i32 = 32768;
+
+// Datapacks with this pack format or newer keep functions in `function` instead of `functions`.
+const SINGULAR_FOLDERS_PACK_FORMAT: i32 = 45;
+
+const STRUCTURE_VOID: &str = "minecraft:structure_void";
+const MARKER: &str = "minecraft:marker";
+
+#[derive(Debug, Clone)]
+pub struct McFunctionOptions {
+    /// World position of the schematic's minimum corner. When unset, commands are
+    /// relative to wherever the function is run.
+    pub origin: Option<(i32, i32, i32)>,
+    /// Emit commands for air too, clearing whatever was there before.
+    pub include_air: bool,
+    /// Commands per function before the rest moves into the next one, which is
+    /// scheduled a tick later to stay under `maxCommandChainLength`.
+    pub max_commands_per_function: usize,
+    pub namespace: String,
+    pub function_name: String,
+    pub pack_format: i32,
+    pub description: String,
+}
+
+impl Default for McFunctionOptions {
+    fn default() -> Self {
+        McFunctionOptions {
+            origin: None,
+            include_air: false,
+            max_commands_per_function: 10000,
+            namespace: "schematic".to_string(),
+            function_name: "build".to_string(),
+            pack_format: 26,
+            description: "Generated schematic".to_string(),
+        }
+    }
+}
+
+/// Builds the functions that place the schematic, as `(path, contents)` pairs where the
+/// path is relative to the namespace. Run `<namespace>:<function_name>` to start the build.
+pub fn to_mcfunctions(schematic: &UniversalSchematic, options: &McFunctionOptions) -> Result<Vec<(String, String)>, SchematicError> {
+    validate_options(options)?;
+
+    let commands = build_commands(schematic, options);
+    let name = &options.function_name;
+    if commands.len() <= options.max_commands_per_function {
+        return Ok(vec![(name.clone(), join_commands(&commands))]);
+    }
+
+    // One command of every part is spent on scheduling the next one.
+    let parts: Vec<&[String]> = commands.chunks(options.max_commands_per_function - 1).collect();
+    let function_id = |path: String| format!("{}:{}/{}", options.namespace, name, path);
+    let relative = options.origin.is_none();
+    // Scheduled functions run at the world spawn, so relative builds leave a marker
+    // behind and resume at it.
+    let tag = format!("{}.{}", options.namespace, name.replace('/', "."));
+    let marker = format!("@e[type={},tag={},limit=1]", MARKER, tag);
+
+    let mut main = Vec::new();
+    if relative {
+        main.push(format!("summon {} ~ ~ ~ {{Tags:[\"{}\"]}}", MARKER, tag));
+    }
+    main.push(format!("function {}", function_id("part_0".to_string())));
+
+    let mut functions = vec![(name.clone(), join_commands(&main))];
+    for (index, part) in parts.iter().enumerate() {
+        let mut commands = part.to_vec();
+        let next = index + 1;
+        if next < parts.len() {
+            let next_function = if relative { format!("resume_{}", next) } else { format!("part_{}", next) };
+            commands.push(format!("schedule function {} 1t", function_id(next_function)));
+        } else if relative {
+            commands.push(format!("kill @e[type={},tag={}]", MARKER, tag));
+        }
+        functions.push((format!("{}/part_{}", name, index), join_commands(&commands)));
+
+        if relative && index > 0 {
+            let resume = format!("execute at {} run function {}", marker, function_id(format!("part_{}", index)));
+            functions.push((format!("{}/resume_{}", name, index), join_commands(&[resume])));
+        }
+    }
+    Ok(functions)
+}
+
+/// Packages the functions from [`to_mcfunctions`] into a datapack zip.
+pub fn to_datapack(schematic: &UniversalSchematic, options: &McFunctionOptions) -> Result<Vec<u8>, SchematicError> {
+    let functions = to_mcfunctions(schematic, options)?;
+    let folder = if options.pack_format >= SINGULAR_FOLDERS_PACK_FORMAT { "function" } else { "functions" };
+
+    let pack_mcmeta = json!({
+        "pack": {
+            "pack_format": options.pack_format,
+            "description": options.description,
+        }
+    });
+
+    let mut zip = ZipWriter::new();
+    zip.add_file("pack.mcmeta", serde_json::to_string_pretty(&pack_mcmeta).map_err(|e| e.to_string())?.as_bytes())?;
+    for (path, contents) in functions {
+        zip.add_file(&format!("data/{}/{}/{}.mcfunction", options.namespace, folder, path), contents.as_bytes())?;
+    }
+    Ok(zip.finish())
+}
+
+fn validate_options(options: &McFunctionOptions) -> Result<(), String> {
+    let valid_path = |value: &str, allow_slash: bool| {
+        !value.is_empty() && value.chars().all(|c| {
+            c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '-' || c == '.' || (allow_slash && c == '/')
+        })
+    };
+    if !valid_path(&options.namespace, false) {
+        return Err(format!("Invalid namespace '{}'", options.namespace));
+    }
+    if !valid_path(&options.function_name, true) {
+        return Err(format!("Invalid function name '{}'", options.function_name));
+    }
+    if options.max_commands_per_function < 2 {
+        return Err("Functions need room for at least two commands".to_string());
+    }
+    Ok(())
+}
+
+fn join_commands(commands: &[String]) -> String {
+    let mut contents = commands.join("\n");
+    contents.push('\n');
+    contents
+}
+
+fn build_commands(schematic: &UniversalSchematic, options: &McFunctionOptions) -> Vec<String> {
+    let merged_region = schematic.get_merged_region();
+    let bounding_box = schematic.get_bounding_box();
+    let offset = |(x, y, z): (i32, i32, i32)| (x - bounding_box.min.0, y - bounding_box.min.1, z - bounding_box.min.2);
+
+    let mut commands = Vec::new();
+    for (min, max) in merge_runs(&merged_region, options.include_air) {
+        let block = merged_region.get_block(min.0, min.1, min.2).unwrap();
+        let mut state = block.to_string();
+        if let Some(block_entity) = merged_region.block_entities.get(&min) {
+            state.push_str(&block_entity_snbt(block_entity));
+        }
+
+        if min == max {
+            commands.push(format!("setblock {} {}", format_position(options, offset(min)), state));
+        } else {
+            commands.push(format!("fill {} {} {}", format_position(options, offset(min)), format_position(options, offset(max)), state));
+        }
+    }
+
+    for entity in &merged_region.entities {
+        let position = (
+            entity.position.0 - bounding_box.min.0 as f64,
+            entity.position.1 - bounding_box.min.1 as f64,
+            entity.position.2 - bounding_box.min.2 as f64,
+        );
+        let position = match options.origin {
+            Some(origin) => format!("{} {} {}", origin.0 as f64 + position.0, origin.1 as f64 + position.1, origin.2 as f64 + position.2),
+            None => format!("~{} ~{} ~{}", position.0, position.1, position.2),
+        };
+        let nbt = entity_snbt(entity);
+        commands.push(format!("summon {} {}{}", entity.id, position, nbt).trim_end().to_string());
+    }
+
+    commands
+}
+
+fn format_position(options: &McFunctionOptions, (x, y, z): (i32, i32, i32)) -> String {
+    match options.origin {
+        Some(origin) => format!("{} {} {}", origin.0 + x, origin.1 + y, origin.2 + z),
+        None => format!("~{} ~{} ~{}", x, y, z),
+    }
+}
+
+// Greedily grows boxes of identical blocks along x, then z, then y, bottom layer first so
+// blocks that need support are placed after what holds them up. Block entities always get
+// their own `setblock`.
+fn merge_runs(region: &Region, include_air: bool) -> Vec<((i32, i32, i32), (i32, i32, i32))> {
+    let bounding_box = region.get_bounding_box();
+    let (width, height, length) = bounding_box.get_dimensions();
+    let min = bounding_box.min;
+    let index = |x: i32, y: i32, z: i32| (x + z * width + y * width * length) as usize;
+
+    let skipped: Vec<bool> = region.palette.iter()
+        .map(|block| block.name == STRUCTURE_VOID || (!include_air && block.name == "minecraft:air"))
+        .collect();
+    let has_block_entity = |x: i32, y: i32, z: i32| region.block_entities.contains_key(&(min.0 + x, min.1 + y, min.2 + z));
+
+    let blocks: Vec<usize> = region.iter_block_indices().collect();
+    let mut visited = vec![false; blocks.len()];
+    let mut runs = Vec::new();
+    for y in 0..height {
+        for z in 0..length {
+            for x in 0..width {
+                let palette_index = blocks[index(x, y, z)];
+                if visited[index(x, y, z)] || skipped[palette_index] {
+                    continue;
+                }
+                let mergeable = |x: i32, y: i32, z: i32| {
+                    !visited[index(x, y, z)] && blocks[index(x, y, z)] == palette_index && !has_block_entity(x, y, z)
+                };
+
+                let (mut x2, mut y2, mut z2) = (x, y, z);
+                if !has_block_entity(x, y, z) {
+                    while x2 + 1 < width && x2 + 2 - x <= MAX_FILL_VOLUME && mergeable(x2 + 1, y, z) {
+                        x2 += 1;
+                    }
+                    let row = x2 - x + 1;
+                    while z2 + 1 < length && row * (z2 + 2 - z) <= MAX_FILL_VOLUME
+                        && (x..=x2).all(|xi| mergeable(xi, y, z2 + 1)) {
+                        z2 += 1;
+                    }
+                    let layer = row * (z2 - z + 1);
+                    while y2 + 1 < height && layer * (y2 + 2 - y) <= MAX_FILL_VOLUME
+                        && (z..=z2).all(|zi| (x..=x2).all(|xi| mergeable(xi, y2 + 1, zi))) {
+                        y2 += 1;
+                    }
+                }
+
+                for yi in y..=y2 {
+                    for zi in z..=z2 {
+                        for xi in x..=x2 {
+                            visited[index(xi, yi, zi)] = true;
+                        }
+                    }
+                }
+                runs.push(((min.0 + x, min.1 + y, min.2 + z), (min.0 + x2, min.1 + y2, min.2 + z2)));
+            }
+        }
+    }
+    runs
+}
+
+fn block_entity_snbt(block_entity: &BlockEntity) -> String {
+    let mut nbt = block_entity.nbt.clone();
+    nbt.remove("Id");
+    nbt.remove("Pos");
+    if nbt.is_empty() {
+        String::new()
+    } else {
+        nbt.to_snbt()
+    }
+}
+
+fn entity_snbt(entity: &Entity) -> String {
+    if entity.nbt.is_empty() {
+        return String::new();
+    }
+    format!(" {}", entity.nbt.to_snbt())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BlockState;
+    use crate::item::ItemStack;
+    use crate::utils::NbtValue;
+    use crate::utils::zip::read_zip_entries;
+
+    #[test]
+    fn test_fill_merging() {
+        let mut schematic = UniversalSchematic::new("Fill".to_string());
+        let stone = BlockState::new("minecraft:stone".to_string());
+        for x in 0..4 {
+            for y in 0..2 {
+                for z in 0..3 {
+                    schematic.set_block(x, y, z, stone.clone());
+                }
+            }
+        }
+        schematic.set_block(0, 2, 0, BlockState::new("minecraft:glass".to_string()));
+        schematic.set_block(3, 2, 2, BlockState::new("minecraft:glass".to_string()));
+
+        let functions = to_mcfunctions(&schematic, &McFunctionOptions::default()).unwrap();
+        assert_eq!(functions.len(), 1);
+        assert_eq!(functions[0].0, "build");
+        let commands: Vec<&str> = functions[0].1.lines().collect();
+        assert_eq!(commands, vec![
+            "fill ~0 ~0 ~0 ~3 ~1 ~2 minecraft:stone",
+            "setblock ~0 ~2 ~0 minecraft:glass",
+            "setblock ~3 ~2 ~2 minecraft:glass",
+        ]);
+
+        let options = McFunctionOptions { origin: Some((100, 64, -10)), include_air: true, ..Default::default() };
+        let functions = to_mcfunctions(&schematic, &options).unwrap();
+        assert!(functions[0].1.starts_with("fill 100 64 -10 103 65 -8 minecraft:stone\n"));
+        assert!(functions[0].1.contains("minecraft:air"));
+    }
+
+    #[test]
+    fn test_block_entities_and_entities() {
+        let mut schematic = UniversalSchematic::new("Chest".to_string());
+        schematic.set_block(0, 0, 0, BlockState::new("minecraft:chest".to_string()));
+        schematic.set_block(1, 0, 0, BlockState::new("minecraft:chest".to_string()));
+        schematic.add_block_entity(BlockEntity::create_chest((0, 0, 0), vec![ItemStack::new("minecraft:diamond", 64).with_slot(0)]));
+        schematic.add_entity(Entity::new("minecraft:armor_stand".to_string(), (0.5, 1.0, 0.5))
+            .with_nbt_data("CustomName".to_string(), NbtValue::String("Stand".to_string())));
+
+        let functions = to_mcfunctions(&schematic, &McFunctionOptions::default()).unwrap();
+        let commands: Vec<&str> = functions[0].1.lines().collect();
+        assert_eq!(commands.len(), 3);
+        assert!(commands[0].starts_with("setblock ~0 ~0 ~0 minecraft:chest{"));
+        assert!(commands[0].contains("minecraft:diamond"));
+        assert!(!commands[0].contains("Pos"));
+        assert_eq!(commands[1], "setblock ~1 ~0 ~0 minecraft:chest");
+        assert!(commands[2].starts_with("summon minecraft:armor_stand ~0.5 ~1 ~0.5 {CustomName:"));
+    }
+
+    #[test]
+    fn test_split_into_datapack() {
+        let mut schematic = UniversalSchematic::new("Split".to_string());
+        for x in 0..10 {
+            let name = if x % 2 == 0 { "minecraft:stone" } else { "minecraft:dirt" };
+            schematic.set_block(x, 0, 0, BlockState::new(name.to_string()));
+        }
+
+        let options = McFunctionOptions { max_commands_per_function: 5, ..Default::default() };
+        let functions = to_mcfunctions(&schematic, &options).unwrap();
+        let names: Vec<&str> = functions.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["build", "build/part_0", "build/part_1", "build/resume_1", "build/part_2", "build/resume_2"]);
+        assert!(functions[0].1.starts_with("summon minecraft:marker ~ ~ ~"));
+        assert!(functions[1].1.ends_with("schedule function schematic:build/resume_1 1t\n"));
+        assert_eq!(functions[1].1.lines().count(), 5);
+        assert!(functions[4].1.ends_with("kill @e[type=minecraft:marker,tag=schematic.build]\n"));
+
+        let datapack = to_datapack(&schematic, &options).unwrap();
+        let entries = read_zip_entries(&datapack);
+        assert_eq!(entries[0].0, "pack.mcmeta");
+        let pack: serde_json::Value = serde_json::from_slice(&entries[0].1).unwrap();
+        assert_eq!(pack["pack"]["pack_format"], 26);
+        assert_eq!(entries[1].0, "data/schematic/functions/build.mcfunction");
+        assert_eq!(entries.len(), 7);
+
+        let invalid = McFunctionOptions { namespace: "Not Valid".to_string(), ..Default::default() };
+        assert!(to_datapack(&schematic, &invalid).is_err());
+    }
+}
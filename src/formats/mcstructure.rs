@@ -0,0 +1,638 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::OnceLock;
+use quartz_nbt::{NbtCompound, NbtList, NbtTag};
+use crate::{UniversalSchematic, BlockState};
+use crate::block_entity::BlockEntity;
+use crate::entity::Entity;
+use crate::formats::mcedit::to_snake_case;
+use crate::formats::schematic::parse_block_state;
+use crate::region::Region;
+use crate::utils::le_nbt::{read_le_nbt, write_le_nbt};
+use crate::error::SchematicError;
+
+// See the header of the file for the rule syntax.
+const BEDROCK_TRANSLATIONS: &str = include_str!("mcstructure_blocks.txt");
+
+// Block state version written into the palette, Bedrock 1.20.80.
+const BEDROCK_BLOCK_VERSION: i32 = 18108416;
+
+const STRUCTURE_VOID: &str = "minecraft:structure_void";
+const WATER: &str = "minecraft:water";
+const WILDCARD: &str = "*";
+
+/// Converts block states between Java and Bedrock naming. Implement this to plug in
+/// a more complete mapping than the built in [`BlockTranslationTable`].
+pub trait BlockTranslator {
+    fn java_to_bedrock(&self, block: &BlockState) -> BlockState;
+    fn bedrock_to_java(&self, block: &BlockState) -> BlockState;
+}
+
+#[derive(Debug, Clone)]
+struct StateRule {
+    java: BlockState,
+    bedrock: BlockState,
+}
+
+impl StateRule {
+    fn renames(&self) -> bool {
+        self.java.name != self.bedrock.name
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BlockTranslationTable {
+    java_names: HashMap<String, String>,
+    bedrock_names: HashMap<String, String>,
+    state_rules: Vec<StateRule>,
+    rules_by_java: HashMap<String, Vec<usize>>,
+    renaming_rules_by_bedrock: HashMap<String, Vec<usize>>,
+    java_properties: HashMap<(String, String), String>,
+    bedrock_properties: HashMap<(String, String), String>,
+    dropped: HashSet<(String, String)>,
+}
+
+impl BlockTranslationTable {
+    /// Parses a rule file in the format of the embedded `mcstructure_blocks.txt`.
+    pub fn parse(rules: &str) -> Result<Self, String> {
+        let mut table = BlockTranslationTable::default();
+        for (line_number, line) in rules.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            match parts.as_slice() {
+                ["block", java, bedrock] => table.add_block_rename(java, bedrock),
+                ["state", java, bedrock] => table.add_state_rule(parse_block_state(java), parse_block_state(bedrock)),
+                ["property", block, java, bedrock] => table.add_property_rename(block, java, bedrock),
+                ["drop", block, property] => table.add_dropped_property(block, property),
+                _ => return Err(format!("Invalid translation rule on line {}: {}", line_number + 1, line)),
+            }
+        }
+        Ok(table)
+    }
+
+    pub fn add_block_rename(&mut self, java: &str, bedrock: &str) {
+        self.java_names.insert(java.to_string(), bedrock.to_string());
+        self.bedrock_names.insert(bedrock.to_string(), java.to_string());
+    }
+
+    /// Maps the listed Java properties to the listed Bedrock ones. When the names
+    /// differ, matching Java states are also renamed.
+    pub fn add_state_rule(&mut self, java: BlockState, bedrock: BlockState) {
+        let index = self.state_rules.len();
+        let rule = StateRule { java, bedrock };
+        if rule.renames() {
+            self.renaming_rules_by_bedrock.entry(rule.bedrock.name.clone()).or_default().push(index);
+        }
+        self.rules_by_java.entry(rule.java.name.clone()).or_default().push(index);
+        self.state_rules.push(rule);
+    }
+
+    /// Renames a property of `block`, or of every block when `block` is `*`.
+    pub fn add_property_rename(&mut self, block: &str, java: &str, bedrock: &str) {
+        self.java_properties.insert((block.to_string(), java.to_string()), bedrock.to_string());
+        self.bedrock_properties.insert((block.to_string(), bedrock.to_string()), java.to_string());
+    }
+
+    pub fn add_dropped_property(&mut self, block: &str, property: &str) {
+        self.dropped.insert((block.to_string(), property.to_string()));
+    }
+
+    fn scoped<'a, V>(map: &'a HashMap<(String, String), V>, block: &str, property: &str) -> Option<&'a V> {
+        map.get(&(block.to_string(), property.to_string()))
+            .or_else(|| map.get(&(WILDCARD.to_string(), property.to_string())))
+    }
+
+    fn is_dropped(&self, block: &str, property: &str) -> bool {
+        self.dropped.contains(&(block.to_string(), property.to_string()))
+            || self.dropped.contains(&(WILDCARD.to_string(), property.to_string()))
+    }
+}
+
+// Rules only consume properties that are still unmatched, so the first rule wins when
+// several Java values collapse onto the same Bedrock value.
+fn matches(remaining: &BTreeMap<String, String>, subset: &BTreeMap<String, String>) -> bool {
+    subset.iter().all(|(key, value)| remaining.get(key) == Some(value))
+}
+
+impl BlockTranslator for BlockTranslationTable {
+    fn java_to_bedrock(&self, block: &BlockState) -> BlockState {
+        let mut remaining = block.properties.clone();
+        remaining.retain(|key, _| !self.is_dropped(&block.name, key));
+
+        let mut name = None;
+        let mut properties = BTreeMap::new();
+        for &index in self.rules_by_java.get(&block.name).into_iter().flatten() {
+            let rule = &self.state_rules[index];
+            if !matches(&remaining, &rule.java.properties) {
+                continue;
+            }
+            for key in rule.java.properties.keys() {
+                remaining.remove(key);
+            }
+            properties.extend(rule.bedrock.properties.clone());
+            if rule.renames() {
+                name = Some(rule.bedrock.name.clone());
+            }
+        }
+
+        for (key, value) in remaining {
+            let key = Self::scoped(&self.java_properties, &block.name, &key).cloned().unwrap_or(key);
+            properties.entry(key).or_insert(value);
+        }
+
+        let name = name
+            .or_else(|| self.java_names.get(&block.name).cloned())
+            .unwrap_or_else(|| block.name.clone());
+        BlockState { name, properties }
+    }
+
+    fn bedrock_to_java(&self, block: &BlockState) -> BlockState {
+        let mut remaining = block.properties.clone();
+        let mut properties = BTreeMap::new();
+
+        // The most specific rule that could have produced this name decides the Java block.
+        let candidates = self.renaming_rules_by_bedrock.get(&block.name).into_iter().flatten()
+            .chain(self.rules_by_java.get(&block.name).into_iter().flatten())
+            .map(|&index| &self.state_rules[index])
+            .filter(|rule| matches(&remaining, &rule.bedrock.properties));
+        let mut best: Option<&StateRule> = None;
+        for rule in candidates {
+            if best.map_or(true, |b| rule.bedrock.properties.len() > b.bedrock.properties.len()) {
+                best = Some(rule);
+            }
+        }
+
+        let name = match best {
+            Some(rule) if rule.renames() => {
+                for key in rule.bedrock.properties.keys() {
+                    remaining.remove(key);
+                }
+                properties.extend(rule.java.properties.clone());
+                rule.java.name.clone()
+            }
+            Some(_) => block.name.clone(),
+            None => self.bedrock_names.get(&block.name).cloned().unwrap_or_else(|| block.name.clone()),
+        };
+
+        for &index in self.rules_by_java.get(&name).into_iter().flatten() {
+            let rule = &self.state_rules[index];
+            if rule.renames()
+                || rule.java.properties.keys().any(|key| properties.contains_key(key))
+                || !matches(&remaining, &rule.bedrock.properties) {
+                continue;
+            }
+            for key in rule.bedrock.properties.keys() {
+                remaining.remove(key);
+            }
+            properties.extend(rule.java.properties.clone());
+        }
+
+        for (key, value) in remaining {
+            let key = Self::scoped(&self.bedrock_properties, &name, &key).cloned().unwrap_or(key);
+            properties.entry(key).or_insert(value);
+        }
+
+        BlockState { name, properties }
+    }
+}
+
+/// The translation table shipped with the crate.
+pub fn default_translator() -> &'static BlockTranslationTable {
+    static TABLE: OnceLock<BlockTranslationTable> = OnceLock::new();
+    TABLE.get_or_init(|| BlockTranslationTable::parse(BEDROCK_TRANSLATIONS)
+        .expect("Embedded Bedrock translation table is invalid"))
+}
+
+pub fn is_mcstructure(data: &[u8]) -> bool {
+    let (root, _) = match read_le_nbt(data) {
+        Ok(result) => result,
+        Err(_) => return false,
+    };
+
+    root.get::<_, i32>("format_version").is_ok() &&
+        root.get::<_, &NbtList>("size").is_ok() &&
+        root.get::<_, &NbtCompound>("structure").is_ok()
+}
+
+pub fn to_mcstructure(schematic: &UniversalSchematic) -> Result<Vec<u8>, SchematicError> {
+    to_mcstructure_with_translator(schematic, default_translator())
+}
+
+pub fn to_mcstructure_with_translator(schematic: &UniversalSchematic, translator: &dyn BlockTranslator) -> Result<Vec<u8>, SchematicError> {
+    let bounding_box = schematic.get_bounding_box();
+    let (width, height, length) = bounding_box.get_dimensions();
+    let origin = bounding_box.min;
+    let merged_region = schematic.get_merged_region();
+
+    let mut palette = NbtList::new();
+    let mut palette_lookup: HashMap<BlockState, i32> = HashMap::new();
+    let mut add_to_palette = |block: BlockState| -> i32 {
+        if let Some(&index) = palette_lookup.get(&block) {
+            return index;
+        }
+        let index = palette.len() as i32;
+        palette.push(NbtTag::Compound(convert_palette_entry(&block)));
+        palette_lookup.insert(block, index);
+        index
+    };
+
+    // Waterlogging is a Java property, Bedrock stores the water in a second layer instead.
+    let mut layers = Vec::with_capacity(merged_region.palette.len());
+    for block in &merged_region.palette {
+        let primary = if block.name == STRUCTURE_VOID {
+            -1
+        } else {
+            add_to_palette(translator.java_to_bedrock(block))
+        };
+        let secondary = if block.get_property("waterlogged").map(String::as_str) == Some("true") {
+            add_to_palette(translator.java_to_bedrock(&BlockState::new(WATER.to_string())
+                .with_property("level".to_string(), "0".to_string())))
+        } else {
+            -1
+        };
+        layers.push((primary, secondary));
+    }
+
+    let volume = (width * height * length) as usize;
+    let mut primary_layer = vec![-1; volume];
+    let mut secondary_layer = vec![-1; volume];
+    let mut block_position_data = NbtCompound::new();
+    for (index, palette_index) in merged_region.iter_block_indices().enumerate() {
+        let (x, y, z) = merged_region.index_to_coords(index);
+        let structure_index = bedrock_index((x - origin.0, y - origin.1, z - origin.2), (height, length));
+        let (primary, secondary) = layers[palette_index];
+        primary_layer[structure_index] = primary;
+        secondary_layer[structure_index] = secondary;
+
+        if let Some(block_entity) = merged_region.block_entities.get(&(x, y, z)) {
+            let mut data = NbtCompound::new();
+            data.insert("block_entity_data", NbtTag::Compound(convert_block_entity(block_entity, (x, y, z))));
+            block_position_data.insert(structure_index.to_string(), NbtTag::Compound(data));
+        }
+    }
+
+    let mut default_palette = NbtCompound::new();
+    default_palette.insert("block_palette", NbtTag::List(palette));
+    default_palette.insert("block_position_data", NbtTag::Compound(block_position_data));
+    let mut palettes = NbtCompound::new();
+    palettes.insert("default", NbtTag::Compound(default_palette));
+
+    let block_indices = NbtList::from(vec![
+        NbtTag::List(NbtList::from(primary_layer.into_iter().map(NbtTag::Int).collect::<Vec<_>>())),
+        NbtTag::List(NbtList::from(secondary_layer.into_iter().map(NbtTag::Int).collect::<Vec<_>>())),
+    ]);
+
+    let entities = NbtList::from(merged_region.entities.iter().map(convert_entity).collect::<Vec<_>>());
+
+    let mut structure = NbtCompound::new();
+    structure.insert("block_indices", NbtTag::List(block_indices));
+    structure.insert("entities", NbtTag::List(entities));
+    structure.insert("palette", NbtTag::Compound(palettes));
+
+    let mut root = NbtCompound::new();
+    root.insert("format_version", NbtTag::Int(1));
+    root.insert("size", int_list(&[width, height, length]));
+    root.insert("structure", NbtTag::Compound(structure));
+    root.insert("structure_world_origin", int_list(&[origin.0, origin.1, origin.2]));
+
+    Ok(write_le_nbt("", &root))
+}
+
+pub fn from_mcstructure(data: &[u8]) -> Result<UniversalSchematic, SchematicError> {
+    from_mcstructure_with_translator(data, default_translator())
+}
+
+pub fn from_mcstructure_with_translator(data: &[u8], translator: &dyn BlockTranslator) -> Result<UniversalSchematic, SchematicError> {
+    let (root, _) = read_le_nbt(data)?;
+
+    let size = read_int_triple(root.get::<_, &NbtList>("size")?)?;
+    if size.0 < 0 || size.1 < 0 || size.2 < 0 {
+        return Err("Invalid structure size".into());
+    }
+    let origin = match root.get::<_, &NbtList>("structure_world_origin") {
+        Ok(list) => read_int_triple(list)?,
+        Err(_) => (0, 0, 0),
+    };
+
+    let structure = root.get::<_, &NbtCompound>("structure")?;
+    let default_palette = structure.get::<_, &NbtCompound>("palette")?
+        .get::<_, &NbtCompound>("default")?;
+
+    let mut palette = Vec::new();
+    for tag in default_palette.get::<_, &NbtList>("block_palette")?.iter() {
+        if let NbtTag::Compound(compound) = tag {
+            palette.push(translator.bedrock_to_java(&parse_palette_entry(compound)?));
+        }
+    }
+
+    let layers = structure.get::<_, &NbtList>("block_indices")?;
+    let primary_layer = read_layer(layers, 0)?;
+    let secondary_layer = if layers.len() > 1 { read_layer(layers, 1)? } else { Vec::new() };
+
+    let volume = (size.0 as usize) * (size.1 as usize) * (size.2 as usize);
+    if primary_layer.len() != volume {
+        return Err(format!("Expected {} block indices, found {}", volume, primary_layer.len()).into());
+    }
+
+    let mut region = Region::new("Main".to_string(), (0, 0, 0), size);
+    let mut state_cache: HashMap<(i32, i32), usize> = HashMap::new();
+
+    for (structure_index, &primary) in primary_layer.iter().enumerate() {
+        let secondary = secondary_layer.get(structure_index).copied().unwrap_or(-1);
+        let region_index = match state_cache.get(&(primary, secondary)) {
+            Some(&index) => index,
+            None => {
+                let mut block = if primary < 0 {
+                    BlockState::new(STRUCTURE_VOID.to_string())
+                } else {
+                    palette.get(primary as usize).cloned()
+                        .ok_or_else(|| format!("Palette index {} out of range", primary))?
+                };
+                let waterlogged = secondary >= 0 && palette.get(secondary as usize)
+                    .map_or(false, |water| water.name == WATER);
+                if waterlogged && block.name != WATER {
+                    block.set_property("waterlogged".to_string(), "true".to_string());
+                }
+                let index = region.get_or_insert_in_palette(block);
+                state_cache.insert((primary, secondary), index);
+                index
+            }
+        };
+        let (x, y, z) = structure_coords(structure_index, size);
+        region.set_block_index(x, y, z, region_index);
+    }
+
+    if let Ok(position_data) = default_palette.get::<_, &NbtCompound>("block_position_data") {
+        for (key, tag) in position_data.inner() {
+            let structure_index: usize = match key.parse() {
+                Ok(index) if index < volume => index,
+                _ => continue,
+            };
+            if let NbtTag::Compound(data) = tag {
+                if let Ok(nbt) = data.get::<_, &NbtCompound>("block_entity_data") {
+                    region.add_block_entity(parse_block_entity(nbt, structure_coords(structure_index, size)));
+                }
+            }
+        }
+    }
+
+    if let Ok(entities) = structure.get::<_, &NbtList>("entities") {
+        for tag in entities.iter() {
+            if let NbtTag::Compound(compound) = tag {
+                if let Some(entity) = parse_entity(compound, origin) {
+                    region.add_entity(entity);
+                }
+            }
+        }
+    }
+
+    let mut schematic = UniversalSchematic::new("Unnamed".to_string());
+    schematic.add_region(region);
+    Ok(schematic)
+}
+
+// Bedrock orders blocks with z varying fastest, then y, then x.
+fn bedrock_index(position: (i32, i32, i32), (height, length): (i32, i32)) -> usize {
+    ((position.0 * height + position.1) * length + position.2) as usize
+}
+
+fn structure_coords(index: usize, size: (i32, i32, i32)) -> (i32, i32, i32) {
+    let index = index as i32;
+    let z = index % size.2;
+    let y = (index / size.2) % size.1;
+    let x = index / (size.1 * size.2);
+    (x, y, z)
+}
+
+fn int_list(values: &[i32]) -> NbtTag {
+    NbtTag::List(NbtList::from(values.iter().map(|&v| NbtTag::Int(v)).collect::<Vec<_>>()))
+}
+
+fn read_int_triple(list: &NbtList) -> Result<(i32, i32, i32), SchematicError> {
+    if list.len() != 3 {
+        return Err("Expected a list of three integers".into());
+    }
+    Ok((list.get::<i32>(0)?, list.get::<i32>(1)?, list.get::<i32>(2)?))
+}
+
+fn read_layer(layers: &NbtList, index: usize) -> Result<Vec<i32>, SchematicError> {
+    let layer = layers.get::<&NbtList>(index)?;
+    let mut indices = Vec::with_capacity(layer.len());
+    for tag in layer.iter() {
+        match tag {
+            NbtTag::Int(value) => indices.push(*value),
+            _ => return Err("Block indices must be integers".into()),
+        }
+    }
+    Ok(indices)
+}
+
+fn convert_palette_entry(block: &BlockState) -> NbtCompound {
+    let mut states = NbtCompound::new();
+    for (key, value) in &block.properties {
+        let tag = match value.as_str() {
+            "true" => NbtTag::Byte(1),
+            "false" => NbtTag::Byte(0),
+            other => match other.parse::<i32>() {
+                Ok(number) => NbtTag::Int(number),
+                Err(_) => NbtTag::String(other.to_string()),
+            },
+        };
+        states.insert(key, tag);
+    }
+
+    let mut entry = NbtCompound::new();
+    entry.insert("name", NbtTag::String(block.name.clone()));
+    entry.insert("states", NbtTag::Compound(states));
+    entry.insert("version", NbtTag::Int(BEDROCK_BLOCK_VERSION));
+    entry
+}
+
+fn parse_palette_entry(entry: &NbtCompound) -> Result<BlockState, SchematicError> {
+    let mut block = BlockState::new(entry.get::<_, &str>("name")?.to_string());
+    if let Ok(states) = entry.get::<_, &NbtCompound>("states") {
+        for (key, tag) in states.inner() {
+            let value = match tag {
+                NbtTag::Byte(b) => (if *b != 0 { "true" } else { "false" }).to_string(),
+                NbtTag::Short(v) => v.to_string(),
+                NbtTag::Int(v) => v.to_string(),
+                NbtTag::Long(v) => v.to_string(),
+                NbtTag::String(s) => s.clone(),
+                _ => continue,
+            };
+            block.set_property(key.clone(), value);
+        }
+    }
+    Ok(block)
+}
+
+// Bedrock block entity ids are CamelCase without a namespace.
+fn java_to_bedrock_block_entity_id(id: &str) -> String {
+    let name = id.strip_prefix("minecraft:").unwrap_or(id);
+    match name {
+        "spawner" | "mob_spawner" => "MobSpawner".to_string(),
+        "note_block" => "Music".to_string(),
+        "enchanting_table" => "EnchantTable".to_string(),
+        "daylight_detector" => "DaylightDetector".to_string(),
+        "sticky_piston" => "PistonArm".to_string(),
+        "piston" => "PistonArm".to_string(),
+        "command_block" => "CommandBlock".to_string(),
+        other => other.split('_')
+            .map(|part| {
+                let mut chars = part.chars();
+                match chars.next() {
+                    Some(first) => first.to_ascii_uppercase().to_string() + chars.as_str(),
+                    None => String::new(),
+                }
+            })
+            .collect(),
+    }
+}
+
+fn bedrock_to_java_block_entity_id(id: &str) -> String {
+    let name = match id {
+        "MobSpawner" => "spawner".to_string(),
+        "Music" => "note_block".to_string(),
+        "EnchantTable" => "enchanting_table".to_string(),
+        "PistonArm" => "piston".to_string(),
+        other => to_snake_case(other),
+    };
+    format!("minecraft:{}", name)
+}
+
+fn convert_block_entity(block_entity: &BlockEntity, position: (i32, i32, i32)) -> NbtCompound {
+    let mut nbt = block_entity.to_format_nbt((block_entity.position.0 - position.0, block_entity.position.1 - position.1, block_entity.position.2 - position.2));
+    nbt.insert("id", NbtTag::String(java_to_bedrock_block_entity_id(&block_entity.id)));
+    nbt
+}
+
+fn parse_block_entity(nbt: &NbtCompound, position: (i32, i32, i32)) -> BlockEntity {
+    let mut block_entity = BlockEntity::from_format_nbt(nbt, (0, 0, 0));
+    block_entity.move_to(position);
+    match nbt.get::<_, &str>("id") {
+        Ok(id) => block_entity.with_id(bedrock_to_java_block_entity_id(id)),
+        Err(_) => block_entity,
+    }
+}
+
+// Bedrock stores entity positions in world space as floats.
+fn convert_entity(entity: &Entity) -> NbtTag {
+    let mut nbt = entity.nbt.to_quartz_nbt();
+    nbt.insert("identifier", NbtTag::String(entity.id.clone()));
+    nbt.insert("Pos", NbtTag::List(NbtList::from(vec![
+        NbtTag::Float(entity.position.0 as f32),
+        NbtTag::Float(entity.position.1 as f32),
+        NbtTag::Float(entity.position.2 as f32),
+    ])));
+    NbtTag::Compound(nbt)
+}
+
+fn parse_entity(compound: &NbtCompound, origin: (i32, i32, i32)) -> Option<Entity> {
+    let id = compound.get::<_, &str>("identifier").ok()?.to_string();
+    let pos = compound.get::<_, &NbtList>("Pos").ok()?;
+    if pos.len() != 3 {
+        return None;
+    }
+    let position = (
+        pos.get::<f32>(0).ok()? as f64 - origin.0 as f64,
+        pos.get::<f32>(1).ok()? as f64 - origin.1 as f64,
+        pos.get::<f32>(2).ok()? as f64 - origin.2 as f64,
+    );
+    Some(Entity::new(id, position).with_nbt_from(compound, &["identifier", "Pos"]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::item::ItemStack;
+
+    fn state(input: &str) -> BlockState {
+        parse_block_state(input)
+    }
+
+    #[test]
+    fn test_block_translation() {
+        let table = default_translator();
+        let cases = [
+            ("minecraft:repeater[delay=2,facing=west,powered=true,locked=false]", "minecraft:powered_repeater[repeater_delay=1,direction=1]"),
+            ("minecraft:redstone_wall_torch[facing=east,lit=false]", "minecraft:unlit_redstone_torch[torch_facing_direction=east]"),
+            ("minecraft:redstone_torch[lit=false]", "minecraft:unlit_redstone_torch[torch_facing_direction=top]"),
+            ("minecraft:oak_log[axis=x]", "minecraft:oak_log[pillar_axis=x]"),
+            ("minecraft:snow_block", "minecraft:snow"),
+            ("minecraft:stone", "minecraft:stone"),
+        ];
+        for (java, bedrock) in cases {
+            assert_eq!(table.java_to_bedrock(&state(java)), state(bedrock), "{}", java);
+        }
+
+        // Dropped properties cannot come back, everything else round trips
+        for (java, _) in &cases[1..] {
+            assert_eq!(table.bedrock_to_java(&table.java_to_bedrock(&state(java))), state(java), "{}", java);
+        }
+
+        let custom = BlockTranslationTable::parse("block minecraft:foo minecraft:bar\nproperty * a b").unwrap();
+        assert_eq!(custom.java_to_bedrock(&state("minecraft:foo[a=1]")), state("minecraft:bar[b=1]"));
+        assert!(BlockTranslationTable::parse("rename foo").is_err());
+    }
+
+    #[test]
+    fn test_mcstructure_roundtrip() {
+        let mut schematic = UniversalSchematic::new("Bedrock".to_string());
+        let stone = BlockState::new("minecraft:stone".to_string());
+        let lever = state("minecraft:lever[face=wall,facing=north,powered=true]");
+        let chest = state("minecraft:chest[facing=south]");
+
+        schematic.set_block(2, 0, 1, stone.clone());
+        schematic.set_block(3, 1, 1, lever.clone());
+        schematic.set_block(2, 0, 2, chest.clone());
+        schematic.add_block_entity(BlockEntity::create_chest((2, 0, 2), vec![ItemStack::new("minecraft:diamond", 64).with_slot(0)]));
+        schematic.add_entity(Entity::new("minecraft:armor_stand".to_string(), (2.5, 1.0, 1.5)));
+
+        let data = to_mcstructure(&schematic).expect("Failed to write mcstructure");
+        assert!(is_mcstructure(&data));
+        assert!(!crate::structure::is_structure(&data));
+
+        let (root, _) = read_le_nbt(&data).unwrap();
+        assert_eq!(read_int_triple(root.get::<_, &NbtList>("structure_world_origin").unwrap()).unwrap(), (2, 0, 1));
+
+        // Coordinates come back relative to the structure origin
+        let loaded = from_mcstructure(&data).expect("Failed to read mcstructure");
+        assert_eq!(loaded.get_block(0, 0, 0), Some(&stone));
+        assert_eq!(loaded.get_block(1, 1, 0), Some(&lever));
+        assert_eq!(loaded.get_block(0, 0, 1), Some(&chest));
+
+        let region = loaded.get_region("Main").unwrap();
+        let block_entity = region.block_entities.get(&(0, 0, 1)).expect("Chest was not read back");
+        assert_eq!(block_entity.id, "minecraft:chest");
+        assert!(block_entity.nbt.get("Items").is_some());
+        assert!(block_entity.nbt.get("x").is_none());
+
+        assert_eq!(region.entities.len(), 1);
+        assert_eq!(region.entities[0].id, "minecraft:armor_stand");
+        assert_eq!(region.entities[0].position, (0.5, 1.0, 0.5));
+    }
+
+    #[test]
+    fn test_waterlogging_uses_second_layer() {
+        let mut schematic = UniversalSchematic::new("Water".to_string());
+        let slab = state("minecraft:oak_slab[type=bottom,waterlogged=true]");
+        schematic.set_block(0, 0, 0, slab.clone());
+        schematic.set_block(0, 0, 1, BlockState::new(STRUCTURE_VOID.to_string()));
+
+        let data = to_mcstructure(&schematic).unwrap();
+        let (root, _) = read_le_nbt(&data).unwrap();
+        let structure = root.get::<_, &NbtCompound>("structure").unwrap();
+        let layers = structure.get::<_, &NbtList>("block_indices").unwrap();
+        let primary = read_layer(layers, 0).unwrap();
+        let secondary = read_layer(layers, 1).unwrap();
+        assert_eq!(primary[1], -1);
+        assert!(secondary[0] >= 0);
+        assert_eq!(secondary[1], -1);
+
+        let loaded = from_mcstructure(&data).unwrap();
+        assert_eq!(loaded.get_block(0, 0, 0), Some(&slab));
+        assert_eq!(loaded.get_block(0, 0, 1), Some(&BlockState::new(STRUCTURE_VOID.to_string())));
+    }
+}
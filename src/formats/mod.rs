@@ -1,2 +1,7 @@
+pub mod anvil;
 pub mod litematic;
-pub mod schematic;
\ No newline at end of file
+pub mod mcedit;
+pub mod mcfunction;
+pub mod mcstructure;
+pub mod schematic;
+pub mod structure;
\ No newline at end of file
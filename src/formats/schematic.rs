@@ -8,8 +8,18 @@ use quartz_nbt::io::Flavor;
 use crate::block_entity::BlockEntity;
 use crate::entity::Entity;
 use crate::region::Region;
+use crate::utils::{NbtMap, NbtValue};
+use crate::error::{SchematicError, Warning};
+use crate::pending_tick::{self, TickKind};
 
 
+/// Sponge schematic format revisions that can be written by `to_schematic_with_version`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SchematicVersion {
+    V2,
+    V3,
+}
+
 pub fn is_schematic(data: &[u8]) -> bool {
     // Decompress the data
     let mut decoder = GzDecoder::new(data);
@@ -25,15 +35,50 @@ pub fn is_schematic(data: &[u8]) -> bool {
     };
 
     // Check for required fields as per the Sponge Schematic Specification
-    root.get::<_, i32>("Version").is_ok() &&
-        root.get::<_, i32>("DataVersion").is_ok() &&
-        root.get::<_, i16>("Width").is_ok() &&
-        root.get::<_, i16>("Height").is_ok() &&
-        root.get::<_, i16>("Length").is_ok() &&
-        root.get::<_, &Vec<i8>>("BlockData").is_ok()
+    match get_v3_root(&root) {
+        Some(schematic) => {
+            schematic.get::<_, i32>("Version").map_or(false, |version| version == 3) &&
+                schematic.get::<_, i32>("DataVersion").is_ok() &&
+                schematic.get::<_, i16>("Width").is_ok() &&
+                schematic.get::<_, i16>("Height").is_ok() &&
+                schematic.get::<_, i16>("Length").is_ok() &&
+                schematic.get::<_, &NbtCompound>("Blocks").is_ok()
+        }
+        None => {
+            root.get::<_, i32>("Version").is_ok() &&
+                root.get::<_, i32>("DataVersion").is_ok() &&
+                root.get::<_, i16>("Width").is_ok() &&
+                root.get::<_, i16>("Height").is_ok() &&
+                root.get::<_, i16>("Length").is_ok() &&
+                root.get::<_, &Vec<i8>>("BlockData").is_ok()
+        }
+    }
 }
 
-pub fn to_schematic(schematic: &UniversalSchematic) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+// Sponge v3 nests everything under a `Schematic` compound. Some writers drop the
+// wrapper and name the root tag instead, so a root with `Version: 3` is accepted too.
+fn get_v3_root(root: &NbtCompound) -> Option<&NbtCompound> {
+    if let Ok(schematic) = root.get::<_, &NbtCompound>("Schematic") {
+        return Some(schematic);
+    }
+    match root.get::<_, i32>("Version") {
+        Ok(3) => Some(root),
+        _ => None,
+    }
+}
+
+pub fn to_schematic(schematic: &UniversalSchematic) -> Result<Vec<u8>, SchematicError> {
+    to_schematic_with_version(schematic, SchematicVersion::V2)
+}
+
+pub fn to_schematic_with_version(schematic: &UniversalSchematic, version: SchematicVersion) -> Result<Vec<u8>, SchematicError> {
+    match version {
+        SchematicVersion::V2 => to_schematic_v2(schematic),
+        SchematicVersion::V3 => to_schematic_v3(schematic),
+    }
+}
+
+fn to_schematic_v2(schematic: &UniversalSchematic) -> Result<Vec<u8>, SchematicError> {
     let mut root = NbtCompound::new();
 
     root.insert("Version", NbtTag::Int(2)); // Schematic format version 2
@@ -58,8 +103,8 @@ pub fn to_schematic(schematic: &UniversalSchematic) -> Result<Vec<u8>, Box<dyn s
     root.insert("Palette", convert_palette(&merged_region.palette).0);
     root.insert("PaletteMax", convert_palette(&merged_region.palette).1);
 
-    let block_data: Vec<u8> = merged_region.blocks.iter()
-        .flat_map(|&block_id| encode_varint(block_id as u32))
+    let block_data: Vec<u8> = merged_region.iter_block_indices()
+        .flat_map(|block_id| encode_varint(block_id as u32))
         .collect();
 
 
@@ -77,13 +122,13 @@ pub fn to_schematic(schematic: &UniversalSchematic) -> Result<Vec<u8>, Box<dyn s
 
     let mut block_entities = NbtList::new();
     for region in schematic.regions.values() {
-        block_entities.extend(convert_block_entities(region).iter().cloned());
+        block_entities.extend(convert_block_entities(region, bounding_box.min).iter().cloned());
     }
     root.insert("BlockEntities", NbtTag::List(block_entities));
 
     let mut entities = NbtList::new();
     for region in schematic.regions.values() {
-        entities.extend(convert_entities(region).iter().cloned());
+        entities.extend(convert_entities(region, bounding_box.min).iter().cloned());
     }
     root.insert("Entities", NbtTag::List(entities));
 
@@ -95,15 +140,80 @@ pub fn to_schematic(schematic: &UniversalSchematic) -> Result<Vec<u8>, Box<dyn s
 }
 
 
+fn to_schematic_v3(schematic: &UniversalSchematic) -> Result<Vec<u8>, SchematicError> {
+    let mut schematic_tag = NbtCompound::new();
+
+    schematic_tag.insert("Version", NbtTag::Int(3));
+    schematic_tag.insert("DataVersion", NbtTag::Int(schematic.metadata.mc_version.unwrap_or(3700)));
+    schematic_tag.insert("Metadata", schematic.metadata.to_nbt());
+
+    let bounding_box = schematic.get_bounding_box();
+    let (width, height, length) = bounding_box.get_dimensions();
+
+    schematic_tag.insert("Width", NbtTag::Short((width as i16).abs()));
+    schematic_tag.insert("Height", NbtTag::Short((height as i16).abs()));
+    schematic_tag.insert("Length", NbtTag::Short((length as i16).abs()));
+    schematic_tag.insert("Offset", NbtTag::IntArray(vec![0, 0, 0]));
+
+    let merged_region = schematic.get_merged_region();
+
+    let mut blocks = NbtCompound::new();
+    blocks.insert("Palette", convert_palette(&merged_region.palette).0);
+
+    let block_data: Vec<i8> = merged_region.iter_block_indices()
+        .flat_map(|block_id| encode_varint(block_id as u32))
+        .map(|byte| byte as i8)
+        .collect();
+    blocks.insert("Data", NbtTag::ByteArray(block_data));
+
+    let mut block_entities = NbtList::new();
+    for region in schematic.regions.values() {
+        for block_entity in region.block_entities.values() {
+            block_entities.push(convert_block_entity_v3(block_entity, bounding_box.min));
+        }
+    }
+    blocks.insert("BlockEntities", NbtTag::List(block_entities));
+
+    // The spec has no place for scheduled ticks, so they go next to the block entities in the
+    // layout vanilla chunks use. Readers that don't know them skip them.
+    blocks.insert("BlockTicks", NbtTag::List(pending_tick::to_chunk_list(&merged_region.pending_ticks, TickKind::Block, bounding_box.min)));
+    blocks.insert("FluidTicks", NbtTag::List(pending_tick::to_chunk_list(&merged_region.pending_ticks, TickKind::Fluid, bounding_box.min)));
+    schematic_tag.insert("Blocks", NbtTag::Compound(blocks));
+
+    let mut entities = NbtList::new();
+    for region in schematic.regions.values() {
+        for entity in &region.entities {
+            entities.push(convert_entity_v3(entity, bounding_box.min));
+        }
+    }
+    schematic_tag.insert("Entities", NbtTag::List(entities));
+
+    let mut root = NbtCompound::new();
+    root.insert("Schematic", NbtTag::Compound(schematic_tag));
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    quartz_nbt::io::write_nbt(&mut encoder, None, &root, quartz_nbt::io::Flavor::Uncompressed)?;
+    Ok(encoder.finish()?)
+}
 
+pub fn from_schematic(data: &[u8]) -> Result<UniversalSchematic, SchematicError> {
+    from_schematic_with_warnings(data).map(|(schematic, _)| schematic)
+}
 
-pub fn from_schematic(data: &[u8]) -> Result<UniversalSchematic, Box<dyn std::error::Error>> {
+/// Like `from_schematic`, but also returns the problems that were worked around while reading.
+pub fn from_schematic_with_warnings(data: &[u8]) -> Result<(UniversalSchematic, Vec<Warning>), SchematicError> {
+    let mut warnings = Vec::new();
     let mut decoder = GzDecoder::new(data);
     let mut decompressed = Vec::new();
-    decoder.read_to_end(&mut decompressed)?;
+    decoder.read_to_end(&mut decompressed).map_err(SchematicError::Decompression)?;
 
     let (root, _) = quartz_nbt::io::read_nbt(&mut std::io::Cursor::new(decompressed), quartz_nbt::io::Flavor::Uncompressed)?;
 
+    if let Some(schematic_tag) = get_v3_root(&root) {
+        let schematic = parse_schematic_v3(schematic_tag, &mut warnings)?;
+        return Ok((schematic, warnings));
+    }
+
 
     let name = if let Some(metadata) = root.get::<_, &NbtCompound>("Metadata").ok() {
         metadata.get::<_, &str>("Name").ok().map(|s| s.to_string())
@@ -120,67 +230,220 @@ pub fn from_schematic(data: &[u8]) -> Result<UniversalSchematic, Box<dyn std::er
     let height = root.get::<_, i16>("Height")? as u32;
     let length = root.get::<_, i16>("Length")? as u32;
 
-    let palette = parse_palette(&root)?;
+    let palette = parse_palette(&root, &mut warnings)?;
 
     let block_data = parse_block_data(&root, width, height, length)?;
 
     let mut region = Region::new("Main".to_string(), (0, 0, 0), (width as i32, height as i32, length as i32));
-    region.palette = palette;
-
-    region.blocks = block_data.iter().map(|&x| x as usize).collect();
+    region.set_block_indices(palette_indices(&block_data, palette.len())?);
+    region.set_palette(palette);
 
     let block_entities = parse_block_entities(&root)?;
     for block_entity in block_entities {
         region.add_block_entity(block_entity);
     }
 
-    let entities = parse_entities(&root)?;
+    let entities = parse_entities(&root, &mut warnings)?;
     for entity in entities {
         region.add_entity(entity);
     }
 
+    schematic.add_region(region);
+    Ok((schematic, warnings))
+}
+
+fn parse_schematic_v3(root: &NbtCompound, warnings: &mut Vec<Warning>) -> Result<UniversalSchematic, SchematicError> {
+    let name = if let Some(metadata) = root.get::<_, &NbtCompound>("Metadata").ok() {
+        metadata.get::<_, &str>("Name").ok().map(|s| s.to_string())
+    } else {
+        None
+    }.unwrap_or_else(|| "Unnamed".to_string());
+
+    let mut schematic = UniversalSchematic::new(name);
+    schematic.metadata.mc_version = root.get::<_, i32>("DataVersion").ok();
+
+    let width = root.get::<_, i16>("Width")? as u16 as u32;
+    let height = root.get::<_, i16>("Height")? as u16 as u32;
+    let length = root.get::<_, i16>("Length")? as u16 as u32;
+
+    let mut region = Region::new("Main".to_string(), (0, 0, 0), (width as i32, height as i32, length as i32));
+
+    // The spec allows schematics that only carry biomes or entities, so `Blocks` is optional.
+    if let Ok(blocks) = root.get::<_, &NbtCompound>("Blocks") {
+        let palette = parse_palette(blocks, warnings)?;
+
+        let block_data = blocks.get::<_, &Vec<i8>>("Data")?;
+        region.set_block_indices(palette_indices(&decode_block_data(block_data, width, height, length)?, palette.len())?);
+        region.set_palette(palette);
+
+        if let Ok(block_entities) = blocks.get::<_, &NbtList>("BlockEntities") {
+            for tag in block_entities.iter() {
+                if let NbtTag::Compound(compound) = tag {
+                    region.add_block_entity(parse_block_entity_v3(compound));
+                }
+            }
+        }
+
+        for (key, kind) in [("BlockTicks", TickKind::Block), ("FluidTicks", TickKind::Fluid)] {
+            if let Ok(ticks) = blocks.get::<_, &NbtList>(key) {
+                region.pending_ticks.extend(pending_tick::from_chunk_list(ticks, kind, (0, 0, 0)));
+            }
+        }
+    }
+
+    // Biomes are not modelled by UniversalSchematic and are skipped.
+
+    if let Ok(entities) = root.get::<_, &NbtList>("Entities") {
+        for tag in entities.iter() {
+            if let NbtTag::Compound(compound) = tag {
+                match parse_entity_v3(compound) {
+                    Ok(entity) => region.add_entity(entity),
+                    Err(e) => warnings.push(Warning::SkippedEntity { reason: e.to_string() }),
+                }
+            }
+        }
+    }
+
     schematic.add_region(region);
     Ok(schematic)
 }
 
+fn convert_block_entity_v3(block_entity: &BlockEntity, origin: (i32, i32, i32)) -> NbtTag {
+    let mut compound = NbtCompound::new();
+    compound.insert("Id", NbtTag::String(block_entity.id.clone()));
+    compound.insert("Pos", relative_pos(block_entity.position, origin));
 
+    let mut data = NbtCompound::new();
+    for (key, value) in &block_entity.nbt {
+        if key == "Id" || key == "Pos" {
+            continue;
+        }
+        data.insert(key, value.to_quartz_nbt());
+    }
+    compound.insert("Data", NbtTag::Compound(data));
+
+    NbtTag::Compound(compound)
+}
+
+fn parse_block_entity_v3(compound: &NbtCompound) -> BlockEntity {
+    let id = compound.get::<_, &str>("Id")
+        .map(|s| s.to_string())
+        .unwrap_or_else(|_| "unknown".to_string());
+    let position = match compound.get::<_, &[i32]>("Pos") {
+        Ok(pos) if pos.len() == 3 => (pos[0], pos[1], pos[2]),
+        _ => (0, 0, 0),
+    };
+
+    // Keep `Id` and `Pos` in the NBT as well so the block entity looks the same as one read from v2.
+    let mut nbt = match compound.get::<_, &NbtCompound>("Data") {
+        Ok(data) => NbtMap::from_quartz_nbt(data),
+        Err(_) => NbtMap::new(),
+    };
+    nbt.insert("Id".to_string(), NbtValue::String(id.clone()));
+    nbt.insert("Pos".to_string(), NbtValue::IntArray(vec![position.0, position.1, position.2]));
+
+    BlockEntity { nbt, id, position }
+}
+
+fn convert_entity_v3(entity: &Entity, origin: (i32, i32, i32)) -> NbtTag {
+    let position = relative_entity_pos(entity.position, origin);
+    let mut compound = NbtCompound::new();
+    compound.insert("Id", NbtTag::String(entity.id.clone()));
+    compound.insert("Pos", NbtTag::List(NbtList::from(vec![
+        NbtTag::Double(position.0),
+        NbtTag::Double(position.1),
+        NbtTag::Double(position.2),
+    ])));
+
+    compound.insert("Data", NbtTag::Compound(entity.nbt.to_quartz_nbt()));
+
+    NbtTag::Compound(compound)
+}
+
+fn parse_entity_v3(compound: &NbtCompound) -> Result<Entity, SchematicError> {
+    let id = compound.get::<_, &str>("Id")?.to_string();
+    let pos = compound.get::<_, &NbtList>("Pos")?;
+    if pos.len() != 3 {
+        return Err("Invalid entity position data".into());
+    }
+    let mut entity = Entity::new(id, (pos.get::<f64>(0)?, pos.get::<f64>(1)?, pos.get::<f64>(2)?));
+
+    if let Ok(data) = compound.get::<_, &NbtCompound>("Data") {
+        entity = entity.with_nbt_from(data, &["id", "Pos"]);
+    }
 
-fn convert_block_entities(region: &Region) -> NbtList {
+    Ok(entity)
+}
+
+// Sponge positions are relative to the schematic's minimum corner, which is where the reader puts the origin.
+fn convert_block_entities(region: &Region, origin: (i32, i32, i32)) -> NbtList {
     let mut block_entities = NbtList::new();
 
-    for (_, block_entity) in &region.block_entities {
-        block_entities.push(block_entity.to_nbt());
+    for block_entity in region.block_entities.values() {
+        let mut compound = block_entity.to_nbt();
+        compound.insert("Id", NbtTag::String(block_entity.id.clone()));
+        compound.insert("Pos", relative_pos(block_entity.position, origin));
+        block_entities.push(NbtTag::Compound(compound));
     }
 
     block_entities
 }
 
-fn convert_entities(region: &Region) -> NbtList {
+fn relative_pos(position: (i32, i32, i32), origin: (i32, i32, i32)) -> NbtTag {
+    NbtTag::IntArray(vec![position.0 - origin.0, position.1 - origin.1, position.2 - origin.2])
+}
+
+fn relative_entity_pos(position: (f64, f64, f64), origin: (i32, i32, i32)) -> (f64, f64, f64) {
+    (position.0 - origin.0 as f64, position.1 - origin.1 as f64, position.2 - origin.2 as f64)
+}
+
+// Entities are relative to the same corner as block entities.
+fn convert_entities(region: &Region, origin: (i32, i32, i32)) -> NbtList {
     let mut entities = NbtList::new();
 
     for entity in &region.entities {
+        let mut entity = entity.clone();
+        entity.position = relative_entity_pos(entity.position, origin);
         entities.push(entity.to_nbt());
     }
 
     entities
 }
 
-fn parse_palette(region_tag: &NbtCompound) -> Result<Vec<BlockState>, Box<dyn std::error::Error>> {
+fn parse_palette(region_tag: &NbtCompound, warnings: &mut Vec<Warning>) -> Result<Vec<BlockState>, SchematicError> {
     let palette_compound = region_tag.get::<_, &NbtCompound>("Palette")?;
-    let palette_max = region_tag.get::<_, i32>("PaletteMax")? as usize;
-    let mut palette = vec![BlockState::new("minecraft:air".to_string()); palette_max + 1];
+    // v3 dropped `PaletteMax`, so fall back to the highest id present in the palette.
+    let palette_max = match region_tag.get::<_, i32>("PaletteMax") {
+        Ok(max) => max as usize,
+        Err(_) => palette_compound.inner().values()
+            .filter_map(|value| if let NbtTag::Int(id) = value { Some(*id as usize) } else { None })
+            .max()
+            .unwrap_or(0),
+    };
+    let mut palette = vec![None; palette_max + 1];
 
     for (block_state_str, value) in palette_compound.inner() {
         if let NbtTag::Int(id) = value {
-            let block_state = parse_block_state(block_state_str);
-            palette[*id as usize] = block_state;
+            if *id < 0 || *id as usize > palette_max {
+                return Err(SchematicError::PaletteIndexOutOfRange { index: *id as usize, palette_len: palette_max + 1 });
+            }
+            palette[*id as usize] = Some(parse_block_state(block_state_str));
         }
     }
 
-    Ok(palette)
+    // Ids the palette skips are filled with air so the indices in the block data still line up.
+    Ok(palette.into_iter().enumerate().map(|(index, block)| block.unwrap_or_else(|| {
+        warnings.push(Warning::MissingPaletteEntry { index });
+        BlockState::new("minecraft:air".to_string())
+    })).collect())
 }
 
-fn parse_block_state(input: &str) -> BlockState {
+// Palettes written by other tools don't always hold valid ids, so anything the strict parser
+// rejects is split on the brackets instead.
+pub(crate) fn parse_block_state(input: &str) -> BlockState {
+    if let Ok(block) = input.parse() {
+        return block;
+    }
     if let Some((name, properties_str)) = input.split_once('[') {
         let name = name.to_string();
         let properties = properties_str
@@ -241,7 +504,7 @@ pub fn encode_varint(value: u32) -> Vec<u8> {
     bytes
 }
 
-fn decode_varint<R: Read>(reader: &mut R) -> Result<u32, Box<dyn std::error::Error>> {
+fn decode_varint<R: Read>(reader: &mut R) -> Result<u32, SchematicError> {
     let mut result = 0u32;
     let mut shift = 0;
     loop {
@@ -258,38 +521,42 @@ fn decode_varint<R: Read>(reader: &mut R) -> Result<u32, Box<dyn std::error::Err
     }
 }
 
-fn parse_block_data(region_tag: &NbtCompound, width: u32, height: u32, length: u32) -> Result<Vec<u32>, Box<dyn std::error::Error>> {
+fn parse_block_data(region_tag: &NbtCompound, width: u32, height: u32, length: u32) -> Result<Vec<u32>, SchematicError> {
     let block_data_i8 = region_tag.get::<_, &Vec<i8>>("BlockData")?;
+    decode_block_data(block_data_i8, width, height, length)
+}
+
+fn decode_block_data(block_data_i8: &[i8], width: u32, height: u32, length: u32) -> Result<Vec<u32>, SchematicError> {
     let block_data_u8: Vec<u8> = block_data_i8.iter().map(|&x| x as u8).collect();
     let mut block_data = Vec::new();
 
-
     let mut reader = Cursor::new(block_data_u8);
     while reader.position() < block_data_i8.len() as u64 {
-        match decode_varint(&mut reader) {
-            Ok(value) => {
-                block_data.push(value);
-            },
-            Err(e) => {
-                println!("Error decoding varint at position {}: {:?}", reader.position(), e);
-                break;
-            }
-        }
+        let position = reader.position();
+        let value = decode_varint(&mut reader)
+            .map_err(|e| SchematicError::InvalidData(format!("Invalid varint at byte {} of the block data: {}", position, e)))?;
+        block_data.push(value);
     }
 
-
     let expected_length = (width * height * length) as usize;
     if block_data.len() != expected_length {
-        println!("Block data length mismatch. Got: {}, Expected: {}", block_data.len(), expected_length);
-        println!("First 10 decoded values: {:?}", &block_data[..10.min(block_data.len())]);
-        println!("Last 10 decoded values: {:?}", &block_data[block_data.len().saturating_sub(10)..]);
+        return Err(SchematicError::BlockDataLengthMismatch { expected: expected_length, actual: block_data.len() });
     }
 
     Ok(block_data)
 }
 
+fn palette_indices(block_data: &[u32], palette_len: usize) -> Result<Vec<usize>, SchematicError> {
+    block_data.iter().map(|&index| {
+        if index as usize >= palette_len {
+            return Err(SchematicError::PaletteIndexOutOfRange { index: index as usize, palette_len });
+        }
+        Ok(index as usize)
+    }).collect()
+}
+
 
-fn parse_block_entities(region_tag: &NbtCompound) -> Result<Vec<BlockEntity>, Box<dyn std::error::Error>> {
+fn parse_block_entities(region_tag: &NbtCompound) -> Result<Vec<BlockEntity>, SchematicError> {
     let block_entities_list = region_tag.get::<_, &NbtList>("BlockEntities")?;
     let mut block_entities = Vec::new();
 
@@ -303,7 +570,7 @@ fn parse_block_entities(region_tag: &NbtCompound) -> Result<Vec<BlockEntity>, Bo
     Ok(block_entities)
 }
 
-fn parse_entities(region_tag: &NbtCompound) -> Result<Vec<Entity>, Box<dyn std::error::Error>> {
+fn parse_entities(region_tag: &NbtCompound, warnings: &mut Vec<Warning>) -> Result<Vec<Entity>, SchematicError> {
     if !region_tag.contains_key("Entities") {
         return Ok(Vec::new());
     }
@@ -312,7 +579,10 @@ fn parse_entities(region_tag: &NbtCompound) -> Result<Vec<Entity>, Box<dyn std::
 
     for tag in entities_list.iter() {
         if let NbtTag::Compound(compound) = tag {
-            entities.push(Entity::from_nbt(compound)?);
+            match Entity::from_nbt(compound) {
+                Ok(entity) => entities.push(entity),
+                Err(reason) => warnings.push(Warning::SkippedEntity { reason }),
+            }
         }
     }
 
@@ -330,6 +600,8 @@ mod tests {
     use super::*;
     use crate::{UniversalSchematic, BlockState};
     use crate::litematic::{from_litematic, to_litematic};
+    use crate::item::ItemStack;
+    use crate::PendingTick;
 
     #[test]
     fn test_schematic_file_generation() {
@@ -415,6 +687,47 @@ mod tests {
         assert_eq!(parsed_data, vec![0, 1, 2, 1, 0, 2, 1, 0]);
     }
 
+    #[test]
+    fn test_malformed_schematic_errors_and_warnings() {
+        let mut root = NbtCompound::new();
+        root.insert("Version", NbtTag::Int(2));
+        root.insert("DataVersion", NbtTag::Int(3700));
+        root.insert("Width", NbtTag::Short(2));
+        root.insert("Height", NbtTag::Short(1));
+        root.insert("Length", NbtTag::Short(1));
+        let mut palette = NbtCompound::new();
+        palette.insert("minecraft:air", NbtTag::Int(0));
+        palette.insert("minecraft:stone", NbtTag::Int(2));
+        root.insert("Palette", NbtTag::Compound(palette));
+        root.insert("BlockEntities", NbtTag::List(NbtList::new()));
+        let mut broken_entity = NbtCompound::new();
+        broken_entity.insert("id", NbtTag::String("minecraft:pig".to_string()));
+        root.insert("Entities", NbtTag::List(NbtList::from(vec![NbtTag::Compound(broken_entity)])));
+
+        let write = |root: &NbtCompound, block_data: Vec<u32>| {
+            let mut root = root.clone();
+            let encoded: Vec<i8> = block_data.iter().flat_map(|&v| encode_varint(v)).map(|x| x as i8).collect();
+            root.insert("BlockData", NbtTag::ByteArray(encoded));
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            quartz_nbt::io::write_nbt(&mut encoder, None, &root, Flavor::Uncompressed).unwrap();
+            encoder.finish().unwrap()
+        };
+
+        let (schematic, warnings) = from_schematic_with_warnings(&write(&root, vec![2, 0])).unwrap();
+        assert_eq!(schematic.get_block(0, 0, 0).unwrap().name, "minecraft:stone");
+        assert_eq!(warnings.len(), 2);
+        assert_eq!(warnings[0], Warning::MissingPaletteEntry { index: 1 });
+        assert!(matches!(warnings[1], Warning::SkippedEntity { .. }));
+
+        assert!(matches!(from_schematic(&write(&root, vec![2])),
+            Err(SchematicError::BlockDataLengthMismatch { expected: 2, actual: 1 })));
+        assert!(matches!(from_schematic(&write(&root, vec![2, 3])),
+            Err(SchematicError::PaletteIndexOutOfRange { index: 3, palette_len: 3 })));
+        root.inner_mut().remove("Width");
+        assert!(matches!(from_schematic(&write(&root, vec![2, 0])), Err(SchematicError::MissingField(_))));
+        assert!(matches!(from_schematic(b"not gzip"), Err(SchematicError::Decompression(_))));
+    }
+
     #[test]
     fn test_convert_palette() {
         let palette = vec![
@@ -435,6 +748,128 @@ mod tests {
     }
 
 
+    #[test]
+    fn test_schematic_v3_roundtrip() {
+        let mut schematic = UniversalSchematic::new("V3 Schematic".to_string());
+        let stone = BlockState::new("minecraft:stone".to_string());
+        let repeater = BlockState::new("minecraft:repeater".to_string())
+            .with_property("facing".to_string(), "east".to_string());
+
+        schematic.set_block(0, 0, 0, stone.clone());
+        schematic.set_block(2, 1, 3, repeater.clone());
+
+        let chest = BlockEntity::create_chest((1, 0, 0), vec![ItemStack::new("minecraft:diamond", 64).with_slot(0)]);
+        schematic.add_block_entity(chest);
+        schematic.add_entity(Entity::new("minecraft:creeper".to_string(), (0.5, 1.0, 0.5)));
+
+        let data = to_schematic_with_version(&schematic, SchematicVersion::V3).expect("Failed to write v3 schematic");
+        assert!(is_schematic(&data));
+
+        let loaded = from_schematic(&data).expect("Failed to read v3 schematic");
+        assert_eq!(loaded.metadata.name, Some("V3 Schematic".to_string()));
+        assert_eq!(loaded.get_block(0, 0, 0), Some(&stone));
+        assert_eq!(loaded.get_block(2, 1, 3), Some(&repeater));
+
+        let region = loaded.get_region("Main").unwrap();
+        assert_eq!(region.entities.len(), 1);
+        assert_eq!(region.entities[0].id, "minecraft:creeper");
+
+        let chest = region.block_entities.get(&(1, 0, 0)).expect("Chest was not read back");
+        assert_eq!(chest.id, "minecraft:chest");
+        assert!(chest.nbt.get("Items").is_some());
+    }
+
+    #[test]
+    fn test_block_entity_pos_is_relative_to_origin() {
+        let mut schematic = UniversalSchematic::new("Offset".to_string());
+        let chest = BlockState::new("minecraft:chest".to_string());
+        schematic.set_block(100, 64, -30, BlockState::new("minecraft:stone".to_string()));
+        schematic.set_block(102, 65, -29, chest.clone());
+        schematic.add_block_entity(BlockEntity::create_chest((102, 65, -29), vec![ItemStack::new("minecraft:diamond", 1).with_slot(0)]));
+
+        for version in [SchematicVersion::V2, SchematicVersion::V3] {
+            let loaded = from_schematic(&to_schematic_with_version(&schematic, version).unwrap()).unwrap();
+            let region = loaded.get_region("Main").unwrap();
+            assert_eq!(region.block_entities.len(), 1);
+            let block_entity = region.block_entities.get(&(2, 1, 1)).expect("Chest is not next to its block");
+            assert_eq!(block_entity.id, "minecraft:chest");
+            assert_eq!(loaded.get_block(2, 1, 1), Some(&chest));
+        }
+    }
+
+    #[test]
+    fn test_entity_pos_is_relative_to_origin() {
+        let mut schematic = UniversalSchematic::new("Offset".to_string());
+        schematic.set_block(10, 0, 0, BlockState::new("minecraft:stone".to_string()));
+        schematic.add_entity(Entity::new("minecraft:armor_stand".to_string(), (10.5, 1.0, 0.5)));
+
+        for version in [SchematicVersion::V2, SchematicVersion::V3] {
+            let loaded = from_schematic(&to_schematic_with_version(&schematic, version).unwrap()).unwrap();
+            let region = loaded.get_region("Main").unwrap();
+            assert_eq!(region.entities.len(), 1);
+            assert_eq!(region.entities[0].position, (0.5, 1.0, 0.5), "Armor stand is not on its block");
+            assert_eq!(loaded.get_block(0, 0, 0).unwrap().name, "minecraft:stone");
+        }
+    }
+
+    #[test]
+    fn test_schematic_v3_pending_ticks() {
+        let mut schematic = UniversalSchematic::new("Ticks".to_string());
+        schematic.set_block(0, 0, 0, BlockState::new("minecraft:water".to_string()));
+        let region_name = schematic.default_region_name.clone();
+        let tick = PendingTick::new(TickKind::Fluid, "minecraft:water".to_string(), (0, 0, 0), 5);
+        schematic.get_region_mut(&region_name).unwrap().add_pending_tick(tick.clone());
+
+        let loaded = from_schematic(&to_schematic_with_version(&schematic, SchematicVersion::V3).unwrap()).unwrap();
+        assert_eq!(loaded.get_region("Main").unwrap().pending_ticks, vec![tick]);
+    }
+
+    #[test]
+    fn test_entity_nbt_round_trip() {
+        let nbt = NbtMap::from_snbt(r#"{Motion:[0.0d,0.0d,0.0d],Rotation:[180.0f,0.0f],UUID:[I;7,8,9,10],Invisible:1b,Item:{id:"minecraft:clock",count:1}}"#).unwrap();
+        let mut frame = Entity::new("minecraft:item_frame".to_string(), (0.5, 1.0, 0.5));
+        frame.nbt = nbt;
+        let mut schematic = UniversalSchematic::new("Entities".to_string());
+        schematic.set_block(0, 0, 0, BlockState::new("minecraft:stone".to_string()));
+        schematic.add_entity(frame.clone());
+
+        let v3 = from_schematic(&to_schematic_with_version(&schematic, SchematicVersion::V3).unwrap()).unwrap();
+        assert_eq!(v3.get_region("Main").unwrap().entities, vec![frame.clone()]);
+        let v2 = from_schematic(&to_schematic(&schematic).unwrap()).unwrap();
+        assert_eq!(v2.get_region("Main").unwrap().entities, vec![frame.clone()]);
+        let litematic = from_litematic(&to_litematic(&schematic).unwrap()).unwrap();
+        assert_eq!(litematic.regions.values().next().unwrap().entities, vec![frame]);
+    }
+
+    #[test]
+    fn test_schematic_v3_layout() {
+        let mut schematic = UniversalSchematic::new("Layout".to_string());
+        schematic.set_block(0, 0, 0, BlockState::new("minecraft:stone".to_string()));
+
+        let data = to_schematic_with_version(&schematic, SchematicVersion::V3).unwrap();
+        let mut decompressed = Vec::new();
+        GzDecoder::new(data.as_slice()).read_to_end(&mut decompressed).unwrap();
+        let (root, _) = quartz_nbt::io::read_nbt(&mut Cursor::new(decompressed), Flavor::Uncompressed).unwrap();
+
+        let schematic_tag = root.get::<_, &NbtCompound>("Schematic").unwrap();
+        assert_eq!(schematic_tag.get::<_, i32>("Version").unwrap(), 3);
+        let blocks = schematic_tag.get::<_, &NbtCompound>("Blocks").unwrap();
+        assert!(blocks.contains_key("Palette"));
+        assert!(blocks.contains_key("Data"));
+        assert!(blocks.contains_key("BlockEntities"));
+        assert!(!schematic_tag.contains_key("BlockData"));
+    }
+
+    #[test]
+    fn test_is_schematic_v2() {
+        let mut schematic = UniversalSchematic::new("V2".to_string());
+        schematic.set_block(0, 0, 0, BlockState::new("minecraft:stone".to_string()));
+
+        let data = to_schematic(&schematic).unwrap();
+        assert!(is_schematic(&data));
+        assert!(!is_schematic(&[0, 1, 2, 3]));
+    }
+
     #[test]
     fn test_import_new_chest_test_schem() {
         let name = "new_chest_test";
@@ -0,0 +1,363 @@
+use std::io::{Cursor, Read};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use quartz_nbt::{NbtCompound, NbtList, NbtTag};
+use quartz_nbt::io::Flavor;
+use crate::{UniversalSchematic, BlockState};
+use crate::block_entity::BlockEntity;
+use crate::entity::Entity;
+use crate::region::Region;
+use crate::error::SchematicError;
+use crate::pending_tick::{self, TickKind};
+
+const STRUCTURE_VOID: &str = "minecraft:structure_void";
+
+pub fn is_structure(data: &[u8]) -> bool {
+    // Decompress the data
+    let mut decoder = GzDecoder::new(data);
+    let mut decompressed = Vec::new();
+    if decoder.read_to_end(&mut decompressed).is_err() {
+        return false;
+    }
+
+    // Read the NBT data
+    let (root, _) = match quartz_nbt::io::read_nbt(&mut Cursor::new(decompressed), Flavor::Uncompressed) {
+        Ok(result) => result,
+        Err(_) => return false,
+    };
+
+    // Check for required fields as per the vanilla structure template format
+    root.get::<_, &NbtList>("size").is_ok() &&
+        root.get::<_, &NbtList>("blocks").is_ok() &&
+        (root.get::<_, &NbtList>("palette").is_ok() || root.get::<_, &NbtList>("palettes").is_ok())
+}
+
+pub fn to_structure(schematic: &UniversalSchematic) -> Result<Vec<u8>, SchematicError> {
+    let mut root = NbtCompound::new();
+
+    root.insert("DataVersion", NbtTag::Int(schematic.metadata.mc_version.unwrap_or(3700)));
+
+    let bounding_box = schematic.get_bounding_box();
+    let (width, height, length) = bounding_box.get_dimensions();
+    root.insert("size", NbtTag::List(NbtList::from(vec![
+        NbtTag::Int(width),
+        NbtTag::Int(height),
+        NbtTag::Int(length),
+    ])));
+
+    let merged_region = schematic.get_merged_region();
+    let origin = bounding_box.min;
+
+    // Structure voids are left out of the block list, which is how the game marks positions it must not touch.
+    let void_index = merged_region.get_palette_index(&BlockState::new(STRUCTURE_VOID.to_string()));
+
+    let palette = NbtList::from(merged_region.palette.iter().map(|block_state| block_state.to_nbt()).collect::<Vec<NbtTag>>());
+    root.insert("palette", NbtTag::List(palette));
+
+    let mut blocks = NbtList::new();
+    for (index, palette_index) in merged_region.iter_block_indices().enumerate() {
+        if Some(palette_index) == void_index {
+            continue;
+        }
+        let (x, y, z) = merged_region.index_to_coords(index);
+
+        let mut block = NbtCompound::new();
+        block.insert("pos", NbtTag::List(NbtList::from(vec![
+            NbtTag::Int(x - origin.0),
+            NbtTag::Int(y - origin.1),
+            NbtTag::Int(z - origin.2),
+        ])));
+        block.insert("state", NbtTag::Int(palette_index as i32));
+        if let Some(block_entity) = merged_region.block_entities.get(&(x, y, z)) {
+            block.insert("nbt", NbtTag::Compound(convert_block_entity(block_entity)));
+        }
+        blocks.push(NbtTag::Compound(block));
+    }
+    root.insert("blocks", NbtTag::List(blocks));
+
+    let mut entities = NbtList::new();
+    for entity in &merged_region.entities {
+        entities.push(convert_entity(entity, origin));
+    }
+    root.insert("entities", NbtTag::List(entities));
+
+    // Not part of the vanilla template, which drops scheduled ticks; the game ignores these lists.
+    root.insert("block_ticks", NbtTag::List(pending_tick::to_chunk_list(&merged_region.pending_ticks, TickKind::Block, origin)));
+    root.insert("fluid_ticks", NbtTag::List(pending_tick::to_chunk_list(&merged_region.pending_ticks, TickKind::Fluid, origin)));
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    quartz_nbt::io::write_nbt(&mut encoder, None, &root, Flavor::Uncompressed)?;
+    Ok(encoder.finish()?)
+}
+
+pub fn from_structure(data: &[u8]) -> Result<UniversalSchematic, SchematicError> {
+    from_structure_with_palette(data, 0)
+}
+
+/// Returns how many palettes a structure carries. Structures with random variants,
+/// such as shipwrecks, use `palettes` and pick one of them when placed.
+pub fn get_structure_palette_count(data: &[u8]) -> Result<usize, SchematicError> {
+    let root = read_structure_root(data)?;
+    if let Ok(palettes) = root.get::<_, &NbtList>("palettes") {
+        Ok(palettes.len())
+    } else {
+        root.get::<_, &NbtList>("palette")?;
+        Ok(1)
+    }
+}
+
+pub fn from_structure_with_palette(data: &[u8], palette_index: usize) -> Result<UniversalSchematic, SchematicError> {
+    let root = read_structure_root(data)?;
+
+    let mut schematic = UniversalSchematic::new("Unnamed".to_string());
+    schematic.metadata.mc_version = root.get::<_, i32>("DataVersion").ok();
+
+    let size = root.get::<_, &NbtList>("size")?;
+    if size.len() != 3 {
+        return Err("Invalid structure size".into());
+    }
+    let size = (size.get::<i32>(0)?, size.get::<i32>(1)?, size.get::<i32>(2)?);
+
+    let palette = parse_palette(&root, palette_index)?;
+
+    let mut region = Region::new("Main".to_string(), (0, 0, 0), size);
+    let bounding_box = region.get_bounding_box();
+    let palette_indices: Vec<usize> = palette.into_iter()
+        .map(|block_state| region.get_or_insert_in_palette(block_state))
+        .collect();
+
+    let blocks = root.get::<_, &NbtList>("blocks")?;
+
+    // Every position missing from the block list is a structure void.
+    if blocks.len() < region.volume() {
+        let void_index = region.get_or_insert_in_palette(BlockState::new(STRUCTURE_VOID.to_string()));
+        region.set_block_indices(vec![void_index; region.volume()]);
+    }
+
+    for tag in blocks.iter() {
+        if let NbtTag::Compound(block) = tag {
+            let pos = block.get::<_, &NbtList>("pos")?;
+            let (x, y, z) = (pos.get::<i32>(0)?, pos.get::<i32>(1)?, pos.get::<i32>(2)?);
+            if !bounding_box.contains((x, y, z)) {
+                return Err(format!("Block position ({}, {}, {}) is outside the structure", x, y, z).into());
+            }
+
+            let state = block.get::<_, i32>("state")? as usize;
+            let palette_index = *palette_indices.get(state)
+                .ok_or_else(|| format!("Palette index {} out of range", state))?;
+            region.set_block_index(x, y, z, palette_index);
+
+            if let Ok(nbt) = block.get::<_, &NbtCompound>("nbt") {
+                region.add_block_entity(parse_block_entity(nbt, (x, y, z)));
+            }
+        }
+    }
+
+    if let Ok(entities) = root.get::<_, &NbtList>("entities") {
+        for tag in entities.iter() {
+            if let NbtTag::Compound(compound) = tag {
+                if let Some(entity) = parse_entity(compound) {
+                    region.add_entity(entity);
+                }
+            }
+        }
+    }
+
+    for (key, kind) in [("block_ticks", TickKind::Block), ("fluid_ticks", TickKind::Fluid)] {
+        if let Ok(ticks) = root.get::<_, &NbtList>(key) {
+            region.pending_ticks.extend(pending_tick::from_chunk_list(ticks, kind, (0, 0, 0)));
+        }
+    }
+
+    schematic.add_region(region);
+    Ok(schematic)
+}
+
+fn read_structure_root(data: &[u8]) -> Result<NbtCompound, SchematicError> {
+    let mut decoder = GzDecoder::new(data);
+    let mut decompressed = Vec::new();
+    decoder.read_to_end(&mut decompressed).map_err(SchematicError::Decompression)?;
+
+    let (root, _) = quartz_nbt::io::read_nbt(&mut Cursor::new(decompressed), Flavor::Uncompressed)?;
+    Ok(root)
+}
+
+fn parse_palette(root: &NbtCompound, palette_index: usize) -> Result<Vec<BlockState>, SchematicError> {
+    let palette = if let Ok(palettes) = root.get::<_, &NbtList>("palettes") {
+        match palettes.get::<&NbtList>(palette_index) {
+            Ok(palette) => palette,
+            Err(_) => return Err(format!("Palette {} not found, structure has {} palettes", palette_index, palettes.len()).into()),
+        }
+    } else if palette_index == 0 {
+        root.get::<_, &NbtList>("palette")?
+    } else {
+        return Err(format!("Palette {} not found, structure has 1 palette", palette_index).into());
+    };
+
+    let mut block_states = Vec::with_capacity(palette.len());
+    for tag in palette.iter() {
+        if let NbtTag::Compound(compound) = tag {
+            block_states.push(BlockState::from_nbt(compound)?);
+        }
+    }
+    Ok(block_states)
+}
+
+// Structure blocks keep their position in `pos`, the block entity NBT leaves it out.
+fn convert_block_entity(block_entity: &BlockEntity) -> NbtCompound {
+    let mut nbt = block_entity.to_format_nbt(block_entity.position);
+    for key in ["x", "y", "z"] {
+        nbt.inner_mut().remove(key);
+    }
+    nbt
+}
+
+fn parse_block_entity(nbt: &NbtCompound, position: (i32, i32, i32)) -> BlockEntity {
+    let mut block_entity = BlockEntity::from_format_nbt(nbt, (0, 0, 0));
+    block_entity.move_to(position);
+    block_entity
+}
+
+fn convert_entity(entity: &Entity, origin: (i32, i32, i32)) -> NbtTag {
+    let position = (
+        entity.position.0 - origin.0 as f64,
+        entity.position.1 - origin.1 as f64,
+        entity.position.2 - origin.2 as f64,
+    );
+    let pos = NbtList::from(vec![
+        NbtTag::Double(position.0),
+        NbtTag::Double(position.1),
+        NbtTag::Double(position.2),
+    ]);
+
+    let mut nbt = entity.nbt.to_quartz_nbt();
+    nbt.insert("id", NbtTag::String(entity.id.clone()));
+    nbt.insert("Pos", NbtTag::List(pos.clone()));
+
+    let mut compound = NbtCompound::new();
+    compound.insert("pos", NbtTag::List(pos));
+    compound.insert("blockPos", NbtTag::List(NbtList::from(vec![
+        NbtTag::Int(position.0.floor() as i32),
+        NbtTag::Int(position.1.floor() as i32),
+        NbtTag::Int(position.2.floor() as i32),
+    ])));
+    compound.insert("nbt", NbtTag::Compound(nbt));
+
+    NbtTag::Compound(compound)
+}
+
+fn parse_entity(compound: &NbtCompound) -> Option<Entity> {
+    let nbt = compound.get::<_, &NbtCompound>("nbt").ok()?;
+    let id = nbt.get::<_, &str>("id").ok()?.to_string();
+
+    let pos = compound.get::<_, &NbtList>("pos").ok()?;
+    if pos.len() != 3 {
+        return None;
+    }
+    let entity = Entity::new(id, (pos.get::<f64>(0).ok()?, pos.get::<f64>(1).ok()?, pos.get::<f64>(2).ok()?))
+        .with_nbt_from(nbt, &["id", "Pos"]);
+
+    Some(entity)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::item::ItemStack;
+    use crate::PendingTick;
+
+    #[test]
+    fn test_structure_roundtrip() {
+        let mut schematic = UniversalSchematic::new("Structure".to_string());
+        let stone = BlockState::new("minecraft:stone".to_string());
+        let lever = BlockState::new("minecraft:lever".to_string())
+            .with_property("face".to_string(), "floor".to_string())
+            .with_property("facing".to_string(), "north".to_string());
+
+        schematic.set_block(0, 0, 0, stone.clone());
+        schematic.set_block(1, 1, 1, lever.clone());
+        schematic.set_block(1, 0, 0, BlockState::new("minecraft:chest".to_string()));
+        schematic.add_block_entity(BlockEntity::create_chest((1, 0, 0), vec![ItemStack::new("minecraft:diamond", 64).with_slot(0)]));
+        schematic.add_entity(Entity::new("minecraft:armor_stand".to_string(), (0.5, 1.0, 0.5)));
+
+        let data = to_structure(&schematic).expect("Failed to write structure");
+        assert!(is_structure(&data));
+        assert!(!crate::schematic::is_schematic(&data));
+
+        let loaded = from_structure(&data).expect("Failed to read structure");
+        assert_eq!(loaded.get_block(0, 0, 0), Some(&stone));
+        assert_eq!(loaded.get_block(1, 1, 1), Some(&lever));
+        assert_eq!(loaded.get_block(0, 1, 1), Some(&BlockState::new("minecraft:air".to_string())));
+
+        let region = loaded.get_region("Main").unwrap();
+        let chest = region.block_entities.get(&(1, 0, 0)).expect("Chest was not read back");
+        assert_eq!(chest.id, "minecraft:chest");
+        assert!(chest.nbt.get("Items").is_some());
+
+        assert_eq!(region.entities.len(), 1);
+        assert_eq!(region.entities[0].id, "minecraft:armor_stand");
+        assert_eq!(region.entities[0].position, (0.5, 1.0, 0.5));
+    }
+
+    #[test]
+    fn test_structure_void_is_skipped() {
+        let mut schematic = UniversalSchematic::new("Voids".to_string());
+        schematic.set_block(0, 0, 0, BlockState::new("minecraft:stone".to_string()));
+        schematic.set_block(1, 0, 0, BlockState::new(STRUCTURE_VOID.to_string()));
+
+        let data = to_structure(&schematic).unwrap();
+        let root = read_structure_root(&data).unwrap();
+        assert_eq!(root.get::<_, &NbtList>("blocks").unwrap().len(), 1);
+
+        let loaded = from_structure(&data).unwrap();
+        assert_eq!(loaded.get_block(1, 0, 0), Some(&BlockState::new(STRUCTURE_VOID.to_string())));
+    }
+
+    #[test]
+    fn test_pending_ticks_are_relative_to_origin() {
+        let mut schematic = UniversalSchematic::new("Ticks".to_string());
+        schematic.set_block(10, 64, 10, BlockState::new("minecraft:stone".to_string()));
+        schematic.set_block(11, 64, 10, BlockState::new("minecraft:repeater".to_string()));
+        let region_name = schematic.default_region_name.clone();
+        schematic.get_region_mut(&region_name).unwrap()
+            .add_pending_tick(PendingTick::new(TickKind::Block, "minecraft:repeater".to_string(), (11, 64, 10), 2).with_priority(-1));
+
+        let loaded = from_structure(&to_structure(&schematic).unwrap()).unwrap();
+        let ticks = &loaded.get_region("Main").unwrap().pending_ticks;
+        assert_eq!(ticks, &vec![PendingTick::new(TickKind::Block, "minecraft:repeater".to_string(), (1, 0, 0), 2).with_priority(-1)]);
+    }
+
+    #[test]
+    fn test_multiple_palettes() {
+        let mut root = NbtCompound::new();
+        root.insert("DataVersion", NbtTag::Int(3700));
+        root.insert("size", NbtTag::List(NbtList::from(vec![NbtTag::Int(1), NbtTag::Int(1), NbtTag::Int(1)])));
+
+        let palettes = NbtList::from(vec![
+            NbtTag::List(NbtList::from(vec![BlockState::new("minecraft:oak_planks".to_string()).to_nbt()])),
+            NbtTag::List(NbtList::from(vec![BlockState::new("minecraft:spruce_planks".to_string()).to_nbt()])),
+        ]);
+        root.insert("palettes", NbtTag::List(palettes));
+
+        let mut block = NbtCompound::new();
+        block.insert("pos", NbtTag::List(NbtList::from(vec![NbtTag::Int(0), NbtTag::Int(0), NbtTag::Int(0)])));
+        block.insert("state", NbtTag::Int(0));
+        root.insert("blocks", NbtTag::List(NbtList::from(vec![NbtTag::Compound(block)])));
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        quartz_nbt::io::write_nbt(&mut encoder, None, &root, Flavor::Uncompressed).unwrap();
+        let data = encoder.finish().unwrap();
+
+        assert!(is_structure(&data));
+        assert_eq!(get_structure_palette_count(&data).unwrap(), 2);
+
+        let first = from_structure(&data).unwrap();
+        assert_eq!(first.get_block(0, 0, 0).unwrap().name, "minecraft:oak_planks");
+
+        let second = from_structure_with_palette(&data, 1).unwrap();
+        assert_eq!(second.get_block(0, 0, 0).unwrap().name, "minecraft:spruce_planks");
+
+        assert!(from_structure_with_palette(&data, 2).is_err());
+    }
+}
@@ -0,0 +1,679 @@
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, VecDeque};
+use std::mem::size_of;
+use crate::{BlockChange, BlockEntityChange, BlockState, SchematicDiff};
+use crate::block_entity::BlockEntity;
+use crate::bounding_box::BoundingBox;
+use crate::diff::missing_from;
+use crate::entity::Entity;
+use crate::region::Region;
+use crate::utils::{NbtMap, NbtValue};
+
+// Undo steps beyond this many estimated bytes are forgotten, oldest first.
+const DEFAULT_MEMORY_BUDGET: usize = 64 * 1024 * 1024;
+
+// A block entity before and after the transaction, `None` where there was none.
+type BlockEntityEdit = (Option<BlockEntity>, Option<BlockEntity>);
+
+/// The bounds of a region and the end of its palette, which block changes alone don't bring
+/// back. Palettes mostly grow, so only the entries past those both sides of a step share are kept.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct RegionLayout {
+    pub(crate) bounds: BoundingBox,
+    pub(crate) palette_tail: Vec<BlockState>,
+}
+
+impl RegionLayout {
+    fn of(region: &Region, shared: usize) -> Self {
+        RegionLayout { bounds: region.get_bounding_box(), palette_tail: region.palette[shared..].to_vec() }
+    }
+}
+
+/// A region as it was before and after a step, `None` where it didn't exist.
+pub(crate) struct RegionChange {
+    pub(crate) name: String,
+    // How many palette entries both sides start with, which the region holds either way.
+    pub(crate) shared: usize,
+    pub(crate) before: Option<RegionLayout>,
+    pub(crate) after: Option<RegionLayout>,
+}
+
+impl RegionChange {
+    /// The whole palette of one side, given the palette the region has now.
+    pub(crate) fn palette(&self, layout: &RegionLayout, current: &[BlockState]) -> Vec<BlockState> {
+        let mut palette = current[..self.shared.min(current.len())].to_vec();
+        palette.extend(layout.palette_tail.iter().cloned());
+        palette
+    }
+
+    fn between(name: String, before: Option<RegionSnapshot>, after: Option<&Region>) -> Option<Self> {
+        let (shared, before, after) = match (before, after) {
+            (None, None) => return None,
+            (None, Some(after)) => (0, None, Some(RegionLayout::of(after, 0))),
+            (Some(before), None) => {
+                let palette = before.palette.expect("a removed region keeps its palette");
+                (0, Some(RegionLayout { bounds: before.bounds, palette_tail: palette }), None)
+            }
+            (Some(before), Some(after)) => {
+                let shared = match &before.palette {
+                    Some(palette) => palette.iter().zip(&after.palette).take_while(|(a, b)| a == b).count(),
+                    None => before.palette_len.min(after.palette.len()),
+                };
+                let tail = before.palette.map_or_else(Vec::new, |palette| palette[shared..].to_vec());
+                (shared, Some(RegionLayout { bounds: before.bounds, palette_tail: tail }), Some(RegionLayout::of(after, shared)))
+            }
+        };
+        (before != after).then_some(RegionChange { name, shared, before, after })
+    }
+}
+
+// A region as a transaction first found it. While edits only append to its palette, the length
+// is all that's needed; the entries are copied once an edit is about to rewrite or drop them.
+struct RegionSnapshot {
+    bounds: BoundingBox,
+    palette_len: usize,
+    palette: Option<Vec<BlockState>>,
+}
+
+/// One committed transaction.
+pub(crate) struct Step {
+    pub(crate) diff: SchematicDiff,
+    pub(crate) regions: Vec<RegionChange>,
+}
+
+// The changes made since `begin_transaction`, keeping the first and latest state of everything
+// touched so editing one block a hundred times costs one entry. Regions are kept as they were
+// when first touched; how they ended up is read when the transaction is committed.
+#[derive(Default)]
+pub(crate) struct Transaction {
+    blocks: HashMap<(i32, i32, i32), (BlockState, BlockState)>,
+    block_entities: HashMap<(i32, i32, i32), BlockEntityEdit>,
+    added_entities: Vec<Entity>,
+    removed_entities: Vec<Entity>,
+    regions: HashMap<String, Option<RegionSnapshot>>,
+    // A running estimate of the bytes held above, checked against the budget as edits come in.
+    size: usize,
+    // Set once `size` passed the budget. Nothing more is recorded and the commit is dropped.
+    overflowed: bool,
+}
+
+impl Transaction {
+    fn into_step(self, regions: &HashMap<String, Region>) -> Step {
+        let by_position = |(x, y, z): (i32, i32, i32)| (y, z, x);
+        let mut blocks: Vec<BlockChange> = self.blocks.into_iter()
+            .filter(|(_, (from, to))| from != to)
+            .map(|(position, (from, to))| BlockChange { position, from, to })
+            .collect();
+        blocks.sort_by_key(|change| by_position(change.position));
+        let mut block_entities: Vec<BlockEntityChange> = self.block_entities.into_iter()
+            .filter(|(_, (from, to))| from != to)
+            .map(|(position, (from, to))| BlockEntityChange { position, from, to })
+            .collect();
+        block_entities.sort_by_key(|change| by_position(change.position));
+
+        // An entity added and removed again within the transaction cancels out.
+        let added: Vec<&Entity> = self.added_entities.iter().collect();
+        let removed: Vec<&Entity> = self.removed_entities.iter().collect();
+        let diff = SchematicDiff {
+            blocks,
+            block_entities,
+            added_entities: missing_from(&added, &removed),
+            removed_entities: missing_from(&removed, &added),
+        };
+        let regions = self.regions.into_iter()
+            .filter_map(|(name, before)| {
+                let after = regions.get(&name);
+                RegionChange::between(name, before, after)
+            })
+            .collect();
+        Step { diff, regions }
+    }
+}
+
+impl Step {
+    fn is_empty(&self) -> bool {
+        self.diff.is_empty() && self.regions.is_empty()
+    }
+}
+
+/// Committed transactions of a schematic, kept as diffs so only what changed is stored.
+pub(crate) struct EditHistory {
+    undo: VecDeque<Step>,
+    redo: Vec<Step>,
+    pending: Option<Transaction>,
+    memory_budget: usize,
+    memory_used: usize,
+}
+
+impl Default for EditHistory {
+    fn default() -> Self {
+        EditHistory {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            pending: None,
+            memory_budget: DEFAULT_MEMORY_BUDGET,
+            memory_used: 0,
+        }
+    }
+}
+
+// A copied schematic starts with an empty history of its own.
+impl Clone for EditHistory {
+    fn clone(&self) -> Self {
+        EditHistory { memory_budget: self.memory_budget, ..EditHistory::default() }
+    }
+}
+
+fn nbt_size(nbt: &NbtMap) -> usize {
+    nbt.iter().map(|(key, value)| key.len() + value_size(value)).sum()
+}
+
+fn value_size(value: &NbtValue) -> usize {
+    size_of::<NbtValue>() + match value {
+        NbtValue::String(text) => text.len(),
+        NbtValue::List(values) => values.iter().map(value_size).sum(),
+        NbtValue::Compound(map) => nbt_size(map),
+        NbtValue::ByteArray(values) => values.len(),
+        NbtValue::IntArray(values) => values.len() * 4,
+        NbtValue::LongArray(values) => values.len() * 8,
+        _ => 0,
+    }
+}
+
+fn block_size(block: &BlockState) -> usize {
+    size_of::<BlockState>() + block.name.len()
+        + block.properties.iter().map(|(key, value)| key.len() + value.len()).sum::<usize>()
+}
+
+fn block_entity_size(block_entity: &Option<BlockEntity>) -> usize {
+    block_entity.as_ref().map_or(0, |block_entity| nbt_size(&block_entity.nbt))
+}
+
+fn entity_size(entity: &Entity) -> usize {
+    size_of::<Entity>() + nbt_size(&entity.nbt)
+}
+
+fn palette_size(palette: &[BlockState]) -> usize {
+    palette.iter().map(block_size).sum()
+}
+
+fn layout_size(layout: &Option<RegionLayout>) -> usize {
+    size_of::<Option<RegionLayout>>() + layout.as_ref().map_or(0, |layout| palette_size(&layout.palette_tail))
+}
+
+// The least a block change recorded by a bulk edit costs, used to turn one down before it runs.
+const MIN_BLOCK_CHANGE_SIZE: usize = size_of::<BlockChange>() + 2 * size_of::<BlockState>();
+
+// A rough count of the bytes a step holds, which is all the budget needs.
+fn estimated_size(step: &Step) -> usize {
+    let diff = &step.diff;
+    let blocks: usize = diff.blocks.iter()
+        .map(|change| size_of::<BlockChange>() + block_size(&change.from) + block_size(&change.to))
+        .sum();
+    let block_entities: usize = diff.block_entities.iter()
+        .map(|change| size_of::<BlockEntityChange>() + block_entity_size(&change.from) + block_entity_size(&change.to))
+        .sum();
+    let entities: usize = diff.added_entities.iter().chain(&diff.removed_entities).map(entity_size).sum();
+    let regions: usize = step.regions.iter()
+        .map(|change| change.name.len() + layout_size(&change.before) + layout_size(&change.after))
+        .sum();
+    blocks + block_entities + entities + regions
+}
+
+impl EditHistory {
+    pub(crate) fn is_recording(&self) -> bool {
+        self.pending.as_ref().is_some_and(|transaction| !transaction.overflowed)
+    }
+
+    pub(crate) fn begin(&mut self) {
+        self.pending.get_or_insert_with(Transaction::default);
+    }
+
+    /// Ends the open transaction, reading how its regions ended up from `regions`. Returns false
+    /// when it changed nothing, or when it was too large for the budget and was dropped.
+    pub(crate) fn commit(&mut self, regions: &HashMap<String, Region>) -> bool {
+        let Some(transaction) = self.pending.take() else { return false };
+        if transaction.overflowed {
+            return false;
+        }
+        let step = transaction.into_step(regions);
+        if step.is_empty() {
+            return false;
+        }
+        self.memory_used -= self.redo.drain(..).map(|step| estimated_size(&step)).sum::<usize>();
+        let size = estimated_size(&step);
+        if size > self.memory_budget {
+            self.clear();
+            return false;
+        }
+        self.memory_used += size;
+        self.undo.push_back(step);
+        self.enforce_budget();
+        true
+    }
+
+    // Stops recording for a bulk edit, which records what it changed once it is done.
+    pub(crate) fn suspend(&mut self) -> Option<Transaction> {
+        self.pending.take()
+    }
+
+    pub(crate) fn resume(&mut self, transaction: Option<Transaction>) {
+        self.pending = transaction;
+    }
+
+    // The open transaction, unless there is none or it outgrew the budget.
+    fn recording(&mut self) -> Option<&mut Transaction> {
+        self.pending.as_mut().filter(|transaction| !transaction.overflowed)
+    }
+
+    fn check_pending_size(&mut self) {
+        let budget = self.memory_budget;
+        if self.pending.as_ref().is_some_and(|transaction| transaction.size > budget) {
+            self.overflow();
+        }
+    }
+
+    // Gives up on a transaction that grew past the budget. The steps before it go too, as the
+    // edits that can no longer be undone would stand between them and the schematic.
+    fn overflow(&mut self) {
+        self.pending = Some(Transaction { overflowed: true, ..Transaction::default() });
+        self.clear();
+    }
+
+    /// Checks that a bulk edit changing up to `blocks` blocks could fit in the budget before its
+    /// blocks are copied, giving up on the transaction right away when it can't. Returns whether
+    /// the edit should be recorded.
+    pub(crate) fn reserve(&mut self, blocks: usize) -> bool {
+        let budget = self.memory_budget;
+        let Some(transaction) = self.recording() else { return false };
+        if transaction.size.saturating_add(blocks.saturating_mul(MIN_BLOCK_CHANGE_SIZE)) > budget {
+            self.overflow();
+            return false;
+        }
+        true
+    }
+
+    pub(crate) fn record_block(&mut self, position: (i32, i32, i32), from: BlockState, to: BlockState) {
+        let Some(transaction) = self.recording() else { return };
+        match transaction.blocks.entry(position) {
+            Entry::Occupied(mut entry) => {
+                transaction.size = transaction.size + block_size(&to) - block_size(&entry.get().1);
+                entry.get_mut().1 = to;
+            }
+            // Nothing to keep for a block that was left as it was.
+            Entry::Vacant(_) if from == to => {}
+            Entry::Vacant(entry) => {
+                transaction.size += size_of::<BlockChange>() + block_size(&from) + block_size(&to);
+                entry.insert((from, to));
+            }
+        }
+        self.check_pending_size();
+    }
+
+    pub(crate) fn record_block_entity(&mut self, position: (i32, i32, i32), from: Option<BlockEntity>, to: Option<BlockEntity>) {
+        let Some(transaction) = self.recording() else { return };
+        match transaction.block_entities.entry(position) {
+            Entry::Occupied(mut entry) => {
+                transaction.size = transaction.size + block_entity_size(&to) - block_entity_size(&entry.get().1);
+                entry.get_mut().1 = to;
+            }
+            Entry::Vacant(entry) => {
+                transaction.size += size_of::<BlockEntityChange>() + block_entity_size(&from) + block_entity_size(&to);
+                entry.insert((from, to));
+            }
+        }
+        self.check_pending_size();
+    }
+
+    pub(crate) fn record_added_entity(&mut self, entity: Entity) {
+        let Some(transaction) = self.recording() else { return };
+        transaction.size += entity_size(&entity);
+        transaction.added_entities.push(entity);
+        self.check_pending_size();
+    }
+
+    pub(crate) fn record_removed_entity(&mut self, entity: Entity) {
+        let Some(transaction) = self.recording() else { return };
+        transaction.size += entity_size(&entity);
+        transaction.removed_entities.push(entity);
+        self.check_pending_size();
+    }
+
+    /// Notes how a region looked before the transaction first touched it, `None` when it
+    /// didn't exist yet. Pass `whole_palette` before an edit that rewrites or drops the palette
+    /// rather than appending to it. Later calls for the same region only add that.
+    pub(crate) fn record_region(&mut self, name: &str, region: Option<&Region>, whole_palette: bool) {
+        let Some(transaction) = self.recording() else { return };
+        match transaction.regions.get_mut(name) {
+            Some(Some(snapshot)) => {
+                if whole_palette && snapshot.palette.is_none() {
+                    // Edits so far only appended, so the palette still starts as it was.
+                    let palette = region.map_or_else(Vec::new, |region| region.palette[..snapshot.palette_len].to_vec());
+                    transaction.size += palette_size(&palette);
+                    snapshot.palette = Some(palette);
+                }
+            }
+            Some(None) => {}
+            None => {
+                let snapshot = region.map(|region| RegionSnapshot {
+                    bounds: region.get_bounding_box(),
+                    palette_len: region.palette.len(),
+                    palette: whole_palette.then(|| region.palette.clone()),
+                });
+                transaction.size += name.len() + size_of::<Option<RegionSnapshot>>()
+                    + snapshot.as_ref().and_then(|snapshot| snapshot.palette.as_deref()).map_or(0, palette_size);
+                transaction.regions.insert(name.to_string(), snapshot);
+            }
+        }
+        self.check_pending_size();
+    }
+
+    pub(crate) fn record_diff(&mut self, diff: &SchematicDiff) {
+        for change in &diff.blocks {
+            self.record_block(change.position, change.from.clone(), change.to.clone());
+        }
+        for change in &diff.block_entities {
+            self.record_block_entity(change.position, change.from.clone(), change.to.clone());
+        }
+        for entity in &diff.added_entities {
+            self.record_added_entity(entity.clone());
+        }
+        for entity in &diff.removed_entities {
+            self.record_removed_entity(entity.clone());
+        }
+    }
+
+    pub(crate) fn take_undo(&mut self) -> Option<Step> {
+        self.undo.pop_back()
+    }
+
+    pub(crate) fn take_redo(&mut self) -> Option<Step> {
+        self.redo.pop()
+    }
+
+    pub(crate) fn push_undo(&mut self, step: Step) {
+        self.undo.push_back(step);
+    }
+
+    pub(crate) fn push_redo(&mut self, step: Step) {
+        self.redo.push(step);
+    }
+
+    pub(crate) fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub(crate) fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.memory_used = 0;
+    }
+
+    pub(crate) fn set_memory_budget(&mut self, bytes: usize) {
+        self.memory_budget = bytes;
+        self.enforce_budget();
+        self.check_pending_size();
+    }
+
+    // Forgets the oldest undo steps, then redo steps, until the history fits the budget.
+    fn enforce_budget(&mut self) {
+        while self.memory_used > self.memory_budget {
+            let Some(step) = self.undo.pop_front().or_else(|| (!self.redo.is_empty()).then(|| self.redo.remove(0))) else { break };
+            self.memory_used -= estimated_size(&step);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{BlockState, UniversalSchematic};
+    use crate::block_entity::BlockEntity;
+    use crate::bounding_box::BoundingBox;
+    use crate::entity::Entity;
+    use crate::region::{PasteMode, Region};
+
+    fn block(state: &str) -> BlockState {
+        state.parse().unwrap()
+    }
+
+    // Everything undo and redo must bring back, beyond what `diff` compares.
+    fn assert_same(schematic: &UniversalSchematic, expected: &UniversalSchematic) {
+        assert!(schematic.diff(expected).is_empty());
+        assert_eq!(schematic.get_bounding_box(), expected.get_bounding_box());
+        let mut names = schematic.get_region_names();
+        names.sort();
+        let mut expected_names = expected.get_region_names();
+        expected_names.sort();
+        assert_eq!(names, expected_names);
+        for name in names {
+            let (region, expected) = (schematic.get_region(&name).unwrap(), expected.get_region(&name).unwrap());
+            assert_eq!(region.get_bounding_box(), expected.get_bounding_box(), "bounds of {}", name);
+            assert_eq!(region.get_palette(), expected.get_palette(), "palette of {}", name);
+            assert_eq!(region.entities, expected.entities, "entities of {}", name);
+        }
+    }
+
+    #[test]
+    fn test_undo_and_redo_transactions() {
+        let mut schematic = UniversalSchematic::new("Test".to_string());
+        schematic.set_block(0, 0, 0, block("stone"));
+        assert!(!schematic.can_undo());
+        let original = schematic.clone();
+
+        schematic.begin_transaction();
+        for _ in 0..100 {
+            schematic.set_block(0, 0, 0, block("dirt"));
+            schematic.set_block(0, 0, 0, block("glass"));
+        }
+        schematic.set_block(1, 0, 0, block("chest"));
+        schematic.add_block_entity(BlockEntity::new("minecraft:chest".to_string(), (1, 0, 0)));
+        schematic.add_entity(Entity::new("minecraft:pig".to_string(), (0.5, 1.0, 0.5)));
+        assert!(schematic.commit());
+        let edited = schematic.clone();
+
+        schematic.begin_transaction();
+        schematic.fill(&BoundingBox::new((0, 1, 0), (1, 1, 1)), block("sand"));
+        assert!(schematic.commit());
+        schematic.begin_transaction();
+        assert!(!schematic.commit());
+
+        assert!(schematic.undo().unwrap());
+        assert_same(&schematic, &edited);
+        assert!(schematic.undo().unwrap());
+        assert_same(&schematic, &original);
+        assert_eq!(schematic.get_block(0, 0, 0), Some(&block("stone")));
+        assert_eq!(schematic.get_block(1, 0, 0), None);
+        assert!(schematic.get_block_entities_as_list().is_empty());
+        assert!(!schematic.undo().unwrap());
+
+        assert!(schematic.redo().unwrap());
+        assert_same(&schematic, &edited);
+        assert!(schematic.can_redo());
+        schematic.begin_transaction();
+        schematic.set_block(5, 0, 0, block("stone"));
+        schematic.commit();
+        assert!(!schematic.can_redo());
+    }
+
+    #[test]
+    fn test_undo_bulk_edits() {
+        let mut schematic = UniversalSchematic::new("Test".to_string());
+        schematic.fill(&BoundingBox::new((0, 0, 0), (3, 1, 3)), block("stone"));
+        schematic.set_block(1, 1, 1, block("chest"));
+        schematic.add_block_entity(BlockEntity::new("minecraft:chest".to_string(), (1, 1, 1)));
+        let original = schematic.clone();
+
+        let mut source = Region::new("Source".to_string(), (0, 0, 0), (2, 1, 1));
+        source.set_block(0, 0, 0, block("gold_block"));
+        source.add_entity(Entity::new("minecraft:pig".to_string(), (0.5, 0.0, 0.5)));
+        schematic.begin_transaction();
+        assert_eq!(schematic.replace(&block("stone"), block("glass")), 31);
+        schematic.paste(&source, (10, 0, 0), PasteMode::SkipAir);
+        schematic.rotate_y(90).unwrap();
+        schematic.fill(&BoundingBox::new((-2, 0, 0), (-1, 0, 0)), block("sand"));
+        schematic.trim_to_content();
+        assert!(schematic.commit());
+        let edited = schematic.clone();
+
+        assert!(schematic.undo().unwrap());
+        assert_same(&schematic, &original);
+        assert_eq!(schematic.get_block_entities_as_list().len(), 1);
+        assert!(schematic.redo().unwrap());
+        assert_same(&schematic, &edited);
+    }
+
+    #[test]
+    fn test_replace_records_only_replaced_blocks() {
+        let mut schematic = UniversalSchematic::new("Test".to_string());
+        schematic.fill(&BoundingBox::new((0, 0, 0), (9, 9, 9)), block("stone"));
+        schematic.set_block(5, 5, 5, block("dirt"));
+
+        schematic.begin_transaction();
+        schematic.replace(&block("dirt"), block("glass"));
+        let transaction = schematic.history.suspend().unwrap();
+        assert_eq!(transaction.blocks.len(), 1);
+        schematic.history.resume(Some(transaction));
+        assert!(schematic.commit());
+        assert!(schematic.undo().unwrap());
+        assert_eq!(schematic.get_block(5, 5, 5), Some(&block("dirt")));
+    }
+
+    #[test]
+    fn test_fill_copies_only_blocks_it_changes() {
+        let mut schematic = UniversalSchematic::new("Test".to_string());
+        let bounds = BoundingBox::new((0, 0, 0), (63, 63, 63));
+        schematic.fill(&bounds, block("stone"));
+        schematic.set_history_budget(4096);
+
+        schematic.begin_transaction();
+        schematic.fill(&bounds, block("stone"));
+        assert!(schematic.history.is_recording());
+        assert!(!schematic.commit());
+
+        // Too large for the budget, which is known before anything is copied.
+        schematic.begin_transaction();
+        schematic.fill(&bounds, block("dirt"));
+        assert!(!schematic.history.is_recording());
+        assert!(!schematic.commit());
+        assert_eq!(schematic.get_block(63, 63, 63), Some(&block("dirt")));
+    }
+
+    #[test]
+    fn test_steps_keep_only_appended_palette_entries() {
+        let mut schematic = UniversalSchematic::new("Test".to_string());
+        let names = ["stone", "dirt", "glass", "sand", "gravel", "oak_planks", "bricks", "obsidian"];
+        for (x, name) in names.iter().enumerate() {
+            schematic.set_block(x as i32, 0, 0, block(name));
+        }
+        let original = schematic.clone();
+        let palette_len = schematic.get_region("Main").unwrap().get_palette().len();
+
+        schematic.begin_transaction();
+        schematic.set_block(0, 0, 0, block("gold_block"));
+        assert!(schematic.commit());
+        let edited = schematic.clone();
+        let step = schematic.history.undo.back().unwrap();
+        assert_eq!(step.regions.len(), 1);
+        let change = &step.regions[0];
+        assert_eq!(change.shared, palette_len);
+        assert!(change.before.as_ref().unwrap().palette_tail.is_empty());
+        assert_eq!(change.after.as_ref().unwrap().palette_tail, vec![block("gold_block")]);
+
+        assert!(schematic.undo().unwrap());
+        assert_same(&schematic, &original);
+        assert!(schematic.redo().unwrap());
+        assert_same(&schematic, &edited);
+    }
+
+    #[test]
+    fn test_undo_apply() {
+        let mut schematic = UniversalSchematic::new("Test".to_string());
+        schematic.set_block(0, 0, 0, block("stone"));
+        let original = schematic.clone();
+        let mut target = schematic.clone();
+        target.set_block(4, 0, 0, block("diamond_block"));
+        target.add_entity(Entity::new("minecraft:pig".to_string(), (0.5, 0.0, 0.5)));
+        let diff = schematic.diff(&target);
+
+        schematic.begin_transaction();
+        schematic.apply(&diff).unwrap();
+        assert!(schematic.commit());
+        let edited = schematic.clone();
+
+        assert!(schematic.undo().unwrap());
+        assert_same(&schematic, &original);
+        assert!(schematic.redo().unwrap());
+        assert_same(&schematic, &edited);
+    }
+
+    #[test]
+    fn test_undo_adding_and_removing_regions() {
+        let mut schematic = UniversalSchematic::new("Test".to_string());
+        schematic.set_block(0, 0, 0, block("stone"));
+        let mut old = Region::new("Old".to_string(), (20, 0, 0), (2, 2, 2));
+        old.set_block(21, 1, 1, block("lapis_block"));
+        old.add_entity(Entity::new("minecraft:cow".to_string(), (20.5, 0.0, 0.5)));
+        schematic.add_region(old);
+        let original = schematic.clone();
+
+        let mut new = Region::new("New".to_string(), (-5, 0, 0), (1, 1, 1));
+        new.set_block(-5, 0, 0, block("glass"));
+        schematic.begin_transaction();
+        assert!(schematic.add_region(new));
+        assert!(schematic.remove_region("Old").is_some());
+        assert!(schematic.commit());
+        let edited = schematic.clone();
+
+        assert!(schematic.undo().unwrap());
+        assert_same(&schematic, &original);
+        assert_eq!(schematic.get_block(21, 1, 1), Some(&block("lapis_block")));
+        assert!(schematic.redo().unwrap());
+        assert_same(&schematic, &edited);
+    }
+
+    #[test]
+    fn test_data_version_conversion_waits_for_commit() {
+        let mut schematic = UniversalSchematic::new("Test".to_string());
+        schematic.metadata.mc_version = Some(3700);
+        schematic.begin_transaction();
+        assert!(!schematic.convert_data_version(3837));
+        assert_eq!(schematic.metadata.mc_version, Some(3700));
+        schematic.commit();
+        assert!(schematic.convert_data_version(3837));
+    }
+
+    #[test]
+    fn test_history_budget_drops_oldest_steps() {
+        let mut schematic = UniversalSchematic::new("Test".to_string());
+        for x in 0..10 {
+            schematic.begin_transaction();
+            schematic.set_block(x, 0, 0, block("stone"));
+            schematic.commit();
+        }
+        schematic.set_history_budget(0);
+        assert!(!schematic.can_undo());
+
+        schematic.set_history_budget(1 << 20);
+        schematic.begin_transaction();
+        schematic.set_block(0, 0, 0, block("dirt"));
+        schematic.commit();
+        // An edit the history didn't see makes the step impossible to undo, and it is kept.
+        schematic.get_region_mut("Main").unwrap().set_block(0, 0, 0, block("glass"));
+        assert!(schematic.undo().is_err());
+        assert!(schematic.can_undo());
+
+        // A transaction outgrowing the budget is dropped, and so is everything before it.
+        schematic.set_history_budget(4096);
+        schematic.begin_transaction();
+        schematic.fill(&BoundingBox::new((0, 0, 0), (15, 15, 15)), block("dirt"));
+        assert!(!schematic.history.is_recording());
+        assert!(!schematic.can_undo());
+        assert!(!schematic.commit());
+        assert!(!schematic.can_undo());
+        assert_eq!(schematic.get_block(15, 15, 15), Some(&block("dirt")));
+    }
+}
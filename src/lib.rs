@@ -5,7 +5,9 @@ mod universal_schematic;
 mod region;
 mod block_state;
 mod entity;
+mod pending_tick;
 pub mod block_entity;
+pub mod block_registry;
 mod formats;
 mod print_utils;
 mod bounding_box;
@@ -15,6 +17,14 @@ mod block_position;
 pub mod utils;
 mod item;
 mod chunk;
+mod transform;
+mod error;
+mod block_storage;
+mod data_fixer;
+mod material_list;
+mod recipes;
+mod diff;
+mod history;
 
 // Feature-specific modules
 #[cfg(feature = "wasm")]
@@ -25,8 +35,16 @@ pub mod ffi;
 // Public re-exports
 pub use universal_schematic::UniversalSchematic;
 pub use block_state::BlockState;
-pub use formats::{litematic, schematic};
-pub use print_utils::{format_schematic, format_json_schematic};
+pub use bounding_box::BoundingBox;
+pub use transform::Axis;
+pub use region::{PasteMode, Region};
+pub use pending_tick::{PendingTick, TickKind};
+pub use material_list::{MaterialEntry, MaterialList};
+pub use diff::{BlockChange, BlockEntityChange, SchematicDiff};
+pub use recipes::{recipes_for, Recipe, RecipeKind, RecipePreference, ResolveOptions, ResolvedMaterials};
+pub use error::{BlockStateParseError, BlockValidationError, SchematicError, SnbtError, Warning};
+pub use formats::{anvil, litematic, mcedit, mcfunction, mcstructure, schematic, structure};
+pub use print_utils::{format_schematic, format_json_schematic, format_diff};
 
 // Re-export WASM types when building with WASM feature
 #[cfg(feature = "wasm")]
@@ -0,0 +1,284 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use serde_json::json;
+use crate::{BlockState, UniversalSchematic};
+use crate::block_entity::BlockEntity;
+use crate::utils::NbtValue;
+
+// `<block> <item>...` per line, see the header of the file.
+const MATERIAL_ITEMS: &str = include_str!("material_items.txt");
+
+// Properties that say how many items were stacked into one block.
+const COUNT_PROPERTIES: [&str; 5] = ["candles", "eggs", "flower_amount", "layers", "pickles"];
+
+const SHULKER_BOX_SLOTS: usize = 27;
+
+struct ItemTable {
+    exact: HashMap<&'static str, Vec<&'static str>>,
+    // Prefix and suffix around the `*`, in file order.
+    patterns: Vec<(&'static str, &'static str, Vec<&'static str>)>,
+}
+
+fn item_table() -> &'static ItemTable {
+    static TABLE: OnceLock<ItemTable> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        let mut table = ItemTable { exact: HashMap::new(), patterns: Vec::new() };
+        for line in MATERIAL_ITEMS.lines() {
+            let mut fields = line.split_whitespace();
+            let Some(block) = fields.next().filter(|block| !block.starts_with('#')) else { continue };
+            let items: Vec<&str> = fields.filter(|item| *item != "-").collect();
+            match block.split_once('*') {
+                Some((prefix, suffix)) => table.patterns.push((prefix, suffix, items)),
+                None => {
+                    table.exact.insert(block, items);
+                }
+            }
+        }
+        table
+    })
+}
+
+// The items that place one block with this id, before any count from its state.
+fn items_for_id(id: &str) -> Vec<String> {
+    let Some(id) = id.strip_prefix("minecraft:") else { return vec![id.to_string()] };
+    let table = item_table();
+    if let Some(items) = table.exact.get(id) {
+        return items.iter().map(|item| format!("minecraft:{}", item)).collect();
+    }
+    for (prefix, suffix, items) in &table.patterns {
+        if id.len() >= prefix.len() + suffix.len() && id.starts_with(prefix) && id.ends_with(suffix) {
+            let matched = &id[prefix.len()..id.len() - suffix.len()];
+            return items.iter().map(|item| format!("minecraft:{}", item.replace('*', matched))).collect();
+        }
+    }
+    vec![format!("minecraft:{}", id)]
+}
+
+fn block_items(block: &BlockState) -> Vec<(String, usize)> {
+    let property = |name: &str| block.properties.get(name).map(String::as_str);
+    // Doors, tall plants and beds are counted once, from their lower half or foot.
+    if property("half") == Some("upper") || property("part") == Some("head") {
+        return Vec::new();
+    }
+    // Only source blocks are placed with a bucket, the game makes the flowing ones.
+    if matches!(block.name.as_str(), "minecraft:water" | "minecraft:lava") && property("level").is_some_and(|level| level != "0") {
+        return Vec::new();
+    }
+
+    let count = if block.name.ends_with("_slab") && property("type") == Some("double") {
+        2
+    } else {
+        COUNT_PROPERTIES.iter()
+            .find_map(|name| property(name)?.parse().ok())
+            .unwrap_or(1)
+    };
+    items_for_id(&block.name).into_iter().map(|item| (item, count)).collect()
+}
+
+fn container_items(block_entity: &BlockEntity) -> Vec<(String, usize)> {
+    let Some(NbtValue::List(items)) = block_entity.nbt.get("Items") else { return Vec::new() };
+    items.iter()
+        .filter_map(|item| {
+            let item = item.as_compound()?;
+            let id = item.get("id")?.as_string()?;
+            // `count` since 1.20.5, `Count` before.
+            let count = item.get("count").or_else(|| item.get("Count")).and_then(NbtValue::as_i32).unwrap_or(1);
+            Some((id.clone(), count.max(0) as usize))
+        })
+        .collect()
+}
+
+// Item suffixes and ids that never stack, such as tools, armor and vehicles.
+const UNSTACKABLE_SUFFIXES: &[&str] = &[
+    "_bucket", "_bed", "shulker_box", "potion", "_sword", "_pickaxe", "_axe", "_shovel", "_hoe",
+    "_helmet", "_chestplate", "_leggings", "_boots", "_horse_armor", "_boat", "_raft", "minecart",
+    "_stew", "_soup", "_on_a_stick",
+];
+const UNSTACKABLE_ITEMS: &[&str] = &[
+    "cake", "totem_of_undying", "elytra", "shield", "bow", "crossbow", "trident", "mace",
+    "fishing_rod", "flint_and_steel", "shears", "brush", "spyglass", "saddle", "wolf_armor",
+    "writable_book", "enchanted_book", "knowledge_book", "debug_stick", "goat_horn", "bundle",
+];
+
+fn max_stack_size(item: &str) -> usize {
+    let id = item.strip_prefix("minecraft:").unwrap_or(item);
+    if UNSTACKABLE_SUFFIXES.iter().any(|suffix| id.ends_with(suffix)) || UNSTACKABLE_ITEMS.contains(&id)
+        || id.starts_with("music_disc_") || id.ends_with("_bundle") {
+        1
+    } else if id.ends_with("_sign") || id.ends_with("_banner")
+        || matches!(id, "bucket" | "snowball" | "egg" | "ender_pearl" | "armor_stand" | "honey_bottle" | "written_book") {
+        16
+    } else {
+        64
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MaterialEntry {
+    pub item: String,
+    pub count: usize,
+    /// How many of the item fit in one inventory slot.
+    pub stack_size: usize,
+}
+
+impl MaterialEntry {
+    /// Full stacks and the items left over.
+    pub fn stacks(&self) -> (usize, usize) {
+        (self.count / self.stack_size, self.count % self.stack_size)
+    }
+
+    /// Shulker boxes needed to carry all of the items.
+    pub fn shulker_boxes(&self) -> usize {
+        self.count.div_ceil(self.stack_size * SHULKER_BOX_SLOTS)
+    }
+}
+
+/// The items needed to build a schematic, like Litematica's material list.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MaterialList {
+    /// Sorted by count, largest first, then by item id.
+    pub entries: Vec<MaterialEntry>,
+}
+
+impl MaterialList {
+    /// Maps every block to the items that place it. With `include_containers`, the items
+    /// stored in chests, barrels and other block entities are added too.
+    pub fn from_schematic(schematic: &UniversalSchematic, include_containers: bool) -> Self {
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for (block, count) in schematic.count_block_types() {
+            for (item, per_block) in block_items(&block) {
+                *counts.entry(item).or_insert(0) += per_block * count;
+            }
+        }
+        if include_containers {
+            for region in schematic.regions.values() {
+                for block_entity in region.block_entities.values() {
+                    for (item, count) in container_items(block_entity) {
+                        *counts.entry(item).or_insert(0) += count;
+                    }
+                }
+            }
+        }
+        MaterialList::from_counts(counts)
+    }
+
+    pub(crate) fn from_counts(counts: HashMap<String, usize>) -> Self {
+        let mut entries: Vec<MaterialEntry> = counts.into_iter()
+            .filter(|(_, count)| *count > 0)
+            .map(|(item, count)| {
+                let stack_size = max_stack_size(&item);
+                MaterialEntry { item, count, stack_size }
+            })
+            .collect();
+        entries.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.item.cmp(&b.item)));
+        MaterialList { entries }
+    }
+
+    pub fn count(&self, item: &str) -> usize {
+        self.entries.iter().find(|entry| entry.item == item).map_or(0, |entry| entry.count)
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("item,count,stacks,remainder,shulker_boxes\n");
+        for entry in &self.entries {
+            let (stacks, remainder) = entry.stacks();
+            csv.push_str(&format!("{},{},{},{},{}\n", entry.item, entry.count, stacks, remainder, entry.shulker_boxes()));
+        }
+        csv
+    }
+
+    pub fn to_json(&self) -> String {
+        let entries: Vec<_> = self.entries.iter().map(|entry| {
+            let (stacks, remainder) = entry.stacks();
+            json!({
+                "item": entry.item,
+                "count": entry.count,
+                "stack_size": entry.stack_size,
+                "stacks": stacks,
+                "remainder": remainder,
+                "shulker_boxes": entry.shulker_boxes(),
+            })
+        }).collect();
+        serde_json::to_string_pretty(&entries).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::item::ItemStack;
+
+    fn block(state: &str) -> BlockState {
+        state.parse().unwrap()
+    }
+
+    #[test]
+    fn test_blocks_map_to_items() {
+        let items = |state: &str| block_items(&block(state));
+        assert_eq!(items("redstone_wire[power=3]"), vec![("minecraft:redstone".to_string(), 1)]);
+        assert_eq!(items("wall_torch[facing=east]"), vec![("minecraft:torch".to_string(), 1)]);
+        assert_eq!(items("redstone_wall_torch"), vec![("minecraft:redstone_torch".to_string(), 1)]);
+        assert_eq!(items("oak_wall_sign"), vec![("minecraft:oak_sign".to_string(), 1)]);
+        assert_eq!(items("stone_slab[type=double]"), vec![("minecraft:stone_slab".to_string(), 2)]);
+        assert_eq!(items("sea_pickle[pickles=3]"), vec![("minecraft:sea_pickle".to_string(), 3)]);
+        assert_eq!(items("water[level=0]"), vec![("minecraft:water_bucket".to_string(), 1)]);
+        assert_eq!(items("potted_poppy"), vec![("minecraft:flower_pot".to_string(), 1), ("minecraft:poppy".to_string(), 1)]);
+        assert_eq!(items("mymod:machine"), vec![("mymod:machine".to_string(), 1)]);
+        assert!(items("water[level=3]").is_empty());
+        assert!(items("oak_door[half=upper]").is_empty());
+        assert!(items("red_bed[part=head]").is_empty());
+        assert!(items("air").is_empty());
+    }
+
+    #[test]
+    fn test_material_list_totals() {
+        let mut schematic = UniversalSchematic::new("Test".to_string());
+        for x in 0..100 {
+            schematic.set_block(x, 0, 0, block("stone"));
+        }
+        schematic.set_block(0, 1, 0, block("oak_door[half=lower]"));
+        schematic.set_block(0, 2, 0, block("oak_door[half=upper]"));
+        schematic.set_block(1, 1, 0, block("chest"));
+        schematic.set_block_entity(
+            crate::block_position::BlockPosition { x: 1, y: 1, z: 0 },
+            BlockEntity::create_chest((1, 1, 0), vec![ItemStack::new("minecraft:stone", 28).with_slot(0)]),
+        );
+
+        let list = MaterialList::from_schematic(&schematic, false);
+        assert_eq!(list.count("minecraft:oak_door"), 1);
+        assert_eq!(list.count("minecraft:air"), 0);
+        assert_eq!(list.entries[0].item, "minecraft:stone");
+        assert_eq!(list.entries[0].stacks(), (1, 36));
+        assert_eq!(list.entries[0].shulker_boxes(), 1);
+
+        let with_containers = MaterialList::from_schematic(&schematic, true);
+        assert_eq!(with_containers.entries[0].count, 128);
+        assert_eq!(with_containers.entries[0].stacks(), (2, 0));
+        assert!(with_containers.to_csv().starts_with("item,count,stacks,remainder,shulker_boxes\nminecraft:stone,128,2,0,1\n"));
+        let json: serde_json::Value = serde_json::from_str(&with_containers.to_json()).unwrap();
+        assert_eq!(json[0]["stacks"], 2);
+    }
+
+    #[test]
+    fn test_unstackable_items() {
+        let mut schematic = UniversalSchematic::new("Test".to_string());
+        schematic.set_block(0, 0, 0, block("chest"));
+        schematic.set_block_entity(
+            crate::block_position::BlockPosition { x: 0, y: 0, z: 0 },
+            BlockEntity::create_chest((0, 0, 0), vec![ItemStack::new("minecraft:diamond_sword", 1).with_slot(0)]),
+        );
+        let list = MaterialList::from_schematic(&schematic, true);
+        let sword = list.entries.iter().find(|entry| entry.item == "minecraft:diamond_sword").unwrap();
+        assert_eq!(sword.stack_size, 1);
+        assert_eq!(sword.shulker_boxes(), 1);
+
+        for item in ["netherite_pickaxe", "iron_axe", "golden_hoe", "leather_helmet", "chainmail_boots", "elytra",
+                     "totem_of_undying", "shield", "bow", "crossbow", "trident", "oak_chest_boat", "tnt_minecart", "music_disc_cat"] {
+            assert_eq!(max_stack_size(&format!("minecraft:{}", item)), 1, "{} should not stack", item);
+        }
+        assert_eq!(max_stack_size("minecraft:written_book"), 16);
+        assert_eq!(max_stack_size("minecraft:axolotl_bucket"), 1);
+        assert_eq!(max_stack_size("minecraft:rail"), 64);
+        assert_eq!(max_stack_size("minecraft:waxed_copper_bulb"), 64);
+    }
+}
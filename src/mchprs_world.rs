@@ -4,40 +4,40 @@ use mchprs_world::{storage::Chunk, TickEntry, TickPriority, World};
 use mchprs_redpiler::{Compiler, CompilerOptions};
 use nbt::{Map, Value};
 use crate::block_entity::BlockEntity as UtilBlockEntity;
-use crate::UniversalSchematic;
-use thiserror::Error;
-
-#[derive(Error, Debug)]
-pub enum MchprsWorldError {
-    #[error("Initialization failed: {0}")]
-    InitializationFailed(String),
-    // Add other error variants as needed
-}
+use crate::{TickKind, UniversalSchematic};
+use crate::error::{SchematicError, Warning};
 
 pub struct MchprsWorld {
     schematic: UniversalSchematic,
     chunks: HashMap<(i32, i32), Chunk>,
     to_be_ticked: Vec<TickEntry>,
     compiler: Compiler,
+    warnings: Vec<Warning>,
 }
 
 impl MchprsWorld {
-    pub fn new(schematic: UniversalSchematic) -> Self {
+    pub fn new(schematic: UniversalSchematic) -> Result<Self, SchematicError> {
         let mut world = MchprsWorld {
             schematic,
             chunks: HashMap::new(),
             to_be_ticked: Vec::new(),
             compiler: Compiler::default(),
+            warnings: Vec::new(),
         };
 
-        world.initialize_chunks().map_err(|e| {
-            MchprsWorldError::InitializationFailed(format!("initialize_chunks failed: {}", e))
-        }).expect("TODO: panic message");
+        world.initialize_chunks()
+            .map_err(|e| SchematicError::Simulation(format!("initialize_chunks failed: {}", e)))?;
 
         world.populate_chunks();
+        world.schedule_pending_ticks();
         world.update_redstone();
         world.initialize_compiler();
-        world
+        Ok(world)
+    }
+
+    /// Blocks that could not be simulated and other problems met since the world was created.
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
     }
 
     fn initialize_compiler(&mut self) {
@@ -118,11 +118,31 @@ impl MchprsWorld {
                     }
                 }
             } else {
-                eprintln!("Warning: Unknown block '{}' at position {:?}", name, pos);
+                self.warnings.push(Warning::UnknownBlock { name, position: (pos.x, pos.y, pos.z) });
             }
         }
     }
 
+    // Block ticks saved with the schematic, such as a repeater partway through its delay, are
+    // handed to the compiler. MCHPRS does not simulate fluids, so fluid ticks are left out.
+    fn schedule_pending_ticks(&mut self) {
+        let ticks: Vec<TickEntry> = self.schematic.regions.values()
+            .flat_map(|region| region.pending_ticks.iter())
+            .filter(|tick| tick.kind == TickKind::Block)
+            .map(|tick| TickEntry {
+                pos: BlockPos::new(tick.position.0, tick.position.1, tick.position.2),
+                ticks_left: tick.delay.max(0) as u32,
+                tick_priority: match tick.priority {
+                    i32::MIN..=-3 => TickPriority::Highest,
+                    -2 => TickPriority::Higher,
+                    -1 => TickPriority::High,
+                    _ => TickPriority::Normal,
+                },
+            })
+            .collect();
+        self.to_be_ticked.extend(ticks);
+    }
+
     fn convert_block_entity(&self, block_entity: UtilBlockEntity) -> Option<BlockEntity> {
         let nbt = block_entity.to_hashmap();
         BlockEntity::from_nbt(&block_entity.id, &nbt)
@@ -173,8 +193,7 @@ impl MchprsWorld {
             self.compiler.on_use_block(pos);
             return;
         }
-        //we clicked on a block that is not a lever so we need to throw an error
-        eprintln!("Error: Tried to use block at {:?} which is not a lever", pos);
+        self.warnings.push(Warning::NotALever { position: (pos.x, pos.y, pos.z) });
     }
 
     pub fn tick(&mut self, number_of_ticks: u32) {
@@ -235,15 +254,12 @@ impl MchprsWorld {
     }
 }
 
-pub fn generate_truth_table(schematic: &UniversalSchematic) -> Vec<HashMap<String, bool>> {
-    let mut world = MchprsWorld::new(schematic.clone());
+pub fn generate_truth_table(schematic: &UniversalSchematic) -> Result<Vec<HashMap<String, bool>>, SchematicError> {
+    let mut world = MchprsWorld::new(schematic.clone())?;
 
     // Find all levers and lamps
     let (inputs, outputs) = find_inputs_and_outputs(&world);
 
-    println!("Inputs: {:?}", inputs);
-    println!("Outputs: {:?}", outputs);
-
     let mut truth_table = Vec::new();
 
     let input_combinations = generate_input_combinations(inputs.len());
@@ -268,9 +284,9 @@ pub fn generate_truth_table(schematic: &UniversalSchematic) -> Vec<HashMap<Strin
 
         truth_table.push(result);
 
-        world = MchprsWorld::new(schematic.clone());
+        world = MchprsWorld::new(schematic.clone())?;
     }
-    truth_table
+    Ok(truth_table)
 }
 
 fn find_inputs_and_outputs(world: &MchprsWorld) -> (Vec<BlockPos>, Vec<BlockPos>) {
@@ -366,7 +382,7 @@ mod tests {
     use std::fs;
     use std::path::Path;
     use super::*;
-    use crate::{schematic, BlockState};
+    use crate::{schematic, BlockState, PendingTick};
     use crate::universal_schematic::SimpleBlockMapping;
 
     fn get_sample_schematic() -> UniversalSchematic {
@@ -427,18 +443,16 @@ mod tests {
     #[test]
     fn test_simple_redstone_line() {
         let schematic = get_sample_schematic();
-        let mut world = MchprsWorld::new(schematic);
+        let mut world = MchprsWorld::new(schematic).unwrap();
 
         for x in 1..15 {
             let power = world.get_redstone_power(BlockPos::new(x, 1, 0));
             assert_eq!(power, 16 - x as u8);
         }
-        println!("{:?}", world.get_block(BlockPos::new(0, 1, 0)).properties());
 
         assert_eq!(world.is_lit(BlockPos::new(15, 1, 0)), true);
 
         world.on_use_block(BlockPos::new(0, 1, 0));
-        println!("{:?}", world.get_block(BlockPos::new(0, 1, 0)).properties());
 
         world.tick(2);
         world.flush();
@@ -462,7 +476,7 @@ mod tests {
     #[test]
     fn test_simple_and_gate() {
         let schematic = get_sample_and_gate_schematic();
-        let mut world = MchprsWorld::new(schematic);
+        let mut world = MchprsWorld::new(schematic).unwrap();
 
         let lever_a_pos = BlockPos::new(0, 0, 0);
         let lever_b_pos = BlockPos::new(2, 0, 0);
@@ -481,12 +495,59 @@ mod tests {
 
                 world.tick(2);
                 world.flush();
-                println!("A: {}, B: {}, Output: {}", a, b, world.is_lit(output_lamp_pos));
                 assert_eq!(world.is_lit(output_lamp_pos), a == 1 && b == 1);
             }
         }
     }
 
+    // A lever switched on behind a repeater that is partway through its delay, with an unlit lamp
+    // in front, saved with one pending tick of `kind` on the repeater. Its input alone would only
+    // schedule a tick four redstone ticks out.
+    fn get_repeater_with_pending_tick(kind: TickKind) -> UniversalSchematic {
+        let mut schematic = UniversalSchematic::new("Ticks".to_string());
+        for x in 0..3 {
+            schematic.set_block(x, 0, 0, BlockState::new("minecraft:gray_concrete".to_string()));
+        }
+        schematic.set_block(0, 1, 0, BlockState::new("minecraft:lever".to_string())
+            .with_property("face".to_string(), "floor".to_string())
+            .with_property("facing".to_string(), "east".to_string())
+            .with_property("powered".to_string(), "true".to_string()));
+        schematic.set_block(1, 1, 0, BlockState::new("minecraft:repeater".to_string())
+            .with_property("delay".to_string(), "4".to_string())
+            .with_property("facing".to_string(), "west".to_string())
+            .with_property("locked".to_string(), "false".to_string())
+            .with_property("powered".to_string(), "false".to_string()));
+        schematic.set_block(2, 1, 0, BlockState::new("minecraft:redstone_lamp".to_string())
+            .with_property("lit".to_string(), "false".to_string()));
+        let region_name = schematic.default_region_name.clone();
+        schematic.get_region_mut(&region_name).unwrap()
+            .add_pending_tick(PendingTick::new(kind, "minecraft:repeater".to_string(), (1, 1, 0), 1).with_priority(-1));
+
+        let data = schematic::to_schematic_with_version(&schematic, schematic::SchematicVersion::V3).expect("Failed to convert to schem");
+        schematic::from_schematic(&data).expect("Failed to parse schem")
+    }
+
+    #[test]
+    fn test_pending_ticks_reach_the_simulation() {
+        let lamp = BlockPos::new(2, 1, 0);
+        // MCHPRS does not simulate fluids, so a fluid tick must not fire the repeater early.
+        let mut kept = MchprsWorld::new(get_repeater_with_pending_tick(TickKind::Block)).unwrap();
+        let mut left_out = MchprsWorld::new(get_repeater_with_pending_tick(TickKind::Fluid)).unwrap();
+        assert!(!kept.is_lit(lamp));
+        assert!(!left_out.is_lit(lamp));
+
+        for world in [&mut kept, &mut left_out] {
+            world.tick(1);
+            world.flush();
+        }
+        assert!(kept.is_lit(lamp));
+        assert!(!left_out.is_lit(lamp));
+
+        left_out.tick(4);
+        left_out.flush();
+        assert!(left_out.is_lit(lamp));
+    }
+
     fn get_comparator_xor_gate() -> UniversalSchematic {
         let block_mappings: &[(&char, SimpleBlockMapping)] = &[
             (&'C', ("gray_concrete", vec![])),
@@ -525,7 +586,8 @@ mod tests {
         l..l
     "#;
 
-        let schematic = UniversalSchematic::from_layers("XOR gate".to_string(), block_mappings, layers);
+        let (schematic, warnings) = UniversalSchematic::from_layers_with_warnings("XOR gate".to_string(), block_mappings, layers);
+        assert!(warnings.is_empty(), "{:?}", warnings);
 
         // Save the schematic
         let schematic_file = crate::schematic::to_schematic(&schematic).expect("Failed to convert to schem");
@@ -578,8 +640,9 @@ mod tests {
         ...
     "#;
 
-        let schematic = UniversalSchematic::from_layers("XOR gate".to_string(), block_mappings, layers);
-        for row in &generate_truth_table(&schematic) {
+        let (schematic, warnings) = UniversalSchematic::from_layers_with_warnings("XOR gate".to_string(), block_mappings, layers);
+        assert!(warnings.is_empty(), "{:?}", warnings);
+        for row in &generate_truth_table(&schematic).unwrap() {
             assert_eq!(*row.get("Output 0").unwrap(), *row.get("Input 0").unwrap() ^ *row.get("Input 1").unwrap());
         }
     }
@@ -588,7 +651,7 @@ mod tests {
     #[test]
     fn test_comparator_xor_gate() {
         let schematic = get_comparator_xor_gate();
-        let mut world = MchprsWorld::new(schematic);
+        let mut world = MchprsWorld::new(schematic).unwrap();
 
         let lever_a_pos = BlockPos::new(0, 1, 4);
         let lever_b_pos = BlockPos::new(3, 1, 4);
@@ -606,7 +669,6 @@ mod tests {
                 }
                 world.tick(4);
                 world.flush();
-                println!("A: {}, B: {}, Output: {}", a, b, world.is_lit(output_lamp_pos));
                 assert_eq!(world.is_lit(output_lamp_pos), a != b);
             }
         }
@@ -614,12 +676,7 @@ mod tests {
 #[test]
 fn test_auto_truth_table_xor_gate() {
     let schematic = get_comparator_xor_gate();
-    let truth_table = generate_truth_table(&schematic);
-
-    println!("XOR Gate Truth Table:");
-    for row in &truth_table {
-        println!("{:?}", row);
-    }
+    let truth_table = generate_truth_table(&schematic).unwrap();
 
     assert_eq!(truth_table.len(), 4);  // 2^2 combinations for 2 inputs
 
@@ -636,12 +693,7 @@ fn test_auto_truth_table_xor_gate() {
     #[test]
     fn test_auto_truth_table_and_gate() {
         let schematic = get_sample_and_gate_schematic();
-        let truth_table = generate_truth_table(&schematic);
-
-        println!("AND Gate Truth Table:");
-        for row in &truth_table {
-            println!("{:?}", row);
-        }
+        let truth_table = generate_truth_table(&schematic).unwrap();
 
         assert_eq!(truth_table.len(), 4);  // 2^2 combinations for 2 inputs
 
@@ -0,0 +1,124 @@
+use quartz_nbt::{NbtCompound, NbtList, NbtTag};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TickKind {
+    Block,
+    Fluid,
+}
+
+impl TickKind {
+    fn litematic_id_key(self) -> &'static str {
+        match self {
+            TickKind::Block => "Block",
+            TickKind::Fluid => "Fluid",
+        }
+    }
+}
+
+/// A block or fluid update the game had scheduled when the blocks were saved,
+/// such as a repeater partway through its delay or water that is still spreading.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PendingTick {
+    pub kind: TickKind,
+    /// The block or fluid the tick was scheduled for, e.g. `minecraft:repeater`.
+    pub id: String,
+    pub position: (i32, i32, i32),
+    /// Game ticks left until the update runs.
+    pub delay: i32,
+    /// Vanilla priority, from -3 (extremely high) to 3 (extremely low).
+    pub priority: i32,
+    /// Orders ticks that are due on the same game tick.
+    pub sub_tick: i64,
+}
+
+impl PendingTick {
+    pub fn new(kind: TickKind, id: String, position: (i32, i32, i32), delay: i32) -> Self {
+        PendingTick {
+            kind,
+            id,
+            position,
+            delay,
+            priority: 0,
+            sub_tick: 0,
+        }
+    }
+
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// The layout Litematica uses in `PendingBlockTicks` and `PendingFluidTicks`.
+    pub(crate) fn to_litematic_nbt(&self, origin: (i32, i32, i32)) -> NbtCompound {
+        let mut nbt = NbtCompound::new();
+        nbt.insert(self.kind.litematic_id_key(), NbtTag::String(self.id.clone()));
+        nbt.insert("Priority", NbtTag::Int(self.priority));
+        nbt.insert("SubTick", NbtTag::Long(self.sub_tick));
+        nbt.insert("Time", NbtTag::Int(self.delay));
+        nbt.insert("x", NbtTag::Int(self.position.0 - origin.0));
+        nbt.insert("y", NbtTag::Int(self.position.1 - origin.1));
+        nbt.insert("z", NbtTag::Int(self.position.2 - origin.2));
+        nbt
+    }
+
+    pub(crate) fn from_litematic_nbt(nbt: &NbtCompound, kind: TickKind, origin: (i32, i32, i32)) -> Option<Self> {
+        Some(PendingTick {
+            kind,
+            id: nbt.get::<_, &str>(kind.litematic_id_key()).ok()?.to_string(),
+            position: (
+                origin.0 + nbt.get::<_, i32>("x").ok()?,
+                origin.1 + nbt.get::<_, i32>("y").ok()?,
+                origin.2 + nbt.get::<_, i32>("z").ok()?,
+            ),
+            delay: nbt.get::<_, i32>("Time").unwrap_or(0),
+            priority: nbt.get::<_, i32>("Priority").unwrap_or(0),
+            sub_tick: nbt.get::<_, i64>("SubTick").unwrap_or(0),
+        })
+    }
+
+    /// The layout of `block_ticks` and `fluid_ticks` in vanilla chunks.
+    pub(crate) fn to_chunk_nbt(&self, origin: (i32, i32, i32)) -> NbtCompound {
+        let mut nbt = NbtCompound::new();
+        nbt.insert("i", NbtTag::String(self.id.clone()));
+        nbt.insert("x", NbtTag::Int(self.position.0 - origin.0));
+        nbt.insert("y", NbtTag::Int(self.position.1 - origin.1));
+        nbt.insert("z", NbtTag::Int(self.position.2 - origin.2));
+        nbt.insert("t", NbtTag::Int(self.delay));
+        nbt.insert("p", NbtTag::Int(self.priority));
+        nbt
+    }
+
+    // Chunks keep ticks in the order they run, so there is no sub tick to read.
+    pub(crate) fn from_chunk_nbt(nbt: &NbtCompound, kind: TickKind, origin: (i32, i32, i32)) -> Option<Self> {
+        Some(PendingTick {
+            kind,
+            id: nbt.get::<_, &str>("i").ok()?.to_string(),
+            position: (
+                origin.0 + nbt.get::<_, i32>("x").ok()?,
+                origin.1 + nbt.get::<_, i32>("y").ok()?,
+                origin.2 + nbt.get::<_, i32>("z").ok()?,
+            ),
+            delay: nbt.get::<_, i32>("t").unwrap_or(0),
+            priority: nbt.get::<_, i32>("p").unwrap_or(0),
+            sub_tick: 0,
+        })
+    }
+}
+
+/// Writes the ticks of one kind as a vanilla chunk tick list, with positions relative to `origin`.
+pub(crate) fn to_chunk_list(ticks: &[PendingTick], kind: TickKind, origin: (i32, i32, i32)) -> NbtList {
+    NbtList::from(ticks.iter()
+        .filter(|tick| tick.kind == kind)
+        .map(|tick| NbtTag::Compound(tick.to_chunk_nbt(origin)))
+        .collect::<Vec<NbtTag>>())
+}
+
+pub(crate) fn from_chunk_list(list: &NbtList, kind: TickKind, origin: (i32, i32, i32)) -> Vec<PendingTick> {
+    list.iter()
+        .filter_map(|tag| match tag {
+            NbtTag::Compound(compound) => PendingTick::from_chunk_nbt(compound, kind, origin),
+            _ => None,
+        })
+        .collect()
+}
@@ -1,4 +1,5 @@
-use crate::{UniversalSchematic, BlockState};
+use std::collections::HashMap;
+use crate::{UniversalSchematic, BlockState, SchematicDiff};
 use crate::metadata::Metadata;
 use crate::region::Region;
 
@@ -40,8 +41,7 @@ pub fn format_region(name: &str, region: &Region) -> String {
     output.push_str(&format!("    Position: {:?}\n", region.position));
     output.push_str(&format!("    Size: {:?}\n", region.size));
     output.push_str("    Blocks:\n");
-    for i in 0..region.blocks.len() {
-        let block_palette_index = region.blocks[i];
+    for (i, block_palette_index) in region.iter_block_indices().enumerate() {
         let block_position = region.index_to_coords(i);
         let block_state = region.palette.get(block_palette_index as usize).unwrap();
         output.push_str(&format!("      {} @ {:?}: {:?}\n", block_palette_index, block_position, block_state));
@@ -93,6 +93,39 @@ pub fn format_block_state(block: &BlockState) -> String {
     output
 }
 
+/// Draws each layer with changed blocks as an x by z grid: `+` for blocks placed in air,
+/// `-` for blocks replaced by air, `~` for other changes and `.` where nothing changed.
+pub fn format_diff(diff: &SchematicDiff) -> String {
+    let Some(bounds) = diff.bounding_box() else { return String::from("No changes\n") };
+    let mut layers: HashMap<i32, HashMap<(i32, i32), char>> = HashMap::new();
+    for change in &diff.blocks {
+        let (x, y, z) = change.position;
+        let symbol = match (change.from.name.as_str(), change.to.name.as_str()) {
+            ("minecraft:air", _) => '+',
+            (_, "minecraft:air") => '-',
+            _ => '~',
+        };
+        layers.entry(y).or_default().insert((x, z), symbol);
+    }
+
+    let mut output = format!("Changed blocks: {}, block entities: {}, entities added: {}, removed: {}\n",
+        diff.blocks.len(), diff.block_entities.len(), diff.added_entities.len(), diff.removed_entities.len());
+    let mut ys: Vec<_> = layers.keys().copied().collect();
+    ys.sort_unstable();
+    for y in ys {
+        let layer = &layers[&y];
+        output.push_str(&format!("Layer y={} ({} changed, x {}..{} across, z {}..{} down):\n",
+            y, layer.len(), bounds.min.0, bounds.max.0, bounds.min.2, bounds.max.2));
+        for z in bounds.min.2..=bounds.max.2 {
+            let row: String = (bounds.min.0..=bounds.max.0)
+                .map(|x| layer.get(&(x, z)).copied().unwrap_or('.'))
+                .collect();
+            output.push_str(&format!("  {}\n", row));
+        }
+    }
+    output
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -135,4 +168,20 @@ mod tests {
         schematic.set_block_in_region("Custom", 5, 5, 5, stone.clone());
         println!("{}", format_schematic(&schematic));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_format_diff_layers() {
+        let mut before = UniversalSchematic::new("Test".to_string());
+        before.set_block(0, 0, 0, BlockState::new("minecraft:stone".to_string()));
+        before.set_block(1, 0, 1, BlockState::new("minecraft:stone".to_string()));
+        let mut after = before.clone();
+        after.set_block(0, 0, 0, BlockState::new("minecraft:dirt".to_string()));
+        after.set_block(1, 0, 1, BlockState::new("minecraft:air".to_string()));
+        after.set_block(2, 3, 0, BlockState::new("minecraft:glass".to_string()));
+
+        let output = format_diff(&before.diff(&after));
+        assert!(output.contains("Layer y=0 (2 changed, x 0..2 across, z 0..1 down):\n  ~..\n  .-.\n"));
+        assert!(output.contains("Layer y=3 (1 changed, x 0..2 across, z 0..1 down):\n  ..+\n  ...\n"));
+        assert_eq!(format_diff(&before.diff(&before)), "No changes\n");
+    }
+}
@@ -0,0 +1,301 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::OnceLock;
+use crate::material_list::MaterialList;
+
+// One recipe, group definition or grouped recipe per line, see the header of the file.
+const RECIPES: &str = include_str!("recipes.txt");
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum RecipeKind {
+    Crafting,
+    Smelting,
+    Stonecutting,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Recipe {
+    pub kind: RecipeKind,
+    pub output: String,
+    /// How many of the output one use of the recipe makes.
+    pub count: usize,
+    pub ingredients: Vec<(String, usize)>,
+}
+
+/// Which recipe to use when an item has more than one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum RecipePreference {
+    /// The item's usual recipe, e.g. oak planks wherever any planks would do.
+    #[default]
+    Usual,
+    /// The recipe that needs the fewest raw materials, e.g. the stonecutter for stairs.
+    FewestRawMaterials,
+}
+
+#[derive(Clone, Debug)]
+pub struct ResolveOptions {
+    pub preference: RecipePreference,
+    /// The kinds of recipe that may be used, so leaving out smelting keeps stone as stone.
+    pub kinds: Vec<RecipeKind>,
+    /// Items that are never broken down, e.g. `minecraft:iron_ingot` next to an iron farm.
+    pub raw_materials: HashSet<String>,
+}
+
+impl Default for ResolveOptions {
+    fn default() -> Self {
+        ResolveOptions {
+            preference: RecipePreference::Usual,
+            kinds: vec![RecipeKind::Crafting, RecipeKind::Smelting, RecipeKind::Stonecutting],
+            raw_materials: HashSet::new(),
+        }
+    }
+}
+
+/// A material list broken down by [`MaterialList::resolve`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ResolvedMaterials {
+    /// What has to be gathered.
+    pub raw: MaterialList,
+    /// Everything made along the way, including the listed items that have a recipe. Counts
+    /// are what the recipes make, which can be more than is needed.
+    pub crafted: MaterialList,
+}
+
+fn parse_item(text: &str) -> Option<(String, usize)> {
+    let (id, count) = match text.split_once('*') {
+        Some((id, count)) => (id, count.parse().ok()?),
+        None => (text, 1),
+    };
+    // A `$` left over means the group had no value for it.
+    if id.is_empty() || id.contains('$') {
+        return None;
+    }
+    Some((format!("minecraft:{}", id), count))
+}
+
+fn parse_recipe(line: &str) -> Option<Recipe> {
+    let mut fields = line.split_whitespace();
+    let kind = match fields.next()? {
+        "crafting" => RecipeKind::Crafting,
+        "smelting" => RecipeKind::Smelting,
+        "stonecutting" => RecipeKind::Stonecutting,
+        _ => return None,
+    };
+    let (output, count) = parse_item(fields.next()?)?;
+    let ingredients = fields.map(parse_item).collect::<Option<Vec<_>>>()?;
+    Some(Recipe { kind, output, count, ingredients })
+}
+
+// Fails with the first line that doesn't parse, or the first expansion of a group line
+// that doesn't.
+fn parse_recipes(text: &str) -> Result<HashMap<String, Vec<Recipe>>, String> {
+    let mut groups: HashMap<&str, Vec<(&str, &str)>> = HashMap::new();
+    let mut recipes: HashMap<String, Vec<Recipe>> = HashMap::new();
+    let mut add = |line: &str| {
+        let recipe = parse_recipe(line).ok_or_else(|| line.to_string())?;
+        recipes.entry(recipe.output.clone()).or_default().push(recipe);
+        Ok::<_, String>(())
+    };
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some(rest) = line.strip_prefix('@') else {
+            add(line)?;
+            continue;
+        };
+        let (group, rest) = rest.split_once(' ').ok_or_else(|| line.to_string())?;
+        if let Some(values) = rest.strip_prefix("= ") {
+            let values: Vec<(&str, &str)> = values.split_whitespace()
+                .map(|value| value.split_once('/').unwrap_or((value, value)))
+                .collect();
+            if values.is_empty() {
+                return Err(line.to_string());
+            }
+            groups.insert(group, values);
+        } else {
+            let values = groups.get(group).ok_or_else(|| line.to_string())?;
+            for (first, second) in values {
+                add(&rest.replace("$1", first).replace("$2", second))?;
+            }
+        }
+    }
+    Ok(recipes)
+}
+
+fn recipe_table() -> &'static HashMap<String, Vec<Recipe>> {
+    static TABLE: OnceLock<HashMap<String, Vec<Recipe>>> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        parse_recipes(RECIPES).unwrap_or_else(|line| panic!("Invalid line in recipes.txt: {}", line))
+    })
+}
+
+/// The known recipes for an item, its usual one first.
+pub fn recipes_for(item: &str) -> &'static [Recipe] {
+    recipe_table().get(item).map_or(&[], Vec::as_slice)
+}
+
+// Picks a recipe for every item with a depth first search. A recipe that needs an item
+// still being resolved would go in a circle, so it is skipped; an item left without a
+// recipe is a raw material.
+struct Resolver<'a> {
+    recipes: &'a HashMap<String, Vec<Recipe>>,
+    options: &'a ResolveOptions,
+    chosen: HashMap<String, Option<&'a Recipe>>,
+    // Raw materials needed to make one of the item.
+    costs: HashMap<String, f64>,
+    in_progress: HashSet<String>,
+    // Every item comes after the ingredients of its chosen recipe.
+    order: Vec<String>,
+}
+
+impl<'a> Resolver<'a> {
+    fn visit(&mut self, item: &str) -> f64 {
+        if let Some(cost) = self.costs.get(item) {
+            return *cost;
+        }
+        self.in_progress.insert(item.to_string());
+
+        let mut best: Option<(&'a Recipe, f64)> = None;
+        if !self.options.raw_materials.contains(item) {
+            let recipes = self.recipes.get(item).map_or(&[][..], Vec::as_slice);
+            for recipe in recipes {
+                if !self.options.kinds.contains(&recipe.kind)
+                    || recipe.ingredients.iter().any(|(ingredient, _)| self.in_progress.contains(ingredient)) {
+                    continue;
+                }
+                let cost = recipe.ingredients.iter()
+                    .map(|(ingredient, count)| self.visit(ingredient) * *count as f64)
+                    .sum::<f64>() / recipe.count as f64;
+                if best.is_none_or(|(_, best_cost)| cost < best_cost) {
+                    best = Some((recipe, cost));
+                }
+                if self.options.preference == RecipePreference::Usual {
+                    break;
+                }
+            }
+        }
+
+        self.in_progress.remove(item);
+        self.chosen.insert(item.to_string(), best.map(|(recipe, _)| recipe));
+        let cost = best.map_or(1.0, |(_, cost)| cost);
+        self.costs.insert(item.to_string(), cost);
+        self.order.push(item.to_string());
+        cost
+    }
+}
+
+fn resolve(list: &MaterialList, recipes: &HashMap<String, Vec<Recipe>>, options: &ResolveOptions) -> ResolvedMaterials {
+    let mut resolver = Resolver {
+        recipes,
+        options,
+        chosen: HashMap::new(),
+        costs: HashMap::new(),
+        in_progress: HashSet::new(),
+        order: Vec::new(),
+    };
+    for entry in &list.entries {
+        resolver.visit(&entry.item);
+    }
+
+    let mut needed: HashMap<String, usize> = list.entries.iter()
+        .map(|entry| (entry.item.clone(), entry.count))
+        .collect();
+    let mut raw = HashMap::new();
+    let mut crafted = HashMap::new();
+    // Items come before their ingredients, so each total is complete before it is broken down.
+    for item in resolver.order.iter().rev() {
+        let Some(count) = needed.get(item).copied().filter(|count| *count > 0) else { continue };
+        match resolver.chosen[item] {
+            Some(recipe) => {
+                let uses = count.div_ceil(recipe.count);
+                *crafted.entry(item.clone()).or_insert(0) += uses * recipe.count;
+                for (ingredient, per_use) in &recipe.ingredients {
+                    *needed.entry(ingredient.clone()).or_insert(0) += uses * per_use;
+                }
+            }
+            None => *raw.entry(item.clone()).or_insert(0) += count,
+        }
+    }
+    ResolvedMaterials {
+        raw: MaterialList::from_counts(raw),
+        crafted: MaterialList::from_counts(crafted),
+    }
+}
+
+impl MaterialList {
+    /// Breaks the items down through their recipes into the raw materials they are made from.
+    pub fn resolve(&self, options: &ResolveOptions) -> ResolvedMaterials {
+        resolve(self, recipe_table(), options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list(items: &[(&str, usize)]) -> MaterialList {
+        MaterialList::from_counts(items.iter().map(|(item, count)| (item.to_string(), *count)).collect())
+    }
+
+    #[test]
+    fn test_every_recipe_line_parses() {
+        assert_eq!(parse_recipes(RECIPES).err(), None);
+        assert_eq!(parse_recipes("brewing potion water").err(), Some("brewing potion water".to_string()));
+        assert_eq!(parse_recipes("crafting stick*x planks").err(), Some("crafting stick*x planks".to_string()));
+        assert_eq!(parse_recipes("@wood crafting $1_door $1_planks*2").err(), Some("@wood crafting $1_door $1_planks*2".to_string()));
+        assert_eq!(parse_recipes("@wood = oak\n@wood crafting $1_door $3_planks*2").err(), Some("crafting oak_door $3_planks*2".to_string()));
+    }
+
+    #[test]
+    fn test_recipe_table_parses() {
+        let lines = RECIPES.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#') && !line.starts_with('@'))
+            .count();
+        let table = recipe_table();
+        assert!(table.values().map(Vec::len).sum::<usize>() > lines);
+        assert!(table.values().flatten().all(|recipe| !recipe.output.contains('$')
+            && recipe.ingredients.iter().all(|(ingredient, _)| !ingredient.contains('$'))));
+
+        let stairs = &recipes_for("minecraft:stone_brick_stairs")[0];
+        assert_eq!(stairs.count, 4);
+        assert_eq!(stairs.ingredients, vec![("minecraft:stone_bricks".to_string(), 6)]);
+        assert_eq!(recipes_for("minecraft:stick").len(), 12);
+    }
+
+    #[test]
+    fn test_resolve_raw_materials() {
+        let resolved = list(&[("minecraft:oak_stairs", 1200), ("minecraft:hopper", 40)]).resolve(&ResolveOptions::default());
+        assert_eq!(resolved.raw.count("minecraft:oak_log"), 450 + 80);
+        assert_eq!(resolved.raw.count("minecraft:raw_iron"), 200);
+        assert_eq!(resolved.crafted.count("minecraft:oak_planks"), 1800 + 320);
+        assert_eq!(resolved.crafted.count("minecraft:hopper"), 40);
+
+        let options = ResolveOptions {
+            raw_materials: HashSet::from(["minecraft:iron_ingot".to_string()]),
+            ..Default::default()
+        };
+        let resolved = list(&[("minecraft:hopper", 40)]).resolve(&options);
+        assert_eq!(resolved.raw.count("minecraft:iron_ingot"), 200);
+        assert_eq!(resolved.raw.count("minecraft:raw_iron"), 0);
+    }
+
+    #[test]
+    fn test_preferences_and_cycles() {
+        let stairs = list(&[("minecraft:stone_brick_stairs", 12)]);
+        let usual = stairs.resolve(&ResolveOptions::default());
+        assert_eq!(usual.crafted.count("minecraft:stone_bricks"), 20);
+        assert_eq!(usual.raw.count("minecraft:cobblestone"), 20);
+        let cheapest = stairs.resolve(&ResolveOptions {
+            preference: RecipePreference::FewestRawMaterials,
+            ..Default::default()
+        });
+        assert_eq!(cheapest.raw.count("minecraft:cobblestone"), 12);
+
+        let recipes = parse_recipes("crafting a b*2\ncrafting b c\ncrafting c a\ncrafting c d*3").unwrap();
+        let resolved = resolve(&list(&[("minecraft:a", 1)]), &recipes, &ResolveOptions::default());
+        assert_eq!(resolved.raw.count("minecraft:d"), 6);
+        assert_eq!(resolved.raw.count("minecraft:a"), 0);
+    }
+}
@@ -1,22 +1,68 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use quartz_nbt::{NbtCompound, NbtList, NbtTag};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use crate::{ BlockState};
 use crate::block_entity::BlockEntity;
 use crate::block_position::BlockPosition;
+use crate::block_storage::{BlockStorage, ChunkedStorage, DenseStorage, RegionStorage};
 use crate::bounding_box::BoundingBox;
 use crate::entity::Entity;
+use crate::data_fixer::DataFixer;
+use crate::error::{SchematicError, Warning};
+use crate::pending_tick::{self, PendingTick, TickKind};
+use crate::transform::{Axis, Transform};
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Debug, Clone)]
 pub struct Region {
     pub name: String,
     pub position: (i32, i32, i32),
     pub size: (i32, i32, i32),
-    pub blocks: Vec<usize>,
+    #[serde(rename = "blocks")]
+    storage: RegionStorage,
     pub(crate) palette: Vec<BlockState>,
+    // Reverse lookup for `palette`, rebuilt whenever the palette is replaced.
+    #[serde(skip)]
+    palette_index: HashMap<BlockState, usize>,
     pub entities: Vec<Entity>,
-    #[serde(serialize_with = "serialize_block_entities", deserialize_with = "deserialize_block_entities")]
+    #[serde(serialize_with = "serialize_block_entities")]
     pub block_entities: HashMap<(i32, i32, i32), BlockEntity>,
+    pub pending_ticks: Vec<PendingTick>,
+}
+
+impl<'de> Deserialize<'de> for Region {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct RegionFields {
+            name: String,
+            position: (i32, i32, i32),
+            size: (i32, i32, i32),
+            blocks: RegionStorage,
+            palette: Vec<BlockState>,
+            entities: Vec<Entity>,
+            #[serde(deserialize_with = "deserialize_block_entities")]
+            block_entities: HashMap<(i32, i32, i32), BlockEntity>,
+            #[serde(default)]
+            pending_ticks: Vec<PendingTick>,
+        }
+
+        let fields = RegionFields::deserialize(deserializer)?;
+        let mut region = Region {
+            name: fields.name,
+            position: fields.position,
+            size: fields.size,
+            storage: fields.blocks,
+            palette: Vec::new(),
+            palette_index: HashMap::new(),
+            entities: fields.entities,
+            block_entities: fields.block_entities,
+            pending_ticks: fields.pending_ticks,
+        };
+        region.set_palette(fields.palette);
+        Ok(region)
+    }
 }
 
 fn serialize_block_entities<S>(
@@ -45,24 +91,46 @@ where
         })
         .collect())
 }
+
+// A dense region that has to reallocate to grow past this many positions switches to
+// chunked storage when fewer than one in `SPARSE_RATIO` of them would hold a block.
+const SPARSE_VOLUME: u64 = 64 * 64 * 64;
+const SPARSE_RATIO: u64 = 64;
+
 impl Region {
     pub fn new(name: String, position: (i32, i32, i32), size: (i32, i32, i32)) -> Self {
         let bounding_box = BoundingBox::from_position_and_size(position, size);
         let volume = bounding_box.volume() as usize;
         let position_and_size = bounding_box.to_position_and_size();
-        let mut palette = Vec::new();
-        palette.push(BlockState::new("minecraft:air".to_string()));
+        let air = BlockState::new("minecraft:air".to_string());
         Region {
             name,
             position: position_and_size.0,
             size: position_and_size.1,
-            blocks: vec![0; volume],
-            palette,
+            storage: RegionStorage::Dense(DenseStorage::new(volume, 0)),
+            palette: vec![air.clone()],
+            palette_index: HashMap::from([(air, 0)]),
             entities: Vec::new(),
             block_entities: HashMap::new(),
+            pending_ticks: Vec::new(),
         }
     }
 
+    /// Creates a region backed by 16³ sections that are only allocated once something is
+    /// placed in them. Suited to huge or mostly empty regions.
+    pub fn new_chunked(name: String, position: (i32, i32, i32), size: (i32, i32, i32)) -> Self {
+        let mut region = Region::new(name, position, (1, 1, 1));
+        region.storage = RegionStorage::Chunked(ChunkedStorage::new(0));
+        let bounding_box = BoundingBox::from_position_and_size(position, size);
+        region.position = bounding_box.min;
+        region.size = bounding_box.get_dimensions();
+        region
+    }
+
+    pub fn is_chunked(&self) -> bool {
+        matches!(self.storage, RegionStorage::Chunked(_))
+    }
+
 
     pub fn get_block_entities_as_list(&self) -> Vec<BlockEntity> {
         self.block_entities.values().cloned().collect()
@@ -78,9 +146,8 @@ impl Region {
             self.expand_to_fit(x, y, z);
         }
 
-        let index = self.coords_to_index(x, y, z);
         let palette_index = self.get_or_insert_in_palette(block);
-        self.blocks[index] = palette_index;
+        self.storage.set(&self.get_bounding_box(), (x, y, z), palette_index);
         true
     }
 
@@ -96,12 +163,11 @@ impl Region {
         BoundingBox::from_position_and_size(self.position, self.size)
     }
 
+    #[cfg(test)]
     fn coords_to_index(&self, x: i32, y: i32, z: i32) -> usize {
         self.get_bounding_box().coords_to_index(x, y, z)
     }
 
-
-
     pub fn index_to_coords(&self, index: usize) -> (i32, i32, i32) {
         self.get_bounding_box().index_to_coords(index)
     }
@@ -118,10 +184,8 @@ impl Region {
             return None;
         }
 
-        let index = self.coords_to_index(x, y, z);
-        let block_index = self.blocks[index];
-        let palette_index = self.palette.get(block_index);
-        palette_index
+        let block_index = self.storage.get(&self.get_bounding_box(), (x, y, z));
+        self.palette.get(block_index)
     }
 
     pub fn get_block_index(&self, x: i32, y: i32, z: i32) -> Option<usize> {
@@ -129,20 +193,108 @@ impl Region {
             return None;
         }
 
-        let index = self.coords_to_index(x, y, z);
-        let block_index = self.blocks[index];
-        Some(block_index)
+        Some(self.storage.get(&self.get_bounding_box(), (x, y, z)))
+    }
+
+    /// Palette indices of every position in the region, in litematic order (x, then z, then y).
+    pub fn iter_block_indices(&self) -> Box<dyn Iterator<Item = usize> + '_> {
+        match &self.storage {
+            RegionStorage::Dense(storage) => Box::new(storage.iter()),
+            RegionStorage::Chunked(storage) => {
+                let bounding_box = self.get_bounding_box();
+                Box::new((0..self.volume()).map(move |index| storage.get(&bounding_box, bounding_box.index_to_coords(index))))
+            }
+        }
+    }
+
+    /// Replaces every block index at once. `indices` must be in litematic order and cover the
+    /// whole region.
+    pub(crate) fn set_block_indices(&mut self, indices: Vec<usize>) {
+        self.storage = RegionStorage::Dense(DenseStorage::from_indices(indices));
+    }
+
+    // Callers make sure the position is inside the region and the index is in the palette.
+    pub(crate) fn set_block_index(&mut self, x: i32, y: i32, z: i32, palette_index: usize) {
+        self.storage.set(&self.get_bounding_box(), (x, y, z), palette_index);
+    }
+
+    fn is_air(&self, palette_index: usize) -> bool {
+        self.palette[palette_index].name == "minecraft:air"
+    }
+
+    // Visits every non-air block, skipping the unallocated parts of chunked storage when they
+    // hold air.
+    pub(crate) fn for_each_non_air(&self, mut f: impl FnMut((i32, i32, i32), usize)) {
+        let bounding_box = self.get_bounding_box();
+        if !self.is_air(self.storage.default_index()) {
+            for index in 0..self.volume() {
+                let position = bounding_box.index_to_coords(index);
+                let block = self.storage.get(&bounding_box, position);
+                if !self.is_air(block) {
+                    f(position, block);
+                }
+            }
+            return;
+        }
+        self.storage.for_each_stored(&bounding_box, &mut |position, block| {
+            if !self.is_air(block) {
+                f(position, block);
+            }
+        });
+    }
+
+    // How many positions hold each palette index.
+    fn count_palette_indices(&self) -> HashMap<usize, usize> {
+        let mut counts = HashMap::new();
+        let mut stored = 0;
+        self.storage.for_each_stored(&self.get_bounding_box(), &mut |_, block| {
+            *counts.entry(block).or_insert(0) += 1;
+            stored += 1;
+        });
+        if stored < self.volume() {
+            *counts.entry(self.storage.default_index()).or_insert(0) += self.volume() - stored;
+        }
+        counts
     }
 
-    fn get_or_insert_in_palette(&mut self, block: BlockState) -> usize {
-        if let Some(index) = self.palette.iter().position(|b| b == &block) {
+    // Moves the region to `new_bounding_box`, switching to chunked storage when a dense
+    // region grows large and mostly empty.
+    fn resize_storage(&mut self, new_bounding_box: &BoundingBox, fill: usize) {
+        let old_bounding_box = self.get_bounding_box();
+        if let RegionStorage::Dense(dense) = &self.storage {
+            if new_bounding_box.volume() > SPARSE_VOLUME && !dense.has_room_for(&old_bounding_box, new_bounding_box) {
+                let filled = dense.iter().filter(|&block| block != fill).count() as u64;
+                if filled * SPARSE_RATIO < new_bounding_box.volume() {
+                    self.storage = RegionStorage::Chunked(ChunkedStorage::from_dense(dense, &old_bounding_box, fill));
+                }
+            }
+        }
+        self.storage.resize(&old_bounding_box, new_bounding_box, fill);
+        let (position, size) = new_bounding_box.to_position_and_size();
+        self.position = position;
+        self.size = size;
+    }
+
+    pub(crate) fn get_or_insert_in_palette(&mut self, block: BlockState) -> usize {
+        if let Some(&index) = self.palette_index.get(&block) {
             index
         } else {
-            self.palette.push(block);
+            self.palette.push(block.clone());
+            self.palette_index.insert(block, self.palette.len() - 1);
             self.palette.len() - 1
         }
     }
 
+    /// Replaces the palette, keeping the reverse index in sync. Duplicate states resolve to
+    /// their first entry.
+    pub(crate) fn set_palette(&mut self, palette: Vec<BlockState>) {
+        self.palette_index = HashMap::with_capacity(palette.len());
+        for (index, block) in palette.iter().enumerate() {
+            self.palette_index.entry(block.clone()).or_insert(index);
+        }
+        self.palette = palette;
+    }
+
     pub fn volume(&self) -> usize {
         self.size.0 as usize * self.size.1 as usize * self.size.2 as usize
     }
@@ -161,16 +313,8 @@ impl Region {
             return;
         }
         //get the air id
-        let air_id = self.palette.iter().position(|b| b.name == "minecraft:air").unwrap();
-        let mut new_blocks = vec![air_id; new_bounding_box.volume() as usize];
-        for index in 0..self.blocks.len() {
-            let (x, y, z) = self.index_to_coords(index);
-            let new_index = new_bounding_box.coords_to_index(x, y, z);
-            new_blocks[new_index] = self.blocks[index];
-        }
-        self.position = new_position;
-        self.size = new_size;
-        self.blocks = new_blocks;
+        let air_id = self.get_or_insert_in_palette(BlockState::new("minecraft:air".to_string()));
+        self.resize_storage(&new_bounding_box, air_id);
     }
 
 
@@ -184,68 +328,24 @@ impl Region {
 
 
     pub fn merge(&mut self, other: &Region) {
-        let bounding_box = self.get_bounding_box().union(&other.get_bounding_box());
-        let other_bounding_box = other.get_bounding_box();
-
-        let combined_bounding_box = bounding_box.union(&other_bounding_box);
-        let new_size = combined_bounding_box.get_dimensions();
-        let new_position = combined_bounding_box.min;
-
-        let mut new_blocks = vec![0; (combined_bounding_box.volume()) as usize];
-        let mut new_palette = self.palette.clone();
-        let mut reverse_new_palette: HashMap<BlockState, usize> = HashMap::new();
-        for (index, block) in self.palette.iter().enumerate() {
-            reverse_new_palette.insert(block.clone(), index);
-        }
-        for index in 0..self.blocks.len() {
-            let (x, y, z) = self.index_to_coords(index);
-            let new_index = ((y - new_position.1) * new_size.0 * new_size.2 + (z - new_position.2) * new_size.0 + (x - new_position.0)) as usize;
-            let block_index = self.blocks[index];
-            let block = &self.palette[block_index];
-            if let Some(palette_index) = reverse_new_palette.get(block) {
-                new_blocks[new_index] = *palette_index;
-            } else {
-                new_blocks[new_index] = new_palette.len();
-                new_palette.push(block.clone());
-                reverse_new_palette.insert(block.clone(), new_palette.len() - 1);
-            }
-        }
+        let combined_bounding_box = self.get_bounding_box().union(&other.get_bounding_box());
+        let air_id = self.get_or_insert_in_palette(BlockState::new("minecraft:air".to_string()));
+        self.resize_storage(&combined_bounding_box, air_id);
 
-        for index in 0..other.blocks.len() {
-            let (x, y, z) = other.index_to_coords(index);
-            let new_index = ((y - new_position.1) * new_size.0 * new_size.2 + (z - new_position.2) * new_size.0 + (x - new_position.0)) as usize;
-            let block_palette_index = other.blocks[index];
-            let block = &other.palette[block_palette_index];
-            if let Some(palette_index) = reverse_new_palette.get(block) {
-                if block.name == "minecraft:air" {
-                    continue;
-                }
-                new_blocks[new_index] = *palette_index;
-            } else {
-                new_palette.push(block.clone());
-                reverse_new_palette.insert(block.clone(), new_palette.len() - 1);
-                if block.name == "minecraft:air" {
-                    continue;
-                }
-                new_blocks[new_index] = new_palette.len() - 1;
-
-            }
+        // Air from the other region never overwrites, but its palette entries are kept.
+        let palette_map: Vec<usize> = other.palette.iter()
+            .map(|block| self.get_or_insert_in_palette(block.clone()))
+            .collect();
+        let mut placed = Vec::new();
+        other.for_each_non_air(|position, block| placed.push((position, palette_map[block])));
+        for ((x, y, z), block) in placed {
+            self.set_block_index(x, y, z, block);
         }
 
-        // Update region properties
-        self.position = new_position;
-        self.size = new_size;
-        self.blocks = new_blocks;
-        self.palette = new_palette;
-
-
         // Merge entities and block entities
         self.merge_entities(other);
         self.merge_block_entities(other);
-    }
-
-    fn calculate_new_index(&self, x: i32, y: i32, z: i32, new_position: &(i32, i32, i32), new_size: &(i32, i32, i32)) -> usize {
-        ((y - new_position.1) * new_size.0 * new_size.2 + (z - new_position.2) * new_size.0 + (x - new_position.0)) as usize
+        self.pending_ticks.extend(other.pending_ticks.iter().cloned());
     }
 
     fn merge_entities(&mut self, other: &Region) {
@@ -275,6 +375,36 @@ impl Region {
         self.block_entities.remove(&position)
     }
 
+    pub fn add_pending_tick(&mut self, tick: PendingTick) {
+        self.pending_ticks.push(tick);
+    }
+
+    // Blocks that end up with the same name after fixing share one palette entry.
+    pub(crate) fn apply_data_fixer(&mut self, fixer: &DataFixer) {
+        let mut palette: Vec<BlockState> = Vec::new();
+        let mapping: Vec<usize> = self.palette.iter()
+            .map(|block| {
+                let fixed = fixer.fix_block(block);
+                palette.iter().position(|existing| *existing == fixed).unwrap_or_else(|| {
+                    palette.push(fixed);
+                    palette.len() - 1
+                })
+            })
+            .collect();
+        self.storage.remap(&mapping);
+        self.set_palette(palette);
+
+        for block_entity in self.block_entities.values_mut() {
+            fixer.fix_block_entity(block_entity);
+        }
+        for entity in &mut self.entities {
+            fixer.fix_entity(entity);
+        }
+        for tick in &mut self.pending_ticks {
+            fixer.fix_pending_tick(tick);
+        }
+    }
+
 
 
     pub fn to_nbt(&self) -> NbtTag {
@@ -284,7 +414,7 @@ impl Region {
         tag.insert("Size", NbtTag::IntArray(vec![self.size.0, self.size.1, self.size.2]));
 
         let mut blocks_tag = NbtCompound::new();
-        for (index, &block_index) in self.blocks.iter().enumerate() {
+        for (index, block_index) in self.iter_block_indices().enumerate() {
             let (x, y, z) = self.index_to_coords(index);
             blocks_tag.insert(&format!("{},{},{}", x, y, z), NbtTag::Int(block_index as i32));
         }
@@ -302,88 +432,100 @@ impl Region {
         }
         tag.insert("BlockEntities", NbtTag::Compound(block_entities_tag));
 
+        for (key, kind) in [("BlockTicks", TickKind::Block), ("FluidTicks", TickKind::Fluid)] {
+            tag.insert(key, NbtTag::List(pending_tick::to_chunk_list(&self.pending_ticks, kind, (0, 0, 0))));
+        }
+
         NbtTag::Compound(tag)
     }
 
-    pub fn from_nbt(nbt: &NbtCompound) -> Result<Self, String> {
-        let name = nbt.get::<_, &str>("Name")
-            .map_err(|e| format!("Failed to get Region Name: {}", e))?
-            .to_string();
+    pub fn from_nbt(nbt: &NbtCompound) -> Result<Self, SchematicError> {
+        Self::from_nbt_with_warnings(nbt).map(|(region, _)| region)
+    }
 
-        let position = match nbt.get::<_, &NbtTag>("Position") {
-            Ok(NbtTag::IntArray(arr)) if arr.len() == 3 => (arr[0], arr[1], arr[2]),
-            _ => return Err("Invalid Position tag".to_string()),
+    /// Like `from_nbt`, but also returns the entities that were skipped as unreadable.
+    pub fn from_nbt_with_warnings(nbt: &NbtCompound) -> Result<(Self, Vec<Warning>), SchematicError> {
+        let mut warnings = Vec::new();
+        let name = nbt.get::<_, &str>("Name")?.to_string();
+
+        let position = match nbt.get::<_, &NbtTag>("Position")? {
+            NbtTag::IntArray(arr) if arr.len() == 3 => (arr[0], arr[1], arr[2]),
+            _ => return Err(SchematicError::Nbt("Invalid Position tag".to_string())),
         };
 
-        let size = match nbt.get::<_, &NbtTag>("Size") {
-            Ok(NbtTag::IntArray(arr)) if arr.len() == 3 => (arr[0], arr[1], arr[2]),
-            _ => return Err("Invalid Size tag".to_string()),
+        let size = match nbt.get::<_, &NbtTag>("Size")? {
+            NbtTag::IntArray(arr) if arr.len() == 3 => (arr[0], arr[1], arr[2]),
+            _ => return Err(SchematicError::Nbt("Invalid Size tag".to_string())),
         };
 
-        let palette_tag = nbt.get::<_, &NbtList>("Palette")
-            .map_err(|e| format!("Failed to get Palette: {}", e))?;
-        let palette: Vec<BlockState> = palette_tag.iter()
-            .filter_map(|tag| {
-                if let NbtTag::Compound(compound) = tag {
-                    BlockState::from_nbt(compound).ok()
-                } else {
-                    None
-                }
+        let palette_tag = nbt.get::<_, &NbtList>("Palette")?;
+        // A dropped entry would shift every index after it, so a bad one fails the region.
+        let palette: Vec<BlockState> = palette_tag.iter().enumerate()
+            .map(|(index, tag)| match tag {
+                NbtTag::Compound(compound) => BlockState::from_nbt(compound)
+                    .map_err(|e| SchematicError::Nbt(format!("Invalid palette entry {}: {}", index, e))),
+                _ => Err(SchematicError::Nbt(format!("Palette entry {} is not a compound", index))),
             })
-            .collect();
+            .collect::<Result<_, _>>()?;
 
-        let blocks_tag = nbt.get::<_, &NbtCompound>("Blocks")
-            .map_err(|e| format!("Failed to get Blocks: {}", e))?;
-        let mut blocks = vec![0; (size.0 * size.1 * size.2) as usize];
+        // Block keys are absolute coordinates, as written by `to_nbt`.
+        let bounding_box = BoundingBox::from_position_and_size(position, size);
+        let blocks_tag = nbt.get::<_, &NbtCompound>("Blocks")?;
+        let mut blocks = vec![0; bounding_box.volume() as usize];
         for (key, value) in blocks_tag.inner() {
             if let NbtTag::Int(index) = value {
-                let coords: Vec<i32> = key.split(',')
-                    .map(|s| s.parse::<i32>().unwrap())
-                    .collect();
-                if coords.len() == 3 {
-                    let block_index = (coords[1] * size.0 * size.2 + coords[2] * size.0 + coords[0]) as usize;
-                    blocks[block_index] = *index as usize;
+                let coords = parse_coordinate_key(key)?;
+                if !bounding_box.contains(coords) {
+                    return Err(SchematicError::Nbt(format!("Block position {} is outside the region", key)));
                 }
+                if *index < 0 || *index as usize >= palette.len() {
+                    return Err(SchematicError::PaletteIndexOutOfRange { index: *index as usize, palette_len: palette.len() });
+                }
+                blocks[bounding_box.coords_to_index(coords.0, coords.1, coords.2)] = *index as usize;
             }
         }
 
-        let entities_tag = nbt.get::<_, &NbtList>("Entities")
-            .map_err(|e| format!("Failed to get Entities: {}", e))?;
-        let entities = entities_tag.iter()
-            .filter_map(|tag| {
-                if let NbtTag::Compound(compound) = tag {
-                    Entity::from_nbt(compound).ok()
-                } else {
-                    None
-                }
-            })
-            .collect();
+        let entities_tag = nbt.get::<_, &NbtList>("Entities")?;
+        let mut entities = Vec::new();
+        for tag in entities_tag.iter() {
+            match tag {
+                NbtTag::Compound(compound) => match Entity::from_nbt(compound) {
+                    Ok(entity) => entities.push(entity),
+                    Err(reason) => warnings.push(Warning::SkippedEntity { reason }),
+                },
+                _ => warnings.push(Warning::SkippedEntity { reason: "Entity is not a compound".to_string() }),
+            }
+        }
 
-        let block_entities_tag = nbt.get::<_, &NbtCompound>("BlockEntities")
-            .map_err(|e| format!("Failed to get BlockEntities: {}", e))?;
+        let block_entities_tag = nbt.get::<_, &NbtCompound>("BlockEntities")?;
         let mut block_entities = HashMap::new();
         for (key, value) in block_entities_tag.inner() {
             if let NbtTag::Compound(be_compound) = value {
-                let coords: Vec<i32> = key.split(',')
-                    .map(|s| s.parse::<i32>().unwrap())
-                    .collect();
-                if coords.len() == 3 {
-                    if let block_entity = BlockEntity::from_nbt(be_compound) {
-                        block_entities.insert((coords[0], coords[1], coords[2]), block_entity);
-                    }
-                }
+                block_entities.insert(parse_coordinate_key(key)?, BlockEntity::from_nbt(be_compound));
             }
         }
 
-        Ok(Region {
+        // Regions written before pending ticks were kept have neither list.
+        let mut pending_ticks = Vec::new();
+        for (key, kind) in [("BlockTicks", TickKind::Block), ("FluidTicks", TickKind::Fluid)] {
+            if let Ok(ticks) = nbt.get::<_, &NbtList>(key) {
+                pending_ticks.extend(pending_tick::from_chunk_list(ticks, kind, (0, 0, 0)));
+            }
+        }
+
+        let mut region = Region {
             name,
             position,
             size,
-            blocks,
-            palette,
+            storage: RegionStorage::Dense(DenseStorage::from_indices(blocks)),
+            palette: Vec::new(),
+            palette_index: HashMap::new(),
             entities,
             block_entities,
-        })
+            pending_ticks,
+        };
+        region.set_palette(palette);
+        Ok((region, warnings))
     }
 
     pub fn to_litematic_nbt(&self) -> NbtCompound {
@@ -413,99 +555,431 @@ impl Region {
     }
 
     pub fn unpack_block_states(&self, packed_states: &[i64]) -> Vec<usize> {
-        let bits_per_block = self.calculate_bits_per_block();
-        let mask = (1 << bits_per_block) - 1;
-        let volume = self.volume();
+        unpack_packed_longs(packed_states, self.calculate_bits_per_block(), self.volume(), true)
+    }
 
-        let mut blocks = Vec::with_capacity(volume);
 
-        for index in 0..volume {
-            let bit_index = index * bits_per_block;
-            let start_long_index = bit_index / 64;
-            let start_offset = bit_index % 64;
 
-            let value = if start_offset + bits_per_block <= 64 {
-                // Block is entirely within one long
-                ((packed_states[start_long_index] as u64) >> start_offset) & (mask as u64)
-            } else {
-                // Block spans two longs
-                let low_bits = ((packed_states[start_long_index] as u64) >> start_offset) & ((1 << (64 - start_offset)) - 1);
-                let high_bits = (packed_states[start_long_index + 1] as u64) & ((1 << (bits_per_block - (64 - start_offset))) - 1);
-                low_bits | (high_bits << (64 - start_offset))
-            };
+    pub(crate) fn create_packed_block_states(&self) -> Vec<i64> {
+        let blocks: Vec<usize> = self.iter_block_indices().collect();
+        pack_packed_longs(&blocks, self.calculate_bits_per_block(), true)
+    }
+
+    pub fn get_palette(&self) -> Vec<BlockState> {
+        self.palette.clone()
+    }
+    pub(crate) fn get_palette_nbt(&self) -> NbtList {
+        let mut palette = NbtList::new();
+        for block in &self.palette {
+            palette.push(block.to_nbt());
+        }
+        palette
+    }
+
 
 
-            blocks.push(value as usize);
+
+    pub fn count_block_types(&self) -> HashMap<BlockState, usize> {
+        let mut block_counts = HashMap::new();
+        for (block_index, count) in self.count_palette_indices() {
+            *block_counts.entry(self.palette[block_index].clone()).or_insert(0) += count;
         }
+        block_counts
+    }
+
+    pub fn count_blocks(&self) -> usize {
+        self.volume() - self.count_palette_indices().get(&0).copied().unwrap_or(0)
+    }
 
-        blocks
+    pub fn get_palette_index(&self, block: &BlockState) -> Option<usize> {
+        self.palette_index.get(block).copied()
     }
 
+    /// Rotates clockwise seen from above by a multiple of 90 degrees, keeping the region's minimum corner.
+    pub fn rotate_y(&mut self, degrees: i32) -> Result<(), String> {
+        let pivot = self.get_bounding_box();
+        self.apply_transform(&Transform::rotation(Axis::Y, degrees)?, &pivot);
+        Ok(())
+    }
 
+    pub fn rotate_x(&mut self, degrees: i32) -> Result<(), String> {
+        let pivot = self.get_bounding_box();
+        self.apply_transform(&Transform::rotation(Axis::X, degrees)?, &pivot);
+        Ok(())
+    }
 
-    pub(crate) fn create_packed_block_states(&self) -> Vec<i64> {
-        let bits_per_block = self.calculate_bits_per_block();
-        let size = self.blocks.len();
-        let expected_len = (size * bits_per_block + 63) / 64; // Equivalent to ceil(size * bits_per_block / 64)
+    pub fn rotate_z(&mut self, degrees: i32) -> Result<(), String> {
+        let pivot = self.get_bounding_box();
+        self.apply_transform(&Transform::rotation(Axis::Z, degrees)?, &pivot);
+        Ok(())
+    }
 
-        let mut packed_states = vec![0i64; expected_len];
-        let mask = (1i64 << bits_per_block) - 1;
+    pub fn mirror(&mut self, axis: Axis) {
+        let pivot = self.get_bounding_box();
+        self.apply_transform(&Transform::mirror(axis), &pivot);
+    }
 
-        for (index, &block_state) in self.blocks.iter().enumerate() {
-            let bit_index = index * bits_per_block;
-            let start_long_index = bit_index / 64;
-            let end_long_index = (bit_index + bits_per_block - 1) / 64;
-            let start_offset = bit_index % 64;
+    // Moves everything as if the pivot box was rotated in place, so several regions
+    // sharing a pivot keep their layout.
+    pub(crate) fn apply_transform(&mut self, transform: &Transform, pivot: &BoundingBox) {
+        let old_bounding_box = self.get_bounding_box();
+        let corner_a = transform.transform_block(old_bounding_box.min, pivot);
+        let corner_b = transform.transform_block(old_bounding_box.max, pivot);
+        let new_bounding_box = BoundingBox::new(
+            (corner_a.0.min(corner_b.0), corner_a.1.min(corner_b.1), corner_a.2.min(corner_b.2)),
+            (corner_a.0.max(corner_b.0), corner_a.1.max(corner_b.1), corner_a.2.max(corner_b.2)),
+        );
 
-            let value = (block_state as i64) & mask;
+        let mut new_storage = self.storage.empty_like(new_bounding_box.volume() as usize);
+        self.storage.for_each_stored(&old_bounding_box, &mut |position, block| {
+            new_storage.set(&new_bounding_box, transform.transform_block(position, pivot), block);
+        });
+
+        let (position, size) = new_bounding_box.to_position_and_size();
+        self.position = position;
+        self.size = size;
+        self.storage = new_storage;
+        self.set_palette(self.palette.iter().map(|block| transform.transform_block_state(block)).collect());
+
+        self.block_entities = self.block_entities.drain().map(|(position, mut block_entity)| {
+            let new_position = transform.transform_block(position, pivot);
+            block_entity.move_to(new_position);
+            (new_position, block_entity)
+        }).collect();
+        for tick in &mut self.pending_ticks {
+            tick.position = transform.transform_block(tick.position, pivot);
+        }
 
-            if start_long_index == end_long_index {
-                packed_states[start_long_index] |= value << start_offset;
-            } else {
-                packed_states[start_long_index] |= value << start_offset;
-                packed_states[end_long_index] |= value >> (64 - start_offset);
-            }
+        for entity in &mut self.entities {
+            entity.position = transform.transform_point(entity.position, pivot);
+            entity.transform_rotation(transform);
         }
+    }
 
-        // Handle negative numbers
-        packed_states.iter_mut().for_each(|x| *x = *x as u64 as i64);
+    /// Sets every position in `bounding_box` to `block`, growing the region if needed.
+    /// Block entities and pending ticks inside the box are removed.
+    pub fn fill(&mut self, bounding_box: &BoundingBox, block: BlockState) {
+        self.expand_to_fit(bounding_box.min.0, bounding_box.min.1, bounding_box.min.2);
+        self.expand_to_fit(bounding_box.max.0, bounding_box.max.1, bounding_box.max.2);
 
-        packed_states
+        let palette_index = self.get_or_insert_in_palette(block);
+        let region_bounding_box = self.get_bounding_box();
+        for index in 0..bounding_box.volume() as usize {
+            self.storage.set(&region_bounding_box, bounding_box.index_to_coords(index), palette_index);
+        }
+        self.block_entities.retain(|position, _| !bounding_box.contains(*position));
+        self.pending_ticks.retain(|tick| !bounding_box.contains(tick.position));
     }
 
-    pub fn get_palette(&self) -> Vec<BlockState> {
-        self.palette.clone()
+    /// Replaces every block matching `pattern` (see [`BlockState::matches_pattern`]) with `to`
+    /// and returns how many blocks changed.
+    pub fn replace(&mut self, pattern: &BlockState, to: BlockState) -> usize {
+        let to_index = self.get_or_insert_in_palette(to);
+        let matches: Vec<bool> = self.palette.iter().enumerate()
+            .map(|(index, block)| index != to_index && block.matches_pattern(pattern))
+            .collect();
+
+        let replaced = self.count_palette_indices().iter()
+            .filter(|(&block, _)| matches[block])
+            .map(|(_, &count)| count)
+            .sum();
+        let bounding_box = self.get_bounding_box();
+        let storage = &self.storage;
+        self.block_entities.retain(|&position, _| {
+            !bounding_box.contains(position) || !matches[storage.get(&bounding_box, position)]
+        });
+
+        let mapping: Vec<usize> = matches.iter().enumerate()
+            .map(|(index, &matched)| if matched { to_index } else { index })
+            .collect();
+        self.storage.remap(&mapping);
+        replaced
     }
-    pub(crate) fn get_palette_nbt(&self) -> NbtList {
-        let mut palette = NbtList::new();
-        for block in &self.palette {
-            palette.push(block.to_nbt());
+
+    /// Visits the positions of the blocks `replace(pattern, to)` would change.
+    pub(crate) fn for_each_replaced(&self, pattern: &BlockState, to: &BlockState, mut f: impl FnMut((i32, i32, i32))) {
+        let matches: Vec<bool> = self.palette.iter()
+            .map(|block| block != to && block.matches_pattern(pattern))
+            .collect();
+        if (0..matches.len()).any(|index| matches[index] && self.is_air(index)) {
+            let bounding_box = self.get_bounding_box();
+            for index in 0..self.volume() {
+                let position = bounding_box.index_to_coords(index);
+                if matches[self.storage.get(&bounding_box, position)] {
+                    f(position);
+                }
+            }
+        } else if matches.contains(&true) {
+            self.for_each_non_air(|position, block| {
+                if matches[block] {
+                    f(position);
+                }
+            });
         }
-        palette
     }
 
+    /// Copies the part of the region inside `bounding_box` into a new region of that size.
+    /// Positions outside this region are left as air.
+    pub fn copy(&self, bounding_box: &BoundingBox) -> Region {
+        let (position, size) = bounding_box.to_position_and_size();
+        let mut copy = if self.is_chunked() {
+            Region::new_chunked(self.name.clone(), position, size)
+        } else {
+            Region::new(self.name.clone(), position, size)
+        };
+        let mut palette_map: HashMap<usize, usize> = HashMap::new();
 
+        let mut copied_blocks = Vec::new();
+        self.for_each_non_air(|position, block| {
+            if bounding_box.contains(position) {
+                copied_blocks.push((position, block));
+            }
+        });
+        for ((x, y, z), block) in copied_blocks {
+            let copied = *palette_map.entry(block)
+                .or_insert_with(|| copy.get_or_insert_in_palette(self.palette[block].clone()));
+            copy.set_block_index(x, y, z, copied);
+        }
 
+        copy.block_entities = self.block_entities.iter()
+            .filter(|(position, _)| bounding_box.contains(**position))
+            .map(|(position, block_entity)| (*position, block_entity.clone()))
+            .collect();
+        copy.pending_ticks = self.pending_ticks.iter()
+            .filter(|tick| bounding_box.contains(tick.position))
+            .cloned()
+            .collect();
+        copy.entities = self.entities.iter()
+            .filter(|entity| bounding_box.contains((
+                entity.position.0.floor() as i32,
+                entity.position.1.floor() as i32,
+                entity.position.2.floor() as i32,
+            )))
+            .cloned()
+            .collect();
+        copy
+    }
 
-    pub fn count_block_types(&self) -> HashMap<BlockState, usize> {
-        let mut block_counts = HashMap::new();
-        for block_index in &self.blocks {
-            let block_state = &self.palette[*block_index];
-            *block_counts.entry(block_state.clone()).or_insert(0) += 1;
+    /// Pastes `source` shifted by `offset`, growing this region to fit. Block entities follow
+    /// their blocks, and entities are always pasted.
+    pub fn paste(&mut self, source: &Region, offset: (i32, i32, i32), mode: PasteMode) {
+        let source_bounding_box = source.get_bounding_box();
+        let min = source_bounding_box.min;
+        let max = source_bounding_box.max;
+        self.expand_to_fit(min.0 + offset.0, min.1 + offset.1, min.2 + offset.2);
+        self.expand_to_fit(max.0 + offset.0, max.1 + offset.1, max.2 + offset.2);
+
+        let palette_map: Vec<usize> = source.palette.iter()
+            .map(|block| self.get_or_insert_in_palette(block.clone()))
+            .collect();
+        let mut pasted = Vec::new();
+        if mode == PasteMode::SkipAir {
+            source.for_each_non_air(|position, block| pasted.push((position, block)));
+        } else {
+            for index in 0..source.volume() {
+                let position = source_bounding_box.index_to_coords(index);
+                pasted.push((position, source.storage.get(&source_bounding_box, position)));
+            }
+        }
+
+        let bounding_box = self.get_bounding_box();
+        let mut replaced = HashSet::new();
+        for ((x, y, z), source_block) in pasted {
+            let block = palette_map[source_block];
+            let destination = (x + offset.0, y + offset.1, z + offset.2);
+            if mode == PasteMode::KeepExisting && !self.is_air(self.storage.get(&bounding_box, destination)) {
+                continue;
+            }
+            self.storage.set(&bounding_box, destination, block);
+            self.block_entities.remove(&destination);
+            if let Some(block_entity) = source.block_entities.get(&(x, y, z)) {
+                let mut block_entity = block_entity.clone();
+                block_entity.move_to(destination);
+                self.block_entities.insert(destination, block_entity);
+            }
+            replaced.insert(destination);
+        }
+
+        // Ticks scheduled for a replaced block go with it, and the pasted block brings its own.
+        self.pending_ticks.retain(|tick| !replaced.contains(&tick.position));
+        for tick in &source.pending_ticks {
+            let destination = (tick.position.0 + offset.0, tick.position.1 + offset.1, tick.position.2 + offset.2);
+            if replaced.contains(&destination) {
+                let mut tick = tick.clone();
+                tick.position = destination;
+                self.pending_ticks.push(tick);
+            }
+        }
+
+        for entity in &source.entities {
+            let mut entity = entity.clone();
+            entity.position = (
+                entity.position.0 + offset.0 as f64,
+                entity.position.1 + offset.1 as f64,
+                entity.position.2 + offset.2 as f64,
+            );
+            self.entities.push(entity);
         }
-        block_counts
     }
 
-    pub fn count_blocks(&self) -> usize {
-        self.blocks.iter().filter(|&&block_index| block_index != 0).count()
+    /// Shrinks `position` and `size` to the smallest box holding every non-air block.
+    /// A region with nothing but air is left as it is.
+    pub fn trim_to_content(&mut self) {
+        let mut content: Option<BoundingBox> = None;
+        self.for_each_non_air(|position, _| {
+            let block_box = BoundingBox::new(position, position);
+            content = Some(match &content {
+                Some(content) => content.union(&block_box),
+                None => block_box,
+            });
+        });
+
+        let Some(content) = content else { return };
+        if content == self.get_bounding_box() {
+            return;
+        }
+        let fill = self.storage.default_index();
+        self.resize_storage(&content, fill);
+        self.block_entities.retain(|position, _| content.contains(*position));
+        self.pending_ticks.retain(|tick| content.contains(tick.position));
+    }
+    /// An empty region covering `bounding_box` and expected to hold about `blocks` blocks,
+    /// chunked when that would leave it mostly empty.
+    pub(crate) fn empty(name: String, bounding_box: &BoundingBox, blocks: usize) -> Region {
+        let (position, size) = bounding_box.to_position_and_size();
+        if bounding_box.volume() > SPARSE_VOLUME && (blocks as u64) * SPARSE_RATIO < bounding_box.volume() {
+            Region::new_chunked(name, position, size)
+        } else {
+            Region::new(name, position, size)
+        }
     }
 
-    pub fn get_palette_index(&self, block: &BlockState) -> Option<usize> {
-        self.palette.iter().position(|b| b == block)
+    /// Moves the region to `bounding_box`, dropping whatever lies outside it, and gives it
+    /// `palette`. States still in use but missing from `palette` are kept after its entries.
+    pub(crate) fn restore_layout(&mut self, bounding_box: &BoundingBox, palette: &[BlockState]) {
+        if *bounding_box != self.get_bounding_box() {
+            let air_id = self.get_or_insert_in_palette(BlockState::new("minecraft:air".to_string()));
+            self.resize_storage(bounding_box, air_id);
+            self.block_entities.retain(|position, _| bounding_box.contains(*position));
+            self.pending_ticks.retain(|tick| bounding_box.contains(tick.position));
+        }
+        if self.palette == palette {
+            return;
+        }
+
+        let used = self.count_palette_indices();
+        let mut restored = palette.to_vec();
+        let mut indices: HashMap<BlockState, usize> = HashMap::new();
+        for (index, block) in palette.iter().enumerate() {
+            indices.entry(block.clone()).or_insert(index);
+        }
+        let mapping: Vec<usize> = self.palette.iter().enumerate()
+            .map(|(index, block)| match indices.get(block) {
+                Some(&restored_index) => restored_index,
+                None if used.contains_key(&index) => {
+                    restored.push(block.clone());
+                    indices.insert(block.clone(), restored.len() - 1);
+                    restored.len() - 1
+                }
+                None => 0,
+            })
+            .collect();
+        self.storage.remap(&mapping);
+        self.set_palette(restored);
     }
+}
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PasteMode {
+    /// Air in the pasted region leaves the destination untouched.
+    SkipAir,
+    /// Every position of the pasted region is copied, air included.
+    Overwrite,
+    /// Only air in the destination is replaced.
+    KeepExisting,
+}
+
+fn parse_coordinate_key(key: &str) -> Result<(i32, i32, i32), SchematicError> {
+    let coords: Vec<i32> = key.split(',')
+        .map(|s| s.parse::<i32>())
+        .collect::<Result<_, _>>()
+        .map_err(|_| SchematicError::Nbt(format!("Invalid position key '{}'", key)))?;
+    match coords[..] {
+        [x, y, z] => Ok((x, y, z)),
+        _ => Err(SchematicError::Nbt(format!("Invalid position key '{}'", key))),
+    }
+}
 
+// Litematica lets a value straddle two longs. Chunk sections written since 1.16 don't,
+// they pad every long with unused bits instead.
+pub(crate) fn unpack_packed_longs(packed_states: &[i64], bits_per_entry: usize, count: usize, spanning: bool) -> Vec<usize> {
+    let mask = (1u64 << bits_per_entry) - 1;
+    let entries_per_long = 64 / bits_per_entry;
 
+    let mut values = Vec::with_capacity(count);
+
+    for index in 0..count {
+        let value = if !spanning {
+            let long_index = index / entries_per_long;
+            let offset = (index % entries_per_long) * bits_per_entry;
+            ((packed_states[long_index] as u64) >> offset) & mask
+        } else {
+            let bit_index = index * bits_per_entry;
+            let start_long_index = bit_index / 64;
+            let start_offset = bit_index % 64;
+
+            if start_offset + bits_per_entry <= 64 {
+                // Value is entirely within one long
+                ((packed_states[start_long_index] as u64) >> start_offset) & mask
+            } else {
+                // Value spans two longs
+                let low_bits = ((packed_states[start_long_index] as u64) >> start_offset) & ((1 << (64 - start_offset)) - 1);
+                let high_bits = (packed_states[start_long_index + 1] as u64) & ((1 << (bits_per_entry - (64 - start_offset))) - 1);
+                low_bits | (high_bits << (64 - start_offset))
+            }
+        };
+
+        values.push(value as usize);
+    }
+
+    values
+}
+
+pub(crate) fn pack_packed_longs(values: &[usize], bits_per_entry: usize, spanning: bool) -> Vec<i64> {
+    let mut packed_states = vec![0u64; packed_longs_len(bits_per_entry, values.len(), spanning)];
+    let mask = (1u64 << bits_per_entry) - 1;
+    let entries_per_long = 64 / bits_per_entry;
+
+    for (index, &value) in values.iter().enumerate() {
+        let value = (value as u64) & mask;
+
+        if !spanning {
+            packed_states[index / entries_per_long] |= value << ((index % entries_per_long) * bits_per_entry);
+            continue;
+        }
+
+        let bit_index = index * bits_per_entry;
+        let start_long_index = bit_index / 64;
+        let end_long_index = (bit_index + bits_per_entry - 1) / 64;
+        let start_offset = bit_index % 64;
+
+        packed_states[start_long_index] |= value << start_offset;
+        if start_long_index != end_long_index {
+            packed_states[end_long_index] |= value >> (64 - start_offset);
+        }
+    }
+
+    packed_states.into_iter().map(|x| x as i64).collect()
+}
+
+// Number of longs `unpack_packed_longs` reads for the given layout.
+pub(crate) fn packed_longs_len(bits_per_entry: usize, count: usize, spanning: bool) -> usize {
+    if spanning {
+        (count * bits_per_entry + 63) / 64
+    } else {
+        let entries_per_long = 64 / bits_per_entry;
+        (count + entries_per_long - 1) / entries_per_long
+    }
 }
 
 #[cfg(test)]
@@ -525,11 +999,14 @@ mod tests {
             name: "Test".to_string(),
             position: (0, 0, 0),
             size: (16, 1, 1),
-            blocks: blocks.clone(),
-            palette,
+            storage: RegionStorage::Dense(DenseStorage::from_indices(blocks.clone())),
+            palette: Vec::new(),
+            palette_index: HashMap::new(),
             entities: Vec::new(),
             block_entities: HashMap::new(),
+            pending_ticks: Vec::new(),
         };
+        region.set_palette(palette);
         let packed_states = region.create_packed_block_states();
         assert_eq!(packed_states.len(), 2);
         assert_eq!(packed_states, vec![-3013672028691362751, 33756]);
@@ -546,7 +1023,7 @@ mod tests {
         assert_eq!(region.name, "Test");
         assert_eq!(region.position, (0, 0, 0));
         assert_eq!(region.size, (2, 2, 2));
-        assert_eq!(region.blocks.len(), 8);
+        assert_eq!(region.iter_block_indices().count(), 8);
         assert_eq!(region.palette.len(), 1);
         assert_eq!(region.palette[0].name, "minecraft:air");
     }
@@ -619,6 +1096,27 @@ mod tests {
         assert_eq!(region.get_block(0, 0, 0), deserialized_region.get_block(0, 0, 0));
     }
 
+    #[test]
+    fn test_from_nbt_rejects_bad_palette_and_reports_bad_entities() {
+        let mut region = Region::new("Test".to_string(), (0, 0, 0), (2, 1, 1));
+        region.set_block(1, 0, 0, BlockState::new("minecraft:stone".to_string()));
+        region.add_entity(Entity::new("minecraft:pig".to_string(), (0.5, 0.0, 0.5)));
+        let NbtTag::Compound(mut nbt) = region.to_nbt() else { panic!("Expected NbtTag::Compound") };
+        let mut entities = nbt.get::<_, &NbtList>("Entities").unwrap().clone();
+        entities.push(NbtCompound::new());
+        nbt.insert("Entities", NbtTag::List(entities));
+
+        let (loaded, warnings) = Region::from_nbt_with_warnings(&nbt).unwrap();
+        assert_eq!(loaded.entities.len(), 1);
+        assert_eq!(warnings.len(), 1);
+        assert!(matches!(warnings[0], Warning::SkippedEntity { .. }));
+
+        let mut palette = nbt.get::<_, &NbtList>("Palette").unwrap().clone();
+        palette[0] = NbtTag::Compound(NbtCompound::new());
+        nbt.insert("Palette", NbtTag::List(palette));
+        assert!(matches!(Region::from_nbt(&nbt), Err(SchematicError::Nbt(_))));
+    }
+
     #[test]
     fn test_to_litematic_nbt() {
         let mut region = Region::new("Test".to_string(), (0, 0, 0), (2, 2, 2));
@@ -1011,4 +1509,200 @@ mod tests {
 
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn test_fill_and_replace() {
+        let mut region = Region::new("Test".to_string(), (0, 0, 0), (2, 2, 2));
+        let stone = BlockState::new("minecraft:stone".to_string());
+        region.add_block_entity(BlockEntity::new("minecraft:chest".to_string(), (1, 1, 1)));
+
+        region.fill(&BoundingBox::new((1, 0, 0), (3, 1, 1)), stone.clone());
+        assert_eq!(region.size, (4, 2, 2));
+        assert_eq!(region.count_blocks(), 12);
+        assert_eq!(region.get_block(0, 0, 0).unwrap().name, "minecraft:air");
+        assert_eq!(region.get_block(3, 1, 1), Some(&stone));
+        assert!(region.block_entities.is_empty());
+
+        let north_stairs = BlockState::new("minecraft:oak_stairs".to_string())
+            .with_property("facing".to_string(), "north".to_string())
+            .with_property("half".to_string(), "bottom".to_string());
+        let top_stairs = north_stairs.clone().with_property("half".to_string(), "top".to_string());
+        region.set_block(0, 0, 0, north_stairs);
+        region.set_block(0, 1, 0, top_stairs.clone());
+
+        let any_stairs = BlockState::new("minecraft:oak_stairs".to_string()).with_property("facing".to_string(), "*".to_string());
+        let only_top = BlockState::new("minecraft:oak_stairs".to_string()).with_property("half".to_string(), "top".to_string());
+        let dirt = BlockState::new("minecraft:dirt".to_string());
+        assert_eq!(region.replace(&only_top, dirt.clone()), 1);
+        assert_eq!(region.get_block(0, 1, 0), Some(&dirt));
+        assert_eq!(region.replace(&any_stairs, dirt.clone()), 1);
+        assert_eq!(region.replace(&any_stairs, dirt.clone()), 0);
+        assert_eq!(region.replace(&stone, dirt.clone()), 12);
+        assert_eq!(region.count_block_types()[&dirt], 14);
+    }
+
+    #[test]
+    fn test_copy_paste_and_trim() {
+        let mut region = Region::new("Test".to_string(), (0, 0, 0), (3, 3, 3));
+        let stone = BlockState::new("minecraft:stone".to_string());
+        region.set_block(0, 0, 0, stone.clone());
+        region.set_block(1, 0, 0, BlockState::new("minecraft:chest".to_string()));
+        region.add_block_entity(BlockEntity::create_chest((1, 0, 0), vec![]));
+        region.add_entity(Entity::new("minecraft:pig".to_string(), (1.5, 0.0, 0.5)));
+
+        let copy = region.copy(&BoundingBox::new((0, 0, 0), (1, 1, 0)));
+        assert_eq!((copy.position, copy.size), ((0, 0, 0), (2, 2, 1)));
+        assert_eq!(copy.get_palette().len(), 3);
+        assert_eq!(copy.block_entities.len(), 1);
+        assert_eq!(copy.entities.len(), 1);
+
+        // Palette entry 0 isn't always air, e.g. after loading a file.
+        let mut filled = Region::new("Filled".to_string(), (0, 0, 0), (2, 1, 1));
+        filled.palette[0] = stone.clone();
+        let filled_copy = filled.copy(&BoundingBox::new((0, 0, 0), (1, 0, 0)));
+        assert_eq!(filled_copy.get_block(1, 0, 0), Some(&stone));
+
+        let mut target = Region::new("Target".to_string(), (0, 0, 0), (1, 1, 1));
+        let dirt = BlockState::new("minecraft:dirt".to_string());
+        target.fill(&BoundingBox::new((5, 0, 0), (6, 1, 0)), dirt.clone());
+        target.paste(&copy, (5, 0, 0), PasteMode::KeepExisting);
+        assert_eq!(target.get_block(5, 0, 0), Some(&dirt));
+        assert!(target.block_entities.is_empty());
+        target.paste(&copy, (5, 0, 0), PasteMode::SkipAir);
+        assert_eq!(target.get_block(5, 0, 0), Some(&stone));
+        assert_eq!(target.get_block(5, 1, 0), Some(&dirt));
+        assert_eq!(target.block_entities[&(6, 0, 0)].position, (6, 0, 0));
+        target.paste(&copy, (5, 0, 0), PasteMode::Overwrite);
+        assert_eq!(target.get_block(5, 1, 0).unwrap().name, "minecraft:air");
+        assert_eq!(target.entities[0].position, (6.5, 0.0, 0.5));
+
+        target.trim_to_content();
+        assert_eq!((target.position, target.size), ((5, 0, 0), (2, 1, 1)));
+        assert_eq!(target.block_entities.len(), 1);
+
+        region.trim_to_content();
+        assert_eq!((region.position, region.size), ((0, 0, 0), (2, 1, 1)));
+    }
+
+    #[test]
+    fn test_replace_keeps_block_entities_outside_the_region() {
+        let mut region = Region::new("Test".to_string(), (0, 0, 0), (2, 1, 1));
+        region.set_block(0, 0, 0, BlockState::new("minecraft:chest".to_string()));
+        region.add_block_entity(BlockEntity::create_chest((0, 0, 0), vec![]));
+        region.set_block_entity(BlockPosition { x: 10, y: 0, z: 0 }, BlockEntity::create_chest((10, 0, 0), vec![]));
+
+        let replaced = region.replace(&BlockState::new("minecraft:chest".to_string()), BlockState::new("minecraft:stone".to_string()));
+        assert_eq!(replaced, 1);
+        assert!(!region.block_entities.contains_key(&(0, 0, 0)));
+        assert!(region.block_entities.contains_key(&(10, 0, 0)));
+    }
+
+    #[test]
+    fn test_palette_index_stays_in_sync() {
+        let stairs = BlockState::new("minecraft:oak_stairs".to_string())
+            .with_property("facing".to_string(), "north".to_string())
+            .with_property("half".to_string(), "top".to_string());
+        let mut same_stairs = BlockState::new("minecraft:oak_stairs".to_string());
+        for i in 0..32 {
+            same_stairs.set_property(format!("extra{}", i), i.to_string());
+        }
+        same_stairs.properties.retain(|key, _| !key.starts_with("extra"));
+        same_stairs.set_property("half".to_string(), "top".to_string());
+        same_stairs.set_property("facing".to_string(), "north".to_string());
+
+        let mut region = Region::new("Test".to_string(), (0, 0, 0), (2, 1, 1));
+        region.set_block(0, 0, 0, stairs.clone());
+        region.set_block(1, 0, 0, same_stairs);
+        assert_eq!(region.get_palette().len(), 2);
+
+        let json = serde_json::to_string(&region).unwrap();
+        let mut deserialized: Region = serde_json::from_str(&json).unwrap();
+        assert_eq!(deserialized.get_palette_index(&stairs), Some(1));
+
+        let mut other = Region::new("Other".to_string(), (2, 0, 0), (1, 1, 1));
+        other.set_block(2, 0, 0, BlockState::new("minecraft:stone".to_string()));
+        deserialized.merge(&other);
+        assert_eq!(deserialized.get_palette_index(&BlockState::new("minecraft:stone".to_string())), Some(2));
+        deserialized.set_block(0, 0, 0, BlockState::new("minecraft:stone".to_string()));
+        assert_eq!(deserialized.get_palette().len(), 3);
+    }
+
+    #[test]
+    fn test_far_apart_blocks_use_chunked_storage() {
+        let mut region = Region::new("Test".to_string(), (0, 0, 0), (1, 1, 1));
+        let redstone = BlockState::new("minecraft:redstone_wire".to_string());
+        region.set_block(0, 0, 0, redstone.clone());
+        region.set_block(5000, 0, 5000, redstone.clone());
+
+        assert!(region.is_chunked());
+        assert_eq!(region.size, (5001, 1, 5001));
+        assert_eq!(region.get_block(5000, 0, 5000), Some(&redstone));
+        assert_eq!(region.get_block(2500, 0, 2500).unwrap().name, "minecraft:air");
+        assert_eq!(region.count_blocks(), 2);
+        assert_eq!(region.count_block_types().get(&redstone), Some(&2));
+
+        region.trim_to_content();
+        assert_eq!(region.size, (5001, 1, 5001));
+        region.set_block(5000, 0, 5000, BlockState::new("minecraft:air".to_string()));
+        region.trim_to_content();
+        assert_eq!(region.size, (1, 1, 1));
+        assert_eq!(region.get_block(0, 0, 0), Some(&redstone));
+    }
+
+    #[test]
+    fn test_growing_region_stays_dense() {
+        let mut region = Region::new("Test".to_string(), (0, 0, 0), (1, 1, 1));
+        let stone = BlockState::new("minecraft:stone".to_string());
+        region.set_block(0, 0, 0, stone.clone());
+        region.set_block(3, 3, 3, stone.clone());
+
+        for x in 4..300 {
+            region.set_block(x, x % 5, 3, stone.clone());
+        }
+        for x in (-40..0).rev() {
+            region.set_block(x, 0, 0, stone.clone());
+        }
+        assert!(!region.is_chunked());
+        assert_eq!(region.size, (340, 5, 4));
+        assert_eq!(region.get_block(0, 0, 0), Some(&stone));
+        assert_eq!(region.get_block(299, 4, 3), Some(&stone));
+        assert_eq!(region.get_block(299, 4, 0).unwrap().name, "minecraft:air");
+        assert_eq!(region.get_block(-40, 0, 0), Some(&stone));
+        assert_eq!(region.count_blocks(), 338);
+
+        // Shrinking and growing back reads air where blocks were dropped.
+        region.trim_to_content();
+        region.set_block(299, 4, 3, BlockState::new("minecraft:air".to_string()));
+        region.trim_to_content();
+        assert_eq!(region.size, (339, 5, 4));
+        region.expand_to_fit(299, 4, 3);
+        assert_eq!(region.get_block(299, 4, 3).unwrap().name, "minecraft:air");
+        let indices: Vec<usize> = region.iter_block_indices().collect();
+        assert_eq!(indices.len(), region.volume());
+        assert_eq!(indices.iter().filter(|&&index| index != 0).count(), 337);
+    }
+
+    #[test]
+    fn test_chunked_matches_dense() {
+        let mut dense = Region::new("Test".to_string(), (-3, 0, -3), (20, 5, 20));
+        let mut chunked = Region::new_chunked("Test".to_string(), (-3, 0, -3), (20, 5, 20));
+        let stairs = BlockState::new("minecraft:oak_stairs".to_string()).with_property("facing".to_string(), "north".to_string());
+        for region in [&mut dense, &mut chunked] {
+            region.set_block(-3, 0, -3, BlockState::new("minecraft:stone".to_string()));
+            region.set_block(16, 4, 2, stairs.clone());
+            region.fill(&BoundingBox::new((0, 1, 0), (17, 1, 3)), BlockState::new("minecraft:glass".to_string()));
+            region.replace(&BlockState::new("minecraft:glass".to_string()), BlockState::new("minecraft:sand".to_string()));
+            region.rotate_y(90).unwrap();
+        }
+
+        let names = |region: &Region| region.iter_block_indices()
+            .map(|index| region.palette[index].to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(chunked.get_bounding_box(), dense.get_bounding_box());
+        assert_eq!(names(&chunked), names(&dense));
+        assert_eq!(chunked.count_blocks(), dense.count_blocks());
+
+        let copy = chunked.copy(&BoundingBox::new((-3, 0, 12), (0, 4, 20)));
+        assert!(copy.is_chunked());
+        assert_eq!(copy.count_blocks(), dense.copy(&BoundingBox::new((-3, 0, 12), (0, 4, 20))).count_blocks());
+    }
+}
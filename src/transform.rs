@@ -0,0 +1,348 @@
+use serde::{Deserialize, Serialize};
+use crate::BlockState;
+use crate::bounding_box::BoundingBox;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Axis {
+    X,
+    Y,
+    Z,
+}
+
+const DIRECTIONS: [(&str, (i32, i32, i32)); 6] = [
+    ("down", (0, -1, 0)),
+    ("up", (0, 1, 0)),
+    ("north", (0, 0, -1)),
+    ("south", (0, 0, 1)),
+    ("west", (-1, 0, 0)),
+    ("east", (1, 0, 0)),
+];
+
+fn direction_vector(name: &str) -> Option<(i32, i32, i32)> {
+    DIRECTIONS.iter().find(|(n, _)| *n == name).map(|(_, v)| *v)
+}
+
+fn direction_name(vector: (i32, i32, i32)) -> Option<&'static str> {
+    DIRECTIONS.iter().find(|(_, v)| *v == vector).map(|(n, _)| *n)
+}
+
+/// A rotation or mirror of the grid, as an orthogonal matrix applied around a pivot box.
+/// Positions are mapped so the transformed pivot box keeps its minimum corner.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Transform {
+    matrix: [[i32; 3]; 3],
+}
+
+impl Transform {
+    /// Rotations follow the game's conventions: a quarter turn around Y is clockwise seen from
+    /// above (north becomes east), around X it tips the top towards the south and around Z
+    /// towards the east.
+    pub fn rotation(axis: Axis, degrees: i32) -> Result<Self, String> {
+        if degrees % 90 != 0 {
+            return Err(format!("Rotations must be a multiple of 90 degrees, got {}", degrees));
+        }
+        let quarter_turn = match axis {
+            Axis::X => [[1, 0, 0], [0, 0, -1], [0, 1, 0]],
+            Axis::Y => [[0, 0, -1], [0, 1, 0], [1, 0, 0]],
+            Axis::Z => [[0, 1, 0], [-1, 0, 0], [0, 0, 1]],
+        };
+        let mut transform = Transform { matrix: [[1, 0, 0], [0, 1, 0], [0, 0, 1]] };
+        for _ in 0..degrees.rem_euclid(360) / 90 {
+            transform = transform.then(&Transform { matrix: quarter_turn });
+        }
+        Ok(transform)
+    }
+
+    /// Flips coordinates along `axis`, so `Axis::X` swaps east and west.
+    pub fn mirror(axis: Axis) -> Self {
+        let mut matrix = [[1, 0, 0], [0, 1, 0], [0, 0, 1]];
+        let index = match axis {
+            Axis::X => 0,
+            Axis::Y => 1,
+            Axis::Z => 2,
+        };
+        matrix[index][index] = -1;
+        Transform { matrix }
+    }
+
+    fn then(&self, next: &Transform) -> Transform {
+        let mut matrix = [[0; 3]; 3];
+        for (row, values) in matrix.iter_mut().enumerate() {
+            for (column, value) in values.iter_mut().enumerate() {
+                *value = (0..3).map(|k| next.matrix[row][k] * self.matrix[k][column]).sum();
+            }
+        }
+        Transform { matrix }
+    }
+
+    fn apply(&self, v: (i32, i32, i32)) -> (i32, i32, i32) {
+        let m = &self.matrix;
+        (
+            m[0][0] * v.0 + m[0][1] * v.1 + m[0][2] * v.2,
+            m[1][0] * v.0 + m[1][1] * v.1 + m[1][2] * v.2,
+            m[2][0] * v.0 + m[2][1] * v.1 + m[2][2] * v.2,
+        )
+    }
+
+    fn apply_f64(&self, v: (f64, f64, f64)) -> (f64, f64, f64) {
+        let m = &self.matrix;
+        (
+            m[0][0] as f64 * v.0 + m[0][1] as f64 * v.1 + m[0][2] as f64 * v.2,
+            m[1][0] as f64 * v.0 + m[1][1] as f64 * v.1 + m[1][2] as f64 * v.2,
+            m[2][0] as f64 * v.0 + m[2][1] as f64 * v.1 + m[2][2] as f64 * v.2,
+        )
+    }
+
+    // Mirrors turn left handed shapes into right handed ones.
+    fn flips_handedness(&self) -> bool {
+        let m = &self.matrix;
+        let determinant = m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0]);
+        determinant < 0
+    }
+
+    pub fn keeps_vertical(&self) -> bool {
+        self.apply((0, 1, 0)).1 != 0
+    }
+
+    fn flips_vertical(&self) -> bool {
+        self.apply((0, 1, 0)) == (0, -1, 0)
+    }
+
+    // Smallest corner of the pivot box once transformed, scaled by two like the block centers.
+    fn doubled_offset(&self, pivot: &BoundingBox) -> (i32, i32, i32) {
+        let (width, height, length) = pivot.get_dimensions();
+        let mut offset = (i32::MAX, i32::MAX, i32::MAX);
+        for corner in [(0, 0, 0), (width, 0, 0), (0, height, 0), (0, 0, length),
+                       (width, height, 0), (width, 0, length), (0, height, length), (width, height, length)] {
+            let c = self.apply((corner.0 * 2, corner.1 * 2, corner.2 * 2));
+            offset = (offset.0.min(c.0), offset.1.min(c.1), offset.2.min(c.2));
+        }
+        offset
+    }
+
+    pub fn transform_block(&self, position: (i32, i32, i32), pivot: &BoundingBox) -> (i32, i32, i32) {
+        // Transform block centers so rotations stay on the integer grid.
+        let center = (
+            2 * (position.0 - pivot.min.0) + 1,
+            2 * (position.1 - pivot.min.1) + 1,
+            2 * (position.2 - pivot.min.2) + 1,
+        );
+        let c = self.apply(center);
+        let offset = self.doubled_offset(pivot);
+        (
+            pivot.min.0 + (c.0 - offset.0 - 1) / 2,
+            pivot.min.1 + (c.1 - offset.1 - 1) / 2,
+            pivot.min.2 + (c.2 - offset.2 - 1) / 2,
+        )
+    }
+
+    pub fn transform_point(&self, position: (f64, f64, f64), pivot: &BoundingBox) -> (f64, f64, f64) {
+        let local = (
+            position.0 - pivot.min.0 as f64,
+            position.1 - pivot.min.1 as f64,
+            position.2 - pivot.min.2 as f64,
+        );
+        let p = self.apply_f64(local);
+        let offset = self.doubled_offset(pivot);
+        (
+            pivot.min.0 as f64 + p.0 - offset.0 as f64 / 2.0,
+            pivot.min.1 as f64 + p.1 - offset.1 as f64 / 2.0,
+            pivot.min.2 as f64 + p.2 - offset.2 as f64 / 2.0,
+        )
+    }
+
+    /// Maps a horizontal angle in degrees, measured clockwise from south like entity yaw.
+    /// Only meaningful for transforms that keep the vertical axis.
+    pub fn transform_yaw(&self, yaw: f64) -> f64 {
+        let radians = yaw.to_radians();
+        let (x, _, z) = self.apply_f64((-radians.sin(), 0.0, radians.cos()));
+        (-x).atan2(z).to_degrees().rem_euclid(360.0)
+    }
+
+    fn transform_direction(&self, name: &str) -> Option<&'static str> {
+        direction_name(self.apply(direction_vector(name)?))
+    }
+
+    /// Rewrites orientation dependent properties. Values that have no counterpart after the
+    /// transform, such as a rail sloping towards the sky, are left as they were.
+    pub fn transform_block_state(&self, block: &BlockState) -> BlockState {
+        let mut transformed = block.clone();
+
+        for (key, value) in &block.properties {
+            let new_value = match key.as_str() {
+                "facing" | "vertical_direction" => self.transform_direction(value).map(str::to_string),
+                "axis" => {
+                    let vector = match value.as_str() {
+                        "x" => (1, 0, 0),
+                        "y" => (0, 1, 0),
+                        "z" => (0, 0, 1),
+                        _ => continue,
+                    };
+                    match self.apply(vector) {
+                        (x, _, _) if x != 0 => Some("x".to_string()),
+                        (_, y, _) if y != 0 => Some("y".to_string()),
+                        _ => Some("z".to_string()),
+                    }
+                }
+                "rotation" if self.keeps_vertical() => value.parse::<i32>().ok().map(|rotation| {
+                    let yaw = self.transform_yaw(rotation as f64 * 22.5);
+                    (((yaw / 22.5).round() as i32).rem_euclid(16)).to_string()
+                }),
+                "orientation" => value.split_once('_').and_then(|(front, top)| {
+                    Some(format!("{}_{}", self.transform_direction(front)?, self.transform_direction(top)?))
+                }),
+                "shape" => self.transform_shape(value),
+                "half" | "type" if self.flips_vertical() => match value.as_str() {
+                    "top" => Some("bottom".to_string()),
+                    "bottom" => Some("top".to_string()),
+                    "upper" => Some("lower".to_string()),
+                    "lower" => Some("upper".to_string()),
+                    _ => None,
+                },
+                "face" | "attachment" if self.flips_vertical() => match value.as_str() {
+                    "floor" => Some("ceiling".to_string()),
+                    "ceiling" => Some("floor".to_string()),
+                    _ => None,
+                },
+                _ => None,
+            };
+            if let Some(new_value) = new_value {
+                transformed.set_property(key.clone(), new_value);
+            }
+        }
+
+        if self.flips_handedness() {
+            for key in ["hinge", "type"] {
+                let swapped = match transformed.get_property(key).map(String::as_str) {
+                    Some("left") => "right",
+                    Some("right") => "left",
+                    _ => continue,
+                };
+                transformed.set_property(key.to_string(), swapped.to_string());
+            }
+        }
+
+        // Connection properties named after directions, as on fences, walls and redstone wire,
+        // move with the directions as long as every target property exists.
+        let connections: Vec<(&String, &'static str)> = block.properties.keys()
+            .filter_map(|key| Some((key, self.transform_direction(key)?)))
+            .collect();
+        if connections.iter().all(|(_, target)| block.properties.contains_key(*target)) {
+            for (key, target) in connections {
+                transformed.set_property(target.to_string(), block.properties[key].clone());
+            }
+        }
+
+        transformed
+    }
+
+    // Rails use direction pairs and slopes, stairs name their corners by hand.
+    fn transform_shape(&self, shape: &str) -> Option<String> {
+        if shape.ends_with("_left") || shape.ends_with("_right") {
+            if !self.flips_handedness() {
+                return None;
+            }
+            return Some(match shape.strip_suffix("_left") {
+                Some(prefix) => format!("{}_right", prefix),
+                None => format!("{}_left", shape.strip_suffix("_right")?),
+            });
+        }
+
+        if let Some(direction) = shape.strip_prefix("ascending_") {
+            let direction = self.transform_direction(direction)?;
+            return match direction {
+                "up" | "down" => None,
+                _ => Some(format!("ascending_{}", direction)),
+            };
+        }
+
+        let (first, second) = shape.split_once('_')?;
+        let first = self.transform_direction(first)?;
+        let second = self.transform_direction(second)?;
+        if [first, second].iter().any(|d| *d == "up" || *d == "down") {
+            return None;
+        }
+        if direction_vector(first)?.0 + direction_vector(second)?.0 == 0
+            && direction_vector(first)?.2 + direction_vector(second)?.2 == 0 {
+            // Straight rails only say which axis they run along.
+            return Some(if direction_vector(first)?.0 != 0 { "east_west" } else { "north_south" }.to_string());
+        }
+        // Curved rails name the north/south side first.
+        let (north_south, east_west) = if direction_vector(first)?.2 != 0 { (first, second) } else { (second, first) };
+        Some(format!("{}_{}", north_south, east_west))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formats::schematic::parse_block_state;
+
+    fn transformed(transform: &Transform, state: &str) -> BlockState {
+        transform.transform_block_state(&parse_block_state(state))
+    }
+
+    #[test]
+    fn test_rotate_block_states() {
+        let rotate = Transform::rotation(Axis::Y, 90).unwrap();
+        let cases = [
+            ("minecraft:oak_stairs[facing=north,half=bottom,shape=inner_left]", "minecraft:oak_stairs[facing=east,half=bottom,shape=inner_left]"),
+            ("minecraft:oak_log[axis=x]", "minecraft:oak_log[axis=z]"),
+            ("minecraft:oak_sign[rotation=0]", "minecraft:oak_sign[rotation=4]"),
+            ("minecraft:rail[shape=north_south]", "minecraft:rail[shape=east_west]"),
+            ("minecraft:rail[shape=south_east]", "minecraft:rail[shape=south_west]"),
+            ("minecraft:rail[shape=ascending_north]", "minecraft:rail[shape=ascending_east]"),
+            ("minecraft:oak_fence[north=true,east=false,south=false,west=false]", "minecraft:oak_fence[north=false,east=true,south=false,west=false]"),
+            ("minecraft:redstone_wire[north=side,east=none,south=up,west=none,power=3]", "minecraft:redstone_wire[north=none,east=side,south=none,west=up,power=3]"),
+            ("minecraft:jigsaw[orientation=north_up]", "minecraft:jigsaw[orientation=east_up]"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(transformed(&rotate, input), parse_block_state(expected), "{}", input);
+        }
+
+        let full_turn = Transform::rotation(Axis::Y, -360).unwrap();
+        assert_eq!(transformed(&full_turn, "minecraft:hopper[facing=west]"), parse_block_state("minecraft:hopper[facing=west]"));
+        assert!(Transform::rotation(Axis::Y, 45).is_err());
+
+        let tip_over = Transform::rotation(Axis::X, 90).unwrap();
+        assert_eq!(transformed(&tip_over, "minecraft:piston[facing=up]"), parse_block_state("minecraft:piston[facing=south]"));
+        assert_eq!(transformed(&tip_over, "minecraft:oak_log[axis=y]"), parse_block_state("minecraft:oak_log[axis=z]"));
+    }
+
+    #[test]
+    fn test_mirror_block_states() {
+        let mirror_x = Transform::mirror(Axis::X);
+        let cases = [
+            ("minecraft:oak_stairs[facing=east,shape=outer_left]", "minecraft:oak_stairs[facing=west,shape=outer_right]"),
+            ("minecraft:oak_door[facing=north,hinge=left,half=upper]", "minecraft:oak_door[facing=north,hinge=right,half=upper]"),
+            ("minecraft:chest[facing=north,type=left]", "minecraft:chest[facing=north,type=right]"),
+            ("minecraft:oak_sign[rotation=4]", "minecraft:oak_sign[rotation=12]"),
+            ("minecraft:rail[shape=north_east]", "minecraft:rail[shape=north_west]"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(transformed(&mirror_x, input), parse_block_state(expected), "{}", input);
+        }
+
+        let mirror_y = Transform::mirror(Axis::Y);
+        assert_eq!(transformed(&mirror_y, "minecraft:oak_slab[type=bottom]"), parse_block_state("minecraft:oak_slab[type=top]"));
+        assert_eq!(transformed(&mirror_y, "minecraft:lever[face=floor,facing=north]"), parse_block_state("minecraft:lever[face=ceiling,facing=north]"));
+        assert_eq!(transformed(&mirror_y, "minecraft:oak_stairs[facing=north,half=top,shape=straight]"), parse_block_state("minecraft:oak_stairs[facing=north,half=bottom,shape=straight]"));
+    }
+
+    #[test]
+    fn test_transform_positions() {
+        let pivot = BoundingBox::new((10, 0, 20), (13, 1, 21));
+        let rotate = Transform::rotation(Axis::Y, 90).unwrap();
+        // The 4x2x2 box becomes 2x2x4 and keeps its minimum corner
+        assert_eq!(rotate.transform_block((10, 0, 20), &pivot), (11, 0, 20));
+        assert_eq!(rotate.transform_block((13, 1, 21), &pivot), (10, 1, 23));
+        assert_eq!(rotate.transform_point((10.5, 0.0, 20.5), &pivot), (11.5, 0.0, 20.5));
+        assert_eq!(rotate.transform_yaw(0.0), 90.0);
+
+        let mirror = Transform::mirror(Axis::Z);
+        assert_eq!(mirror.transform_block((10, 0, 20), &pivot), (10, 0, 21));
+        assert_eq!(mirror.transform_yaw(0.0), 180.0);
+    }
+}
@@ -1,20 +1,28 @@
 use std::collections::HashMap;
 use quartz_nbt::{NbtCompound, NbtTag};
 use serde::{Deserialize, Serialize};
-use crate::{ BlockState};
+use crate::{ BlockState, MaterialList, SchematicDiff};
+use crate::diff::block_at;
 use crate::block_entity::BlockEntity;
 use crate::block_position::BlockPosition;
+use crate::block_registry::{ValidationIssue, LATEST_DATA_VERSION};
 use crate::bounding_box::BoundingBox;
 use crate::chunk::Chunk;
+use crate::data_fixer::DataFixer;
 use crate::entity::Entity;
 use crate::metadata::Metadata;
-use crate::region::Region;
+use crate::region::{PasteMode, Region};
+use crate::transform::{Axis, Transform};
+use crate::error::{SchematicError, Warning};
+use crate::history::{EditHistory, RegionChange, RegionLayout};
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct UniversalSchematic {
     pub metadata: Metadata,
     pub regions: HashMap<String, Region>,
     pub default_region_name: String,
+    #[serde(skip)]
+    pub(crate) history: EditHistory,
 }
 
 pub type SimpleBlockMapping = (&'static str, Vec<(&'static str, &'static str)>);
@@ -28,6 +36,7 @@ impl UniversalSchematic {
             },
             regions: HashMap::new(),
             default_region_name: "Main".to_string(),
+            history: EditHistory::default(),
         }
     }
 
@@ -38,16 +47,25 @@ impl UniversalSchematic {
     }
 
     pub fn set_block_in_region(&mut self, region_name: &str, x: i32, y: i32, z: i32, block: BlockState) -> bool {
-        let region = self.regions.entry(region_name.to_string()).or_insert_with(|| {
-            Region::new(region_name.to_string(), (x, y, z), (1, 1, 1))
-        });
+        self.record_region(region_name);
+        self.record_block((x, y, z), |schematic| {
+            let region = schematic.regions.entry(region_name.to_string()).or_insert_with(|| {
+                Region::new(region_name.to_string(), (x, y, z), (1, 1, 1))
+            });
 
-        region.set_block(x, y, z, block)
+            region.set_block(x, y, z, block)
+        })
     }
 
 
         pub fn from_layers(name: String, block_mappings: &[(&'static char, SimpleBlockMapping)], layers: &str) -> Self {
+            Self::from_layers_with_warnings(name, block_mappings, layers).0
+        }
+
+        /// Like `from_layers`, but also returns a warning for every character without a mapping.
+        pub fn from_layers_with_warnings(name: String, block_mappings: &[(&'static char, SimpleBlockMapping)], layers: &str) -> (Self, Vec<Warning>) {
             let mut schematic = UniversalSchematic::new(name);
+            let mut warnings = Vec::new();
             let full_mappings = Self::convert_to_full_mappings(block_mappings);
 
             let layers: Vec<&str> = layers.split("\n\n")
@@ -63,16 +81,18 @@ impl UniversalSchematic {
 
                 for (z, row) in rows.iter().enumerate() {
                     for (x, c) in row.chars().enumerate() {
-                        if let Some(block_state) = full_mappings.get(&c) {
-                            schematic.set_block(x as i32, y as i32, z as i32, block_state.clone());
-                        } else if c != ' ' {
-                            println!("Warning: Unknown character '{}' at position ({}, {}, {})", c, x, y, z);
+                        // Characters without a mapping are left as air.
+                        match full_mappings.get(&c) {
+                            Some(block_state) => {
+                                schematic.set_block(x as i32, y as i32, z as i32, block_state.clone());
+                            }
+                            None => warnings.push(Warning::UnknownLayerCharacter { character: c, position: (x as i32, y as i32, z as i32) }),
                         }
                     }
                 }
             }
 
-            schematic
+            (schematic, warnings)
         }
 
     fn convert_to_full_mappings(simple_mappings: &[(&'static char, SimpleBlockMapping)]) -> HashMap<char, BlockState> {
@@ -115,21 +135,22 @@ impl UniversalSchematic {
     }
 
     pub fn set_block_entity_in_region(&mut self, region_name: &str, position: BlockPosition, block_entity: BlockEntity) -> bool {
-        let region = self.regions.entry(region_name.to_string()).or_insert_with(|| {
-            Region::new(region_name.to_string(), (position.x, position.y, position.z), (1, 1, 1))
-        });
-
-        region.set_block_entity(position, block_entity)
-
+        self.record_region(region_name);
+        self.record_block_entity((position.x, position.y, position.z), |schematic| {
+            let region = schematic.regions.entry(region_name.to_string()).or_insert_with(|| {
+                Region::new(region_name.to_string(), (position.x, position.y, position.z), (1, 1, 1))
+            });
 
+            region.set_block_entity(position, block_entity)
+        })
     }
 
     pub fn get_blocks(&self) -> Vec<BlockState> {
         let mut blocks: Vec<BlockState> = Vec::new();
         for region in self.regions.values() {
             let region_palette = region.get_palette();
-            for block_index in &region.blocks {
-                blocks.push(region_palette[*block_index as usize].clone());
+            for block_index in region.iter_block_indices() {
+                blocks.push(region_palette[block_index].clone());
             }
         }
         blocks
@@ -205,13 +226,34 @@ impl UniversalSchematic {
         if self.regions.contains_key(&region.name) {
             false
         } else {
+            if !self.history.is_recording() {
+                self.regions.insert(region.name.clone(), region);
+                return true;
+            }
+            self.record_region(&region.name);
+            for entity in &region.entities {
+                self.history.record_added_entity(entity.clone());
+            }
+            let snapshot = self.snapshot_positions(|schematic, f| schematic.for_each_covered_position(&region, f));
             self.regions.insert(region.name.clone(), region);
+            self.record_snapshot(snapshot);
             true
         }
     }
 
     pub fn remove_region(&mut self, name: &str) -> Option<Region> {
-        self.regions.remove(name)
+        let region = self.regions.get(name)?;
+        if !self.history.is_recording() {
+            return self.regions.remove(name);
+        }
+        self.history.record_region(name, Some(region), true);
+        let snapshot = self.snapshot_positions(|schematic, f| schematic.for_each_covered_position(&schematic.regions[name], f));
+        let region = self.regions.remove(name)?;
+        self.record_snapshot(snapshot);
+        for entity in &region.entities {
+            self.history.record_removed_entity(entity.clone());
+        }
+        Some(region)
     }
 
     pub fn get_region(&self, name: &str) -> Option<&Region> {
@@ -233,16 +275,21 @@ impl UniversalSchematic {
     }
 
     pub fn add_block_entity_in_region(&mut self, region_name: &str, block_entity: BlockEntity) -> bool {
-        let region = self.regions.entry(region_name.to_string()).or_insert_with(|| {
-            Region::new(region_name.to_string(), block_entity.position, (1, 1, 1))
-        });
+        self.record_region(region_name);
+        self.record_block_entity(block_entity.position, |schematic| {
+            let region = schematic.regions.entry(region_name.to_string()).or_insert_with(|| {
+                Region::new(region_name.to_string(), block_entity.position, (1, 1, 1))
+            });
 
-        region.add_block_entity(block_entity);
-        true
+            region.add_block_entity(block_entity);
+            true
+        })
     }
 
     pub fn remove_block_entity_in_region(&mut self, region_name: &str, position: (i32, i32, i32)) -> Option<BlockEntity> {
-        self.regions.get_mut(region_name)?.remove_block_entity(position)
+        self.record_block_entity(position, |schematic| {
+            schematic.regions.get_mut(region_name)?.remove_block_entity(position)
+        })
     }
 
     pub fn add_block_entity(&mut self, block_entity: BlockEntity) -> bool {
@@ -256,17 +303,25 @@ impl UniversalSchematic {
     }
 
     pub fn add_entity_in_region(&mut self, region_name: &str, entity: Entity) -> bool {
+        self.record_region(region_name);
         let region = self.regions.entry(region_name.to_string()).or_insert_with(|| {
             let rounded_position = (entity.position.0.round() as i32, entity.position.1.round() as i32, entity.position.2.round() as i32);
             Region::new(region_name.to_string(), rounded_position, (1, 1, 1))
         });
 
+        if self.history.is_recording() {
+            self.history.record_added_entity(entity.clone());
+        }
         region.add_entity(entity);
         true
     }
 
     pub fn remove_entity_in_region(&mut self, region_name: &str, index: usize) -> Option<Entity> {
-        self.regions.get_mut(region_name)?.remove_entity(index)
+        let entity = self.regions.get_mut(region_name)?.remove_entity(index)?;
+        if self.history.is_recording() {
+            self.history.record_removed_entity(entity.clone());
+        }
+        Some(entity)
     }
 
     pub fn add_entity(&mut self, entity: Entity) -> bool {
@@ -295,28 +350,33 @@ impl UniversalSchematic {
         root
     }
 
-    pub fn from_nbt(nbt: NbtCompound) -> Result<Self, String> {
-        let metadata = Metadata::from_nbt(nbt.get::<_, &NbtCompound>("Metadata")
-            .map_err(|e| format!("Failed to get Metadata: {}", e))?)?;
+    pub fn from_nbt(nbt: NbtCompound) -> Result<Self, SchematicError> {
+        Self::from_nbt_with_warnings(nbt).map(|(schematic, _)| schematic)
+    }
+
+    /// Like `from_nbt`, but also returns the problems that were worked around while reading.
+    pub fn from_nbt_with_warnings(nbt: NbtCompound) -> Result<(Self, Vec<Warning>), SchematicError> {
+        let mut warnings = Vec::new();
+        let metadata = Metadata::from_nbt(nbt.get::<_, &NbtCompound>("Metadata")?)?;
 
-        let regions_tag = nbt.get::<_, &NbtCompound>("Regions")
-            .map_err(|e| format!("Failed to get Regions: {}", e))?;
+        let regions_tag = nbt.get::<_, &NbtCompound>("Regions")?;
         let mut regions = HashMap::new();
         for (region_name, region_tag) in regions_tag.inner() {
             if let NbtTag::Compound(region_compound) = region_tag {
-                regions.insert(region_name.to_string(), Region::from_nbt(&region_compound.clone())?);
+                let (region, region_warnings) = Region::from_nbt_with_warnings(region_compound)?;
+                warnings.extend(region_warnings);
+                regions.insert(region_name.to_string(), region);
             }
         }
 
-        let default_region_name = nbt.get::<_, &str>("DefaultRegion")
-            .map_err(|e| format!("Failed to get DefaultRegion: {}", e))?
-            .to_string();
+        let default_region_name = nbt.get::<_, &str>("DefaultRegion")?.to_string();
 
-        Ok(UniversalSchematic {
+        Ok((UniversalSchematic {
             metadata,
             regions,
             default_region_name,
-        })
+            history: EditHistory::default(),
+        }, warnings))
     }
 
 
@@ -334,11 +394,476 @@ impl UniversalSchematic {
         bounding_box
     }
 
-    pub fn to_schematic(&self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    /// Fills `bounding_box` in the default region, creating it if needed. Other regions overlapping
+    /// the box are filled too so lookups agree on the result.
+    pub fn fill(&mut self, bounding_box: &BoundingBox, block: BlockState) {
+        let region_name = self.default_region_name.clone();
+        if self.history.is_recording() {
+            self.record_region(&region_name);
+            let overlapping: Vec<String> = self.regions.values()
+                .filter(|region| region.get_bounding_box().intersects(bounding_box))
+                .map(|region| region.name.clone())
+                .collect();
+            for name in overlapping {
+                self.record_region(&name);
+            }
+        }
+        // Only blocks that differ from `block`, or hold a block entity, are copied beforehand.
+        let changed = |schematic: &Self, f: &mut dyn FnMut((i32, i32, i32))| {
+            for index in 0..bounding_box.volume() as usize {
+                let position = bounding_box.index_to_coords(index);
+                if schematic.would_change(position, &block) {
+                    f(position);
+                }
+            }
+        };
+        self.record_positions(changed, |schematic| {
+            schematic.fill_unrecorded(&region_name, bounding_box, block.clone())
+        });
+    }
+
+    fn fill_unrecorded(&mut self, region_name: &str, bounding_box: &BoundingBox, block: BlockState) {
+        let region_name = region_name.to_string();
+        let (position, size) = bounding_box.to_position_and_size();
+        self.regions.entry(region_name.clone())
+            .or_insert_with(|| Region::new(region_name.clone(), position, size));
+
+        for region in self.regions.values_mut() {
+            if region.name == region_name {
+                region.fill(bounding_box, block.clone());
+            } else if region.get_bounding_box().intersects(bounding_box) {
+                let region_box = region.get_bounding_box();
+                let overlap = BoundingBox::new(
+                    (region_box.min.0.max(bounding_box.min.0), region_box.min.1.max(bounding_box.min.1), region_box.min.2.max(bounding_box.min.2)),
+                    (region_box.max.0.min(bounding_box.max.0), region_box.max.1.min(bounding_box.max.1), region_box.max.2.min(bounding_box.max.2)),
+                );
+                region.fill(&overlap, block.clone());
+            }
+        }
+    }
+
+    /// Replaces matching blocks in every region, returning the total number replaced.
+    pub fn replace(&mut self, pattern: &BlockState, to: BlockState) -> usize {
+        self.record_all_regions(false);
+        let replaced = |schematic: &Self, f: &mut dyn FnMut((i32, i32, i32))| {
+            for region in schematic.regions.values() {
+                region.for_each_replaced(pattern, &to, &mut *f);
+            }
+        };
+        self.record_positions(replaced, |schematic| {
+            schematic.regions.values_mut().map(|region| region.replace(pattern, to.clone())).sum()
+        })
+    }
+
+    /// Copies everything inside `bounding_box` into one region, named after the default region.
+    pub fn copy(&self, bounding_box: &BoundingBox) -> Region {
+        let (position, size) = bounding_box.to_position_and_size();
+        let mut copy = Region::new(self.default_region_name.clone(), position, size);
+        for region in self.regions.values() {
+            if region.get_bounding_box().intersects(bounding_box) {
+                copy.paste(&region.copy(bounding_box), (0, 0, 0), PasteMode::SkipAir);
+            }
+        }
+        copy
+    }
+
+    pub fn paste(&mut self, source: &Region, offset: (i32, i32, i32), mode: PasteMode) {
+        let region_name = self.default_region_name.clone();
+        let bounding_box = source.get_bounding_box();
+        let position = (bounding_box.min.0 + offset.0, bounding_box.min.1 + offset.1, bounding_box.min.2 + offset.2);
+        if self.history.is_recording() {
+            self.record_region(&region_name);
+            for entity in &source.entities {
+                let mut entity = entity.clone();
+                entity.position = (entity.position.0 + offset.0 as f64, entity.position.1 + offset.1 as f64, entity.position.2 + offset.2 as f64);
+                self.history.record_added_entity(entity);
+            }
+        }
+        // Pasting changes a block when it differs from the source's, or either has a block entity.
+        let pasted = |schematic: &Self, f: &mut dyn FnMut((i32, i32, i32))| {
+            let mut paste = |(x, y, z): (i32, i32, i32), block: &BlockState| {
+                let destination = (x + offset.0, y + offset.1, z + offset.2);
+                if source.block_entities.contains_key(&(x, y, z)) || schematic.would_change(destination, block) {
+                    f(destination);
+                }
+            };
+            if mode == PasteMode::SkipAir {
+                source.for_each_non_air(|position, block| paste(position, &source.palette[block]));
+            } else {
+                for index in 0..bounding_box.volume() as usize {
+                    let (x, y, z) = bounding_box.index_to_coords(index);
+                    if let Some(block) = source.get_block(x, y, z) {
+                        paste((x, y, z), block);
+                    }
+                }
+            }
+        };
+        self.record_positions(pasted, |schematic| {
+            let region = schematic.regions.entry(region_name.clone())
+                .or_insert_with(|| Region::new(region_name, position, bounding_box.get_dimensions()));
+            region.paste(source, offset, mode);
+        });
+    }
+
+    pub fn trim_to_content(&mut self) {
+        // Only bounds and the block entities left on air outside the content change.
+        self.record_all_regions(false);
+        let block_entities = |schematic: &Self, f: &mut dyn FnMut((i32, i32, i32))| {
+            for region in schematic.regions.values() {
+                region.block_entities.keys().for_each(|&position| f(position));
+            }
+        };
+        self.record_positions(block_entities, |schematic| {
+            for region in schematic.regions.values_mut() {
+                region.trim_to_content();
+            }
+        });
+    }
+
+    /// Rotates every region around the schematic's bounding box, which keeps its minimum corner.
+    pub fn rotate_y(&mut self, degrees: i32) -> Result<(), String> {
+        self.apply_transform(&Transform::rotation(Axis::Y, degrees)?);
+        Ok(())
+    }
+
+    pub fn rotate_x(&mut self, degrees: i32) -> Result<(), String> {
+        self.apply_transform(&Transform::rotation(Axis::X, degrees)?);
+        Ok(())
+    }
+
+    pub fn rotate_z(&mut self, degrees: i32) -> Result<(), String> {
+        self.apply_transform(&Transform::rotation(Axis::Z, degrees)?);
+        Ok(())
+    }
+
+    pub fn mirror(&mut self, axis: Axis) {
+        self.apply_transform(&Transform::mirror(axis));
+    }
+
+    fn apply_transform(&mut self, transform: &Transform) {
+        let pivot = self.get_bounding_box();
+        if !self.history.is_recording() {
+            for region in self.regions.values_mut() {
+                region.apply_transform(transform, &pivot);
+            }
+            return;
+        }
+
+        // Every block moves and its state may turn, so each palette is rewritten, and what changes
+        // is where blocks were and where they go. Air staying air changes nothing.
+        self.record_all_regions(true);
+        let moved = |schematic: &Self, f: &mut dyn FnMut((i32, i32, i32))| {
+            for region in schematic.regions.values() {
+                let mut visit = |position| {
+                    f(position);
+                    f(transform.transform_block(position, &pivot));
+                };
+                region.for_each_non_air(|position, _| visit(position));
+                region.block_entities.keys().for_each(|&position| visit(position));
+            }
+        };
+        let entities: Vec<Entity> = self.regions.values().flat_map(|region| region.entities.iter().cloned()).collect();
+        self.record_positions(moved, |schematic| {
+            for region in schematic.regions.values_mut() {
+                region.apply_transform(transform, &pivot);
+            }
+        });
+        for entity in entities {
+            self.history.record_removed_entity(entity);
+        }
+        let entities: Vec<Entity> = self.regions.values().flat_map(|region| region.entities.iter().cloned()).collect();
+        for entity in entities {
+            self.history.record_added_entity(entity);
+        }
+    }
+
+    /// Renames blocks, block properties and item ids so the schematic matches `data_version`,
+    /// upgrading or downgrading from `metadata.mc_version`. Items are renamed wherever block
+    /// entities and entities hold them, and block ticks follow their block's new id. Item
+    /// components and other changes to the NBT layout are left alone. Returns false, changing nothing,
+    /// when the schematic doesn't say which version it was saved with, or while a transaction is
+    /// open, as the conversion can't be undone.
+    pub fn convert_data_version(&mut self, data_version: i32) -> bool {
+        if self.history.is_recording() {
+            return false;
+        }
+        let Some(current) = self.metadata.mc_version else { return false };
+        let fixer = DataFixer::new(current, data_version);
+        if !fixer.is_empty() {
+            for region in self.regions.values_mut() {
+                region.apply_data_fixer(&fixer);
+            }
+        }
+        self.metadata.mc_version = Some(data_version);
+        true
+    }
+
+    /// Starts recording edits as one undo step. Blocks, block entities and entities set through
+    /// this type are recorded, as are `fill`, `replace`, `paste`, `apply`, rotations, mirroring,
+    /// trimming and adding or removing regions, along with the bounds and palette of every region
+    /// they touch. Edits made directly on a region are not, and `convert_data_version` is refused
+    /// until the transaction is committed. Does nothing when a transaction is already open.
+    pub fn begin_transaction(&mut self) {
+        self.history.begin();
+    }
+
+    /// Closes the open transaction. Returns false when there was none, it changed nothing, or it
+    /// was dropped for not fitting the history budget, see [`UniversalSchematic::set_history_budget`].
+    pub fn commit(&mut self) -> bool {
+        self.history.commit(&self.regions)
+    }
+
+    /// Reverts the last committed transaction, committing an open one first. Returns false when
+    /// there is nothing to undo, or an error, keeping the step, when the schematic was changed in
+    /// ways the history didn't see.
+    pub fn undo(&mut self) -> Result<bool, SchematicError> {
+        self.history.commit(&self.regions);
+        let Some(step) = self.history.take_undo() else { return Ok(false) };
+        let layouts = step.regions.iter().map(|change| (change, change.before.as_ref())).collect();
+        if let Err(error) = self.apply_step(&step.diff.inverse(), layouts) {
+            self.history.push_undo(step);
+            return Err(error);
+        }
+        self.history.push_redo(step);
+        Ok(true)
+    }
+
+    /// Reapplies the last undone transaction, see [`UniversalSchematic::undo`].
+    pub fn redo(&mut self) -> Result<bool, SchematicError> {
+        self.history.commit(&self.regions);
+        let Some(step) = self.history.take_redo() else { return Ok(false) };
+        let layouts = step.regions.iter().map(|change| (change, change.after.as_ref())).collect();
+        if let Err(error) = self.apply_step(&step.diff, layouts) {
+            self.history.push_redo(step);
+            return Err(error);
+        }
+        self.history.push_undo(step);
+        Ok(true)
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.history.can_undo()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        self.history.can_redo()
+    }
+
+    /// Caps the estimated memory of the undo and redo steps. The oldest steps are dropped first
+    /// when it is exceeded. An open transaction growing past the cap stops recording and is
+    /// dropped along with every older step, which could no longer be undone past it. The default
+    /// is 64 MiB.
+    pub fn set_history_budget(&mut self, bytes: usize) {
+        self.history.set_memory_budget(bytes);
+    }
+
+    pub fn clear_history(&mut self) {
+        self.history.clear();
+    }
+
+    // Applies one side of a history step. Regions are created or grown first so the diff lands
+    // in them, then given the exact bounds and palette they had, or removed if they didn't exist.
+    fn apply_step(&mut self, diff: &SchematicDiff, layouts: Vec<(&RegionChange, Option<&RegionLayout>)>) -> Result<(), SchematicError> {
+        let current: Vec<(&str, Option<FullLayout>)> = layouts.iter()
+            .map(|(change, _)| {
+                let layout = self.regions.get(&change.name).map(|region| (region.get_bounding_box(), region.palette.clone()));
+                (change.name.as_str(), layout)
+            })
+            .collect();
+        for (change, layout) in &layouts {
+            let Some(layout) = layout else { continue };
+            match self.regions.get_mut(&change.name) {
+                Some(region) => {
+                    region.expand_to_fit(layout.bounds.min.0, layout.bounds.min.1, layout.bounds.min.2);
+                    region.expand_to_fit(layout.bounds.max.0, layout.bounds.max.1, layout.bounds.max.2);
+                }
+                None => {
+                    let blocks = diff.blocks.iter().filter(|change| layout.bounds.contains(change.position)).count();
+                    self.regions.insert(change.name.clone(), Region::empty(change.name.clone(), &layout.bounds, blocks));
+                }
+            }
+        }
+        if let Err(error) = self.apply(diff) {
+            for (name, layout) in current {
+                self.restore_region(name, layout);
+            }
+            return Err(error);
+        }
+        for (change, layout) in layouts {
+            let layout = layout.map(|layout| {
+                let palette = self.regions.get(&change.name).map_or(&[][..], |region| &region.palette[..]);
+                (layout.bounds.clone(), change.palette(layout, palette))
+            });
+            self.restore_region(&change.name, layout);
+        }
+        Ok(())
+    }
+
+    fn restore_region(&mut self, name: &str, layout: Option<FullLayout>) {
+        match layout {
+            Some((bounds, palette)) => self.regions.entry(name.to_string())
+                .or_insert_with(|| Region::empty(name.to_string(), &bounds, 0))
+                .restore_layout(&bounds, &palette),
+            None => {
+                self.regions.remove(name);
+            }
+        }
+    }
+
+    fn record_region(&mut self, name: &str) {
+        self.history.record_region(name, self.regions.get(name), false);
+    }
+
+    fn record_all_regions(&mut self, whole_palette: bool) {
+        for region in self.regions.values() {
+            self.history.record_region(&region.name, Some(region), whole_palette);
+        }
+    }
+
+    fn block_entity_at(&self, position: (i32, i32, i32)) -> Option<BlockEntity> {
+        self.regions.values()
+            .find_map(|region| region.block_entities.get(&position))
+            .cloned()
+    }
+
+    // Runs an edit of one block, noting its state before and after in an open transaction.
+    fn record_block<T>(&mut self, position: (i32, i32, i32), edit: impl FnOnce(&mut Self) -> T) -> T {
+        if !self.history.is_recording() {
+            return edit(self);
+        }
+        let from = block_at(self, position);
+        let result = edit(self);
+        let to = block_at(self, position);
+        self.history.record_block(position, from, to);
+        result
+    }
+
+    fn record_block_entity<T>(&mut self, position: (i32, i32, i32), edit: impl FnOnce(&mut Self) -> T) -> T {
+        if !self.history.is_recording() {
+            return edit(self);
+        }
+        let from = self.block_entity_at(position);
+        let result = edit(self);
+        let to = self.block_entity_at(position);
+        self.history.record_block_entity(position, from, to);
+        result
+    }
+
+    // Whether placing `block` at `position` would change what is there, counting the block
+    // entity it would drop.
+    fn would_change(&self, (x, y, z): (i32, i32, i32), block: &BlockState) -> bool {
+        let differs = match self.get_block(x, y, z) {
+            Some(current) => current != block,
+            None => block.name != "minecraft:air" || !block.properties.is_empty(),
+        };
+        differs || self.regions.values().any(|region| region.block_entities.contains_key(&(x, y, z)))
+    }
+
+    // Runs a bulk edit that changes blocks and block entities only at the positions `positions`
+    // visits, noting their state before and after in an open transaction. Entities and regions
+    // are recorded by the caller.
+    fn record_positions<T>(&mut self, positions: impl Fn(&Self, &mut dyn FnMut((i32, i32, i32))), edit: impl FnOnce(&mut Self) -> T) -> T {
+        let snapshot = self.snapshot_positions(positions);
+        let transaction = self.history.suspend();
+        let result = edit(self);
+        self.history.resume(transaction);
+        self.record_snapshot(snapshot);
+        result
+    }
+
+    // Copies what is at the positions `positions` visits, once they are counted and known to fit
+    // in the history's budget. Too many give up on the transaction instead.
+    fn snapshot_positions(&mut self, positions: impl Fn(&Self, &mut dyn FnMut((i32, i32, i32)))) -> Option<Snapshot> {
+        if !self.history.is_recording() {
+            return None;
+        }
+        let mut count = 0;
+        positions(self, &mut |_| count += 1);
+        if !self.history.reserve(count) {
+            return None;
+        }
+        let mut snapshot = Snapshot { positions: Vec::with_capacity(count), blocks: Vec::with_capacity(count), block_entities: HashMap::new() };
+        positions(self, &mut |position| {
+            snapshot.positions.push(position);
+            snapshot.blocks.push(block_at(self, position));
+            if let Some(block_entity) = self.block_entity_at(position) {
+                snapshot.block_entities.insert(position, block_entity);
+            }
+        });
+        Some(snapshot)
+    }
+
+    fn record_snapshot(&mut self, snapshot: Option<Snapshot>) {
+        let Some(Snapshot { positions, blocks, mut block_entities }) = snapshot else { return };
+        for (&position, from) in positions.iter().zip(blocks) {
+            self.history.record_block(position, from, block_at(self, position));
+        }
+        for position in positions {
+            let (from, to) = (block_entities.remove(&position), self.block_entity_at(position));
+            if from.is_some() || to.is_some() {
+                self.history.record_block_entity(position, from, to);
+            }
+        }
+    }
+
+    // The positions where adding or removing `region` can change what the schematic holds: its
+    // blocks and block entities, or all of it when another region overlaps it.
+    fn for_each_covered_position(&self, region: &Region, f: &mut dyn FnMut((i32, i32, i32))) {
+        let bounds = region.get_bounding_box();
+        if self.regions.values().any(|other| other.name != region.name && other.get_bounding_box().intersects(&bounds)) {
+            for index in 0..bounds.volume() as usize {
+                f(bounds.index_to_coords(index));
+            }
+            return;
+        }
+        region.block_entities.keys().for_each(|&position| f(position));
+        region.for_each_non_air(|position, _| f(position));
+    }
+
+    /// Lists every palette entry the game would not accept, checked against the registry for
+    /// `metadata.mc_version`, or the latest known version when that is missing.
+    pub fn validate(&self) -> Vec<ValidationIssue> {
+        let data_version = self.metadata.mc_version.unwrap_or(LATEST_DATA_VERSION);
+        let mut issues = Vec::new();
+        for (name, region) in &self.regions {
+            for (block, count) in region.count_block_types() {
+                if let Err(error) = block.validate(data_version) {
+                    issues.push(ValidationIssue { region: name.clone(), block, count, error });
+                }
+            }
+        }
+        issues.sort_by_cached_key(|issue| (issue.region.clone(), issue.block.to_string()));
+        issues
+    }
+
+    /// The changes that turn this schematic into `other`.
+    pub fn diff(&self, other: &UniversalSchematic) -> SchematicDiff {
+        SchematicDiff::between(self, other)
+    }
+
+    /// Applies a diff made against an identical schematic, see [`SchematicDiff::apply_to`].
+    pub fn apply(&mut self, diff: &SchematicDiff) -> Result<(), SchematicError> {
+        if !self.history.is_recording() {
+            return diff.apply_to(self);
+        }
+        // A diff that applies changes exactly what it says, so it is recorded as it is.
+        let region_name = self.default_region_name.clone();
+        self.record_region(&region_name);
+        self.record_all_regions(false);
+        let transaction = self.history.suspend();
+        let result = diff.apply_to(self);
+        self.history.resume(transaction);
+        if result.is_ok() {
+            self.history.record_diff(diff);
+        }
+        result
+    }
+
+    pub fn to_schematic(&self) -> Result<Vec<u8>, SchematicError> {
         crate::formats::schematic::to_schematic(self)
     }
 
-    pub fn from_schematic(data: &[u8]) -> Result<Self, Box<dyn std::error::Error>> {
+    pub fn from_schematic(data: &[u8]) -> Result<Self, SchematicError> {
         crate::formats::schematic::from_schematic(data)
     }
 
@@ -353,6 +878,11 @@ impl UniversalSchematic {
         block_counts
     }
 
+    /// The items needed to build the schematic, see [`MaterialList::from_schematic`].
+    pub fn material_list(&self, include_containers: bool) -> MaterialList {
+        MaterialList::from_schematic(self, include_containers)
+    }
+
     pub fn split_into_chunks(&self, chunk_width: i32, chunk_height: i32, chunk_length: i32) -> Vec<Chunk> {
         use std::collections::HashMap;
         let mut chunk_map: HashMap<(i32, i32, i32), Vec<BlockPosition>> = HashMap::new();
@@ -392,11 +922,11 @@ impl UniversalSchematic {
 
     pub fn iter_blocks(&self) -> impl Iterator<Item = (BlockPosition, &BlockState)> {
         self.regions.values().flat_map(|region| {
-            region.blocks.iter().enumerate().filter_map(move |(index, block_index)| {
+            region.iter_block_indices().enumerate().filter_map(move |(index, block_index)| {
                 let (x, y, z) = region.index_to_coords(index);
                 Some((
                     BlockPosition { x, y, z },
-                    &region.palette[*block_index as usize]
+                    &region.palette[block_index]
                 ))
             })
         })
@@ -426,6 +956,16 @@ impl UniversalSchematic {
 
 
 
+}
+
+// The bounds and whole palette of a region.
+type FullLayout = (BoundingBox, Vec<BlockState>);
+
+// Block states and block entities at the positions a bulk edit may change, copied before it runs.
+struct Snapshot {
+    positions: Vec<(i32, i32, i32)>,
+    blocks: Vec<BlockState>,
+    block_entities: HashMap<(i32, i32, i32), BlockEntity>,
 }
 
 #[cfg(test)]
@@ -434,6 +974,7 @@ mod tests {
     use quartz_nbt::io::{read_nbt, write_nbt};
     use crate::block_entity;
     use crate::item::ItemStack;
+    use crate::utils::NbtValue;
     use super::*;
 
 
@@ -537,7 +1078,7 @@ mod tests {
         let mut schematic = UniversalSchematic::new("Test Schematic".to_string());
 
         let entity = Entity::new("minecraft:creeper".to_string(), (10.5, 65.0, 20.5))
-            .with_nbt_data("Fuse".to_string(), "30".to_string());
+            .with_nbt_data("Fuse".to_string(), NbtValue::Short(30));
 
         assert!(schematic.add_entity(entity.clone()));
 
@@ -701,8 +1242,111 @@ mod tests {
         assert_eq!(merged_region.get_block(1, 1, 1), Some(&BlockState::new("minecraft:dirt".to_string())));
     }
 
+    #[test]
+    fn test_rotate_and_mirror_schematic() {
+        let mut schematic = UniversalSchematic::new("Test Schematic".to_string());
+        let stairs = BlockState::new("minecraft:oak_stairs".to_string()).with_property("facing".to_string(), "east".to_string());
+        let mut region1 = Region::new("Region1".to_string(), (0, 0, 0), (3, 1, 1));
+        region1.set_block(0, 0, 0, stairs);
+        region1.set_block(2, 0, 0, BlockState::new("minecraft:chest".to_string()));
+        region1.add_block_entity(BlockEntity::create_chest((2, 0, 0), vec![ItemStack::new("minecraft:stone", 1).with_slot(0)]));
+        region1.add_entity(Entity::new("minecraft:armor_stand".to_string(), (0.5, 0.0, 0.5))
+            .with_nbt_data("Rotation".to_string(), NbtValue::List(vec![NbtValue::Float(0.0), NbtValue::Float(0.0)])));
+        let mut region2 = Region::new("Region2".to_string(), (0, 0, 1), (1, 1, 2));
+        region2.set_block(0, 0, 2, BlockState::new("minecraft:stone".to_string()));
+        schematic.add_region(region1);
+        schematic.add_region(region2);
+        let original = schematic.clone();
+
+        schematic.rotate_y(90).unwrap();
+        assert_eq!(schematic.get_bounding_box(), BoundingBox::new((0, 0, 0), (2, 0, 2)));
+        assert_eq!(schematic.get_block(2, 0, 0).unwrap().get_property("facing"), Some(&"south".to_string()));
+        assert_eq!(schematic.get_block(2, 0, 2).unwrap().name, "minecraft:chest");
+        assert_eq!(schematic.get_block(0, 0, 0).unwrap().name, "minecraft:stone");
+
+        let region1 = schematic.get_region("Region1").unwrap();
+        assert_eq!(region1.size, (1, 1, 3));
+        assert_eq!(region1.block_entities[&(2, 0, 2)].position, (2, 0, 2));
+        assert_eq!(region1.entities[0].position, (2.5, 0.0, 0.5));
+        assert_eq!(region1.entities[0].rotation(), Some((90.0, 0.0)));
+
+        schematic.rotate_y(270).unwrap();
+        for region in original.regions.values() {
+            let rotated = schematic.get_region(&region.name).unwrap();
+            assert_eq!((rotated.position, rotated.size), (region.position, region.size));
+            for index in 0..region.volume() {
+                let (x, y, z) = region.index_to_coords(index);
+                assert_eq!(rotated.get_block(x, y, z), region.get_block(x, y, z));
+            }
+        }
+
+        schematic.mirror(Axis::X);
+        assert_eq!(schematic.get_block(2, 0, 0).unwrap().get_property("facing"), Some(&"west".to_string()));
+        assert_eq!(schematic.get_block(2, 0, 2).unwrap().name, "minecraft:stone");
+        assert!(schematic.rotate_x(45).is_err());
+    }
 
+    #[test]
+    fn test_schematic_editing() {
+        let mut schematic = UniversalSchematic::new("Test Schematic".to_string());
+        let stone = BlockState::new("minecraft:stone".to_string());
+        let glass = BlockState::new("minecraft:glass".to_string());
+        schematic.add_region(Region::new("Other".to_string(), (2, 0, 0), (2, 1, 1)));
+
+        schematic.fill(&BoundingBox::new((0, 0, 0), (2, 0, 0)), stone.clone());
+        assert_eq!(schematic.get_block(2, 0, 0), Some(&stone));
+        assert_eq!(schematic.get_region("Other").unwrap().get_block(3, 0, 0).unwrap().name, "minecraft:air");
+        assert_eq!(schematic.replace(&stone, glass.clone()), 4);
+
+        let copy = schematic.copy(&BoundingBox::new((1, 0, 0), (3, 0, 0)));
+        assert_eq!(copy.count_blocks(), 2);
+        schematic.paste(&copy, (0, 2, 0), PasteMode::SkipAir);
+        assert_eq!(schematic.get_block(1, 2, 0), Some(&glass));
+
+        schematic.trim_to_content();
+        assert_eq!(schematic.get_region("Other").unwrap().size, (1, 1, 1));
+        assert_eq!(schematic.get_bounding_box(), BoundingBox::new((0, 0, 0), (2, 2, 0)));
+    }
 
+    #[test]
+    fn test_convert_data_version() {
+        let mut schematic = UniversalSchematic::new("Old Path".to_string());
+        let dirt_path = BlockState::new("minecraft:dirt_path".to_string());
+        schematic.set_block(0, 0, 0, BlockState::new("minecraft:grass_path".to_string()));
+        schematic.set_block(1, 0, 0, dirt_path.clone());
+        assert!(!schematic.convert_data_version(3953));
+
+        schematic.metadata.mc_version = Some(2586);
+        assert!(schematic.convert_data_version(3953));
+        assert_eq!(schematic.metadata.mc_version, Some(3953));
+        assert_eq!(schematic.get_block(0, 0, 0), Some(&dirt_path));
+        assert_eq!(schematic.get_block(1, 0, 0), Some(&dirt_path));
+        assert_eq!(schematic.get_region("Main").unwrap().get_palette().len(), 2);
+    }
 
+    #[test]
+    fn test_validate_reports_palette_entries() {
+        let mut schematic = UniversalSchematic::new("Test".to_string());
+        schematic.set_block(0, 0, 0, BlockState::new("minecraft:stone".to_string()));
+        schematic.set_block(1, 0, 0, BlockState::new("minecraft:short_grass".to_string()));
+        schematic.set_block(2, 0, 0, BlockState::new("minecraft:short_grass".to_string()));
+        assert!(schematic.validate().is_empty());
+
+        schematic.metadata.mc_version = Some(2586);
+        let issues = schematic.validate();
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].block.name, "minecraft:short_grass");
+        assert_eq!(issues[0].count, 2);
+    }
 
+    #[test]
+    fn test_from_layers_warns_about_unknown_characters() {
+        let mappings: &[(&char, SimpleBlockMapping)] = &[(&'C', ("stone", vec![]))];
+        let (schematic, warnings) = UniversalSchematic::from_layers_with_warnings("Test".to_string(), mappings, "CC\nC?\n\nC.");
+        assert_eq!(schematic.get_block(1, 0, 0).unwrap().name, "minecraft:stone");
+        assert_eq!(warnings, vec![
+            Warning::UnknownLayerCharacter { character: '?', position: (1, 0, 1) },
+            Warning::UnknownLayerCharacter { character: '.', position: (1, 1, 0) },
+        ]);
+    }
 }
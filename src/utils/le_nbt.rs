@@ -0,0 +1,245 @@
+use std::io::{Cursor, Read};
+use quartz_nbt::{NbtCompound, NbtList, NbtTag};
+
+// Little-endian NBT as used by Bedrock Edition. quartz_nbt only speaks the big-endian
+// Java flavor, so this reads into and writes from the same quartz types.
+
+const TAG_END: u8 = 0;
+const TAG_BYTE: u8 = 1;
+const TAG_SHORT: u8 = 2;
+const TAG_INT: u8 = 3;
+const TAG_LONG: u8 = 4;
+const TAG_FLOAT: u8 = 5;
+const TAG_DOUBLE: u8 = 6;
+const TAG_BYTE_ARRAY: u8 = 7;
+const TAG_STRING: u8 = 8;
+const TAG_LIST: u8 = 9;
+const TAG_COMPOUND: u8 = 10;
+const TAG_INT_ARRAY: u8 = 11;
+const TAG_LONG_ARRAY: u8 = 12;
+
+// Guards against malformed input sending the reader into unbounded recursion.
+const MAX_DEPTH: usize = 512;
+
+pub fn read_le_nbt(data: &[u8]) -> Result<(NbtCompound, String), String> {
+    let mut reader = Cursor::new(data);
+    let tag_type = read_u8(&mut reader)?;
+    if tag_type != TAG_COMPOUND {
+        return Err(format!("Expected root compound tag, found tag type {}", tag_type));
+    }
+    let name = read_string(&mut reader)?;
+    let root = read_compound(&mut reader, 0)?;
+    Ok((root, name))
+}
+
+pub fn write_le_nbt(name: &str, root: &NbtCompound) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.push(TAG_COMPOUND);
+    write_string(&mut out, name);
+    write_compound(&mut out, root);
+    out
+}
+
+fn read_exact<const N: usize>(reader: &mut Cursor<&[u8]>) -> Result<[u8; N], String> {
+    let mut buffer = [0u8; N];
+    reader.read_exact(&mut buffer).map_err(|e| format!("Unexpected end of NBT data: {}", e))?;
+    Ok(buffer)
+}
+
+fn read_u8(reader: &mut Cursor<&[u8]>) -> Result<u8, String> {
+    Ok(read_exact::<1>(reader)?[0])
+}
+
+fn read_i32(reader: &mut Cursor<&[u8]>) -> Result<i32, String> {
+    Ok(i32::from_le_bytes(read_exact::<4>(reader)?))
+}
+
+fn read_length(reader: &mut Cursor<&[u8]>) -> Result<usize, String> {
+    let length = read_i32(reader)?;
+    if length < 0 {
+        return Err(format!("Negative NBT length {}", length));
+    }
+    let remaining = reader.get_ref().len() as u64 - reader.position();
+    if length as u64 > remaining {
+        return Err(format!("NBT length {} exceeds the remaining {} bytes", length, remaining));
+    }
+    Ok(length as usize)
+}
+
+fn read_string(reader: &mut Cursor<&[u8]>) -> Result<String, String> {
+    let length = u16::from_le_bytes(read_exact::<2>(reader)?) as usize;
+    let mut bytes = vec![0u8; length];
+    reader.read_exact(&mut bytes).map_err(|e| format!("Unexpected end of NBT data: {}", e))?;
+    String::from_utf8(bytes).map_err(|e| format!("Invalid UTF-8 in NBT string: {}", e))
+}
+
+fn read_compound(reader: &mut Cursor<&[u8]>, depth: usize) -> Result<NbtCompound, String> {
+    let mut compound = NbtCompound::new();
+    loop {
+        let tag_type = read_u8(reader)?;
+        if tag_type == TAG_END {
+            return Ok(compound);
+        }
+        let name = read_string(reader)?;
+        let tag = read_payload(reader, tag_type, depth + 1)?;
+        compound.insert(name, tag);
+    }
+}
+
+fn read_payload(reader: &mut Cursor<&[u8]>, tag_type: u8, depth: usize) -> Result<NbtTag, String> {
+    if depth > MAX_DEPTH {
+        return Err("NBT nesting is too deep".to_string());
+    }
+    let tag = match tag_type {
+        TAG_BYTE => NbtTag::Byte(read_u8(reader)? as i8),
+        TAG_SHORT => NbtTag::Short(i16::from_le_bytes(read_exact::<2>(reader)?)),
+        TAG_INT => NbtTag::Int(read_i32(reader)?),
+        TAG_LONG => NbtTag::Long(i64::from_le_bytes(read_exact::<8>(reader)?)),
+        TAG_FLOAT => NbtTag::Float(f32::from_le_bytes(read_exact::<4>(reader)?)),
+        TAG_DOUBLE => NbtTag::Double(f64::from_le_bytes(read_exact::<8>(reader)?)),
+        TAG_BYTE_ARRAY => {
+            let length = read_length(reader)?;
+            let mut bytes = vec![0u8; length];
+            reader.read_exact(&mut bytes).map_err(|e| format!("Unexpected end of NBT data: {}", e))?;
+            NbtTag::ByteArray(bytes.into_iter().map(|b| b as i8).collect())
+        }
+        TAG_STRING => NbtTag::String(read_string(reader)?),
+        TAG_LIST => {
+            let element_type = read_u8(reader)?;
+            let length = read_length(reader)?;
+            let mut list = Vec::with_capacity(length);
+            if element_type != TAG_END {
+                for _ in 0..length {
+                    list.push(read_payload(reader, element_type, depth + 1)?);
+                }
+            }
+            NbtTag::List(NbtList::from(list))
+        }
+        TAG_COMPOUND => NbtTag::Compound(read_compound(reader, depth)?),
+        TAG_INT_ARRAY => {
+            let length = read_length(reader)?;
+            let mut values = Vec::with_capacity(length);
+            for _ in 0..length {
+                values.push(read_i32(reader)?);
+            }
+            NbtTag::IntArray(values)
+        }
+        TAG_LONG_ARRAY => {
+            let length = read_length(reader)?;
+            let mut values = Vec::with_capacity(length);
+            for _ in 0..length {
+                values.push(i64::from_le_bytes(read_exact::<8>(reader)?));
+            }
+            NbtTag::LongArray(values)
+        }
+        other => return Err(format!("Unknown NBT tag type {}", other)),
+    };
+    Ok(tag)
+}
+
+fn tag_type(tag: &NbtTag) -> u8 {
+    match tag {
+        NbtTag::Byte(_) => TAG_BYTE,
+        NbtTag::Short(_) => TAG_SHORT,
+        NbtTag::Int(_) => TAG_INT,
+        NbtTag::Long(_) => TAG_LONG,
+        NbtTag::Float(_) => TAG_FLOAT,
+        NbtTag::Double(_) => TAG_DOUBLE,
+        NbtTag::ByteArray(_) => TAG_BYTE_ARRAY,
+        NbtTag::String(_) => TAG_STRING,
+        NbtTag::List(_) => TAG_LIST,
+        NbtTag::Compound(_) => TAG_COMPOUND,
+        NbtTag::IntArray(_) => TAG_INT_ARRAY,
+        NbtTag::LongArray(_) => TAG_LONG_ARRAY,
+    }
+}
+
+fn write_string(out: &mut Vec<u8>, value: &str) {
+    out.extend_from_slice(&(value.len() as u16).to_le_bytes());
+    out.extend_from_slice(value.as_bytes());
+}
+
+fn write_compound(out: &mut Vec<u8>, compound: &NbtCompound) {
+    for (name, tag) in compound.inner() {
+        out.push(tag_type(tag));
+        write_string(out, name);
+        write_payload(out, tag);
+    }
+    out.push(TAG_END);
+}
+
+fn write_payload(out: &mut Vec<u8>, tag: &NbtTag) {
+    match tag {
+        NbtTag::Byte(v) => out.push(*v as u8),
+        NbtTag::Short(v) => out.extend_from_slice(&v.to_le_bytes()),
+        NbtTag::Int(v) => out.extend_from_slice(&v.to_le_bytes()),
+        NbtTag::Long(v) => out.extend_from_slice(&v.to_le_bytes()),
+        NbtTag::Float(v) => out.extend_from_slice(&v.to_le_bytes()),
+        NbtTag::Double(v) => out.extend_from_slice(&v.to_le_bytes()),
+        NbtTag::ByteArray(v) => {
+            out.extend_from_slice(&(v.len() as i32).to_le_bytes());
+            out.extend(v.iter().map(|&b| b as u8));
+        }
+        NbtTag::String(v) => write_string(out, v),
+        NbtTag::List(list) => {
+            let element_type = list.iter().next().map_or(TAG_END, tag_type);
+            out.push(element_type);
+            out.extend_from_slice(&(list.len() as i32).to_le_bytes());
+            for element in list.iter() {
+                write_payload(out, element);
+            }
+        }
+        NbtTag::Compound(compound) => write_compound(out, compound),
+        NbtTag::IntArray(v) => {
+            out.extend_from_slice(&(v.len() as i32).to_le_bytes());
+            for value in v {
+                out.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        NbtTag::LongArray(v) => {
+            out.extend_from_slice(&(v.len() as i32).to_le_bytes());
+            for value in v {
+                out.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_le_nbt_roundtrip() {
+        let mut inner = NbtCompound::new();
+        inner.insert("name", NbtTag::String("minecraft:stone".to_string()));
+        inner.insert("version", NbtTag::Int(17959425));
+
+        let mut root = NbtCompound::new();
+        root.insert("format_version", NbtTag::Int(1));
+        root.insert("size", NbtTag::List(NbtList::from(vec![NbtTag::Int(1), NbtTag::Int(2), NbtTag::Int(3)])));
+        root.insert("empty", NbtTag::List(NbtList::new()));
+        root.insert("pos", NbtTag::List(NbtList::from(vec![NbtTag::Float(0.5), NbtTag::Float(-1.25)])));
+        root.insert("bytes", NbtTag::ByteArray(vec![-1, 0, 1]));
+        root.insert("longs", NbtTag::LongArray(vec![i64::MIN, 42]));
+        root.insert("block", NbtTag::Compound(inner));
+
+        let bytes = write_le_nbt("", &root);
+        // Type, empty name, then the first entry starts with its own tag type
+        assert_eq!(&bytes[..3], &[TAG_COMPOUND, 0, 0]);
+
+        let (read, name) = read_le_nbt(&bytes).unwrap();
+        assert_eq!(name, "");
+        assert_eq!(read, root);
+    }
+
+    #[test]
+    fn test_le_nbt_rejects_truncated_data() {
+        let mut root = NbtCompound::new();
+        root.insert("format_version", NbtTag::Int(1));
+        let bytes = write_le_nbt("", &root);
+
+        assert!(read_le_nbt(&bytes[..bytes.len() - 3]).is_err());
+        assert!(read_le_nbt(&[TAG_INT, 0, 0]).is_err());
+    }
+}
@@ -1,3 +1,6 @@
 mod nbt;
+mod snbt;
+pub(crate) mod le_nbt;
+pub(crate) mod zip;
 
 pub use nbt::{NbtValue, NbtMap};
\ No newline at end of file
@@ -42,6 +42,14 @@ impl NbtMap {
         self.0.remove(key)
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
     pub fn iter(&self) -> std::collections::hash_map::Iter<String, NbtValue> {
         self.0.iter()
     }
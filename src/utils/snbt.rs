@@ -0,0 +1,359 @@
+use std::fmt;
+use crate::error::SnbtError;
+use super::{NbtMap, NbtValue};
+
+impl NbtValue {
+    /// Parses a single SNBT value, e.g. `{id:"minecraft:stone",Count:1b}` or `[I;1,2]`.
+    pub fn from_snbt(input: &str) -> Result<NbtValue, SnbtError> {
+        let mut parser = Parser { input, position: 0 };
+        let value = parser.parse_value(0)?;
+        parser.expect_end()?;
+        Ok(value)
+    }
+
+    /// Prints the value as SNBT with vanilla type suffixes. Compound keys are sorted so the
+    /// output is stable.
+    pub fn to_snbt(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl NbtMap {
+    /// Parses an SNBT compound like `{Items:[{id:"minecraft:stone",Count:1b}]}`.
+    pub fn from_snbt(input: &str) -> Result<NbtMap, SnbtError> {
+        let mut parser = Parser { input, position: 0 };
+        parser.skip_whitespace();
+        let map = parser.parse_compound(1)?;
+        parser.expect_end()?;
+        Ok(map)
+    }
+
+    pub fn to_snbt(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl fmt::Display for NbtValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NbtValue::Byte(v) => write!(f, "{}b", v),
+            NbtValue::Short(v) => write!(f, "{}s", v),
+            NbtValue::Int(v) => write!(f, "{}", v),
+            NbtValue::Long(v) => write!(f, "{}L", v),
+            NbtValue::Float(v) => write!(f, "{}f", v),
+            NbtValue::Double(v) => write!(f, "{}d", v),
+            NbtValue::String(v) => write_quoted(f, v),
+            NbtValue::Compound(v) => write!(f, "{}", v),
+            NbtValue::List(values) => write_list(f, "", values.iter()),
+            NbtValue::ByteArray(values) => write_list(f, "B;", values.iter().map(|v| format!("{}b", v))),
+            NbtValue::IntArray(values) => write_list(f, "I;", values.iter()),
+            NbtValue::LongArray(values) => write_list(f, "L;", values.iter().map(|v| format!("{}L", v))),
+        }
+    }
+}
+
+impl fmt::Display for NbtMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut entries: Vec<_> = self.iter().collect();
+        entries.sort_by(|a, b| a.0.cmp(b.0));
+        write!(f, "{{")?;
+        for (i, (key, value)) in entries.into_iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            if !key.is_empty() && key.chars().all(is_unquoted_char) {
+                write!(f, "{}", key)?;
+            } else {
+                write_quoted(f, key)?;
+            }
+            write!(f, ":{}", value)?;
+        }
+        write!(f, "}}")
+    }
+}
+
+fn write_list<T: fmt::Display>(f: &mut fmt::Formatter<'_>, prefix: &str, values: impl Iterator<Item = T>) -> fmt::Result {
+    write!(f, "[{}", prefix)?;
+    for (i, value) in values.enumerate() {
+        if i > 0 {
+            write!(f, ",")?;
+        }
+        write!(f, "{}", value)?;
+    }
+    write!(f, "]")
+}
+
+// Like vanilla, single quotes are only used when they avoid escaping.
+fn write_quoted(f: &mut fmt::Formatter<'_>, value: &str) -> fmt::Result {
+    let quote = if value.contains('"') && !value.contains('\'') { '\'' } else { '"' };
+    write!(f, "{}", quote)?;
+    for c in value.chars() {
+        if c == quote || c == '\\' {
+            write!(f, "\\")?;
+        }
+        write!(f, "{}", c)?;
+    }
+    write!(f, "{}", quote)
+}
+
+// Guards against deeply nested input overflowing the stack, as in `le_nbt`.
+const MAX_DEPTH: usize = 512;
+
+fn is_unquoted_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.' | '+')
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, message: impl Into<String>) -> SnbtError {
+        SnbtError { position: self.position, message: message.into() }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.input[self.position..].chars().next()
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = &self.input[self.position..];
+        self.position += rest.len() - rest.trim_start().len();
+    }
+
+    fn eat(&mut self, expected: char) -> bool {
+        self.skip_whitespace();
+        if self.peek() == Some(expected) {
+            self.position += expected.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), SnbtError> {
+        if self.eat(expected) {
+            Ok(())
+        } else {
+            Err(self.error(format!("Expected '{}'", expected)))
+        }
+    }
+
+    fn expect_end(&mut self) -> Result<(), SnbtError> {
+        self.skip_whitespace();
+        if self.position < self.input.len() {
+            return Err(self.error("Unexpected trailing data"));
+        }
+        Ok(())
+    }
+
+    fn parse_value(&mut self, depth: usize) -> Result<NbtValue, SnbtError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{') => Ok(NbtValue::Compound(self.parse_compound(depth + 1)?)),
+            Some('[') => self.parse_list(depth + 1),
+            Some(quote @ ('"' | '\'')) => Ok(NbtValue::String(self.parse_quoted(quote)?)),
+            Some(_) => {
+                let token = self.parse_unquoted();
+                if token.is_empty() {
+                    return Err(self.error("Expected a value"));
+                }
+                Ok(typed_value(token))
+            }
+            None => Err(self.error("Expected a value")),
+        }
+    }
+
+    fn check_depth(&self, depth: usize) -> Result<(), SnbtError> {
+        if depth > MAX_DEPTH {
+            return Err(self.error("SNBT nesting is too deep"));
+        }
+        Ok(())
+    }
+
+    fn parse_compound(&mut self, depth: usize) -> Result<NbtMap, SnbtError> {
+        self.check_depth(depth)?;
+        self.expect('{')?;
+        let mut map = NbtMap::new();
+        if self.eat('}') {
+            return Ok(map);
+        }
+        loop {
+            self.skip_whitespace();
+            // Only a quoted key may be empty, as `""` is how one is printed.
+            let key = match self.peek() {
+                Some(quote @ ('"' | '\'')) => self.parse_quoted(quote)?,
+                _ => match self.parse_unquoted() {
+                    "" => return Err(self.error("Expected a key")),
+                    key => key.to_string(),
+                },
+            };
+            self.expect(':')?;
+            let value = self.parse_value(depth)?;
+            map.insert(key, value);
+            if !self.eat(',') {
+                self.expect('}')?;
+                return Ok(map);
+            }
+        }
+    }
+
+    fn parse_list(&mut self, depth: usize) -> Result<NbtValue, SnbtError> {
+        self.check_depth(depth)?;
+        self.expect('[')?;
+        let rest = &self.input[self.position..];
+        let array_type = match rest.as_bytes() {
+            [kind @ (b'B' | b'I' | b'L'), b';', ..] => Some(*kind),
+            _ => None,
+        };
+        if array_type.is_some() {
+            self.position += 2;
+        }
+
+        let mut values = Vec::new();
+        if !self.eat(']') {
+            loop {
+                let start = self.position;
+                let value = self.parse_value(depth)?;
+                values.push((start, value));
+                if !self.eat(',') {
+                    self.expect(']')?;
+                    break;
+                }
+            }
+        }
+
+        let Some(kind) = array_type else {
+            return Ok(NbtValue::List(values.into_iter().map(|(_, value)| value).collect()));
+        };
+        let mut integers = Vec::with_capacity(values.len());
+        for (start, value) in values {
+            // Narrower integers are widened, anything else doesn't fit the array.
+            let integer = match (kind, value) {
+                (_, NbtValue::Byte(v)) => v as i64,
+                (b'I' | b'L', NbtValue::Short(v)) => v as i64,
+                (b'I' | b'L', NbtValue::Int(v)) => v as i64,
+                (b'L', NbtValue::Long(v)) => v,
+                _ => return Err(SnbtError { position: start, message: "Wrong element type in typed array".to_string() }),
+            };
+            integers.push(integer);
+        }
+        Ok(match kind {
+            b'B' => NbtValue::ByteArray(integers.into_iter().map(|v| v as i8).collect()),
+            b'I' => NbtValue::IntArray(integers.into_iter().map(|v| v as i32).collect()),
+            _ => NbtValue::LongArray(integers),
+        })
+    }
+
+    fn parse_quoted(&mut self, quote: char) -> Result<String, SnbtError> {
+        let start = self.position;
+        let mut chars = self.input[start + 1..].char_indices();
+        let mut value = String::new();
+        loop {
+            match chars.next() {
+                Some((offset, c)) if c == quote => {
+                    self.position = start + 1 + offset + c.len_utf8();
+                    return Ok(value);
+                }
+                Some((_, '\\')) => match chars.next() {
+                    Some((_, 'n')) => value.push('\n'),
+                    Some((_, 't')) => value.push('\t'),
+                    Some((_, 'r')) => value.push('\r'),
+                    Some((_, c @ ('\\' | '"' | '\''))) => value.push(c),
+                    Some((offset, c)) => {
+                        return Err(SnbtError { position: start + 1 + offset, message: format!("Invalid escape '\\{}'", c) });
+                    }
+                    None => break,
+                },
+                Some((_, c)) => value.push(c),
+                None => break,
+            }
+        }
+        Err(SnbtError { position: start, message: "Unterminated string".to_string() })
+    }
+
+    fn parse_unquoted(&mut self) -> &'a str {
+        let rest = &self.input[self.position..];
+        let end = rest.find(|c| !is_unquoted_char(c)).unwrap_or(rest.len());
+        self.position += end;
+        &rest[..end]
+    }
+}
+
+fn is_decimal(token: &str) -> bool {
+    token.chars().any(|c| c.is_ascii_digit())
+        && token.chars().all(|c| c.is_ascii_digit() || matches!(c, '.' | 'e' | 'E' | '+' | '-'))
+}
+
+// Unquoted tokens that don't read as a number (or a boolean) are strings, as in vanilla.
+fn typed_value(token: &str) -> NbtValue {
+    match token {
+        "true" => return NbtValue::Byte(1),
+        "false" => return NbtValue::Byte(0),
+        _ => {}
+    }
+    let (body, suffix) = token.split_at(token.len() - 1);
+    let parsed = match suffix {
+        "b" | "B" => body.parse().ok().map(NbtValue::Byte),
+        "s" | "S" => body.parse().ok().map(NbtValue::Short),
+        "l" | "L" => body.parse().ok().map(NbtValue::Long),
+        "f" | "F" if is_decimal(body) => body.parse().ok().map(NbtValue::Float),
+        "d" | "D" if is_decimal(body) => body.parse().ok().map(NbtValue::Double),
+        _ => token.parse().ok().map(NbtValue::Int).or_else(|| {
+            (token.contains('.') && is_decimal(token)).then(|| token.parse().ok().map(NbtValue::Double)).flatten()
+        }),
+    };
+    parsed.unwrap_or_else(|| NbtValue::String(token.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snbt_round_trip() {
+        let input = r#"{Items:[{Count:1b,Slot:0b,id:"minecraft:stone"}],Lock:'say "hi"',"odd key":[I;1,-2],longs:[L;5L],bytes:[B;1b,2b],pos:[0.5d,64.0f],s:3s,big:9000000000L}"#;
+        let map = NbtMap::from_snbt(input).unwrap();
+        assert_eq!(map.get("s"), Some(&NbtValue::Short(3)));
+        assert_eq!(map.get("odd key"), Some(&NbtValue::IntArray(vec![1, -2])));
+        assert_eq!(map.get("Lock").and_then(|v| v.as_string()).map(String::as_str), Some("say \"hi\""));
+        assert_eq!(map.get("pos"), Some(&NbtValue::List(vec![NbtValue::Double(0.5), NbtValue::Float(64.0)])));
+
+        let printed = map.to_snbt();
+        assert!(printed.starts_with(r#"{Items:[{Count:1b,Slot:0b,id:"minecraft:stone"}],Lock:'say "hi"',big:9000000000L,bytes:[B;1b,2b]"#));
+        assert_eq!(NbtMap::from_snbt(&printed).unwrap(), map);
+    }
+
+    #[test]
+    fn test_snbt_scalars() {
+        assert_eq!(NbtValue::from_snbt("true").unwrap(), NbtValue::Byte(1));
+        assert_eq!(NbtValue::from_snbt("1.5").unwrap(), NbtValue::Double(1.5));
+        assert_eq!(NbtValue::from_snbt("1e5").unwrap(), NbtValue::String("1e5".to_string()));
+        assert_eq!(NbtValue::from_snbt("300b").unwrap(), NbtValue::String("300b".to_string()));
+        assert_eq!(NbtValue::from_snbt("\"a\\\\b\\\"\"").unwrap(), NbtValue::String("a\\b\"".to_string()));
+        assert_eq!(NbtValue::String("a\\b".to_string()).to_snbt(), "\"a\\\\b\"");
+
+        let error = NbtMap::from_snbt("{a:1,}").unwrap_err();
+        assert_eq!(error.position, 5);
+        assert!(NbtValue::from_snbt("[I;1b,2L]").is_err());
+        assert!(NbtValue::from_snbt("\"open").is_err());
+    }
+
+    #[test]
+    fn test_snbt_empty_key_round_trips() {
+        let map = NbtMap::from_snbt(r#"{"":1b,'':2b}"#).unwrap();
+        assert_eq!(map.get(""), Some(&NbtValue::Byte(2)));
+        assert_eq!(NbtMap::from_snbt(&map.to_snbt()).unwrap(), map);
+        assert!(NbtMap::from_snbt("{:1b}").is_err());
+    }
+
+    #[test]
+    fn test_snbt_nesting_limit() {
+        let nested = |depth: usize| format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+        assert!(NbtValue::from_snbt(&nested(MAX_DEPTH)).is_ok());
+        let error = NbtValue::from_snbt(&nested(100_000)).unwrap_err();
+        assert_eq!(error.message, "SNBT nesting is too deep");
+        assert!(NbtMap::from_snbt(&format!("{}{}", "{a:".repeat(100_000), "}".repeat(100_000))).is_err());
+    }
+}
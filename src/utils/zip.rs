@@ -0,0 +1,143 @@
+use std::io::Write;
+use flate2::write::DeflateEncoder;
+use flate2::{Compression, Crc};
+
+// Just enough of the zip format to package datapacks: deflated entries, no zip64,
+// timestamps left at the DOS epoch.
+
+const LOCAL_FILE_HEADER: u32 = 0x04034b50;
+const CENTRAL_DIRECTORY_HEADER: u32 = 0x02014b50;
+const END_OF_CENTRAL_DIRECTORY: u32 = 0x06054b50;
+
+const VERSION: u16 = 20;
+const UTF8_NAMES: u16 = 0x0800;
+const DEFLATE: u16 = 8;
+const DOS_EPOCH_DATE: u16 = (1 << 5) | 1;
+
+pub(crate) struct ZipWriter {
+    data: Vec<u8>,
+    central_directory: Vec<u8>,
+    entries: u16,
+}
+
+impl ZipWriter {
+    pub fn new() -> Self {
+        ZipWriter {
+            data: Vec::new(),
+            central_directory: Vec::new(),
+            entries: 0,
+        }
+    }
+
+    pub fn add_file(&mut self, name: &str, contents: &[u8]) -> Result<(), String> {
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(contents).map_err(|e| e.to_string())?;
+        let compressed = encoder.finish().map_err(|e| e.to_string())?;
+
+        let mut crc = Crc::new();
+        crc.update(contents);
+
+        let offset = self.data.len();
+        if offset + compressed.len() > u32::MAX as usize || self.entries == u16::MAX {
+            return Err("Zip archive is too large".to_string());
+        }
+
+        let mut fields = Vec::with_capacity(26);
+        for value in [VERSION, UTF8_NAMES, DEFLATE, 0, DOS_EPOCH_DATE] {
+            fields.extend_from_slice(&value.to_le_bytes());
+        }
+        for value in [crc.sum(), compressed.len() as u32, contents.len() as u32] {
+            fields.extend_from_slice(&value.to_le_bytes());
+        }
+        fields.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        fields.extend_from_slice(&0u16.to_le_bytes());
+
+        self.data.extend_from_slice(&LOCAL_FILE_HEADER.to_le_bytes());
+        self.data.extend_from_slice(&fields);
+        self.data.extend_from_slice(name.as_bytes());
+        self.data.extend_from_slice(&compressed);
+
+        // The central directory repeats the local header with a few extra fields.
+        self.central_directory.extend_from_slice(&CENTRAL_DIRECTORY_HEADER.to_le_bytes());
+        self.central_directory.extend_from_slice(&VERSION.to_le_bytes());
+        self.central_directory.extend_from_slice(&fields);
+        for value in [0u16, 0, 0] {
+            self.central_directory.extend_from_slice(&value.to_le_bytes());
+        }
+        self.central_directory.extend_from_slice(&0u32.to_le_bytes());
+        self.central_directory.extend_from_slice(&(offset as u32).to_le_bytes());
+        self.central_directory.extend_from_slice(name.as_bytes());
+
+        self.entries += 1;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Vec<u8> {
+        let central_directory_offset = self.data.len() as u32;
+        let central_directory_size = self.central_directory.len() as u32;
+        self.data.append(&mut self.central_directory);
+
+        self.data.extend_from_slice(&END_OF_CENTRAL_DIRECTORY.to_le_bytes());
+        for value in [0u16, 0, self.entries, self.entries] {
+            self.data.extend_from_slice(&value.to_le_bytes());
+        }
+        self.data.extend_from_slice(&central_directory_size.to_le_bytes());
+        self.data.extend_from_slice(&central_directory_offset.to_le_bytes());
+        self.data.extend_from_slice(&0u16.to_le_bytes());
+        self.data
+    }
+}
+
+#[cfg(test)]
+pub(crate) fn read_zip_entries(data: &[u8]) -> Vec<(String, Vec<u8>)> {
+    use std::io::Read;
+    use flate2::read::DeflateDecoder;
+
+    let u16_at = |offset: usize| u16::from_le_bytes([data[offset], data[offset + 1]]) as usize;
+    let u32_at = |offset: usize| u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]]) as usize;
+
+    let mut entries = Vec::new();
+    let mut offset = 0;
+    while u32_at(offset) == LOCAL_FILE_HEADER as usize {
+        let compressed_size = u32_at(offset + 18);
+        let name_length = u16_at(offset + 26);
+        let extra_length = u16_at(offset + 28);
+        let name_start = offset + 30;
+        let data_start = name_start + name_length + extra_length;
+
+        let name = String::from_utf8(data[name_start..name_start + name_length].to_vec()).unwrap();
+        let mut contents = Vec::new();
+        DeflateDecoder::new(&data[data_start..data_start + compressed_size]).read_to_end(&mut contents).unwrap();
+
+        let mut crc = Crc::new();
+        crc.update(&contents);
+        assert_eq!(crc.sum() as usize, u32_at(offset + 14), "CRC mismatch for {}", name);
+
+        entries.push((name, contents));
+        offset = data_start + compressed_size;
+    }
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_zip_roundtrip() {
+        let mut zip = ZipWriter::new();
+        zip.add_file("pack.mcmeta", b"{\"pack\":{}}").unwrap();
+        zip.add_file("data/test/function/build.mcfunction", "setblock ~ ~ ~ minecraft:stone\n".repeat(100).as_bytes()).unwrap();
+        let data = zip.finish();
+
+        let entries = read_zip_entries(&data);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0], ("pack.mcmeta".to_string(), b"{\"pack\":{}}".to_vec()));
+        assert_eq!(entries[1].0, "data/test/function/build.mcfunction");
+
+        // End of central directory record with both entries
+        let end = &data[data.len() - 22..];
+        assert_eq!(u32::from_le_bytes([end[0], end[1], end[2], end[3]]), END_OF_CENTRAL_DIRECTORY);
+        assert_eq!(u16::from_le_bytes([end[10], end[11]]), 2);
+    }
+}
@@ -6,12 +6,18 @@ use web_sys::console;
 use crate::{
     UniversalSchematic,
     BlockState,
-    formats::{litematic, schematic},
-    print_utils::{format_schematic as print_schematic, format_json_schematic as print_json_schematic},
+    RecipePreference,
+    ResolveOptions,
+    SchematicDiff,
+    formats::{litematic, mcedit, mcfunction, mcstructure, schematic, structure},
+    print_utils::{format_diff, format_schematic as print_schematic, format_json_schematic as print_json_schematic},
     block_position::BlockPosition,
+    block_entity::BlockEntity,
+    block_registry,
     mchprs_world::MchprsWorld,
+    utils::NbtMap,
 };
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use mchprs_blocks::BlockPos;
 
 
@@ -43,7 +49,7 @@ impl SchematicWrapper {
     }
 
 
-    pub fn create_simulation_world(&self) -> MchprsWorldWrapper {
+    pub fn create_simulation_world(&self) -> Result<MchprsWorldWrapper, JsValue> {
         MchprsWorldWrapper::new(self)
     }
 
@@ -53,6 +59,12 @@ impl SchematicWrapper {
             self.from_litematic(data)
         } else if schematic::is_schematic(data) {
             self.from_schematic(data)
+        } else if mcedit::is_mcedit(data) {
+            self.from_mcedit(data)
+        } else if structure::is_structure(data) {
+            self.from_structure(data)
+        } else if mcstructure::is_mcstructure(data) {
+            self.from_mcstructure(data)
         } else {
             Err(JsValue::from_str("Unknown or unsupported schematic format"))
         }
@@ -74,11 +86,142 @@ impl SchematicWrapper {
         Ok(())
     }
 
+    pub fn from_mcedit(&mut self, data: &[u8]) -> Result<(), JsValue> {
+        self.0 = mcedit::from_mcedit(data)
+            .map_err(|e| JsValue::from_str(&format!("MCEdit schematic parsing error: {}", e)))?;
+        Ok(())
+    }
+
     pub fn to_schematic(&self) -> Result<Vec<u8>, JsValue> {
         schematic::to_schematic(&self.0)
             .map_err(|e| JsValue::from_str(&format!("Schematic conversion error: {}", e)))
     }
 
+    pub fn to_schematic_v3(&self) -> Result<Vec<u8>, JsValue> {
+        schematic::to_schematic_with_version(&self.0, schematic::SchematicVersion::V3)
+            .map_err(|e| JsValue::from_str(&format!("Schematic conversion error: {}", e)))
+    }
+
+    pub fn from_structure(&mut self, data: &[u8]) -> Result<(), JsValue> {
+        self.0 = structure::from_structure(data)
+            .map_err(|e| JsValue::from_str(&format!("Structure parsing error: {}", e)))?;
+        Ok(())
+    }
+
+    pub fn to_structure(&self) -> Result<Vec<u8>, JsValue> {
+        structure::to_structure(&self.0)
+            .map_err(|e| JsValue::from_str(&format!("Structure conversion error: {}", e)))
+    }
+
+    pub fn from_mcstructure(&mut self, data: &[u8]) -> Result<(), JsValue> {
+        self.0 = mcstructure::from_mcstructure(data)
+            .map_err(|e| JsValue::from_str(&format!("Bedrock structure parsing error: {}", e)))?;
+        Ok(())
+    }
+
+    pub fn to_mcstructure(&self) -> Result<Vec<u8>, JsValue> {
+        mcstructure::to_mcstructure(&self.0)
+            .map_err(|e| JsValue::from_str(&format!("Bedrock structure conversion error: {}", e)))
+    }
+
+    /// Upgrades or downgrades block and item names to `data_version`. Call it after loading,
+    /// or before exporting for an older game version.
+    pub fn convert_data_version(&mut self, data_version: i32) -> bool {
+        self.0.convert_data_version(data_version)
+    }
+
+    /// The material list as an array of `{ item, count, stack_size, stacks, remainder, shulker_boxes }`.
+    pub fn material_list(&self, include_containers: bool) -> Result<JsValue, JsValue> {
+        js_sys::JSON::parse(&self.0.material_list(include_containers).to_json())
+    }
+
+    pub fn material_list_csv(&self, include_containers: bool) -> String {
+        self.0.material_list(include_containers).to_csv()
+    }
+
+    /// The material list broken down through recipes, as `{ raw, crafted }` arrays shaped like
+    /// `material_list`.
+    pub fn raw_materials(&self, include_containers: bool, fewest_raw_materials: bool) -> Result<JsValue, JsValue> {
+        let options = ResolveOptions {
+            preference: if fewest_raw_materials { RecipePreference::FewestRawMaterials } else { RecipePreference::Usual },
+            ..Default::default()
+        };
+        let resolved = self.0.material_list(include_containers).resolve(&options);
+        let obj = js_sys::Object::new();
+        js_sys::Reflect::set(&obj, &"raw".into(), &js_sys::JSON::parse(&resolved.raw.to_json())?)?;
+        js_sys::Reflect::set(&obj, &"crafted".into(), &js_sys::JSON::parse(&resolved.crafted.to_json())?)?;
+        Ok(obj.into())
+    }
+
+    /// Groups the edits until `commit` into one undo step, so an editor can record a brush
+    /// stroke without copying the schematic.
+    pub fn begin_transaction(&mut self) {
+        self.0.begin_transaction();
+    }
+
+    pub fn commit(&mut self) -> bool {
+        self.0.commit()
+    }
+
+    pub fn undo(&mut self) -> Result<bool, JsValue> {
+        self.0.undo().map_err(|e| JsValue::from_str(&format!("Undo error: {}", e)))
+    }
+
+    pub fn redo(&mut self) -> Result<bool, JsValue> {
+        self.0.redo().map_err(|e| JsValue::from_str(&format!("Redo error: {}", e)))
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.0.can_undo()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        self.0.can_redo()
+    }
+
+    pub fn set_history_budget(&mut self, bytes: usize) {
+        self.0.set_history_budget(bytes);
+    }
+
+    pub fn clear_history(&mut self) {
+        self.0.clear_history();
+    }
+
+    /// The changes that turn this schematic into `other`, in the compact JSON diff format.
+    pub fn diff(&self, other: &SchematicWrapper) -> Result<String, JsValue> {
+        self.0.diff(&other.0).to_json()
+            .map_err(|e| JsValue::from_str(&format!("Diff error: {}", e)))
+    }
+
+    pub fn apply_diff(&mut self, diff_json: &str) -> Result<(), JsValue> {
+        SchematicDiff::from_json(diff_json)
+            .and_then(|diff| self.0.apply(&diff))
+            .map_err(|e| JsValue::from_str(&format!("Diff error: {}", e)))
+    }
+
+    /// The changed layers between this schematic and `other`, drawn as text.
+    pub fn format_diff(&self, other: &SchematicWrapper) -> String {
+        format_diff(&self.0.diff(&other.0))
+    }
+
+    /// One message per palette entry the game would reject, e.g. an unknown property.
+    pub fn validate(&self) -> Vec<String> {
+        self.0.validate()
+            .into_iter()
+            .map(|issue| format!("{} ({} in {}): {}", issue.block, issue.count, issue.region, issue.error))
+            .collect()
+    }
+
+    pub fn to_datapack(&self, namespace: &str, function_name: &str) -> Result<Vec<u8>, JsValue> {
+        let options = mcfunction::McFunctionOptions {
+            namespace: namespace.to_string(),
+            function_name: function_name.to_string(),
+            ..Default::default()
+        };
+        mcfunction::to_datapack(&self.0, &options)
+            .map_err(|e| JsValue::from_str(&format!("Datapack conversion error: {}", e)))
+    }
+
     pub fn set_block(&mut self, x: i32, y: i32, z: i32, block_name: &str) {
         self.0.set_block(x, y, z, BlockState::new(block_name.to_string()));
     }
@@ -93,8 +236,8 @@ impl SchematicWrapper {
         block_name: &str,
         properties: &JsValue,
     ) -> Result<(), JsValue> {
-        // Convert JsValue to HashMap<String, String>
-        let mut props = HashMap::new();
+        // Convert JsValue to BTreeMap<String, String>
+        let mut props = BTreeMap::new();
 
         if !properties.is_undefined() && !properties.is_null() {
             let obj: Object = properties.clone().dyn_into().map_err(|_| {
@@ -166,6 +309,25 @@ impl SchematicWrapper {
         }
     }
 
+    pub fn get_block_entity_snbt(&self, x: i32, y: i32, z: i32) -> Option<String> {
+        self.0.get_block_entity(BlockPosition { x, y, z }).map(|block_entity| block_entity.nbt.to_snbt())
+    }
+
+    pub fn set_block_entity_snbt(&mut self, x: i32, y: i32, z: i32, snbt: &str) -> Result<(), JsValue> {
+        let nbt = NbtMap::from_snbt(snbt).map_err(|e| JsValue::from_str(&e.to_string()))?;
+        let position = BlockPosition { x, y, z };
+        let mut block_entity = match self.0.get_block_entity(position) {
+            Some(block_entity) => block_entity.clone(),
+            None => {
+                let id = self.0.get_block(x, y, z).map(|block| block.name.clone()).unwrap_or_default();
+                BlockEntity::new(id, (x, y, z))
+            }
+        };
+        block_entity.nbt = nbt;
+        self.0.set_block_entity(position, block_entity);
+        Ok(())
+    }
+
     pub fn get_all_block_entities(&self) -> JsValue {
         let block_entities = self.0.get_block_entities_as_list();
         let js_block_entities = Array::new();
@@ -300,14 +462,15 @@ impl SchematicWrapper {
 #[wasm_bindgen]
 impl MchprsWorldWrapper {
     #[wasm_bindgen(constructor)]
-    pub fn new(schematic: &SchematicWrapper) -> Self {
+    pub fn new(schematic: &SchematicWrapper) -> Result<MchprsWorldWrapper, JsValue> {
         console::log_1(&"Creating MchprsWorldWrapper".into());
 
-        let world = MchprsWorld::new(schematic.0.clone());
+        let world = MchprsWorld::new(schematic.0.clone())
+            .map_err(|e| JsValue::from_str(&format!("Simulation world error: {}", e)))?;
 
         console::log_1(&"MchprsWorld created successfully".into());
 
-        MchprsWorldWrapper { world }
+        Ok(MchprsWorldWrapper { world })
     }
 
     pub fn on_use_block(&mut self, x: i32, y: i32, z: i32) {
@@ -359,6 +522,14 @@ impl BlockStateWrapper {
         }
         js_properties.into()
     }
+
+    pub fn validate(&self, data_version: i32) -> Result<(), JsValue> {
+        self.0.validate(data_version).map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
+    pub fn with_defaults(&mut self) {
+        self.0 = self.0.clone().with_defaults();
+    }
 }
 
 
@@ -371,4 +542,23 @@ pub fn debug_schematic(schematic: &SchematicWrapper) -> String {
 #[wasm_bindgen]
 pub fn debug_json_schematic(schematic: &SchematicWrapper) -> String {
     format!("{}\n{}", schematic.debug_info(), print_json_schematic(&schematic.0))
-}
\ No newline at end of file
+}
+
+/// Every vanilla block id in `data_version`, for autocomplete.
+#[wasm_bindgen]
+pub fn block_ids(data_version: i32) -> Vec<String> {
+    block_registry::block_ids(data_version).into_iter().map(str::to_string).collect()
+}
+
+/// The properties of `id` in `data_version`, as `{ name: [default, ...other values] }`,
+/// or undefined when the block doesn't exist there.
+#[wasm_bindgen]
+pub fn block_properties(id: &str, data_version: i32) -> JsValue {
+    let Some(definition) = block_registry::block_definition(id, data_version) else { return JsValue::UNDEFINED };
+    let js_properties = js_sys::Object::new();
+    for property in &definition.properties {
+        let values = property.values.iter().map(|value| JsValue::from_str(value)).collect::<js_sys::Array>();
+        js_sys::Reflect::set(&js_properties, &JsValue::from_str(&property.name), &values).unwrap();
+    }
+    js_properties.into()
+}